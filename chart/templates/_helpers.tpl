@@ -0,0 +1,102 @@
+{{/* RBAC rules required by the operator. */}}
+{{- define "ytdl-operator.rules" }}
+- apiGroups: [""]
+  resources:
+  - secrets
+  verbs:
+  - get
+- apiGroups:
+  - ""
+  resources:
+  - pods
+  verbs:
+  - create
+  - delete
+  - get
+  - list
+  - patch
+  - update
+  - watch
+- apiGroups: [""]
+  resources:
+  - configmaps
+  verbs:
+  - create
+  - delete
+  - get
+  - list
+  - patch
+  - update
+  - watch
+- apiGroups: [""]
+  resources:
+  - persistentvolumeclaims
+  verbs:
+  - create
+  - get
+- apiGroups: [""]
+  resources:
+  - pods/log
+  verbs:
+  - get
+- apiGroups: ["coordination.k8s.io"]
+  resources:
+  - leases
+  verbs:
+  - create
+  - get
+  - update
+- apiGroups: ["events.k8s.io"]
+  resources:
+  - events
+  verbs:
+  - create
+- apiGroups: ["ytdl.beebs.dev"]
+  resources:
+  - defaulttargets
+  - hostpolicies
+  verbs:
+  - get
+  - list
+  - watch
+- apiGroups: ["ytdl.beebs.dev"]
+  resources:
+  - targets
+  - s3targets
+  - webhooktargets
+  - sqltargets
+  - mongodbtargets
+  - redistargets
+  - gcstargets
+  - azureblobtargets
+  - volumetargets
+  - sftptargets
+  - webdavtargets
+  - kafkatargets
+  - natstargets
+  verbs:
+  - get
+- apiGroups: ["ytdl.beebs.dev"]
+  resources:
+  - downloads
+  - downloads/status
+  - executors
+  - executors/status
+  verbs:
+  - create
+  - delete
+  - get
+  - list
+  - patch
+  - update
+  - watch
+{{- end }}
+
+{{/* Name of the Secret with the admission webhook's serving certificate. */}}
+{{- define "ytdl-operator.webhookTlsSecret" -}}
+{{- if .Values.webhook.certManager.enabled -}}
+{{ .Release.Name }}-webhook-tls
+{{- else -}}
+{{ .Values.webhook.tlsSecret }}
+{{- end -}}
+{{- end }}
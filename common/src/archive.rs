@@ -0,0 +1,307 @@
+//! Download archive support. The archive records the IDs of the videos
+//! that were downloaded successfully, equivalent to youtube-dl's
+//! `--download-archive`. Both the query pod and the Download controller
+//! consult it before creating Executors, and the controller appends the
+//! IDs of the Executors that succeed.
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::{Api, ObjectMeta, PostParams},
+    Client, ResourceExt,
+};
+use redis::AsyncCommands;
+use s3::bucket::Bucket;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use ytdl_types::{Download, DownloadArchiveSpec, S3Target};
+
+use crate::{get_s3_target_bucket, redis::get_redis_connection, sse::with_sse, Entity, Error};
+
+/// Key in the archive ConfigMap for the list of IDs.
+pub const ARCHIVE_KEY: &str = "archive.txt";
+
+/// Returns the set of IDs in the Download's archive, or None if
+/// the Download does not have an archive.
+pub async fn load_archive(
+    client: Client,
+    instance: &Download,
+) -> Result<Option<HashSet<String>>, Error> {
+    let spec = match instance.spec.archive {
+        Some(ref spec) => spec,
+        None => return Ok(None),
+    };
+    let namespace = instance.namespace().unwrap();
+    let backend = get_backend(spec)?;
+    Ok(Some(read_archive(client, &namespace, &backend).await?))
+}
+
+/// Cache of the archives loaded by the Download controller, keyed by
+/// their location, so that the reconciliations of a large Download
+/// only read its archive in full after it changes.
+#[derive(Default)]
+pub struct ArchiveCache {
+    archives: Mutex<HashMap<String, CachedArchive>>,
+}
+
+/// The IDs of an archive as of the version they were read at.
+struct CachedArchive {
+    version: String,
+    ids: Arc<HashSet<String>>,
+}
+
+impl ArchiveCache {
+    /// Returns the set of IDs in the Download's archive, or None if
+    /// the Download does not have an archive. The archive is only read
+    /// again once its version changes.
+    pub async fn load(
+        &self,
+        client: Client,
+        instance: &Download,
+    ) -> Result<Option<Arc<HashSet<String>>>, Error> {
+        let spec = match instance.spec.archive {
+            Some(ref spec) => spec,
+            None => return Ok(None),
+        };
+        let namespace = instance.namespace().unwrap();
+        let backend = get_backend(spec)?;
+        let key = backend.cache_key(&namespace);
+        // The version is read before the IDs, so IDs that are newer than
+        // their version are read again next time instead of going stale.
+        let version = get_version(client.clone(), &namespace, &backend).await?;
+        if let Some(cached) = self.archives.lock().unwrap().get(&key) {
+            if cached.version == version {
+                return Ok(Some(cached.ids.clone()));
+            }
+        }
+        let ids = Arc::new(read_archive(client, &namespace, &backend).await?);
+        self.archives.lock().unwrap().insert(
+            key,
+            CachedArchive {
+                version,
+                ids: ids.clone(),
+            },
+        );
+        Ok(Some(ids))
+    }
+}
+
+/// Reads the IDs in the archive.
+async fn read_archive(
+    client: Client,
+    namespace: &str,
+    backend: &Backend<'_>,
+) -> Result<HashSet<String>, Error> {
+    let ids = match *backend {
+        Backend::ConfigMap(name) => {
+            let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+            let cm = api.get_opt(name).await?;
+            cm.and_then(|cm| cm.data)
+                .and_then(|mut data| data.remove(ARCHIVE_KEY))
+                .map(|archive| parse_archive(&archive))
+                .unwrap_or_default()
+        }
+        Backend::S3 { target, key } => {
+            let bucket = get_archive_bucket(client, namespace, target).await?;
+            let res = bucket.get_object(key).await?;
+            if res.status_code() == 404 {
+                // The archive is created with the first append.
+                HashSet::new()
+            } else {
+                parse_archive(std::str::from_utf8(res.bytes())?)
+            }
+        }
+        Backend::Redis { target, key } => {
+            let mut con = get_redis_connection(client, namespace, target).await?;
+            con.smembers::<_, HashSet<String>>(key).await?
+        }
+    };
+    Ok(ids)
+}
+
+/// Returns a version of the archive that changes whenever its IDs do,
+/// without reading them: the resourceVersion of the ConfigMap, the
+/// ETag of the S3 object, and the cardinality of the Redis set, as IDs
+/// are only ever added to it. Archives that don't exist yet have an
+/// empty version.
+async fn get_version(
+    client: Client,
+    namespace: &str,
+    backend: &Backend<'_>,
+) -> Result<String, Error> {
+    let version = match *backend {
+        Backend::ConfigMap(name) => {
+            // ConfigMaps are at most 1 MiB, so only parsing is saved.
+            let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+            api.get_opt(name)
+                .await?
+                .and_then(|cm| cm.resource_version())
+                .unwrap_or_default()
+        }
+        Backend::S3 { target, key } => {
+            let bucket = get_archive_bucket(client, namespace, target).await?;
+            let (head, status_code) = bucket.head_object(key).await?;
+            if status_code == 404 {
+                String::new()
+            } else {
+                head.e_tag.unwrap_or_default()
+            }
+        }
+        Backend::Redis { target, key } => {
+            let mut con = get_redis_connection(client, namespace, target).await?;
+            con.scard::<_, u64>(key).await?.to_string()
+        }
+    };
+    Ok(version)
+}
+
+/// Returns the bucket of the named S3Target.
+async fn get_archive_bucket(
+    client: Client,
+    namespace: &str,
+    target: &str,
+) -> Result<Bucket, Error> {
+    let target = Api::<S3Target>::namespaced(client.clone(), namespace)
+        .get(target)
+        .await?;
+    get_s3_target_bucket(client, namespace, &target.spec).await
+}
+
+/// Returns true if every entity in the batch is in the archive, in
+/// which case its Executor can be culled.
+pub fn is_archived(archive: Option<&HashSet<String>>, batch: &[Entity]) -> bool {
+    match archive {
+        Some(archive) => batch.iter().all(|entity| archive.contains(&entity.id)),
+        None => false,
+    }
+}
+
+/// Adds the IDs to the Download's archive. Does nothing if the
+/// Download does not have an archive.
+pub async fn append_archive(
+    client: Client,
+    instance: &Download,
+    ids: &[String],
+) -> Result<(), Error> {
+    let spec = match instance.spec.archive {
+        Some(ref spec) => spec,
+        None => return Ok(()),
+    };
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let namespace = instance.namespace().unwrap();
+    match get_backend(spec)? {
+        Backend::ConfigMap(name) => {
+            let api: Api<ConfigMap> = Api::namespaced(client, &namespace);
+            match api.get_opt(name).await? {
+                Some(mut cm) => {
+                    let data = cm.data.get_or_insert_with(BTreeMap::new);
+                    let archive = data.entry(ARCHIVE_KEY.to_owned()).or_default();
+                    append_lines(archive, ids);
+                    // The resourceVersion is retained for optimistic concurrency.
+                    api.replace(name, &PostParams::default(), &cm).await?;
+                }
+                None => {
+                    let mut archive = String::new();
+                    append_lines(&mut archive, ids);
+                    let cm = ConfigMap {
+                        metadata: ObjectMeta {
+                            name: Some(name.to_owned()),
+                            namespace: Some(namespace),
+                            ..ObjectMeta::default()
+                        },
+                        data: Some(BTreeMap::from([(ARCHIVE_KEY.to_owned(), archive)])),
+                        ..ConfigMap::default()
+                    };
+                    api.create(&PostParams::default(), &cm).await?;
+                }
+            }
+        }
+        Backend::S3 { target, key } => {
+            let target = Api::<S3Target>::namespaced(client.clone(), &namespace)
+                .get(target)
+                .await?;
+            let bucket = get_s3_target_bucket(client, &namespace, &target.spec).await?;
+            // S3 objects cannot be appended to, so the whole archive is
+            // rewritten. Only the Download controller writes to it.
+            let res = bucket.get_object(key).await?;
+            let mut archive = if res.status_code() == 404 {
+                String::new()
+            } else {
+                std::str::from_utf8(res.bytes())?.to_owned()
+            };
+            append_lines(&mut archive, ids);
+            let res = with_sse(&bucket, target.spec.sse.as_ref())
+                .put_object(key, archive.as_bytes())
+                .await?;
+            if res.status_code() != 200 {
+                return Err(Error::S3UploadError {
+                    status_code: res.status_code(),
+                });
+            }
+        }
+        Backend::Redis { target, key } => {
+            let mut con = get_redis_connection(client, &namespace, target).await?;
+            con.sadd::<_, _, ()>(key, ids).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The storage backend of an archive.
+enum Backend<'a> {
+    ConfigMap(&'a str),
+    S3 { target: &'a str, key: &'a str },
+    Redis { target: &'a str, key: &'a str },
+}
+
+impl Backend<'_> {
+    /// Returns the key of the archive in the cache, which is shared by
+    /// the Downloads that use the same archive.
+    fn cache_key(&self, namespace: &str) -> String {
+        match *self {
+            Backend::ConfigMap(name) => format!("{}/configmap/{}", namespace, name),
+            Backend::S3 { target, key } => format!("{}/s3/{}/{}", namespace, target, key),
+            Backend::Redis { target, key } => format!("{}/redis/{}/{}", namespace, target, key),
+        }
+    }
+}
+
+/// Returns the single backend specified by the archive.
+fn get_backend(spec: &DownloadArchiveSpec) -> Result<Backend<'_>, Error> {
+    match (&spec.config_map, &spec.s3, &spec.redis) {
+        (Some(name), None, None) => Ok(Backend::ConfigMap(name)),
+        (None, Some(s3), None) => Ok(Backend::S3 {
+            target: &s3.target,
+            key: &s3.key,
+        }),
+        (None, None, Some(redis)) => Ok(Backend::Redis {
+            target: &redis.target,
+            key: &redis.key,
+        }),
+        _ => Err(Error::UserInputError(
+            "archive must specify exactly one of configMap, s3, or redis".to_owned(),
+        )),
+    }
+}
+
+/// Parses the IDs from an archive with one ID per line.
+fn parse_archive(archive: &str) -> HashSet<String> {
+    archive
+        .lines()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Appends the IDs to an archive with one ID per line.
+fn append_lines(archive: &mut String, ids: &[String]) {
+    for id in ids {
+        if !archive.is_empty() && !archive.ends_with('\n') {
+            archive.push('\n');
+        }
+        archive.push_str(id);
+    }
+}
@@ -0,0 +1,83 @@
+//! Account credentials for sites that require logging in rather than
+//! cookies. The credentials are stored in a `Secret` that the pod
+//! builders mount into the executor container, where they are passed
+//! to youtube-dl either as `--username`/`--password` or as a netrc file.
+use k8s_openapi::api::core::v1::Pod;
+use std::{os::unix::fs::PermissionsExt, path::Path};
+
+use crate::{pod::mount_secret, Error};
+
+/// Key in the Secret with the account's username.
+pub const USERNAME_KEY: &str = "username";
+
+/// Key in the Secret with the account's password.
+pub const PASSWORD_KEY: &str = "password";
+
+/// Optional key in the Secret with the two-factor authentication code.
+pub const TWOFACTOR_KEY: &str = "twofactor";
+
+/// Key in the Secret with a netrc file, which holds credentials
+/// for any number of sites keyed by extractor name. Takes precedence
+/// over the username and password.
+pub const NETRC_KEY: &str = "netrc";
+
+/// Name of the volume the auth Secret is mounted from.
+const AUTH_VOLUME_NAME: &str = "auth";
+
+/// Directory the auth Secret is mounted at.
+const AUTH_MOUNT_PATH: &str = "/auth";
+
+/// Mounts the credentials in the named Secret into the executor container.
+pub fn mount_auth(pod: &mut Pod, secret_name: &str) {
+    mount_secret(pod, AUTH_VOLUME_NAME, secret_name, AUTH_MOUNT_PATH);
+}
+
+/// Returns the youtube-dl arguments for the mounted credentials, or
+/// an empty list if none were mounted. A netrc file is copied to the
+/// home directory, where youtube-dl looks for it with `--netrc`, and
+/// made private as Python's netrc parser refuses readable files.
+pub fn get_auth_args() -> Result<Vec<String>, Error> {
+    let mount = Path::new(AUTH_MOUNT_PATH);
+    let netrc = mount.join(NETRC_KEY);
+    if netrc.exists() {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/root".to_owned());
+        let path = Path::new(&home).join(".netrc");
+        std::fs::copy(&netrc, &path)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        return Ok(vec!["--netrc".to_owned()]);
+    }
+    let username = read_key(mount, USERNAME_KEY)?;
+    let password = read_key(mount, PASSWORD_KEY)?;
+    let (username, password) = match (username, password) {
+        (Some(username), Some(password)) => (username, password),
+        (None, None) => return Ok(Vec::new()),
+        _ => {
+            return Err(Error::UserInputError(format!(
+                "auth secret must have both {} and {}, or {}",
+                USERNAME_KEY, PASSWORD_KEY, NETRC_KEY
+            )))
+        }
+    };
+    let mut args = vec![
+        "--username".to_owned(),
+        username,
+        "--password".to_owned(),
+        password,
+    ];
+    if let Some(twofactor) = read_key(mount, TWOFACTOR_KEY)? {
+        args.push("--twofactor".to_owned());
+        args.push(twofactor);
+    }
+    Ok(args)
+}
+
+/// Reads a key of the mounted Secret, trimming the trailing newline
+/// that is easily left in when creating the Secret from a file.
+fn read_key(mount: &Path, key: &str) -> Result<Option<String>, Error> {
+    let path = mount.join(key);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let value = std::fs::read_to_string(path)?;
+    Ok(Some(value.trim_end_matches(&['\r', '\n'][..]).to_owned()))
+}
@@ -0,0 +1,399 @@
+//! Azure Blob Storage, per [`AzureBlobTargetSpec`](ytdl_types::AzureBlobTargetSpec).
+//! Like [`gcs`](crate::gcs), this is a small client for the requests the
+//! controllers and download pods make: looking up blobs and uploading
+//! them as block blobs. Requests are signed with the account key or
+//! shared access signature of the connection string in the target's
+//! Secret or, without one, authorized with a managed identity's token.
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::{Hmac, Mac};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use reqwest::{Method, StatusCode, Url};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use ytdl_types::AzureBlobTargetSpec;
+
+use crate::{proxy::get_storage_http_client, Error};
+
+/// Key in the Secret with the storage account's connection string.
+pub const CONNECTION_STRING_KEY: &str = "connection_string";
+
+/// Key in the Secret with the client ID of a user-assigned identity.
+pub const CLIENT_ID_KEY: &str = "client_id";
+
+/// Version of the blob service REST API. Bearer tokens require at
+/// least 2017-11-09.
+const API_VERSION: &str = "2021-08-06";
+
+/// Token endpoint of the instance metadata service.
+const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// Resource the managed identity's tokens are requested for.
+const STORAGE_RESOURCE: &str = "https://storage.azure.com/";
+
+/// Prefix of the headers with the user metadata of a blob.
+const METADATA_PREFIX: &str = "x-ms-meta-";
+
+/// Tokens are renewed this long before they expire, so that a token
+/// never expires in the middle of a request.
+const TOKEN_MARGIN: Duration = Duration::from_secs(300);
+
+/// A client for a single container. Clones share the access token.
+#[derive(Clone)]
+pub struct AzureContainer {
+    /// Name of the container.
+    pub name: String,
+    account: String,
+    endpoint: String,
+    http: reqwest::Client,
+    auth: Arc<Auth>,
+    token: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+/// How requests to the blob service are authorized.
+enum Auth {
+    /// Shared Key signatures with the decoded account key.
+    SharedKey(Vec<u8>),
+
+    /// A shared access signature appended to each request's query.
+    Sas(String),
+
+    /// Bearer tokens of the managed identity with the client ID,
+    /// or the system-assigned identity without one.
+    ManagedIdentity(Option<String>),
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// Lifetime of the token in seconds, which IMDS encodes as a string.
+    expires_in: String,
+}
+
+/// The properties of a blob read by the controllers.
+#[derive(Debug, Clone)]
+pub struct BlobProperties {
+    /// Size of the blob in bytes.
+    pub size: u64,
+
+    /// User metadata of the blob.
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl AzureContainer {
+    /// Returns the properties of the blob, or None if it does not exist.
+    pub async fn head_object(&self, key: &str) -> Result<Option<BlobProperties>, Error> {
+        let res = self
+            .send(Method::HEAD, key, &[], Vec::new(), Vec::new())
+            .await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let res = check(res).await?;
+        let headers = res.headers();
+        let size = headers
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|size| size.to_str().ok())
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(0);
+        let metadata = headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let name = name.as_str().strip_prefix(METADATA_PREFIX)?;
+                Some((name.to_owned(), value.to_str().ok()?.to_owned()))
+            })
+            .collect();
+        Ok(Some(BlobProperties { size, metadata }))
+    }
+
+    /// Uploads the blob with a single request, which is how content
+    /// smaller than a block is uploaded.
+    pub async fn put_blob(
+        &self,
+        key: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        metadata: &BTreeMap<String, String>,
+    ) -> Result<(), Error> {
+        let mut headers = blob_headers(content_type, metadata);
+        headers.push(("x-ms-blob-type".to_owned(), "BlockBlob".to_owned()));
+        let res = self.send(Method::PUT, key, &[], headers, body).await?;
+        check(res).await?;
+        Ok(())
+    }
+
+    /// Stages a block of the blob. Blocks are only part of the blob
+    /// once they are committed with [`put_block_list`](Self::put_block_list).
+    pub async fn put_block(&self, key: &str, block_id: &str, body: Vec<u8>) -> Result<(), Error> {
+        let query = [("comp", "block"), ("blockid", block_id)];
+        let res = self
+            .send(Method::PUT, key, &query, Vec::new(), body)
+            .await?;
+        check(res).await?;
+        Ok(())
+    }
+
+    /// Commits the staged blocks, in order, as the content of the blob.
+    pub async fn put_block_list(
+        &self,
+        key: &str,
+        block_ids: &[String],
+        content_type: &str,
+        metadata: &BTreeMap<String, String>,
+    ) -> Result<(), Error> {
+        let mut body = String::from(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#);
+        for block_id in block_ids {
+            body.push_str(&format!("<Latest>{}</Latest>", block_id));
+        }
+        body.push_str("</BlockList>");
+        let headers = blob_headers(content_type, metadata);
+        let res = self
+            .send(
+                Method::PUT,
+                key,
+                &[("comp", "blocklist")],
+                headers,
+                body.into_bytes(),
+            )
+            .await?;
+        check(res).await?;
+        Ok(())
+    }
+
+    /// Sends the request for the blob, authorized per the target's
+    /// credentials.
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        mut headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, Error> {
+        let mut url = Url::parse(&self.endpoint)
+            .map_err(|e| Error::UserInputError(format!("invalid Azure endpoint: {}", e)))?;
+        url.path_segments_mut()
+            .map_err(|_| Error::UserInputError("invalid Azure endpoint".to_owned()))?
+            .pop_if_empty()
+            .push(&self.name)
+            .extend(key.split('/'));
+        for (name, value) in query {
+            url.query_pairs_mut().append_pair(name, value);
+        }
+        let date = chrono::Utc::now()
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        headers.push(("x-ms-date".to_owned(), date));
+        headers.push(("x-ms-version".to_owned(), API_VERSION.to_owned()));
+        match *self.auth {
+            Auth::SharedKey(ref account_key) => {
+                let signature = self.sign(account_key, &method, &url, query, &headers, body.len());
+                let authorization = format!("SharedKey {}:{}", self.account, signature);
+                headers.push(("authorization".to_owned(), authorization));
+            }
+            Auth::Sas(ref sas) => {
+                let query = match url.query() {
+                    Some(query) => format!("{}&{}", query, sas),
+                    None => sas.clone(),
+                };
+                url.set_query(Some(&query));
+            }
+            Auth::ManagedIdentity(ref client_id) => {
+                let token = self.token(client_id.as_deref()).await?;
+                headers.push(("authorization".to_owned(), format!("Bearer {}", token)));
+            }
+        }
+        let mut req = self.http.request(method, url);
+        for (name, value) in headers {
+            req = req.header(name, value);
+        }
+        Ok(req.body(body).send().await?)
+    }
+
+    /// Returns the Shared Key signature of the request. Requests never
+    /// set the standard headers other than Content-Length, so their
+    /// lines of the string to sign are empty.
+    fn sign(
+        &self,
+        account_key: &[u8],
+        method: &Method,
+        url: &Url,
+        query: &[(&str, &str)],
+        headers: &[(String, String)],
+        content_length: usize,
+    ) -> String {
+        let content_length = match content_length {
+            0 => String::new(),
+            n => n.to_string(),
+        };
+        let mut to_sign = format!("{}\n\n\n{}\n", method, content_length);
+        to_sign.push_str(&"\n".repeat(8));
+        let mut canonical_headers: Vec<_> = headers
+            .iter()
+            .map(|(name, value)| (name.to_lowercase(), value.trim()))
+            .filter(|(name, _)| name.starts_with("x-ms-"))
+            .collect();
+        canonical_headers.sort();
+        for (name, value) in canonical_headers {
+            to_sign.push_str(&format!("{}:{}\n", name, value));
+        }
+        to_sign.push_str(&format!("/{}{}", self.account, url.path()));
+        let mut canonical_query: Vec<_> = query.to_vec();
+        canonical_query.sort();
+        for (name, value) in canonical_query {
+            to_sign.push_str(&format!("\n{}:{}", name, value));
+        }
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(account_key).expect("HMAC accepts keys of any size");
+        mac.update(to_sign.as_bytes());
+        BASE64.encode(mac.finalize().into_bytes())
+    }
+
+    /// Returns an access token of the managed identity, requesting a
+    /// new one if the cached token is about to expire.
+    async fn token(&self, client_id: Option<&str>) -> Result<String, Error> {
+        if let Some((ref token, expires)) = *self.token.lock().unwrap() {
+            if Instant::now() + TOKEN_MARGIN < expires {
+                return Ok(token.clone());
+            }
+        }
+        let mut req = self
+            .http
+            .get(IMDS_TOKEN_URL)
+            .header("Metadata", "true")
+            .query(&[
+                ("api-version", "2018-02-01"),
+                ("resource", STORAGE_RESOURCE),
+            ]);
+        if let Some(client_id) = client_id {
+            req = req.query(&[("client_id", client_id)]);
+        }
+        let res: TokenResponse = check(req.send().await?).await?.json().await?;
+        let expires_in = res.expires_in.parse().unwrap_or(0);
+        let expires = Instant::now() + Duration::from_secs(expires_in);
+        *self.token.lock().unwrap() = Some((res.access_token.clone(), expires));
+        Ok(res.access_token)
+    }
+}
+
+/// Returns the client for the container described by the AzureBlobTarget
+/// spec.
+pub async fn get_azure_container(
+    client: Client,
+    namespace: &str,
+    spec: &AzureBlobTargetSpec,
+) -> Result<AzureContainer, Error> {
+    let mut fields = match spec.secret {
+        Some(ref secret) => get_secret_fields(client, namespace, secret).await?,
+        None => BTreeMap::new(),
+    };
+    let (account, endpoint, auth) = match fields.remove(CONNECTION_STRING_KEY) {
+        Some(connection_string) => parse_connection_string(&connection_string)?,
+        None => {
+            let account = spec.account.clone().ok_or_else(|| {
+                Error::UserInputError(
+                    "Azure account is required with a managed identity".to_owned(),
+                )
+            })?;
+            let endpoint = format!("https://{}.blob.core.windows.net", account);
+            let client_id = fields.remove(CLIENT_ID_KEY);
+            (account, endpoint, Auth::ManagedIdentity(client_id))
+        }
+    };
+    Ok(AzureContainer {
+        name: spec.container.clone(),
+        account: spec.account.clone().unwrap_or(account),
+        endpoint: spec.endpoint.clone().unwrap_or(endpoint),
+        http: get_storage_http_client()?,
+        auth: Arc::new(auth),
+        token: Arc::new(Mutex::new(None)),
+    })
+}
+
+/// Returns the ID of the numbered block. IDs must have the same length
+/// for every block of a blob.
+pub fn block_id(n: usize) -> String {
+    BASE64.encode(format!("{:08}", n))
+}
+
+/// Returns the account name, blob endpoint, and credentials of the
+/// connection string, e.g. `AccountName=...;AccountKey=...`.
+fn parse_connection_string(connection_string: &str) -> Result<(String, String, Auth), Error> {
+    let fields: BTreeMap<&str, &str> = connection_string
+        .split(';')
+        .filter_map(|field| field.trim().split_once('='))
+        .collect();
+    let account = fields.get("AccountName").copied().unwrap_or_default();
+    let endpoint = match fields.get("BlobEndpoint") {
+        Some(endpoint) => endpoint.trim_end_matches('/').to_owned(),
+        None => format!(
+            "{}://{}.blob.{}",
+            fields.get("DefaultEndpointsProtocol").unwrap_or(&"https"),
+            account,
+            fields.get("EndpointSuffix").unwrap_or(&"core.windows.net")
+        ),
+    };
+    let auth = match (
+        fields.get("AccountKey"),
+        fields.get("SharedAccessSignature"),
+    ) {
+        (Some(key), _) => Auth::SharedKey(
+            BASE64
+                .decode(key)
+                .map_err(|e| Error::UserInputError(format!("invalid Azure account key: {}", e)))?,
+        ),
+        (None, Some(sas)) => Auth::Sas(sas.trim_start_matches('?').to_owned()),
+        (None, None) => {
+            return Err(Error::UserInputError(
+                "Azure connection string has neither an AccountKey nor a SharedAccessSignature"
+                    .to_owned(),
+            ))
+        }
+    };
+    Ok((account.to_owned(), endpoint, auth))
+}
+
+/// Returns the fields of the named Secret as strings.
+async fn get_secret_fields(
+    client: Client,
+    namespace: &str,
+    name: &str,
+) -> Result<BTreeMap<String, String>, Error> {
+    let secret = Api::<Secret>::namespaced(client, namespace)
+        .get(name)
+        .await?;
+    Ok(secret
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, String::from_utf8(value.0).ok()?.trim().to_owned())))
+        .collect())
+}
+
+/// Returns the headers setting the content type and user metadata of
+/// the blob.
+fn blob_headers(content_type: &str, metadata: &BTreeMap<String, String>) -> Vec<(String, String)> {
+    let mut headers = vec![("x-ms-blob-content-type".to_owned(), content_type.to_owned())];
+    headers.extend(
+        metadata
+            .iter()
+            .map(|(key, value)| (format!("{}{}", METADATA_PREFIX, key), value.clone())),
+    );
+    headers
+}
+
+/// Returns the response if it succeeded, or its error otherwise.
+async fn check(res: reqwest::Response) -> Result<reqwest::Response, Error> {
+    if res.status().is_success() {
+        return Ok(res);
+    }
+    Err(Error::AzureError {
+        status_code: res.status().as_u16(),
+        message: res.text().await.unwrap_or_default(),
+    })
+}
@@ -0,0 +1,167 @@
+//! Private CA certificates for target endpoints. Each target may name a
+//! `Secret` with the CA bundle its endpoint's certificate is signed by,
+//! so that self-signed or private-CA endpoints are verified rather than
+//! needing verification disabled. The pod builders mount the bundles of
+//! the Executor's targets into the executor container, where they are
+//! trusted in addition to the system's CA certificates.
+use k8s_openapi::{api::core::v1::Pod, NamespaceResourceScope};
+use kube::{Api, Client, Resource};
+use serde::de::DeserializeOwned;
+use std::{fmt::Debug, path::Path};
+use tracing::info;
+use ytdl_types::{
+    AzureBlobTarget, GcsTarget, KafkaTarget, MongoDBTarget, NatsTarget, RedisTarget, S3Target,
+    SqlTarget, Target, WebDavTarget, WebhookTarget,
+};
+
+use crate::{pod::mount_secret, Error};
+
+/// Key in the Secret with the PEM-encoded CA certificates.
+pub const CA_BUNDLE_KEY: &str = "ca.crt";
+
+/// Directory the CA bundle Secrets are mounted under, one
+/// subdirectory per Secret.
+const CA_BUNDLES_MOUNT_PATH: &str = "/ca-bundles";
+
+/// The system's CA certificates combined with the mounted bundles.
+const COMBINED_BUNDLE_PATH: &str = "/tmp/ca-bundle.crt";
+
+/// Locations of the system's CA certificates on common distributions.
+const SYSTEM_BUNDLE_PATHS: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/cert.pem",
+];
+
+/// Returns the names of the CA bundle Secrets of the targets
+/// referenced by the named Target, without duplicates.
+pub async fn get_ca_bundle_secrets(
+    client: Client,
+    namespace: &str,
+    target_name: &str,
+) -> Result<Vec<String>, Error> {
+    let api: Api<Target> = Api::namespaced(client.clone(), namespace);
+    let spec = match api.get_opt(target_name).await? {
+        Some(target) => target.spec,
+        None => return Ok(Vec::new()),
+    };
+    let mut secrets = Vec::new();
+    for target_ref in vec![spec.metadata, spec.audiovisual, spec.thumbnail]
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let name = &target_ref.name;
+        let secret = match target_ref.kind.as_str() {
+            "S3Target" => {
+                get_secret::<S3Target>(&client, namespace, name, |t| t.spec.ca_bundle_secret)
+                    .await?
+            }
+            "WebhookTarget" => {
+                get_secret::<WebhookTarget>(&client, namespace, name, |t| t.spec.ca_bundle_secret)
+                    .await?
+            }
+            "SqlTarget" => {
+                get_secret::<SqlTarget>(&client, namespace, name, |t| t.spec.ca_bundle_secret)
+                    .await?
+            }
+            "MongoDBTarget" => {
+                get_secret::<MongoDBTarget>(&client, namespace, name, |t| t.spec.ca_bundle_secret)
+                    .await?
+            }
+            "RedisTarget" => {
+                get_secret::<RedisTarget>(&client, namespace, name, |t| t.spec.ca_bundle_secret)
+                    .await?
+            }
+            "GcsTarget" => {
+                get_secret::<GcsTarget>(&client, namespace, name, |t| t.spec.ca_bundle_secret)
+                    .await?
+            }
+            "AzureBlobTarget" => {
+                get_secret::<AzureBlobTarget>(&client, namespace, name, |t| t.spec.ca_bundle_secret)
+                    .await?
+            }
+            "WebDavTarget" => {
+                get_secret::<WebDavTarget>(&client, namespace, name, |t| t.spec.ca_bundle_secret)
+                    .await?
+            }
+            "KafkaTarget" => {
+                get_secret::<KafkaTarget>(&client, namespace, name, |t| t.spec.ca_bundle_secret)
+                    .await?
+            }
+            "NatsTarget" => {
+                get_secret::<NatsTarget>(&client, namespace, name, |t| t.spec.ca_bundle_secret)
+                    .await?
+            }
+            // Unknown kinds are rejected by validation.
+            _ => None,
+        };
+        if let Some(secret) = secret {
+            if !secrets.contains(&secret) {
+                secrets.push(secret);
+            }
+        }
+    }
+    Ok(secrets)
+}
+
+/// Mounts each of the CA bundle Secrets into the executor container.
+pub fn mount_ca_bundles(pod: &mut Pod, secrets: &[String]) {
+    for (i, secret) in secrets.iter().enumerate() {
+        mount_secret(
+            pod,
+            &format!("ca-bundle-{}", i),
+            secret,
+            &format!("{}/{}", CA_BUNDLES_MOUNT_PATH, secret),
+        );
+    }
+}
+
+/// Trusts the mounted CA bundles, if any, in every TLS client the
+/// executor creates afterwards. The bundles are appended to a copy of
+/// the system's CA certificates, which OpenSSL is pointed at through
+/// `SSL_CERT_FILE`, so the S3, HTTP, and database clients all pick
+/// them up. Must be called before any client is created.
+pub fn install_ca_bundles() -> Result<(), Error> {
+    let mounted = Path::new(CA_BUNDLES_MOUNT_PATH);
+    if !mounted.exists() {
+        return Ok(());
+    }
+    let mut combined = SYSTEM_BUNDLE_PATHS
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .unwrap_or_default();
+    let mut count = 0;
+    for entry in std::fs::read_dir(mounted)? {
+        let path = entry?.path().join(CA_BUNDLE_KEY);
+        if !path.exists() {
+            continue;
+        }
+        combined.push('\n');
+        combined.push_str(&std::fs::read_to_string(&path)?);
+        count += 1;
+    }
+    if count == 0 {
+        return Ok(());
+    }
+    std::fs::write(COMBINED_BUNDLE_PATH, combined)?;
+    std::env::set_var("SSL_CERT_FILE", COMBINED_BUNDLE_PATH);
+    info!(count, "Installed target CA bundles");
+    Ok(())
+}
+
+/// Returns the CA bundle Secret of the named target, or None if it
+/// does not exist or has no CA bundle.
+async fn get_secret<K>(
+    client: &Client,
+    namespace: &str,
+    name: &str,
+    secret: fn(K) -> Option<String>,
+) -> Result<Option<String>, Error>
+where
+    K: Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Debug,
+    <K as Resource>::DynamicType: Default,
+{
+    let api: Api<K> = Api::namespaced(client.clone(), namespace);
+    Ok(api.get_opt(name).await?.and_then(secret))
+}
@@ -0,0 +1,155 @@
+//! Fault injection for soak testing the retry and backoff subsystems
+//! without a CI environment. Faults are only injected when enabled with
+//! the hidden `--chaos-*` flags of the operator, which passes them on to
+//! the query and download pods through the environment. Never enable
+//! this in production.
+use k8s_openapi::api::core::v1::EnvVar;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::RwLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+use crate::Error;
+
+/// Environment variable with the probability that an S3 upload fails.
+pub const CHAOS_S3_FAILURE_RATE_ENV: &str = "CHAOS_S3_FAILURE_RATE";
+
+/// Environment variable with the probability that a reconciliation
+/// deletes the resource's pod.
+pub const CHAOS_POD_DELETION_RATE_ENV: &str = "CHAOS_POD_DELETION_RATE";
+
+/// Environment variable with the maximum delay, in milliseconds, added
+/// before a reconciliation talks to the API server.
+pub const CHAOS_API_DELAY_MS_ENV: &str = "CHAOS_API_DELAY_MS";
+
+/// Rates and delays of the injected faults. All zero by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChaosConfig {
+    /// Probability in `[0, 1]` that an S3 upload fails with a 503.
+    pub s3_failure_rate: f64,
+
+    /// Probability in `[0, 1]` that a reconciliation deletes the pod.
+    pub pod_deletion_rate: f64,
+
+    /// Maximum random delay before a reconciliation proceeds.
+    pub api_delay: Duration,
+}
+
+static CONFIG: RwLock<ChaosConfig> = RwLock::new(ChaosConfig {
+    s3_failure_rate: 0.0,
+    pod_deletion_rate: 0.0,
+    api_delay: Duration::ZERO,
+});
+
+impl ChaosConfig {
+    /// Reads the config from the environment of a query/download pod.
+    pub fn from_env() -> Self {
+        let get = |name: &str| -> f64 {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0.0)
+        };
+        ChaosConfig {
+            s3_failure_rate: get(CHAOS_S3_FAILURE_RATE_ENV),
+            pod_deletion_rate: get(CHAOS_POD_DELETION_RATE_ENV),
+            api_delay: Duration::from_millis(get(CHAOS_API_DELAY_MS_ENV) as u64),
+        }
+    }
+
+    /// Returns true if any fault is injected.
+    pub fn is_enabled(&self) -> bool {
+        self.s3_failure_rate > 0.0 || self.pod_deletion_rate > 0.0 || !self.api_delay.is_zero()
+    }
+}
+
+/// Sets the faults injected by this process.
+pub fn configure(config: ChaosConfig) {
+    if config.is_enabled() {
+        warn!(?config, "Chaos mode is enabled, faults will be injected");
+    }
+    *CONFIG.write().unwrap() = config;
+}
+
+/// Returns the faults injected by this process.
+pub fn get_config() -> ChaosConfig {
+    *CONFIG.read().unwrap()
+}
+
+/// Returns the environment that enables the same faults in a pod.
+pub fn get_pod_env() -> Vec<EnvVar> {
+    let config = get_config();
+    if !config.is_enabled() {
+        return vec![];
+    }
+    vec![
+        (
+            CHAOS_S3_FAILURE_RATE_ENV,
+            config.s3_failure_rate.to_string(),
+        ),
+        (
+            CHAOS_POD_DELETION_RATE_ENV,
+            config.pod_deletion_rate.to_string(),
+        ),
+        (
+            CHAOS_API_DELAY_MS_ENV,
+            config.api_delay.as_millis().to_string(),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, value)| EnvVar {
+        name: name.to_owned(),
+        value: Some(value),
+        ..EnvVar::default()
+    })
+    .collect()
+}
+
+/// Fails with a 503 at the configured S3 failure rate.
+pub fn s3_fault() -> Result<(), Error> {
+    if roll(get_config().s3_failure_rate) {
+        warn!("Chaos: injecting S3 upload failure");
+        return Err(Error::S3UploadError { status_code: 503 });
+    }
+    Ok(())
+}
+
+/// Returns true at the configured pod deletion rate.
+pub fn pod_deletion_fault() -> bool {
+    let delete = roll(get_config().pod_deletion_rate);
+    if delete {
+        warn!("Chaos: injecting pod deletion");
+    }
+    delete
+}
+
+/// Sleeps for a random duration up to the configured API delay.
+pub async fn api_delay_fault() {
+    let max = get_config().api_delay;
+    if max.is_zero() {
+        return;
+    }
+    tokio::time::sleep(max.mul_f64(random())).await;
+}
+
+/// Returns true with the given probability.
+fn roll(rate: f64) -> bool {
+    rate > 0.0 && random() < rate
+}
+
+/// Returns a random number in `[0, 1)`. The quality of the randomness
+/// doesn't matter here, so the std hasher's random keys are used
+/// instead of pulling in a dependency.
+fn random() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
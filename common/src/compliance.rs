@@ -0,0 +1,119 @@
+//! Metadata-only compliance mode. Organizations that must not store
+//! audiovisual content can force metadata/thumbnail-only downloads for
+//! specific namespaces or extractors. Downloads that request audiovisual
+//! content under the policy are rejected, and the Executors created for
+//! videos from a restricted extractor never download it regardless.
+use ytdl_types::{ContentType, DownloadSpec};
+
+use crate::{query_engine::get_inputs, wants_content, Entity, FieldError};
+
+/// Environment variable with the comma-separated namespaces in which
+/// only metadata and thumbnails may be stored.
+pub const METADATA_ONLY_NAMESPACES_ENV: &str = "METADATA_ONLY_NAMESPACES";
+
+/// Environment variable with the comma-separated youtube-dl extractors
+/// (e.g. `youtube`) for which only metadata and thumbnails may be stored.
+pub const METADATA_ONLY_EXTRACTORS_ENV: &str = "METADATA_ONLY_EXTRACTORS";
+
+/// The cluster's metadata-only policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataOnlyPolicy {
+    /// Namespaces in which audiovisual content may not be stored.
+    pub namespaces: Vec<String>,
+
+    /// Lowercase extractor names for which audiovisual content may not
+    /// be stored.
+    pub extractors: Vec<String>,
+}
+
+impl MetadataOnlyPolicy {
+    /// Reads the policy from the environment. Nothing is restricted
+    /// if the variables are unset.
+    pub fn from_env() -> Self {
+        MetadataOnlyPolicy {
+            namespaces: get_list(METADATA_ONLY_NAMESPACES_ENV),
+            extractors: get_list(METADATA_ONLY_EXTRACTORS_ENV)
+                .into_iter()
+                .map(|extractor| extractor.to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Returns the problems with a Download in the namespace that
+    /// requests audiovisual content contrary to the policy. Inputs are
+    /// matched to extractors by the labels of their host, e.g. the
+    /// `youtube` extractor matches `https://www.youtube.com/...`.
+    pub fn validate(&self, namespace: &str, spec: &DownloadSpec) -> Vec<FieldError> {
+        if !wants_content(&spec.content, ContentType::Audiovisual) {
+            return vec![];
+        }
+        let reason = if self.namespaces.iter().any(|ns| ns == namespace) {
+            format!("namespace {}", namespace)
+        } else if let Some(extractor) = get_inputs(spec)
+            .into_iter()
+            .find_map(|input| self.get_input_extractor(input))
+        {
+            format!("extractor {}", extractor)
+        } else {
+            return vec![];
+        };
+        vec![FieldError::new(
+            "content",
+            format!(
+                "must not include audiovisual, as only metadata and thumbnails may be stored for {}",
+                reason
+            ),
+        )]
+    }
+
+    /// Returns true if audiovisual content may not be downloaded for any
+    /// entity in the batch, per the extractor reported by youtube-dl.
+    pub fn restricts_batch(&self, batch: &[Entity]) -> bool {
+        !self.extractors.is_empty()
+            && batch.iter().any(|entity| {
+                get_entity_extractor(&entity.metadata)
+                    .map_or(false, |extractor| self.extractors.contains(&extractor))
+            })
+    }
+
+    /// Returns the restricted extractor matching the input's host.
+    fn get_input_extractor(&self, input: &str) -> Option<&str> {
+        let url = reqwest::Url::parse(input.trim()).ok()?;
+        let host = url.host_str()?.to_lowercase();
+        self.extractors
+            .iter()
+            .find(|extractor| host.split('.').any(|label| label == extractor.as_str()))
+            .map(String::as_str)
+    }
+}
+
+/// Removes audiovisual from the content types, keeping the rest.
+pub fn strip_audiovisual(content: &Option<Vec<ContentType>>) -> Option<Vec<ContentType>> {
+    let content = content.clone().unwrap_or_else(|| ContentType::ALL.to_vec());
+    Some(
+        content
+            .into_iter()
+            .filter(|content_type| *content_type != ContentType::Audiovisual)
+            .collect(),
+    )
+}
+
+/// Returns the lowercase extractor key from the entity's info json.
+fn get_entity_extractor(metadata: &str) -> Option<String> {
+    let info: serde_json::Value = serde_json::from_str(metadata).ok()?;
+    info.get("extractor_key")
+        .or_else(|| info.get("extractor"))
+        .and_then(|extractor| extractor.as_str())
+        .map(str::to_lowercase)
+}
+
+/// Parses a comma-separated list from the environment variable.
+pub(crate) fn get_list(name: &str) -> Vec<String> {
+    std::env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
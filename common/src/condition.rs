@@ -0,0 +1,83 @@
+use ytdl_types::Condition;
+
+/// The resource has reached its desired final state.
+pub const READY: &str = "Ready";
+
+/// The controller is actively working towards the desired state.
+pub const RECONCILING: &str = "Reconciling";
+
+/// The resource has failed and will not progress on its own.
+pub const STALLED: &str = "Stalled";
+
+/// Whether the resource's spec passed validation. When `False`, the
+/// message lists the problems with each field.
+pub const SPEC_VALID: &str = "SpecValid";
+
+/// Summary of a resource's phase in terms of the standard conditions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Ready,
+    Reconciling,
+    Stalled,
+
+    /// Reconciliation was paused by the user, so none of the
+    /// conditions are `True`.
+    Suspended,
+}
+
+/// Sets the `Ready`, `Reconciling`, and `Stalled` conditions according
+/// to the resource's health. The phase is used as the reason for each
+/// condition.
+pub fn set_health_conditions(
+    conditions: &mut Vec<Condition>,
+    health: Health,
+    phase: &str,
+    message: Option<&str>,
+    generation: Option<i64>,
+) {
+    for (type_, value) in [
+        (READY, health == Health::Ready),
+        (RECONCILING, health == Health::Reconciling),
+        (STALLED, health == Health::Stalled),
+    ] {
+        set_condition(
+            conditions,
+            Condition {
+                type_: type_.to_owned(),
+                status: if value { "True" } else { "False" }.to_owned(),
+                reason: Some(phase.to_owned()),
+                message: message.map(str::to_owned),
+                last_transition_time: None,
+                observed_generation: generation,
+            },
+        );
+    }
+}
+
+/// Inserts or replaces the condition with the same type. The last
+/// transition time is only updated if the condition's status changed.
+pub fn set_condition(conditions: &mut Vec<Condition>, mut condition: Condition) {
+    let now = chrono::Utc::now().to_rfc3339();
+    match conditions.iter_mut().find(|c| c.type_ == condition.type_) {
+        Some(existing) => {
+            condition.last_transition_time = if existing.status == condition.status {
+                existing.last_transition_time.take().or(Some(now))
+            } else {
+                Some(now)
+            };
+            *existing = condition;
+        }
+        None => {
+            condition.last_transition_time = Some(now);
+            conditions.push(condition);
+        }
+    }
+}
+
+/// Returns the condition with the given type, if it is set.
+pub fn get_condition<'a>(
+    conditions: Option<&'a [Condition]>,
+    type_: &str,
+) -> Option<&'a Condition> {
+    conditions?.iter().find(|c| c.type_ == type_)
+}
@@ -0,0 +1,38 @@
+//! Cookies for authenticated downloads, e.g. of age-restricted or
+//! membership content. The cookies are stored in a `Secret` that the
+//! pod builders mount into the executor container, where youtube-dl
+//! reads them with `--cookies`.
+use k8s_openapi::api::core::v1::Pod;
+use std::path::Path;
+
+use crate::{pod::mount_secret, Error};
+
+/// Key in the Secret with the cookies, in the Netscape format.
+pub const COOKIES_KEY: &str = "cookies.txt";
+
+/// Name of the volume the cookies Secret is mounted from.
+const COOKIES_VOLUME_NAME: &str = "cookies";
+
+/// Directory the cookies Secret is mounted at.
+const COOKIES_MOUNT_PATH: &str = "/cookies";
+
+/// Writable copy of the cookies passed to youtube-dl, which saves
+/// the cookies back to the file when it exits.
+const COOKIES_FILE_PATH: &str = "/tmp/cookies.txt";
+
+/// Mounts the cookies in the named Secret into the executor container.
+pub fn mount_cookies(pod: &mut Pod, secret_name: &str) {
+    mount_secret(pod, COOKIES_VOLUME_NAME, secret_name, COOKIES_MOUNT_PATH);
+}
+
+/// Returns the path of the cookies file to pass to youtube-dl, or
+/// None if no cookies were mounted. The mounted file is read-only,
+/// so it is copied somewhere youtube-dl can write to.
+pub fn get_cookies_file() -> Result<Option<String>, Error> {
+    let mounted = Path::new(COOKIES_MOUNT_PATH).join(COOKIES_KEY);
+    if !mounted.exists() {
+        return Ok(None);
+    }
+    std::fs::copy(&mounted, COOKIES_FILE_PATH)?;
+    Ok(Some(COOKIES_FILE_PATH.to_owned()))
+}
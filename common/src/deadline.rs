@@ -0,0 +1,61 @@
+//! Deadline of the download pods, per
+//! [`DownloadSpec::download_timeout`](ytdl_types::DownloadSpec::download_timeout).
+//! The controller computes the deadline when it creates the pod and
+//! passes it to the executor, which aborts its uploads by then and
+//! reports the timeout. The pod's `activeDeadlineSeconds` is set a grace
+//! period later, so Kubernetes only kills an executor that failed to.
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::{EnvVar, Pod};
+use std::{env, time::Duration};
+use ytdl_types::Executor;
+
+use crate::{units::parse_duration, Error};
+
+/// Environment variable with the executor's deadline, as an RFC 3339
+/// timestamp.
+pub const DEADLINE_ENV: &str = "DOWNLOAD_DEADLINE";
+
+/// Time between the executor's deadline and the pod's, for aborting
+/// the uploads and writing the termination message.
+pub const DEADLINE_GRACE: Duration = Duration::from_secs(30);
+
+/// Returns the Executor's download timeout, or None if the download
+/// pod may run for as long as it takes.
+pub fn get_download_timeout(instance: &Executor) -> Result<Option<Duration>, Error> {
+    let timeout = match instance.spec.download_timeout {
+        Some(ref timeout) => parse_duration(timeout)?,
+        None => return Ok(None),
+    };
+    Ok(Some(timeout).filter(|timeout| !timeout.is_zero()))
+}
+
+/// Returns the environment variable passing the deadline of a pod
+/// created now to the executor.
+pub fn get_deadline_env(timeout: Duration) -> Result<EnvVar, Error> {
+    let timeout = chrono::Duration::from_std(timeout)
+        .map_err(|e| Error::UserInputError(format!("invalid download timeout: {}", e)))?;
+    Ok(EnvVar {
+        name: DEADLINE_ENV.to_owned(),
+        value: Some((Utc::now() + timeout).to_rfc3339()),
+        ..EnvVar::default()
+    })
+}
+
+/// Sets the pod's own deadline, which is counted from the time the
+/// pod starts rather than when it is created, so it always falls
+/// after the executor's.
+pub fn set_active_deadline(pod: &mut Pod, timeout: Duration) {
+    let spec = pod.spec.get_or_insert_with(Default::default);
+    spec.active_deadline_seconds = Some((timeout + DEADLINE_GRACE).as_secs() as i64);
+}
+
+/// Returns the deadline passed to the executor, if any.
+pub fn get_deadline() -> Result<Option<DateTime<Utc>>, Error> {
+    let deadline = match env::var(DEADLINE_ENV) {
+        Ok(deadline) => deadline,
+        Err(_) => return Ok(None),
+    };
+    DateTime::parse_from_rfc3339(&deadline)
+        .map(|deadline| Some(deadline.with_timezone(&Utc)))
+        .map_err(|e| Error::UserInputError(format!("invalid {}: {}", DEADLINE_ENV, e)))
+}
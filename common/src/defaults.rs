@@ -0,0 +1,82 @@
+//! Default values for the optional spec fields. The mutating admission
+//! webhook fills these in so the persisted spec shows what the
+//! controllers will actually do.
+//!
+//! The thumbnail `format` and `filter` are not covered. No spec in
+//! the types has those fields yet: the executor reads them from an
+//! output spec the types don't define. The format couldn't be filled
+//! in at admission anyway, as it falls back to that of the downloaded
+//! thumbnail.
+use serde_json::{json, Value};
+use ytdl_types::{AgeRestrictedPolicy, CullPolicy, GeoBlockedPolicy, NatsPayload, QueryEngineKind};
+
+use crate::{DEFAULT_BATCH_SIZE, DEFAULT_MAX_RETRIES, DEFAULT_REGION, DEFAULT_TEMPLATE};
+
+/// Default HTTP method for [`WebhookTargetSpec::method`](ytdl_types::WebhookTargetSpec::method).
+pub const DEFAULT_WEBHOOK_METHOD: &str = "POST";
+
+/// Default request timeout for [`WebhookTargetSpec::timeout`](ytdl_types::WebhookTargetSpec::timeout).
+pub const DEFAULT_WEBHOOK_TIMEOUT: &str = "10s";
+
+/// Default stall window for [`DownloadSpec::stall_timeout`](ytdl_types::DownloadSpec::stall_timeout).
+pub const DEFAULT_STALL_TIMEOUT: &str = "5m";
+
+/// Default document ID template for [`MongoDBTargetSpec::id`](ytdl_types::MongoDBTargetSpec::id).
+pub const DEFAULT_DOCUMENT_ID_TEMPLATE: &str = "%(id)s";
+
+/// Default message key template for [`KafkaTargetSpec::key`](ytdl_types::KafkaTargetSpec::key).
+pub const DEFAULT_KAFKA_KEY_TEMPLATE: &str = "%(id)s";
+
+/// Returns the default values of the optional spec fields for the
+/// given kind, keyed by the fields' json names.
+pub fn get_spec_defaults(kind: &str) -> Vec<(&'static str, Value)> {
+    match kind {
+        "Download" => vec![
+            ("engine", json!(QueryEngineKind::default())),
+            ("ignoreErrors", json!(false)),
+            ("batchSize", json!(DEFAULT_BATCH_SIZE)),
+            ("ageRestricted", json!(AgeRestrictedPolicy::default())),
+            ("geoBlocked", json!(GeoBlockedPolicy::default())),
+            ("maxRetries", json!(DEFAULT_MAX_RETRIES)),
+            ("stallTimeout", json!(DEFAULT_STALL_TIMEOUT)),
+            ("suspend", json!(false)),
+            ("cull", json!(CullPolicy::default())),
+        ],
+        "S3Target" => vec![
+            ("key", json!(DEFAULT_TEMPLATE)),
+            ("region", json!(DEFAULT_REGION)),
+        ],
+        "WebhookTarget" => vec![
+            ("method", json!(DEFAULT_WEBHOOK_METHOD)),
+            ("timeout", json!(DEFAULT_WEBHOOK_TIMEOUT)),
+        ],
+        "MongoDBTarget" => vec![("id", json!(DEFAULT_DOCUMENT_ID_TEMPLATE))],
+        "RedisTarget" => vec![("key", json!(DEFAULT_TEMPLATE))],
+        "GcsTarget" => vec![("key", json!(DEFAULT_TEMPLATE))],
+        "AzureBlobTarget" => vec![("key", json!(DEFAULT_TEMPLATE))],
+        "VolumeTarget" => vec![("path", json!(DEFAULT_TEMPLATE))],
+        "SftpTarget" => vec![("path", json!(DEFAULT_TEMPLATE))],
+        "WebDavTarget" => vec![("path", json!(DEFAULT_TEMPLATE))],
+        "KafkaTarget" => vec![("key", json!(DEFAULT_KAFKA_KEY_TEMPLATE))],
+        "NatsTarget" => vec![("payload", json!(NatsPayload::default()))],
+        _ => vec![],
+    }
+}
+
+/// Sets the omitted optional fields of the spec to their defaults.
+/// Returns `true` if any field was set.
+pub fn apply_spec_defaults(kind: &str, spec: &mut Value) -> bool {
+    let spec = match spec.as_object_mut() {
+        Some(spec) => spec,
+        None => return false,
+    };
+    let mut changed = false;
+    for (field, value) in get_spec_defaults(kind) {
+        let entry = spec.entry(field).or_insert(Value::Null);
+        if entry.is_null() {
+            *entry = value;
+            changed = true;
+        }
+    }
+    changed
+}
@@ -0,0 +1,30 @@
+//! Idempotent deletion of resources. The controllers frequently race
+//! with Kubernetes garbage collection and with their own previous
+//! reconciliations, so a resource that is already gone is treated the
+//! same as one that was deleted successfully.
+use kube::api::{Api, DeleteParams, PropagationPolicy};
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+
+use crate::Error;
+
+/// Deletes the named resource with the given propagation policy.
+/// Returns false if the resource did not exist.
+pub async fn delete_opt<K>(
+    api: &Api<K>,
+    name: &str,
+    policy: PropagationPolicy,
+) -> Result<bool, Error>
+where
+    K: Clone + DeserializeOwned + Debug,
+{
+    let dp = DeleteParams {
+        propagation_policy: Some(policy),
+        ..DeleteParams::default()
+    };
+    match api.delete(name, &dp).await {
+        Ok(_) => Ok(true),
+        Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
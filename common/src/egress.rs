@@ -0,0 +1,19 @@
+use kube::Resource;
+
+/// Annotation on the Executor through which the download pod reports
+/// the total number of bytes it downloaded from the video service.
+/// An annotation is used instead of a status field so the report is
+/// never clobbered by the controller's own status updates.
+pub const BYTES_DOWNLOADED_ANNOTATION: &str = "ytdl.beebs.dev/bytes-downloaded";
+
+/// Returns the number of bytes reported by the download pods. The
+/// value is cumulative across retries of the download pod.
+pub fn get_bytes_downloaded<K: Resource>(instance: &K) -> u64 {
+    instance
+        .meta()
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(BYTES_DOWNLOADED_ANNOTATION))
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
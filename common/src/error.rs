@@ -26,6 +26,54 @@ pub enum Error {
     #[error("S3 upload error code {status_code}")]
     S3UploadError { status_code: u16 },
 
+    /// Unsuccessful response from the GCS JSON API or token endpoint.
+    #[error("GCS error code {status_code}: {message}")]
+    GcsError { status_code: u16, message: String },
+
+    /// Error signing the assertion for a GCS access token.
+    #[error("JWT error: {source}")]
+    JwtError {
+        #[from]
+        source: jsonwebtoken::errors::Error,
+    },
+
+    /// Unsuccessful response from the Azure blob service or the
+    /// managed identity endpoint.
+    #[error("Azure error code {status_code}: {message}")]
+    AzureError { status_code: u16, message: String },
+
+    /// Unsuccessful response from a WebDAV server.
+    #[error("WebDAV error code {status_code}: {message}")]
+    WebDavError { status_code: u16, message: String },
+
+    /// Error producing a message to a Kafka topic.
+    #[error("Kafka error: {message}")]
+    KafkaError { message: String },
+
+    /// Error publishing a message to a NATS subject.
+    #[error("NATS error: {message}")]
+    NatsError { message: String },
+
+    /// Error writing metadata to a SQL database.
+    #[error("SQL error: {message}")]
+    SqlError { message: String },
+
+    /// Error writing a document or GridFS file to MongoDB.
+    #[error("MongoDB error: {message}")]
+    MongoDbError { message: String },
+
+    /// Any error originating from libssh2, e.g. a rejected login or
+    /// a failed SFTP request.
+    #[error("SSH error: {source}")]
+    SshError {
+        #[from]
+        source: ssh2::Error,
+    },
+
+    /// The SFTP server's host key is not the one configured.
+    #[error("host key of {host} has fingerprint {fingerprint}, which is not the configured one")]
+    HostKeyMismatch { host: String, fingerprint: String },
+
     /// Error converting a string to UTF-8
     #[error("UTF-8 error: {source}")]
     Utf8Error {
@@ -82,6 +130,30 @@ pub enum Error {
     #[error("youtube-dl exit code {exit_code}")]
     YoutubeDlError { exit_code: i32 },
 
+    /// youtube-dl downloaded nothing for longer than the stall timeout.
+    #[error("no bytes downloaded for {seconds} seconds")]
+    Stalled { seconds: u64 },
+
+    /// The download pod's deadline passed before it finished.
+    #[error("download deadline exceeded")]
+    DeadlineExceeded,
+
+    /// A replicated object's checksum differs from the original's.
+    #[error("checksum mismatch for replica of {key} in bucket {bucket}")]
+    ChecksumMismatch { bucket: String, key: String },
+
+    /// Nonzero exit code from ffmpeg.
+    #[error("ffmpeg exit code {exit_code}")]
+    FfmpegError { exit_code: i32 },
+
+    /// youtube-dl reported that the video is behind an age gate.
+    #[error("age restricted: {0}")]
+    AgeRestricted(String),
+
+    /// youtube-dl reported that the video is not available in this region.
+    #[error("geo blocked: {0}")]
+    GeoBlocked(String),
+
     /// Non-200 response when downloading thumbnail.
     #[error("thumbnail download error: {status_code}")]
     ThumbnailDownloadError { status_code: u16 },
@@ -100,6 +172,51 @@ pub enum Error {
         source: image::error::ImageError,
     },
 
+    /// Any error originating from the `redis` crate.
+    #[error("Redis error: {source}")]
+    RedisError {
+        #[from]
+        source: redis::RedisError,
+    },
+
     #[error("pod scheduling error: {0}")]
     PodSchedulingError(String),
+
+    /// One or more fields of the resource's spec are invalid.
+    #[error("invalid spec: {}", format_field_errors(.0))]
+    ValidationError(Vec<FieldError>),
+}
+
+/// A problem with a single field of a resource's spec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// Path of the field relative to the spec, e.g. `"queryInterval"`.
+    pub field: String,
+
+    /// Human-readable description of the problem.
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Joins the field errors into a single message.
+pub fn format_field_errors(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
 }
@@ -0,0 +1,130 @@
+//! Validation of the user's extra youtube-dl arguments. Some options
+//! would break the executor (e.g. redirecting the output away from the
+//! stream it uploads) or escape the pod's sandbox (e.g. `--exec`), so
+//! they are always rejected. Cluster operators may restrict the options
+//! further with an allowlist and/or a denylist.
+use ytdl_types::DownloadSpec;
+
+use crate::{compliance::get_list, FieldError};
+
+/// Environment variable with the comma-separated youtube-dl options
+/// (e.g. `--format,--limit-rate`) that may be passed in `extraArgs`.
+/// Any option is allowed if unset.
+pub const EXTRA_ARGS_ALLOWED_ENV: &str = "EXTRA_ARGS_ALLOWED";
+
+/// Environment variable with the comma-separated youtube-dl options
+/// that may not be passed in `extraArgs`, in addition to the
+/// [`RESERVED_OPTIONS`].
+pub const EXTRA_ARGS_DENIED_ENV: &str = "EXTRA_ARGS_DENIED";
+
+/// Options that are never allowed in `extraArgs`, either because they
+/// run arbitrary commands or because the operator manages them.
+pub const RESERVED_OPTIONS: &[&str] = &[
+    // Arbitrary command execution.
+    "--exec",
+    "--exec-before-download",
+    "--netrc-cmd",
+    // The executor uploads the video from youtube-dl's stdout.
+    "-o",
+    "--output",
+    "-P",
+    "--paths",
+    // The executor passes the queried metadata.
+    "--load-info-json",
+    "-a",
+    "--batch-file",
+    "--config-location",
+    "--config-locations",
+    // Everything after it would be downloaded as another URL.
+    "--",
+    // Set with the proxy, cookiesSecret, and authSecret fields.
+    "--proxy",
+    "--cookies",
+    "--cookies-from-browser",
+    "-u",
+    "--username",
+    "-p",
+    "--password",
+    "--netrc",
+    "--netrc-location",
+];
+
+/// Short options of youtube-dl that take no value, which may be
+/// followed by more short options in the same argument.
+const SHORT_FLAGS: &str = "46FJUchijkqsvwx";
+
+/// The cluster's policy on extra youtube-dl arguments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtraArgsPolicy {
+    /// Options that may be passed, or empty to allow any.
+    pub allowed: Vec<String>,
+
+    /// Options that may not be passed besides the reserved ones.
+    pub denied: Vec<String>,
+}
+
+impl ExtraArgsPolicy {
+    /// Reads the policy from the environment. Only the reserved
+    /// options are rejected if the variables are unset.
+    pub fn from_env() -> Self {
+        ExtraArgsPolicy {
+            allowed: get_list(EXTRA_ARGS_ALLOWED_ENV),
+            denied: get_list(EXTRA_ARGS_DENIED_ENV),
+        }
+    }
+
+    /// Returns the problems with the extra arguments of the Download.
+    pub fn validate(&self, spec: &DownloadSpec) -> Vec<FieldError> {
+        let args = match spec.extra_args {
+            Some(ref args) => args,
+            None => return vec![],
+        };
+        let mut errors = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let field = format!("extraArgs[{}]", i);
+            for option in get_options(arg) {
+                if RESERVED_OPTIONS.contains(&option.as_str()) {
+                    errors.push(FieldError::new(
+                        &field,
+                        format!("option {} is reserved and may not be overridden", option),
+                    ));
+                } else if self.denied.iter().any(|denied| *denied == option) {
+                    errors.push(FieldError::new(
+                        &field,
+                        format!("option {} is denied by the cluster's policy", option),
+                    ));
+                } else if !self.allowed.is_empty()
+                    && !self.allowed.iter().any(|allowed| *allowed == option)
+                {
+                    errors.push(FieldError::new(
+                        &field,
+                        format!("option {} is not allowed by the cluster's policy", option),
+                    ));
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// Returns the options named by the argument, which is empty for an
+/// option's value. A long option's inline value (`--output=...`) is
+/// ignored, and `--` is returned as is. A group of short options (`-xo...`) names each flag up to
+/// the first option that takes a value, as the rest of the group is
+/// that option's value, so a reserved option can't hide in a group.
+fn get_options(arg: &str) -> Vec<String> {
+    if let Some(long) = arg.strip_prefix("--") {
+        let name = long.split('=').next().unwrap_or_default();
+        return vec![format!("--{}", name)];
+    }
+    let mut options = Vec::new();
+    if let Some(group) = arg.strip_prefix('-') {
+        for flag in group.chars() {
+            options.push(format!("-{}", flag));
+            if !SHORT_FLAGS.contains(flag) {
+                break;
+            }
+        }
+    }
+    options
+}
@@ -0,0 +1,176 @@
+use k8s_openapi::api::core::v1::{ContainerStateTerminated, Pod};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::Error;
+
+/// Path to the file Kubernetes reads a container's termination message
+/// from. The executor writes a json-encoded [`Failure`] here before it
+/// exits with an error so the controller can classify the failure.
+pub const TERMINATION_LOG_PATH: &str = "/dev/termination-log";
+
+/// Name of the executor container within query and download pods.
+pub const EXECUTOR_CONTAINER_NAME: &str = "executor";
+
+/// Substrings of youtube-dl output that indicate an age gate.
+const AGE_RESTRICTED_PATTERNS: &[&str] = &[
+    "sign in to confirm your age",
+    "age-restricted",
+    "age restricted",
+    "inappropriate for some users",
+];
+
+/// Substrings of youtube-dl output that indicate a geo-block.
+const GEO_BLOCKED_PATTERNS: &[&str] = &[
+    "not available in your country",
+    "blocked it in your country",
+    "geo restriction",
+    "geo-restricted",
+    "geo restricted",
+];
+
+/// Classification of an executor failure.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureReason {
+    /// The video is behind an age gate.
+    AgeRestricted,
+
+    /// The video is not available in the VPN's current region.
+    GeoBlocked,
+
+    /// The download pod's deadline passed before it finished.
+    DeadlineExceeded,
+
+    /// Any failure that has not been classified.
+    Unknown,
+}
+
+/// Exit status of a failed executor, which tells the stage it failed
+/// in. The controller retries every stage but configuration, as a
+/// misconfigured resource fails the same way however often it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    /// The resource, its targets, or the pod's environment is invalid.
+    Config = 10,
+
+    /// The VPN sidecar failed to connect or to rotate its exit IP.
+    Vpn = 11,
+
+    /// youtube-dl or the thumbnail request failed.
+    Download = 12,
+
+    /// Storing the content or publishing the metadata failed.
+    Upload = 13,
+}
+
+impl ExitCode {
+    /// Returns the process exit status.
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// Returns the exit code with the given exit status, if any. Other
+    /// statuses are the executor panicking or being killed.
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            10 => Some(ExitCode::Config),
+            11 => Some(ExitCode::Vpn),
+            12 => Some(ExitCode::Download),
+            13 => Some(ExitCode::Upload),
+            _ => None,
+        }
+    }
+
+    /// Returns true if running the executor again may succeed.
+    pub fn is_retryable(self) -> bool {
+        self != ExitCode::Config
+    }
+}
+
+impl fmt::Display for ExitCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stage = match self {
+            ExitCode::Config => "configuration",
+            ExitCode::Vpn => "VPN",
+            ExitCode::Download => "download",
+            ExitCode::Upload => "upload",
+        };
+        write!(f, "{} error", stage)
+    }
+}
+
+/// Structured description of why an executor failed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// Classification of the failure.
+    pub reason: FailureReason,
+
+    /// Human-readable error message.
+    pub message: String,
+}
+
+impl From<&Error> for Failure {
+    fn from(err: &Error) -> Self {
+        let reason = match err {
+            Error::AgeRestricted(_) => FailureReason::AgeRestricted,
+            Error::GeoBlocked(_) => FailureReason::GeoBlocked,
+            Error::DeadlineExceeded => FailureReason::DeadlineExceeded,
+            _ => FailureReason::Unknown,
+        };
+        Failure {
+            reason,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl Failure {
+    /// Writes the failure to the termination log so that it
+    /// appears in the pod's status after the container exits.
+    pub fn write(&self) -> Result<(), Error> {
+        std::fs::write(TERMINATION_LOG_PATH, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Classifies a line of youtube-dl output, returning the reason
+/// for the failure if the line indicates a known failure mode.
+pub fn classify_output(line: &str) -> Option<FailureReason> {
+    let line = line.to_lowercase();
+    if AGE_RESTRICTED_PATTERNS.iter().any(|p| line.contains(p)) {
+        return Some(FailureReason::AgeRestricted);
+    }
+    if GEO_BLOCKED_PATTERNS.iter().any(|p| line.contains(p)) {
+        return Some(FailureReason::GeoBlocked);
+    }
+    None
+}
+
+/// Returns the structured failure reported by the executor container
+/// of the given pod, if it terminated with one.
+pub fn get_pod_failure(pod: &Pod) -> Option<Failure> {
+    get_executor_termination(pod)?
+        .message
+        .as_deref()
+        .and_then(|message| serde_json::from_str(message).ok())
+}
+
+/// Returns the exit code of the executor container of the given pod,
+/// if it terminated with one.
+pub fn get_pod_exit_code(pod: &Pod) -> Option<ExitCode> {
+    ExitCode::from_code(get_executor_termination(pod)?.exit_code)
+}
+
+/// Returns the terminated state of the pod's executor container.
+fn get_executor_termination(pod: &Pod) -> Option<&ContainerStateTerminated> {
+    pod.status
+        .as_ref()?
+        .container_statuses
+        .as_ref()?
+        .iter()
+        .find(|cs| cs.name == EXECUTOR_CONTAINER_NAME)?
+        .state
+        .as_ref()?
+        .terminated
+        .as_ref()
+}
@@ -0,0 +1,116 @@
+use chrono::NaiveDate;
+use ytdl_types::{Download, UpcomingPolicy};
+
+use crate::{
+    match_filter::MatchFilter,
+    skip::{
+        DATE_AFTER_POLICY, DATE_BEFORE_POLICY, MATCH_FILTER_POLICY, MAX_DURATION_POLICY,
+        MAX_FILESIZE_POLICY, UPCOMING_POLICY,
+    },
+    units::{parse_date, parse_duration, parse_filesize},
+    upcoming::{get_release_time, is_upcoming},
+    Error,
+};
+
+/// Describes why an entity was excluded by the Download's filters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterSkip {
+    /// Name of the filter responsible, e.g. `"maxDuration"`.
+    pub policy: &'static str,
+
+    /// Human-readable explanation of why the entity was excluded.
+    pub reason: String,
+}
+
+/// Returns why the entity should be skipped according to the
+/// Download's filters, or `None` if the entity should be downloaded.
+/// Both the query pod and the Download controller apply these filters
+/// so that they agree on which entities are assigned to Executors.
+pub fn check_filters(
+    instance: &Download,
+    metadata: &serde_json::Value,
+) -> Result<Option<FilterSkip>, Error> {
+    if let Some(ref max_filesize) = instance.spec.max_filesize {
+        let max_filesize = parse_filesize(max_filesize)?;
+        // Prefer the exact size, falling back to youtube-dl's estimate.
+        let filesize = metadata
+            .get("filesize")
+            .and_then(|v| v.as_u64())
+            .or_else(|| metadata.get("filesize_approx").and_then(|v| v.as_u64()));
+        if let Some(filesize) = filesize {
+            if filesize > max_filesize {
+                return Ok(Some(FilterSkip {
+                    policy: MAX_FILESIZE_POLICY,
+                    reason: format!(
+                        "file size {} exceeds maxFilesize {}",
+                        filesize, max_filesize
+                    ),
+                }));
+            }
+        }
+    }
+    if let Some(ref max_duration) = instance.spec.max_duration {
+        let max_duration = parse_duration(max_duration)?;
+        if let Some(duration) = metadata.get("duration").and_then(|v| v.as_f64()) {
+            if duration > max_duration.as_secs_f64() {
+                return Ok(Some(FilterSkip {
+                    policy: MAX_DURATION_POLICY,
+                    reason: format!(
+                        "duration {}s exceeds maxDuration {}s",
+                        duration,
+                        max_duration.as_secs()
+                    ),
+                }));
+            }
+        }
+    }
+    // Entities without an upload date are kept, as youtube-dl does.
+    let upload_date = metadata
+        .get("upload_date")
+        .and_then(|v| v.as_str())
+        .and_then(|v| NaiveDate::parse_from_str(v, "%Y%m%d").ok());
+    if let Some(upload_date) = upload_date {
+        if let Some(ref date_after) = instance.spec.date_after {
+            let date_after = parse_date(date_after)?;
+            if upload_date < date_after {
+                return Ok(Some(FilterSkip {
+                    policy: DATE_AFTER_POLICY,
+                    reason: format!(
+                        "upload date {} is before dateAfter {}",
+                        upload_date, date_after
+                    ),
+                }));
+            }
+        }
+        if let Some(ref date_before) = instance.spec.date_before {
+            let date_before = parse_date(date_before)?;
+            if upload_date > date_before {
+                return Ok(Some(FilterSkip {
+                    policy: DATE_BEFORE_POLICY,
+                    reason: format!(
+                        "upload date {} is after dateBefore {}",
+                        upload_date, date_before
+                    ),
+                }));
+            }
+        }
+    }
+    if let Some(ref match_filter) = instance.spec.match_filter {
+        if let Some(condition) = MatchFilter::parse(match_filter)?.check(metadata) {
+            return Ok(Some(FilterSkip {
+                policy: MATCH_FILTER_POLICY,
+                reason: format!("does not match filter condition {}", condition),
+            }));
+        }
+    }
+    if is_upcoming(metadata) && instance.spec.upcoming.unwrap_or_default() == UpcomingPolicy::Skip {
+        return Ok(Some(FilterSkip {
+            policy: UPCOMING_POLICY,
+            reason: match get_release_time(metadata) {
+                Some(release) => format!("scheduled to start at {}", release.to_rfc3339()),
+                None => "has yet to start".to_owned(),
+            },
+        }));
+    }
+    Ok(None)
+}
@@ -0,0 +1,338 @@
+//! Google Cloud Storage, per [`GcsTargetSpec`](ytdl_types::GcsTargetSpec).
+//! rust-s3 doesn't speak the JSON API, so this is a small client for the
+//! requests the controllers and download pods make: looking up objects
+//! and uploading them with resumable uploads. Access tokens are minted
+//! from the service account key in the target's Secret or, without one,
+//! requested from the GKE metadata server.
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use ytdl_types::GcsTargetSpec;
+
+use crate::{proxy::get_storage_http_client, Error};
+
+/// Key in the Secret with the service account key json.
+pub const SERVICE_ACCOUNT_KEY: &str = "service_account.json";
+
+/// Default endpoint of the JSON API.
+pub const DEFAULT_GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// OAuth scope of the access tokens.
+const SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+
+/// Token endpoint used if the service account key doesn't name one.
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Token endpoint of the GKE metadata server.
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// Lifetime of the assertions exchanged for access tokens.
+const ASSERTION_LIFETIME: Duration = Duration::from_secs(3600);
+
+/// Tokens are renewed this long before they expire, so that a token
+/// never expires in the middle of a request.
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+/// A client for a single bucket. Clones share the access token.
+#[derive(Clone)]
+pub struct GcsBucket {
+    /// Name of the bucket.
+    pub name: String,
+    endpoint: String,
+    http: reqwest::Client,
+    key: Option<Arc<ServiceAccountKey>>,
+    token: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+/// The fields of a service account key used to mint access tokens.
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: Option<String>,
+}
+
+/// Claims of the assertion exchanged for an access token.
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// The fields of an object resource read by the controllers.
+#[derive(Deserialize, Debug, Clone)]
+pub struct GcsObject {
+    /// Size of the object in bytes, which the API encodes as a string.
+    pub size: String,
+
+    /// User metadata of the object.
+    pub metadata: Option<BTreeMap<String, String>>,
+}
+
+/// State of a resumable upload after a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadStatus {
+    /// The object was created.
+    Done,
+
+    /// The upload needs more bytes. GCS has persisted this many bytes,
+    /// which may be fewer than were sent.
+    Incomplete(u64),
+}
+
+impl GcsBucket {
+    /// Returns the object, or None if it does not exist.
+    pub async fn head_object(&self, key: &str) -> Result<Option<GcsObject>, Error> {
+        let mut url = self.url(&["storage", "v1", "b", &self.name, "o", key])?;
+        url.query_pairs_mut().append_pair("fields", "size,metadata");
+        let res = self
+            .http
+            .get(url)
+            .bearer_auth(self.token().await?)
+            .send()
+            .await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(check(res).await?.json().await?))
+    }
+
+    /// Starts a resumable upload of the object and returns the URI
+    /// of the upload session, which the content is sent to.
+    pub async fn start_upload(
+        &self,
+        key: &str,
+        content_type: &str,
+        metadata: &BTreeMap<String, String>,
+    ) -> Result<String, Error> {
+        let mut url = self.url(&["upload", "storage", "v1", "b", &self.name, "o"])?;
+        url.query_pairs_mut()
+            .append_pair("uploadType", "resumable")
+            .append_pair("name", key);
+        let res = self
+            .http
+            .post(url)
+            .bearer_auth(self.token().await?)
+            .header("X-Upload-Content-Type", content_type)
+            .json(&serde_json::json!({
+                "name": key,
+                "contentType": content_type,
+                "metadata": metadata,
+            }))
+            .send()
+            .await?;
+        check(res)
+            .await?
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .map(str::to_owned)
+            .ok_or_else(|| {
+                Error::UnknownError("GCS did not return a resumable upload session".to_owned())
+            })
+    }
+
+    /// Sends the chunk of the upload starting at the offset. The total
+    /// size is only known, and must be given, with the last chunk.
+    pub async fn put_chunk(
+        &self,
+        session: &str,
+        chunk: &[u8],
+        offset: u64,
+        total: Option<u64>,
+    ) -> Result<UploadStatus, Error> {
+        let total = total.map_or_else(|| "*".to_owned(), |total| total.to_string());
+        let range = if chunk.is_empty() {
+            format!("bytes */{}", total)
+        } else {
+            format!(
+                "bytes {}-{}/{}",
+                offset,
+                offset + chunk.len() as u64 - 1,
+                total
+            )
+        };
+        let res = self
+            .http
+            .put(session)
+            .header(reqwest::header::CONTENT_RANGE, range)
+            .body(chunk.to_vec())
+            .send()
+            .await?;
+        upload_status(res).await
+    }
+
+    /// Returns the status of the upload, e.g. after a chunk failed
+    /// mid-request, so that it resumes from the persisted bytes.
+    pub async fn query_upload(&self, session: &str) -> Result<UploadStatus, Error> {
+        let res = self
+            .http
+            .put(session)
+            .header(reqwest::header::CONTENT_RANGE, "bytes */*")
+            .send()
+            .await?;
+        upload_status(res).await
+    }
+
+    /// Cancels the upload, discarding the bytes persisted so far.
+    pub async fn cancel_upload(&self, session: &str) -> Result<(), Error> {
+        let res = self.http.delete(session).send().await?;
+        // A cancelled upload is answered with 499.
+        if res.status().as_u16() == 499 {
+            return Ok(());
+        }
+        check(res).await?;
+        Ok(())
+    }
+
+    /// Returns an access token, minting a new one if the cached
+    /// token is about to expire.
+    async fn token(&self) -> Result<String, Error> {
+        if let Some((ref token, expires)) = *self.token.lock().unwrap() {
+            if Instant::now() + TOKEN_MARGIN < expires {
+                return Ok(token.clone());
+            }
+        }
+        let res = match self.key {
+            Some(ref key) => {
+                let token_uri = key.token_uri.as_deref().unwrap_or(DEFAULT_TOKEN_URI);
+                let iat = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                let claims = Claims {
+                    iss: &key.client_email,
+                    scope: SCOPE,
+                    aud: token_uri,
+                    iat,
+                    exp: iat + ASSERTION_LIFETIME.as_secs(),
+                };
+                let assertion = jsonwebtoken::encode(
+                    &Header::new(Algorithm::RS256),
+                    &claims,
+                    &EncodingKey::from_rsa_pem(key.private_key.as_bytes())?,
+                )?;
+                self.http
+                    .post(token_uri)
+                    .form(&[
+                        ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                        ("assertion", assertion.as_str()),
+                    ])
+                    .send()
+                    .await?
+            }
+            None => {
+                self.http
+                    .get(METADATA_TOKEN_URL)
+                    .header("Metadata-Flavor", "Google")
+                    .send()
+                    .await?
+            }
+        };
+        let res: TokenResponse = check(res).await?.json().await?;
+        let expires = Instant::now() + Duration::from_secs(res.expires_in);
+        *self.token.lock().unwrap() = Some((res.access_token.clone(), expires));
+        Ok(res.access_token)
+    }
+
+    /// Returns the URL of the API path, with each segment encoded.
+    fn url(&self, segments: &[&str]) -> Result<Url, Error> {
+        let mut url = Url::parse(&self.endpoint)
+            .map_err(|e| Error::UserInputError(format!("invalid GCS endpoint: {}", e)))?;
+        url.path_segments_mut()
+            .map_err(|_| Error::UserInputError("invalid GCS endpoint".to_owned()))?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+}
+
+/// Returns the client for the bucket described by the GcsTarget spec.
+pub async fn get_gcs_bucket(
+    client: Client,
+    namespace: &str,
+    spec: &GcsTargetSpec,
+) -> Result<GcsBucket, Error> {
+    let key = match spec.secret {
+        Some(ref secret) => {
+            let key = get_service_account_key(client, namespace, secret).await?;
+            Some(Arc::new(key))
+        }
+        None => None,
+    };
+    Ok(GcsBucket {
+        name: spec.bucket.clone(),
+        endpoint: spec
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| DEFAULT_GCS_ENDPOINT.to_owned()),
+        http: get_storage_http_client()?,
+        key,
+        token: Arc::new(Mutex::new(None)),
+    })
+}
+
+/// Returns the service account key stored in the named Secret.
+async fn get_service_account_key(
+    client: Client,
+    namespace: &str,
+    name: &str,
+) -> Result<ServiceAccountKey, Error> {
+    let secret = Api::<Secret>::namespaced(client, namespace)
+        .get(name)
+        .await?;
+    let key = secret
+        .data
+        .and_then(|mut data| data.remove(SERVICE_ACCOUNT_KEY))
+        .ok_or_else(|| {
+            Error::UserInputError(format!(
+                "GCS secret {} is missing the {} field",
+                name, SERVICE_ACCOUNT_KEY
+            ))
+        })?;
+    Ok(serde_json::from_slice(&key.0)?)
+}
+
+/// Returns the response if it succeeded, or its error otherwise.
+async fn check(res: reqwest::Response) -> Result<reqwest::Response, Error> {
+    if res.status().is_success() {
+        return Ok(res);
+    }
+    Err(Error::GcsError {
+        status_code: res.status().as_u16(),
+        message: res.text().await.unwrap_or_default(),
+    })
+}
+
+/// Returns the status of the upload from the response to a chunk. An
+/// incomplete upload is answered with 308 and the persisted range.
+async fn upload_status(res: reqwest::Response) -> Result<UploadStatus, Error> {
+    if res.status() != StatusCode::PERMANENT_REDIRECT {
+        check(res).await?;
+        return Ok(UploadStatus::Done);
+    }
+    // The range is formatted as `bytes=0-{last}`, and is missing if
+    // no bytes have been persisted yet.
+    let persisted = res
+        .headers()
+        .get(reqwest::header::RANGE)
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| range.rsplit('-').next())
+        .and_then(|last| last.parse::<u64>().ok())
+        .map_or(0, |last| last + 1);
+    Ok(UploadStatus::Incomplete(persisted))
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+use ytdl_types::PhaseTransition;
+
+/// Maximum number of entries kept in a resource's phase history.
+pub const MAX_PHASE_HISTORY: usize = 16;
+
+/// Appends the phase transition to the history, discarding the
+/// oldest entries so that at most [`MAX_PHASE_HISTORY`] remain.
+pub fn record_transition(history: &mut Vec<PhaseTransition>, phase: &str, message: Option<&str>) {
+    history.push(PhaseTransition {
+        phase: phase.to_owned(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        message: message.map(ToOwned::to_owned),
+    });
+    if history.len() > MAX_PHASE_HISTORY {
+        history.drain(..history.len() - MAX_PHASE_HISTORY);
+    }
+}
+
+/// Window in which pod creations are counted to detect flapping pods.
+pub const POD_START_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Records the creation of a pod, discarding the entries older than
+/// [`POD_START_WINDOW`]. Returns the number of pods created within the
+/// window, including this one.
+pub fn record_pod_start(starts: &mut Vec<String>) -> usize {
+    let now = chrono::Utc::now();
+    starts.retain(|start| {
+        chrono::DateTime::parse_from_rfc3339(start).map_or(false, |start| {
+            now.signed_duration_since(start).num_seconds() < POD_START_WINDOW.as_secs() as i64
+        })
+    });
+    starts.push(now.to_rfc3339());
+    starts.len()
+}
@@ -0,0 +1,79 @@
+//! Enforcement of [`HostPolicy`] resources and the operator-wide host
+//! policy, which restrict the hosts Downloads may download from. The
+//! admission webhook rejects Downloads that violate them, and the
+//! Download controller refuses to query them.
+use kube::{api::ListParams, Api, Client};
+use ytdl_types::{DownloadSpec, HostPolicy, HostPolicySpec};
+
+use crate::{query_engine::get_inputs, Error, FieldError};
+
+/// Returns the problems with the Download's inputs under the operator-wide
+/// policy and those of the namespace's [`HostPolicy`] resources.
+pub async fn validate_host_policies(
+    client: Client,
+    namespace: &str,
+    spec: &DownloadSpec,
+    global: &HostPolicySpec,
+) -> Result<Vec<FieldError>, Error> {
+    let api: Api<HostPolicy> = Api::namespaced(client, namespace);
+    let mut policies = vec![global.clone()];
+    policies.extend(
+        api.list(&ListParams::default())
+            .await?
+            .items
+            .into_iter()
+            .map(|policy| policy.spec),
+    );
+    let mut errors: Vec<FieldError> = Vec::new();
+    for error in get_inputs(spec)
+        .into_iter()
+        .flat_map(|input| check_hosts(input, &policies))
+    {
+        if !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
+    Ok(errors)
+}
+
+/// Returns the problems with the input under the given policies. The
+/// host must match every allowlist and none of the blocklists.
+pub fn check_hosts(input: &str, policies: &[HostPolicySpec]) -> Vec<FieldError> {
+    let host = get_input_host(input);
+    let mut errors: Vec<FieldError> = Vec::new();
+    for policy in policies {
+        let error = match (&host, &policy.allowed_hosts, &policy.blocked_hosts) {
+            (Some(host), _, Some(blocked)) if blocked.iter().any(|p| matches(host, p)) => {
+                FieldError::new("input", format!("host {} is blocked", host))
+            }
+            (Some(host), Some(allowed), _) if !allowed.iter().any(|p| matches(host, p)) => {
+                FieldError::new("input", format!("host {} is not allowed", host))
+            }
+            // Non-URL inputs are resolved by youtube-dl, so their
+            // host cannot be checked against the allowlist.
+            (None, Some(_), _) => FieldError::new(
+                "input",
+                "must be a URL when the allowed hosts are restricted",
+            ),
+            _ => continue,
+        };
+        if !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
+    errors
+}
+
+/// Returns the lowercase host of the input, if it is a URL.
+fn get_input_host(input: &str) -> Option<String> {
+    reqwest::Url::parse(input.trim())
+        .ok()?
+        .host_str()
+        .map(str::to_lowercase)
+}
+
+/// Returns true if the host is the pattern or one of its subdomains.
+fn matches(host: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim().trim_start_matches("*.").to_lowercase();
+    host == pattern || host.ends_with(&format!(".{}", pattern))
+}
@@ -0,0 +1,135 @@
+//! Kafka topics, per [`KafkaTargetSpec`](ytdl_types::KafkaTargetSpec).
+//! The targets and their Secrets are resolved into librdkafka producer
+//! properties here, so that only the download pods, which produce the
+//! messages, link a Kafka client.
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use std::collections::BTreeMap;
+use ytdl_types::{KafkaTarget, KafkaTargetSpec, Target};
+
+use crate::{defaults::DEFAULT_KAFKA_KEY_TEMPLATE, template_key, Error};
+
+/// SASL mechanisms accepted by [`KafkaSaslSpec::mechanism`](ytdl_types::KafkaSaslSpec::mechanism).
+pub const SASL_MECHANISMS: &[&str] = &["PLAIN", "SCRAM-SHA-256", "SCRAM-SHA-512"];
+
+/// SASL mechanism if the spec doesn't set one.
+const DEFAULT_SASL_MECHANISM: &str = "PLAIN";
+
+/// Keys in the SASL Secret with the user's credentials.
+const USERNAME_KEY: &str = "username";
+const PASSWORD_KEY: &str = "password";
+
+/// Keys in the TLS Secret with the client certificate and key.
+const TLS_CERT_KEY: &str = "tls.crt";
+const TLS_KEY_KEY: &str = "tls.key";
+
+/// A topic of a Kafka cluster.
+#[derive(Debug, Clone)]
+pub struct KafkaTopic {
+    /// Name of the KafkaTarget, for logging.
+    pub name: String,
+
+    /// Name of the topic.
+    pub topic: String,
+
+    /// librdkafka properties of the producer, including credentials.
+    pub config: BTreeMap<String, String>,
+}
+
+/// A topic and the key of the message produced to it.
+pub type KafkaOutput = (KafkaTopic, String);
+
+/// Returns the Kafka outputs of the metadata for the named Target,
+/// with the message keys rendered from the metadata.
+pub async fn get_kafka_outputs(
+    client: Client,
+    namespace: &str,
+    target_name: &str,
+    metadata: &serde_json::Value,
+) -> Result<Vec<KafkaOutput>, Error> {
+    let api: Api<Target> = Api::namespaced(client.clone(), namespace);
+    let refs = match api.get_opt(target_name).await? {
+        Some(target) => target.spec.metadata.unwrap_or_default(),
+        None => return Ok(Vec::new()),
+    };
+    let targets: Api<KafkaTarget> = Api::namespaced(client.clone(), namespace);
+    let mut outputs = Vec::new();
+    for target_ref in refs {
+        if target_ref.kind != "KafkaTarget" {
+            continue;
+        }
+        let spec = targets.get(&target_ref.name).await?.spec;
+        let template = spec.key.as_deref().unwrap_or(DEFAULT_KAFKA_KEY_TEMPLATE);
+        let key = template_key(metadata, template)?;
+        let topic = KafkaTopic {
+            name: target_ref.name,
+            topic: spec.topic.clone(),
+            config: get_producer_config(client.clone(), namespace, &spec).await?,
+        };
+        outputs.push((topic, key));
+    }
+    Ok(outputs)
+}
+
+/// Returns the librdkafka properties of a producer for the brokers
+/// described by the KafkaTarget spec.
+async fn get_producer_config(
+    client: Client,
+    namespace: &str,
+    spec: &KafkaTargetSpec,
+) -> Result<BTreeMap<String, String>, Error> {
+    let mut config = BTreeMap::new();
+    config.insert("bootstrap.servers".to_owned(), spec.brokers.join(","));
+    let protocol = match (spec.sasl.is_some(), spec.tls.is_some()) {
+        (false, false) => "plaintext",
+        (false, true) => "ssl",
+        (true, false) => "sasl_plaintext",
+        (true, true) => "sasl_ssl",
+    };
+    config.insert("security.protocol".to_owned(), protocol.to_owned());
+    if let Some(ref sasl) = spec.sasl {
+        let mut fields = get_secret_fields(client.clone(), namespace, &sasl.secret).await?;
+        let mechanism = sasl.mechanism.as_deref().unwrap_or(DEFAULT_SASL_MECHANISM);
+        config.insert("sasl.mechanism".to_owned(), mechanism.to_owned());
+        let username = take_field(&mut fields, &sasl.secret, USERNAME_KEY)?;
+        let password = take_field(&mut fields, &sasl.secret, PASSWORD_KEY)?;
+        config.insert("sasl.username".to_owned(), username);
+        config.insert("sasl.password".to_owned(), password);
+    }
+    if let Some(ref secret) = spec.tls.as_ref().and_then(|tls| tls.secret.clone()) {
+        let mut fields = get_secret_fields(client, namespace, secret).await?;
+        let cert = take_field(&mut fields, secret, TLS_CERT_KEY)?;
+        let key = take_field(&mut fields, secret, TLS_KEY_KEY)?;
+        config.insert("ssl.certificate.pem".to_owned(), cert);
+        config.insert("ssl.key.pem".to_owned(), key);
+    }
+    Ok(config)
+}
+
+/// Returns the fields of the named Secret as strings.
+async fn get_secret_fields(
+    client: Client,
+    namespace: &str,
+    name: &str,
+) -> Result<BTreeMap<String, String>, Error> {
+    let secret = Api::<Secret>::namespaced(client, namespace)
+        .get(name)
+        .await?;
+    Ok(secret
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, String::from_utf8(value.0).ok()?)))
+        .collect())
+}
+
+/// Removes the field from the Secret's fields, which is required.
+fn take_field(
+    fields: &mut BTreeMap<String, String>,
+    secret: &str,
+    key: &str,
+) -> Result<String, Error> {
+    fields.remove(key).ok_or_else(|| {
+        Error::UserInputError(format!("Kafka Secret {} has no {} field", secret, key))
+    })
+}
@@ -1,18 +1,69 @@
 use awsregion::Region;
 use k8s_openapi::api::core::v1::{PodStatus, Secret};
 use kube::{
-    api::{Api, ObjectMeta, PostParams, Resource},
+    api::{Api, ListParams, ObjectMeta, PostParams, Resource},
     Client, ResourceExt,
 };
 use s3::{bucket::Bucket, creds::Credentials};
+use std::collections::{BTreeMap, HashSet};
 use tokio::time::Duration;
 use ytdl_types::*;
 
+pub mod archive;
+pub mod auth;
+pub mod azure;
+pub mod ca_bundle;
+pub mod chaos;
+pub mod compliance;
+pub mod condition;
+pub mod cookies;
+pub mod deadline;
+pub mod defaults;
+pub mod delete;
+pub mod egress;
+pub mod extra_args;
+pub mod failure;
+pub mod filter;
+pub mod gcs;
+pub mod history;
+pub mod host_policy;
+pub mod kafka;
+pub mod logging;
+pub mod manifest;
+pub mod match_filter;
+pub mod metadata_fields;
+pub mod mongodb;
+pub mod naming;
+pub mod nats;
+pub mod normalize;
+pub mod object_headers;
 pub mod pod;
+pub mod progress;
+pub mod propagate;
+pub mod proxy;
+pub mod query_engine;
+pub mod redis;
+pub mod replication;
+pub mod results;
+pub mod sftp;
+pub mod skip;
+pub mod sql;
+pub mod sse;
+pub mod storage_class;
+pub mod store;
+pub mod tagging;
+pub mod timing;
+pub mod units;
+pub mod upcoming;
+pub mod upload;
+pub mod validate;
+pub mod volume;
+pub mod webdav;
+pub mod work_volume;
 
 mod error;
 
-pub use error::Error;
+pub use error::{format_field_errors, Error, FieldError};
 
 /// Reconciliation return value to requeue the resource immediately.
 pub const IMMEDIATELY: Duration = Duration::ZERO;
@@ -23,28 +74,41 @@ pub const DEFAULT_REGION: &str = "us-east-1";
 /// Default output key template.
 pub const DEFAULT_TEMPLATE: &str = "%(id)s.%(ext)s";
 
+/// Default number of Entities assigned to a single DownloadJob.
+pub const DEFAULT_BATCH_SIZE: u32 = 1;
+
+/// Default number of times a failed download pod is recreated.
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
 /// Default image to use for the executor. The executor
 /// image is responsible for downloading the video and
 /// thumbnail from the video service, and uploading them
 /// to the storage backend in the desired formats.
 pub const DEFAULT_EXECUTOR_IMAGE: &str = "thavlik/ytdl-executor:latest";
 
+/// Environment variable with the default rate limit of download pods,
+/// in the format accepted by youtube-dl's `--limit-rate`.
+pub const DEFAULT_RATE_LIMIT_ENV: &str = "DEFAULT_RATE_LIMIT";
+
 /// Key in the ConfigMap for the metadata/info jsonl.
 pub const INFO_JSONL_KEY: &str = "info.jsonl";
 
+/// Label on the metadata ConfigMaps, which the Download controller
+/// watches so it doesn't have to watch every ConfigMap.
+pub const METADATA_LABEL: &str = "ytdl.beebs.dev/metadata";
+
 /// A tuple containing an S3 Bucket and key, which is the
 /// final output specification for videos and thumbnails.
 /// The spec is ultimately resolved into this object.
 pub type Output = (Bucket, String);
 
-/// Creates a child DownloadJob resource for the given Entity.
+/// Creates a child DownloadJob resource for the given batch of Entities.
 pub async fn create_executor(
     client: Client,
     instance: &Download,
-    id: String,
-    metadata: String,
+    batch: Vec<Entity>,
 ) -> Result<(), Error> {
-    let executor = get_entity_executor(instance, id, metadata);
+    let executor = get_entity_executor(instance, batch);
     let api: Api<DownloadJob> = Api::namespaced(client, instance.namespace().as_ref().unwrap());
     api.create(&PostParams::default(), &executor).await?;
     Ok(())
@@ -54,11 +118,114 @@ pub fn get_executor_service_account_name() -> Result<String, Error> {
     Ok(std::env::var("EXECUTOR_SERVICE_ACCOUNT_NAME")?)
 }
 
+/// Returns the operator's default rate limit for download pods
+/// whose Download doesn't set one, if any.
+pub fn get_default_rate_limit() -> Option<String> {
+    std::env::var(DEFAULT_RATE_LIMIT_ENV)
+        .ok()
+        .filter(|rate_limit| !rate_limit.trim().is_empty())
+}
+
+/// Returns the maximum number of Entities that are assigned
+/// to a single DownloadJob. Always at least one.
+pub fn get_batch_size(instance: &Download) -> usize {
+    instance
+        .spec
+        .batch_size
+        .unwrap_or(DEFAULT_BATCH_SIZE)
+        .max(1) as usize
+}
+
+/// Returns the metadata json for every Entity assigned to the
+/// DownloadJob, in the order they should be downloaded.
+pub fn get_job_metadata(instance: &DownloadJob) -> Vec<&str> {
+    let mut metadata = vec![instance.spec.metadata.as_str()];
+    if let Some(ref batch) = instance.spec.batch {
+        metadata.extend(batch.iter().map(|m| m.as_str()));
+    }
+    metadata
+}
+
+/// Returns the Entities assigned to the DownloadJob, in the order they
+/// are downloaded. Metadata without an ID is left out.
+pub fn get_job_entities(instance: &DownloadJob) -> Vec<Entity> {
+    get_job_metadata(instance)
+        .into_iter()
+        .filter_map(|metadata| {
+            let info: serde_json::Value = serde_json::from_str(metadata).ok()?;
+            Some(Entity {
+                id: info.get("id")?.as_str()?.to_owned(),
+                metadata: metadata.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the IDs of the Entities assigned to the Download's existing
+/// DownloadJobs. These keep their batches, so that the batches don't
+/// shift when new videos appear in the input, and only the remaining
+/// Entities are batched.
+pub async fn get_assigned_ids(
+    client: Client,
+    instance: &Download,
+) -> Result<HashSet<String>, Error> {
+    let uid = instance.uid();
+    let api: Api<DownloadJob> = Api::namespaced(client, instance.namespace().as_ref().unwrap());
+    Ok(api
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .filter(|job| {
+            job.owner_references()
+                .iter()
+                .any(|oref| oref.controller == Some(true) && Some(&oref.uid) == uid.as_ref())
+        })
+        .flat_map(|job| get_job_entities(&job))
+        .map(|entity| entity.id)
+        .collect())
+}
+
+/// Returns true if the given content type should be stored.
+/// If no content types are specified, all content is stored.
+pub fn wants_content(content: &Option<Vec<ContentType>>, content_type: ContentType) -> bool {
+    content
+        .as_deref()
+        .unwrap_or(&ContentType::ALL)
+        .contains(&content_type)
+}
+
 /// Returns the phase of the Download
 pub fn get_download_phase(instance: &Download) -> Result<DownloadPhase, Error> {
     Ok(instance.status.as_ref().unwrap().phase.unwrap())
 }
 
+/// Returns the names of the targets for the Download, which are
+/// either specified explicitly or inherited from the namespace's
+/// DefaultTargets resources.
+pub fn get_targets(instance: &Download) -> &[String] {
+    if !instance.spec.targets.is_empty() {
+        return &instance.spec.targets;
+    }
+    instance
+        .status
+        .as_ref()
+        .and_then(|status| status.targets.as_deref())
+        .unwrap_or_default()
+}
+
+/// Returns the time left before a finished resource is deleted per
+/// its `ttlSecondsAfterFinished`, which is zero once it has expired.
+/// Returns None if the resource has no TTL or has not finished.
+pub fn get_remaining_ttl(
+    completion_time: Option<&str>,
+    ttl_seconds: Option<u32>,
+) -> Option<Duration> {
+    let completion_time = chrono::DateTime::parse_from_rfc3339(completion_time?).ok()?;
+    let ttl = chrono::Duration::seconds(ttl_seconds? as i64);
+    let remaining = completion_time + ttl - chrono::Utc::now();
+    Some(remaining.to_std().unwrap_or(Duration::ZERO))
+}
+
 /// Returns the phase of the DownloadJob.
 pub fn get_executor_phase(instance: &DownloadJob) -> Result<DownloadJobPhase, Error> {
     Ok(instance.status.as_ref().unwrap().phase.unwrap())
@@ -113,8 +280,11 @@ async fn output_from_spec(
     output_spec: &S3OutputSpec,
 ) -> Result<Output, Error> {
     // Build the S3 Bucket object for uploading.
-    let region = get_s3_region(output_spec)?;
-    let credentials = get_s3_creds(client, namespace, output_spec).await?;
+    let region = get_s3_region(
+        output_spec.region.as_deref(),
+        output_spec.endpoint.as_deref(),
+    )?;
+    let credentials = get_s3_creds(client, namespace, output_spec.secret.as_deref()).await?;
     let bucket = Bucket::new(&output_spec.bucket, region, credentials)?;
     // Use the default template if none is specified.
     let template = match output_spec.key {
@@ -130,7 +300,7 @@ async fn output_from_spec(
 /// video's metadata. This requires deserializing the
 /// metadata and iterating over its contents to replace
 /// the template variables with their values.
-fn template_key(metadata: &serde_json::Value, template: &str) -> Result<String, Error> {
+pub(crate) fn template_key(metadata: &serde_json::Value, template: &str) -> Result<String, Error> {
     // Parse the metadata into a generic json object.
     let metadata = metadata
         .as_object()
@@ -146,8 +316,13 @@ fn template_key(metadata: &serde_json::Value, template: &str) -> Result<String,
         let key = format!("%({})s", key);
         // Default to an empty string if the value is not a string.
         let value = value.as_str().unwrap_or("");
+        // IDs are normalized so that they are always safe in keys.
+        let value = match key.as_str() {
+            "%(id)s" => naming::normalize_id(value),
+            _ => value.to_owned(),
+        };
         // Replace the template variable with the value.
-        result = result.replace(&key, value);
+        result = result.replace(&key, &value);
     }
     if result.find("%").is_some() {
         // There are still template variables that were not replaced.
@@ -160,14 +335,26 @@ fn template_key(metadata: &serde_json::Value, template: &str) -> Result<String,
     Ok(result)
 }
 
-/// Returns the S3 credentials for the given S3OutputSpec.
+/// Returns the S3 Bucket described by the given S3Target spec.
+pub async fn get_s3_target_bucket(
+    client: Client,
+    namespace: &str,
+    spec: &S3TargetSpec,
+) -> Result<Bucket, Error> {
+    let region = get_s3_region(spec.region.as_deref(), spec.endpoint.as_deref())?;
+    let credentials = get_s3_creds(client, namespace, spec.secret.as_deref()).await?;
+    Ok(Bucket::new(&spec.bucket, region, credentials)?)
+}
+
+/// Returns the S3 credentials stored in the named Secret, or the
+/// default credentials if no Secret is specified.
 async fn get_s3_creds(
     client: Client,
     namespace: &str,
-    spec: &S3OutputSpec,
+    secret: Option<&str>,
 ) -> Result<Credentials, Error> {
-    match spec.secret {
-        Some(ref secret) => {
+    match secret {
+        Some(secret) => {
             let api: Api<Secret> = Api::namespaced(client, namespace);
             let secret = api.get(secret).await?;
             let access_key_id = get_secret_value(&secret, "access_key_id")?;
@@ -200,19 +387,20 @@ fn get_secret_value(secret: &Secret, key: &str) -> Result<Option<String>, Error>
     })
 }
 
-/// Returns the S3 Region object for the given S3OutputSpec.
-fn get_s3_region(spec: &S3OutputSpec) -> Result<Region, Error> {
-    let region = match spec.region.as_ref() {
+/// Returns the S3 Region object for the given region name and
+/// optional custom endpoint.
+fn get_s3_region(region: Option<&str>, endpoint: Option<&str>) -> Result<Region, Error> {
+    let region = match region {
         // Use the region from the spec.
         Some(region) => region.to_owned(),
         // Use the default region.
         None => DEFAULT_REGION.to_owned(),
     };
-    Ok(match spec.endpoint.as_ref() {
+    Ok(match endpoint {
         // Custom endpoint support (e.g. https://nyc3.digitaloceanspaces.com)
         Some(endpoint) => Region::Custom {
             region,
-            endpoint: endpoint.clone(),
+            endpoint: endpoint.to_owned(),
         },
         // The Region object is based solely on the region name.
         None => region.parse()?,
@@ -244,30 +432,105 @@ pub struct Entity {
     pub metadata: String,
 }
 
+/// Returns the name of the DownloadJob for the batch of Entities,
+/// which is derived from the Download's name and the first Entity's ID.
+pub fn get_executor_name(instance: &Download, batch: &[Entity]) -> String {
+    naming::child_name(&instance.name_any(), &naming::normalize_id(&batch[0].id))
+}
+
 /// Returns an DownloadJob owned by the Download resource that
-/// is configured for the Entity.
-pub fn get_entity_executor(instance: &Download, id: String, metadata: String) -> DownloadJob {
+/// is configured for the batch of Entities. The first Entity
+/// in the batch determines the name of the DownloadJob.
+pub fn get_entity_executor(instance: &Download, batch: Vec<Entity>) -> DownloadJob {
     // Make the Download the owner of the DownloadJob.
     let oref = instance.controller_owner_ref(&()).unwrap();
-    DownloadJob {
+    let name = get_executor_name(instance, &batch);
+    // Audiovisual content is never downloaded for restricted extractors.
+    let content = if compliance::MetadataOnlyPolicy::from_env().restricts_batch(&batch) {
+        compliance::strip_audiovisual(&instance.spec.content)
+    } else {
+        instance.spec.content.clone()
+    };
+    let mut batch = batch.into_iter();
+    let first = batch
+        .next()
+        .expect("batch must contain at least one entity");
+    // Any remaining Entities are downloaded by the same pod.
+    let rest: Vec<String> = batch.map(|entity| entity.metadata).collect();
+    let mut executor = DownloadJob {
         metadata: ObjectMeta {
-            name: Some(format!("{}-{}", instance.name_any(), id)),
+            name: Some(name),
             namespace: Some(instance.namespace().unwrap()),
             owner_references: Some(vec![oref]),
+            // The normalized ID is selectable, while the annotation
+            // preserves the original.
+            labels: Some(BTreeMap::from([(
+                naming::ID_LABEL.to_owned(),
+                naming::normalize_id(&first.id),
+            )])),
+            annotations: Some(BTreeMap::from([(
+                naming::ORIGINAL_ID_ANNOTATION.to_owned(),
+                first.id.clone(),
+            )])),
             ..Default::default()
         },
         spec: DownloadJobSpec {
-            // The DownloadJob's metadata is the Entity's metadata.
-            metadata,
+            // The DownloadJob's metadata is the first Entity's metadata.
+            metadata: first.metadata,
+            // Subsequent Entities are processed sequentially by the same pod.
+            batch: if rest.is_empty() { None } else { Some(rest) },
             // Inherit the Download's executor image.
             executor: instance.spec.executor.clone(),
+            // Inherit the Download's content types.
+            content,
+            // Inherit the Download's age restriction policy.
+            age_restricted: instance.spec.age_restricted,
+            // Inherit the Download's geo-block policy.
+            geo_blocked: instance.spec.geo_blocked,
+            geo_regions: instance.spec.geo_regions.clone(),
+            // Inherit the Download's file size cap.
+            max_filesize: instance.spec.max_filesize.clone(),
+            // Inherit the Download's rate limit, or the operator's default.
+            rate_limit: instance
+                .spec
+                .rate_limit
+                .clone()
+                .or_else(get_default_rate_limit),
+            // Inherit the Download's chapter splitting.
+            split_chapters: instance.spec.split_chapters,
+            // Inherit the Download's retry limit.
+            max_retries: instance.spec.max_retries,
+            // Inherit the Download's stall detection window.
+            stall_timeout: instance.spec.stall_timeout.clone(),
+            // Inherit the Download's pod deadline.
+            download_timeout: instance.spec.download_timeout.clone(),
+            // Inherit the Download's scratch volume.
+            work_volume: instance.spec.work_volume.clone(),
+            // Inherit the Download's cookies.
+            cookies_secret: instance.spec.cookies_secret.clone(),
+            // Inherit the Download's account credentials.
+            auth_secret: instance.spec.auth_secret.clone(),
+            // Inherit the Download's VPN configuration.
+            vpn: instance.spec.vpn.clone(),
+            proxy: instance.spec.proxy.clone(),
+            // Inherit the Download's pod overrides.
+            pod_template: instance.spec.pod_template.clone(),
+            // Inherit the Download's cleanup policy.
+            ttl_seconds_after_finished: instance.spec.ttl_seconds_after_finished,
             // Inherit the Download's extra arguments.
-            extra: instance.spec.extra.clone(),
+            extra_args: instance.spec.extra_args.clone(),
             // Inherit the Download's output spec.
             output: instance.spec.output.clone(),
         },
         ..Default::default()
-    }
+    };
+    // Inherit the Download's user-defined labels and annotations.
+    propagate::propagate_metadata(
+        &instance.metadata,
+        &mut executor.metadata,
+        &propagate::get_propagate_prefixes(),
+    );
+    executor
 }
 
 /// Returns the [`DownloadJob`] with the given name/namespace.
@@ -0,0 +1,22 @@
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// Environment variable that selects the log format. Set to `json`
+/// for structured logs suitable for aggregation (e.g. Loki or
+/// Elasticsearch). Any other value yields human-readable logs.
+pub const LOG_FORMAT_ENV: &str = "LOG_FORMAT";
+
+/// Default log filter if `RUST_LOG` is not set.
+const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Initializes the global tracing subscriber. The verbosity is
+/// controlled with the standard `RUST_LOG` environment variable
+/// and the output format with [`LOG_FORMAT_ENV`].
+pub fn init() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let builder = fmt().with_env_filter(filter);
+    match std::env::var(LOG_FORMAT_ENV).as_deref() {
+        Ok("json") => builder.json().flatten_event(true).init(),
+        _ => builder.init(),
+    }
+}
@@ -0,0 +1,187 @@
+//! Signed completion manifests. The download pods report the objects
+//! they upload, and once a Download succeeds the controller collects
+//! them into a manifest that is uploaded to the Download's S3 metadata
+//! targets, optionally alongside an HMAC signature.
+use hmac::{Hmac, Mac};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client, Resource, ResourceExt};
+use serde::Serialize;
+use sha2::Sha256;
+use tracing::{info, warn};
+use ytdl_types::{Download, ManifestSpec, S3Target, StoredObject, Target};
+
+use crate::{
+    get_s3_target_bucket, get_targets, sse::with_sse, storage_class::with_storage_class, Error,
+};
+
+/// Annotation on the Executor through which the download pod reports
+/// the objects it uploaded, as a json array of [`StoredObject`].
+pub const OBJECTS_ANNOTATION: &str = "ytdl.beebs.dev/objects";
+
+/// Key of the S3 user metadata with the object's hex-encoded SHA-256
+/// checksum. Only set on objects whose content is hashed before it's
+/// uploaded, as S3 takes the metadata before the body.
+pub const CHECKSUM_METADATA_KEY: &str = "sha256";
+
+/// Default object key template for the manifest.
+pub const DEFAULT_MANIFEST_KEY: &str = "manifests/%(namespace)s/%(name)s.json";
+
+/// Field of the signing Secret that holds the key.
+const SIGNING_KEY_FIELD: &str = "key";
+
+/// Suffix added to the manifest's key for its signature.
+const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// The manifest of a completed Download.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub download: String,
+    pub namespace: String,
+    pub uid: String,
+    #[serde(rename = "completedAt")]
+    pub completed_at: String,
+    pub objects: Vec<StoredObject>,
+}
+
+/// Returns the objects reported by the download pods.
+pub fn get_stored_objects<K: Resource>(instance: &K) -> Vec<StoredObject> {
+    instance
+        .meta()
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(OBJECTS_ANNOTATION))
+        .and_then(|value| serde_json::from_str(value).ok())
+        .unwrap_or_default()
+}
+
+/// Generates the Download's manifest and uploads it to each of its
+/// S3 metadata targets. Metadata targets of other kinds can't store
+/// objects and are skipped. Does nothing if the Download has no
+/// manifest configured.
+pub async fn publish_manifest(
+    client: Client,
+    instance: &Download,
+    mut objects: Vec<StoredObject>,
+) -> Result<(), Error> {
+    let spec = match instance.spec.manifest {
+        Some(ref spec) => spec,
+        None => return Ok(()),
+    };
+    let namespace = instance.namespace().unwrap();
+    objects.sort_by(|a, b| (&a.bucket, &a.key).cmp(&(&b.bucket, &b.key)));
+    let manifest = Manifest {
+        download: instance.name_any(),
+        namespace: namespace.clone(),
+        uid: instance.uid().unwrap(),
+        completed_at: chrono::Utc::now().to_rfc3339(),
+        objects,
+    };
+    let body = serde_json::to_vec_pretty(&manifest)?;
+    let signature = match spec.signing_secret_ref {
+        Some(ref secret) => Some(sign(
+            &get_signing_key(client.clone(), &namespace, secret).await?,
+            &body,
+        )),
+        None => None,
+    };
+    let key = get_manifest_key(spec, &manifest);
+    let targets = get_metadata_s3_targets(client.clone(), instance).await?;
+    if targets.is_empty() {
+        warn!("Download has no S3 metadata target to upload the manifest to");
+    }
+    for target in targets {
+        let bucket = get_s3_target_bucket(client.clone(), &namespace, &target.spec).await?;
+        let bucket = with_sse(&bucket, target.spec.sse.as_ref());
+        let bucket = with_storage_class(&bucket, target.spec.storage_class.as_deref());
+        put(&bucket, &key, &body).await?;
+        if let Some(ref signature) = signature {
+            put(
+                &bucket,
+                &format!("{}{}", key, SIGNATURE_SUFFIX),
+                signature.as_bytes(),
+            )
+            .await?;
+        }
+        info!(
+            bucket = %bucket.name,
+            key = %key,
+            objects = manifest.objects.len(),
+            signed = signature.is_some(),
+            "Uploaded manifest"
+        );
+    }
+    Ok(())
+}
+
+/// Returns the hex-encoded HMAC-SHA256 of the body.
+pub fn sign(key: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Returns the manifest's object key.
+fn get_manifest_key(spec: &ManifestSpec, manifest: &Manifest) -> String {
+    spec.key
+        .as_deref()
+        .unwrap_or(DEFAULT_MANIFEST_KEY)
+        .replace("%(namespace)s", &manifest.namespace)
+        .replace("%(name)s", &manifest.download)
+        .replace("%(uid)s", &manifest.uid)
+}
+
+/// Returns the signing key from the Secret.
+async fn get_signing_key(client: Client, namespace: &str, name: &str) -> Result<Vec<u8>, Error> {
+    let secret = Api::<Secret>::namespaced(client, namespace)
+        .get(name)
+        .await?;
+    secret
+        .data
+        .and_then(|mut data| data.remove(SIGNING_KEY_FIELD))
+        .map(|key| key.0)
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| {
+            Error::UserInputError(format!(
+                "manifest signing secret {} is missing the {} field",
+                name, SIGNING_KEY_FIELD
+            ))
+        })
+}
+
+/// Returns the S3Targets the Download stores metadata in.
+async fn get_metadata_s3_targets(
+    client: Client,
+    instance: &Download,
+) -> Result<Vec<S3Target>, Error> {
+    let namespace = instance.namespace().unwrap();
+    let targets: Api<Target> = Api::namespaced(client.clone(), &namespace);
+    let s3_targets: Api<S3Target> = Api::namespaced(client, &namespace);
+    let mut names: Vec<String> = Vec::new();
+    for name in get_targets(instance) {
+        let target = match targets.get_opt(name).await? {
+            Some(target) => target,
+            None => continue,
+        };
+        for target_ref in target.spec.metadata.unwrap_or_default() {
+            if target_ref.kind == "S3Target" && !names.contains(&target_ref.name) {
+                names.push(target_ref.name);
+            }
+        }
+    }
+    let mut result = Vec::with_capacity(names.len());
+    for name in names {
+        result.push(s3_targets.get(&name).await?);
+    }
+    Ok(result)
+}
+
+/// Uploads the object, failing on a non-200 response.
+async fn put(bucket: &s3::bucket::Bucket, key: &str, body: &[u8]) -> Result<(), Error> {
+    let res = bucket.put_object(key, body).await?;
+    if res.status_code() != 200 {
+        return Err(Error::S3UploadError {
+            status_code: res.status_code(),
+        });
+    }
+    Ok(())
+}
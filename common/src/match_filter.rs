@@ -0,0 +1,232 @@
+//! Evaluation of yt-dlp's `--match-filter` expressions, so the Download
+//! controller agrees with the query pod on which entities are excluded.
+//! An expression is a list of conditions joined by `&`, all of which must
+//! hold for the entity to be downloaded:
+//!
+//! - `field` / `!field`: the field is present and truthy, or it isn't.
+//! - `field OP value`: numeric comparisons with `<`, `<=`, `>`, `>=`,
+//!   `=`, `!=`, where the value may have a size suffix like `100M`, and
+//!   string comparisons with `=`, `!=`, `^=` (starts with), `$=` (ends
+//!   with), and `*=` (contains). Any operator may be negated by prefixing
+//!   it with `!`, e.g. `!*=`. String values may be quoted.
+//! - A `?` after the operator also matches entities missing the field,
+//!   e.g. `duration <? 600`.
+//!
+//! Regular expressions (`~=`) are not supported.
+use crate::{units::parse_filesize, Error};
+
+/// Operators, longest first so that prefixes don't shadow them.
+const OPERATORS: &[&str] = &["<=", ">=", "!=", "^=", "$=", "*=", "<", ">", "="];
+
+/// A parsed `--match-filter` expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchFilter {
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    /// The field is present and truthy, or absent/falsy if negated.
+    Unary { field: String, negated: bool },
+
+    /// The field compares to the value with the operator.
+    Binary {
+        field: String,
+        op: String,
+        negated: bool,
+        none_inclusive: bool,
+        value: String,
+    },
+}
+
+impl MatchFilter {
+    /// Parses the expression, failing on syntax yt-dlp would reject
+    /// and on regular expressions, which are not supported.
+    pub fn parse(expression: &str) -> Result<Self, Error> {
+        let conditions = expression
+            .split('&')
+            .map(str::trim)
+            .filter(|condition| !condition.is_empty())
+            .map(parse_condition)
+            .collect::<Result<Vec<_>, _>>()?;
+        if conditions.is_empty() {
+            return Err(Error::UserInputError(
+                "match filter has no conditions".to_owned(),
+            ));
+        }
+        Ok(MatchFilter { conditions })
+    }
+
+    /// Returns the first condition the entity fails, or None if
+    /// the entity matches the filter.
+    pub fn check(&self, metadata: &serde_json::Value) -> Option<String> {
+        self.conditions
+            .iter()
+            .find(|condition| !condition.matches(metadata))
+            .map(ToString::to_string)
+    }
+}
+
+impl Condition {
+    fn matches(&self, metadata: &serde_json::Value) -> bool {
+        match self {
+            Condition::Unary { field, negated } => {
+                let truthy = match metadata.get(field) {
+                    None | Some(serde_json::Value::Null) => false,
+                    Some(serde_json::Value::Bool(value)) => *value,
+                    Some(serde_json::Value::Number(value)) => value.as_f64() != Some(0.0),
+                    Some(serde_json::Value::String(value)) => !value.is_empty(),
+                    Some(serde_json::Value::Array(value)) => !value.is_empty(),
+                    Some(serde_json::Value::Object(value)) => !value.is_empty(),
+                };
+                truthy != *negated
+            }
+            Condition::Binary {
+                field,
+                op,
+                negated,
+                none_inclusive,
+                value,
+            } => {
+                let actual = match metadata.get(field) {
+                    None | Some(serde_json::Value::Null) => return *none_inclusive,
+                    Some(actual) => actual,
+                };
+                compare(actual, op, value) != *negated
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Unary { field, negated } => {
+                write!(f, "{}{}", if *negated { "!" } else { "" }, field)
+            }
+            Condition::Binary {
+                field,
+                op,
+                negated,
+                none_inclusive,
+                value,
+            } => write!(
+                f,
+                "{} {}{}{} {}",
+                field,
+                if *negated { "!" } else { "" },
+                op,
+                if *none_inclusive { "?" } else { "" },
+                value
+            ),
+        }
+    }
+}
+
+/// Compares the field's value to the condition's value.
+fn compare(actual: &serde_json::Value, op: &str, value: &str) -> bool {
+    if let Some(actual) = actual.as_f64() {
+        let expected = match value.parse::<f64>() {
+            Ok(expected) => expected,
+            Err(_) => match parse_filesize(value) {
+                Ok(expected) => expected as f64,
+                // A number never matches a non-numeric value.
+                Err(_) => return false,
+            },
+        };
+        return match op {
+            "<" => actual < expected,
+            "<=" => actual <= expected,
+            ">" => actual > expected,
+            ">=" => actual >= expected,
+            "=" => actual == expected,
+            "!=" => actual != expected,
+            _ => false,
+        };
+    }
+    let actual = match actual {
+        serde_json::Value::String(actual) => actual.clone(),
+        actual => actual.to_string(),
+    };
+    match op {
+        "=" => actual == value,
+        "!=" => actual != value,
+        "^=" => actual.starts_with(value),
+        "$=" => actual.ends_with(value),
+        "*=" => actual.contains(value),
+        _ => false,
+    }
+}
+
+/// Parses a single condition, e.g. `duration >? 60`.
+fn parse_condition(condition: &str) -> Result<Condition, Error> {
+    let invalid = |reason: &str| {
+        Error::UserInputError(format!(
+            "invalid match filter condition {}: {}",
+            condition, reason
+        ))
+    };
+    let field_len = condition
+        .trim_start_matches('!')
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+        .unwrap_or_else(|| condition.trim_start_matches('!').len());
+    let rest = condition.trim_start_matches('!')[field_len..].trim();
+    if rest.is_empty() {
+        let negated = condition.starts_with('!');
+        let field = condition.trim_start_matches('!');
+        if field.is_empty() {
+            return Err(invalid("missing field"));
+        }
+        return Ok(Condition::Unary {
+            field: field.to_owned(),
+            negated,
+        });
+    }
+    if condition.starts_with('!') {
+        return Err(invalid(
+            "only unary conditions can be negated with a leading !",
+        ));
+    }
+    let field = condition[..field_len].to_owned();
+    if field.is_empty() {
+        return Err(invalid("missing field"));
+    }
+    if rest.starts_with("~=") || rest.starts_with("!~=") {
+        return Err(invalid("regular expressions are not supported"));
+    }
+    // A leading ! negates any operator, except != which is its own.
+    let (negated, rest) = match rest.strip_prefix('!') {
+        Some(stripped) if !rest.starts_with("!=") => (true, stripped.trim_start()),
+        _ => (false, rest),
+    };
+    let op = OPERATORS
+        .iter()
+        .find(|op| rest.starts_with(*op))
+        .ok_or_else(|| invalid("unknown operator"))?;
+    let rest = &rest[op.len()..];
+    let (none_inclusive, rest) = match rest.strip_prefix('?') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let value = unquote(rest.trim());
+    if value.is_empty() {
+        return Err(invalid("missing value"));
+    }
+    Ok(Condition::Binary {
+        field,
+        op: (*op).to_owned(),
+        negated,
+        none_inclusive,
+        value: value.to_owned(),
+    })
+}
+
+/// Removes matching single or double quotes around the value.
+fn unquote(value: &str) -> &str {
+    for quote in ['\'', '"'] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
@@ -0,0 +1,119 @@
+//! Selection of the info json fields persisted to metadata targets,
+//! per [`DownloadSpec::metadata_fields`](ytdl_types::DownloadSpec::metadata_fields).
+use serde_json::{Map, Value};
+use ytdl_types::MetadataFieldsSpec;
+
+use crate::FieldError;
+
+/// Keys of the info json that map caption languages to their tracks.
+const CAPTION_KEYS: &[&str] = &["subtitles", "automatic_captions"];
+
+/// Returns the info json with only the fields selected by the spec,
+/// or the whole info json if there is no spec.
+pub fn select_fields(spec: Option<&MetadataFieldsSpec>, metadata: &Value) -> Value {
+    let spec = match spec {
+        Some(spec) => spec,
+        None => return metadata.clone(),
+    };
+    let mut selected = match spec.include {
+        Some(ref include) => {
+            let mut selected = Value::Object(Map::new());
+            for path in include {
+                if let Some(value) = get_path(metadata, path) {
+                    set_path(&mut selected, path, value.clone());
+                }
+            }
+            selected
+        }
+        None => metadata.clone(),
+    };
+    for path in spec.exclude.iter().flatten() {
+        remove_path(&mut selected, path);
+    }
+    if let Some(ref languages) = spec.caption_languages {
+        for key in CAPTION_KEYS {
+            if let Some(Value::Object(tracks)) = selected.get_mut(*key) {
+                tracks.retain(|language, _| matches_language(language, languages));
+            }
+        }
+    }
+    selected
+}
+
+/// Returns the problems with the field selection of the Download.
+pub fn validate_metadata_fields(spec: &MetadataFieldsSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let lists = vec![
+        ("metadataFields.include", &spec.include),
+        ("metadataFields.exclude", &spec.exclude),
+    ];
+    for (field, paths) in lists {
+        for (i, path) in paths.iter().flatten().enumerate() {
+            if path.split('.').any(str::is_empty) {
+                errors.push(FieldError::new(
+                    format!("{}[{}]", field, i),
+                    "must be keys separated by '.'",
+                ));
+            }
+        }
+    }
+    for (i, language) in spec.caption_languages.iter().flatten().enumerate() {
+        if language.trim().is_empty() {
+            errors.push(FieldError::new(
+                format!("metadataFields.captionLanguages[{}]", i),
+                "must not be empty",
+            ));
+        }
+    }
+    errors
+}
+
+/// Returns true if the caption language is one of the languages
+/// or a regional variant of one, e.g. `en-US` for `en`.
+fn matches_language(language: &str, languages: &[String]) -> bool {
+    let base = language.split('-').next().unwrap_or_default();
+    languages
+        .iter()
+        .any(|wanted| language.eq_ignore_ascii_case(wanted) || base.eq_ignore_ascii_case(wanted))
+}
+
+/// Returns the value at the dotted path, if any.
+fn get_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| value.get(key))
+}
+
+/// Sets the value at the dotted path, creating the objects it's in.
+fn set_path(value: &mut Value, path: &str, new: Value) {
+    let mut keys = path.split('.').peekable();
+    let mut current = value;
+    while let Some(key) = keys.next() {
+        let object = match current {
+            Value::Object(object) => object,
+            _ => return,
+        };
+        if keys.peek().is_none() {
+            object.insert(key.to_owned(), new);
+            return;
+        }
+        current = object
+            .entry(key.to_owned())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+}
+
+/// Removes the value at the dotted path, if any.
+fn remove_path(value: &mut Value, path: &str) {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (get_path_mut(value, parent), key),
+        None => (Some(value), path),
+    };
+    if let Some(Value::Object(object)) = parent {
+        object.remove(key);
+    }
+}
+
+/// Returns the value at the dotted path mutably, if any.
+fn get_path_mut<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.')
+        .try_fold(value, |value, key| value.get_mut(key))
+}
@@ -0,0 +1,105 @@
+//! Naming of child resources. Names derived from user input, such as
+//! `{download}-{video id}`, may be too long or contain characters that
+//! are not allowed in resource names, e.g. the uppercase letters and
+//! underscores in YouTube IDs. Such names are made RFC 1123 compliant
+//! and suffixed with a short hash of the original name, so that inputs
+//! differing only in the invalid characters do not collide.
+
+/// Label on Executors with the normalized ID of their first video.
+pub const ID_LABEL: &str = "ytdl.beebs.dev/id";
+
+/// Annotation on Executors with the original ID of their first video.
+pub const ORIGINAL_ID_ANNOTATION: &str = "ytdl.beebs.dev/original-id";
+
+/// Maximum length of a child resource name. This is the limit for DNS
+/// labels and label values, so child names are usable as either.
+pub const MAX_NAME_LENGTH: usize = 63;
+
+/// Number of hex digits in the hash suffix.
+const HASH_LENGTH: usize = 8;
+
+/// Returns the deterministic, RFC 1123 compliant name of the child of
+/// `parent` identified by `suffix`. Names that are already valid are
+/// returned unchanged.
+pub fn child_name(parent: &str, suffix: &str) -> String {
+    let name = format!("{}-{}", parent, suffix);
+    if is_valid_name(&name) {
+        return name;
+    }
+    // Replace runs of invalid characters with a single dash.
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9') => sanitized.push(c),
+            _ if sanitized.ends_with('-') => {}
+            _ => sanitized.push('-'),
+        }
+    }
+    // Leave room for the hash suffix and its separator.
+    sanitized.truncate(MAX_NAME_LENGTH - HASH_LENGTH - 1);
+    let sanitized = sanitized.trim_matches('-');
+    let hash = format!("{:016x}", fnv1a(name.as_bytes()));
+    if sanitized.is_empty() {
+        return hash[..HASH_LENGTH].to_owned();
+    }
+    format!("{}-{}", sanitized, &hash[..HASH_LENGTH])
+}
+
+/// Returns the normalized form of a video ID, which is safe to use in
+/// resource names, label values, and S3 keys. Most IDs (e.g. YouTube's)
+/// are returned unchanged. IDs with other characters or more than 63
+/// characters are sanitized and suffixed with a hash of the original,
+/// which is preserved in the [`ORIGINAL_ID_ANNOTATION`] of the Executor.
+pub fn normalize_id(id: &str) -> String {
+    if is_valid_id(id) {
+        return id.to_owned();
+    }
+    // Replace runs of unsafe characters with a single dash.
+    let mut sanitized = String::with_capacity(id.len());
+    for c in id.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' => sanitized.push(c),
+            _ if sanitized.ends_with('-') => {}
+            _ => sanitized.push('-'),
+        }
+    }
+    sanitized.truncate(MAX_NAME_LENGTH - HASH_LENGTH - 1);
+    let sanitized = sanitized.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+    let hash = &format!("{:016x}", fnv1a(id.as_bytes()))[..HASH_LENGTH];
+    if sanitized.is_empty() {
+        return hash.to_owned();
+    }
+    format!("{}-{}", sanitized, hash)
+}
+
+/// Returns true if the ID is a valid label value, which also makes it
+/// safe for S3 keys.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_NAME_LENGTH
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'.')
+        && id.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && id.ends_with(|c: char| c.is_ascii_alphanumeric())
+}
+
+/// Returns true if the name is a valid RFC 1123 label.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+/// 64-bit FNV-1a hash. Used instead of the std hasher, whose output is
+/// not guaranteed to be stable across Rust releases, because the names
+/// must stay the same when the operator is upgraded.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
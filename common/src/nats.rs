@@ -0,0 +1,137 @@
+//! NATS subjects, per [`NatsTargetSpec`](ytdl_types::NatsTargetSpec).
+//! Like [`kafka`](crate::kafka), the targets and their Secrets are
+//! resolved here, so that only the download pods, which publish the
+//! messages, link a NATS client.
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use std::collections::BTreeMap;
+use ytdl_types::{NatsJetStreamSpec, NatsPayload, NatsTarget, Target};
+
+use crate::{template_key, Error};
+
+/// Key in the Secret with a user credentials file.
+pub const CREDS_KEY: &str = "creds";
+
+/// Key in the Secret with an authentication token.
+pub const TOKEN_KEY: &str = "token";
+
+/// Keys in the Secret with the user's name and password.
+pub const USERNAME_KEY: &str = "username";
+pub const PASSWORD_KEY: &str = "password";
+
+/// How the client authenticates with the servers.
+#[derive(Debug, Clone)]
+pub enum NatsCredentials {
+    None,
+    Creds(String),
+    Token(String),
+    UserAndPassword(String, String),
+}
+
+/// A subject of a NATS cluster.
+#[derive(Debug, Clone)]
+pub struct NatsSubject {
+    /// Name of the NatsTarget, for logging.
+    pub name: String,
+
+    /// URLs of the servers.
+    pub servers: Vec<String>,
+
+    /// Subject rendered from the metadata.
+    pub subject: String,
+
+    /// What is published to the subject.
+    pub payload: NatsPayload,
+
+    /// How the client authenticates with the servers.
+    pub credentials: NatsCredentials,
+
+    /// JetStream persistence, or None to publish to core NATS.
+    pub jet_stream: Option<NatsJetStreamSpec>,
+}
+
+/// Returns the NATS subjects of the metadata for the named Target,
+/// rendered from the metadata.
+pub async fn get_nats_outputs(
+    client: Client,
+    namespace: &str,
+    target_name: &str,
+    metadata: &serde_json::Value,
+) -> Result<Vec<NatsSubject>, Error> {
+    let api: Api<Target> = Api::namespaced(client.clone(), namespace);
+    let refs = match api.get_opt(target_name).await? {
+        Some(target) => target.spec.metadata.unwrap_or_default(),
+        None => return Ok(Vec::new()),
+    };
+    let targets: Api<NatsTarget> = Api::namespaced(client.clone(), namespace);
+    let mut outputs = Vec::new();
+    for target_ref in refs {
+        if target_ref.kind != "NatsTarget" {
+            continue;
+        }
+        let spec = targets.get(&target_ref.name).await?.spec;
+        let subject = template_key(metadata, &spec.subject)?;
+        // The metadata is untrusted, so it mustn't add tokens that
+        // aren't valid in a subject.
+        if !is_valid_subject(&subject) {
+            return Err(Error::UserInputError(format!(
+                "subject {} of NatsTarget {} is not a valid subject",
+                subject, target_ref.name
+            )));
+        }
+        let credentials = match spec.secret {
+            Some(ref secret) => get_credentials(client.clone(), namespace, secret).await?,
+            None => NatsCredentials::None,
+        };
+        outputs.push(NatsSubject {
+            name: target_ref.name,
+            servers: spec.servers,
+            subject,
+            payload: spec.payload.unwrap_or_default(),
+            credentials,
+            jet_stream: spec.jet_stream,
+        });
+    }
+    Ok(outputs)
+}
+
+/// Returns true if the subject can be published to, i.e. it has no
+/// empty tokens, wildcards, or whitespace.
+pub fn is_valid_subject(subject: &str) -> bool {
+    subject.split('.').all(|token| {
+        !token.is_empty() && token != "*" && token != ">" && !token.contains(char::is_whitespace)
+    })
+}
+
+/// Returns the credentials in the named Secret, preferring a
+/// credentials file, then a token, then a user and password.
+async fn get_credentials(
+    client: Client,
+    namespace: &str,
+    name: &str,
+) -> Result<NatsCredentials, Error> {
+    let secret = Api::<Secret>::namespaced(client, namespace)
+        .get(name)
+        .await?;
+    let mut fields: BTreeMap<String, String> = secret
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, String::from_utf8(value.0).ok()?)))
+        .collect();
+    if let Some(creds) = fields.remove(CREDS_KEY) {
+        return Ok(NatsCredentials::Creds(creds));
+    }
+    if let Some(token) = fields.remove(TOKEN_KEY) {
+        return Ok(NatsCredentials::Token(token.trim().to_owned()));
+    }
+    match (fields.remove(USERNAME_KEY), fields.remove(PASSWORD_KEY)) {
+        (Some(username), Some(password)) => {
+            Ok(NatsCredentials::UserAndPassword(username, password))
+        }
+        _ => Err(Error::UserInputError(format!(
+            "NATS Secret {} needs a {}, {}, or {} and {} field",
+            name, CREDS_KEY, TOKEN_KEY, USERNAME_KEY, PASSWORD_KEY
+        ))),
+    }
+}
@@ -0,0 +1,143 @@
+//! Normalized metadata. The field names of the info json follow
+//! youtube-dl and yt-dlp releases, so the query also stores each
+//! entity in a stable, versioned schema alongside the raw info json.
+//! Consumers reading the normalized metadata are unaffected when the
+//! info json shifts, as its fields are only ever added to under the
+//! same [`SCHEMA_VERSION`].
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+use crate::Error;
+
+/// Key in the metadata ConfigMap for the normalized metadata jsonl.
+/// Each line is a json-encoded [`NormalizedMetadata`], in the same
+/// order as the lines of the info jsonl.
+pub const NORMALIZED_JSONL_KEY: &str = "normalized.jsonl";
+
+/// Version of the [`NormalizedMetadata`] schema. Incremented whenever
+/// a field is removed or its meaning changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// An entity's metadata in the normalized schema.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NormalizedMetadata {
+    /// Version of the schema, i.e. [`SCHEMA_VERSION`].
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+
+    /// Video ID.
+    pub id: String,
+
+    /// Title of the video.
+    pub title: Option<String>,
+
+    /// Name of the channel or account that uploaded the video.
+    pub uploader: Option<String>,
+
+    /// Duration of the video in seconds.
+    pub duration: Option<f64>,
+
+    /// Date the video was uploaded, formatted as `YYYY-MM-DD`.
+    #[serde(rename = "uploadDate")]
+    pub upload_date: Option<String>,
+
+    /// Summary of the formats available for the video.
+    pub formats: FormatsSummary,
+}
+
+/// Summary of the formats listed in the info json.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FormatsSummary {
+    /// Number of formats.
+    pub count: usize,
+
+    /// Largest height in pixels of the formats with video.
+    #[serde(rename = "maxHeight")]
+    pub max_height: Option<u64>,
+
+    /// Whether any format has an audio track.
+    #[serde(rename = "hasAudio")]
+    pub has_audio: bool,
+
+    /// Distinct file extensions of the formats, sorted.
+    pub extensions: Vec<String>,
+}
+
+/// Maps the info json into the normalized schema, or returns None
+/// if it has no video ID. Fields renamed by yt-dlp are read from
+/// either name.
+pub fn normalize(metadata: &Value) -> Option<NormalizedMetadata> {
+    Some(NormalizedMetadata {
+        schema_version: SCHEMA_VERSION,
+        id: get_str(metadata, &["id"])?,
+        title: get_str(metadata, &["title", "fulltitle"]),
+        uploader: get_str(metadata, &["uploader", "channel", "uploader_id"]),
+        duration: metadata.get("duration").and_then(Value::as_f64),
+        upload_date: get_upload_date(metadata),
+        formats: summarize_formats(metadata),
+    })
+}
+
+/// Normalizes each line of the info jsonl, encoding the results as
+/// jsonl. Lines without a video ID are left out.
+pub fn to_normalized_jsonl(lines: &[String]) -> Result<String, Error> {
+    let lines = lines
+        .iter()
+        .filter_map(|line| normalize(&serde_json::from_str(line).ok()?))
+        .map(|normalized| serde_json::to_string(&normalized))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lines.join("\n"))
+}
+
+/// Returns the first of the keys with a non-empty string value.
+fn get_str(metadata: &Value, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| metadata.get(*key)?.as_str())
+        .find(|value| !value.is_empty())
+        .map(str::to_owned)
+}
+
+/// Returns the upload date from `upload_date`, which youtube-dl
+/// formats as `YYYYMMDD`, falling back on the upload timestamp.
+fn get_upload_date(metadata: &Value) -> Option<String> {
+    if let Some(date) = metadata.get("upload_date").and_then(Value::as_str) {
+        if date.len() == 8 && date.chars().all(|c| c.is_ascii_digit()) {
+            return Some(format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]));
+        }
+    }
+    let timestamp = metadata.get("timestamp")?.as_i64()?;
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d").to_string())
+}
+
+/// Summarizes the `formats` array of the info json.
+fn summarize_formats(metadata: &Value) -> FormatsSummary {
+    let formats = match metadata.get("formats").and_then(Value::as_array) {
+        Some(formats) => formats,
+        None => return FormatsSummary::default(),
+    };
+    // A codec of "none" means the format lacks that track.
+    let has_track = |format: &Value, key: &str| {
+        format
+            .get(key)
+            .and_then(Value::as_str)
+            .map_or(false, |codec| codec != "none")
+    };
+    let extensions: BTreeSet<&str> = formats
+        .iter()
+        .filter_map(|format| format.get("ext")?.as_str())
+        .collect();
+    FormatsSummary {
+        count: formats.len(),
+        max_height: formats
+            .iter()
+            .filter(|format| has_track(format, "vcodec"))
+            .filter_map(|format| format.get("height")?.as_u64())
+            .max(),
+        has_audio: formats.iter().any(|format| has_track(format, "acodec")),
+        extensions: extensions.into_iter().map(str::to_owned).collect(),
+    }
+}
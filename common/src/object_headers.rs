@@ -0,0 +1,61 @@
+//! Custom headers of uploaded objects, per
+//! [`S3TargetSpec::object_headers`](ytdl_types::S3TargetSpec::object_headers),
+//! e.g. for CDN caching or to have browsers play videos inline. The
+//! headers are sent with the requests that create objects.
+use s3::bucket::Bucket;
+use std::collections::BTreeMap;
+
+use crate::FieldError;
+
+/// Header that overrides the detected Content-Type.
+pub const CONTENT_TYPE_HEADER: &str = "content-type";
+
+/// Standard headers that S3 stores with an object and returns
+/// when it is read.
+const OBJECT_HEADERS: &[&str] = &[
+    "cache-control",
+    "content-disposition",
+    "content-encoding",
+    "content-language",
+    CONTENT_TYPE_HEADER,
+    "expires",
+];
+
+/// Prefix of the headers with user-defined object metadata.
+const USER_METADATA_PREFIX: &str = "x-amz-meta-";
+
+/// Returns a copy of the bucket that sends the headers with the
+/// objects it uploads, or a plain copy if there are none.
+pub fn with_object_headers(bucket: &Bucket, headers: Option<&BTreeMap<String, String>>) -> Bucket {
+    let mut bucket = bucket.clone();
+    for (name, value) in headers.into_iter().flatten() {
+        bucket.add_header(&name.to_lowercase(), value);
+    }
+    bucket
+}
+
+/// Returns the problems with the object headers of the S3Target.
+pub fn validate_object_headers(headers: &BTreeMap<String, String>) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    for (name, value) in headers {
+        let field = format!("objectHeaders.{}", name);
+        let lower = name.to_lowercase();
+        let printable = value.chars().all(|c| c.is_ascii() && !c.is_ascii_control());
+        if !OBJECT_HEADERS.contains(&lower.as_str()) && !lower.starts_with(USER_METADATA_PREFIX) {
+            errors.push(FieldError::new(
+                field,
+                format!(
+                    "must be one of {} or start with {}",
+                    OBJECT_HEADERS.join(", "),
+                    USER_METADATA_PREFIX
+                ),
+            ));
+        } else if value.is_empty() || !printable {
+            errors.push(FieldError::new(
+                field,
+                "must be a non-empty printable ascii value",
+            ));
+        }
+    }
+    errors
+}
@@ -2,12 +2,25 @@ use const_format::concatcp;
 use k8s_openapi::{
     api::core::v1::{
         Capabilities, Container, EmptyDirVolumeSource, EnvVar, EnvVarSource, Pod, PodSpec,
-        SecretKeySelector, SecurityContext, Volume, VolumeMount,
+        SecretKeySelector, SecretVolumeSource, SecurityContext, Volume, VolumeMount,
     },
     apimachinery::pkg::apis::meta::v1::OwnerReference,
 };
-use kube::api::ObjectMeta;
+use kube::{
+    api::{Api, ListParams, ObjectMeta, PropagationPolicy},
+    Client, ResourceExt,
+};
 use std::collections::BTreeMap;
+use ytdl_types::{PodTemplate, ProxySpec, VpnSpec};
+
+use crate::{
+    chaos, delete::delete_opt, failure::EXECUTOR_CONTAINER_NAME, progress, proxy::get_proxy_env,
+    Error,
+};
+
+/// Label on each pod with the uid of the resource that controls it.
+/// Pod names are generated, so pods are found by this label instead.
+pub const OWNER_UID_LABEL: &str = "ytdl.beebs.dev/owner-uid";
 
 /// The IP service to use for getting the public IP address.
 pub const IP_SERVICE: &str = "https://api.ipify.org";
@@ -32,11 +45,102 @@ pub const IP_FILE_PATH: &str = concatcp!(SHARED_PATH, "/ip");
 /// modular nature of the sidecar.
 const DEFAULT_VPN_IMAGE: &str = "qmcgaw/gluetun:v3.32.0";
 
-/// Creates the container spec for the VPN sidecar.
-pub fn get_vpn_sidecar() -> Container {
+/// VPN provider used if none is specified.
+const DEFAULT_VPN_PROVIDER: &str = "private internet access";
+
+/// Name of the Secret with the VPN credentials if none is specified.
+const DEFAULT_VPN_SECRET: &str = "pia-creds";
+
+/// Returns true if the user opted out of the VPN sidecar.
+pub fn is_vpn_disabled(vpn: Option<&VpnSpec>) -> bool {
+    vpn.and_then(|vpn| vpn.disabled).unwrap_or(false)
+}
+
+/// Returns true if the pod has a VPN sidecar, which is the case unless
+/// the VPN is disabled or a proxy is used instead.
+pub fn has_vpn_sidecar(vpn: Option<&VpnSpec>, proxy: Option<&ProxySpec>) -> bool {
+    !is_vpn_disabled(vpn) && proxy.is_none()
+}
+
+/// Creates the container spec for the VPN sidecar per the user's
+/// configuration. If a server region is specified, the VPN will only
+/// connect to servers in that region, overriding the configured regions.
+/// Otherwise, the provider's default is used.
+pub fn get_vpn_sidecar(vpn: Option<&VpnSpec>, region: Option<String>) -> Container {
+    let vpn = vpn.cloned().unwrap_or_default();
+    let secret = vpn
+        .secret_ref
+        .unwrap_or_else(|| DEFAULT_VPN_SECRET.to_owned());
+    let mut env = vec![
+        // https://github.com/qdm12/gluetun/wiki/
+        EnvVar {
+            name: "VPN_SERVICE_PROVIDER".to_owned(),
+            value: Some(
+                vpn.provider
+                    .unwrap_or_else(|| DEFAULT_VPN_PROVIDER.to_owned()),
+            ),
+            ..Default::default()
+        },
+        EnvVar {
+            name: "IP_SERVICE".to_owned(),
+            value: Some(IP_SERVICE.to_owned()),
+            ..Default::default()
+        },
+        EnvVar {
+            name: "OPENVPN_USER".to_owned(),
+            value_from: Some(EnvVarSource {
+                secret_key_ref: Some(SecretKeySelector {
+                    name: Some(secret.clone()),
+                    key: "username".to_owned(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        EnvVar {
+            name: "OPENVPN_PASSWORD".to_owned(),
+            value_from: Some(EnvVarSource {
+                secret_key_ref: Some(SecretKeySelector {
+                    name: Some(secret),
+                    key: "password".to_owned(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    ];
+    let regions = match region {
+        // Restrict the VPN to servers in the given region.
+        Some(region) => Some(region),
+        None => vpn.regions.map(|regions| regions.join(",")),
+    };
+    if let Some(regions) = regions {
+        env.push(EnvVar {
+            name: "SERVER_REGIONS".to_owned(),
+            value: Some(regions),
+            ..Default::default()
+        });
+    }
+    // The VPN's firewall drops inbound connections to ports that
+    // aren't opened, which would keep the controller from scraping
+    // the download progress.
+    if let Some(port) = progress::get_pod_env().and_then(|var| var.value) {
+        env.push(EnvVar {
+            name: "FIREWALL_INPUT_PORTS".to_owned(),
+            value: Some(port),
+            ..Default::default()
+        });
+    }
+    // User-specified variables take precedence over the ones above.
+    for var in vpn.env.unwrap_or_default() {
+        env.retain(|existing| existing.name != var.name);
+        env.push(var);
+    }
     Container {
         name: "vpn".to_owned(),
-        image: Some(DEFAULT_VPN_IMAGE.to_owned()),
+        image: Some(vpn.image.unwrap_or_else(|| DEFAULT_VPN_IMAGE.to_owned())),
         image_pull_policy: Some("IfNotPresent".to_owned()),
         security_context: Some(SecurityContext {
             capabilities: Some(Capabilities {
@@ -45,44 +149,7 @@ pub fn get_vpn_sidecar() -> Container {
             }),
             ..Default::default()
         }),
-        env: Some(vec![
-            // TODO: configure gluetun env vars
-            // https://github.com/qdm12/gluetun/wiki/
-            EnvVar {
-                name: "VPN_SERVICE_PROVIDER".to_owned(),
-                value: Some("private internet access".to_owned()),
-                ..Default::default()
-            },
-            EnvVar {
-                name: "IP_SERVICE".to_owned(),
-                value: Some(IP_SERVICE.to_owned()),
-                ..Default::default()
-            },
-            EnvVar {
-                name: "OPENVPN_USER".to_owned(),
-                value_from: Some(EnvVarSource {
-                    secret_key_ref: Some(SecretKeySelector {
-                        name: Some("pia-creds".to_owned()),
-                        key: "username".to_owned(),
-                        ..Default::default()
-                    }),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            },
-            EnvVar {
-                name: "OPENVPN_PASSWORD".to_owned(),
-                value_from: Some(EnvVarSource {
-                    secret_key_ref: Some(SecretKeySelector {
-                        name: Some("pia-creds".to_owned()),
-                        key: "password".to_owned(),
-                        ..Default::default()
-                    }),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            },
-        ]),
+        env: Some(env),
         ..Container::default()
     }
 }
@@ -112,16 +179,80 @@ fn get_init_container() -> Container {
     }
 }
 
+/// Creates the pod with the given container and a VPN sidecar, unless
+/// the VPN is disabled or a proxy is used instead, in which case the
+/// proxy is passed to the container. The user's pod template, if any,
+/// is merged into the generated pod. The pod's name is generated from the given prefix
+/// so that it never collides with pods created by users.
 pub fn masked_pod(
-    name: String,
+    name_prefix: String,
     namespace: String,
     owner_references: Option<Vec<OwnerReference>>,
     service_account_name: String,
-    container: Container,
+    mut container: Container,
+    vpn: Option<&VpnSpec>,
+    vpn_region: Option<String>,
+    proxy: Option<&ProxySpec>,
+    pod_template: Option<&PodTemplate>,
 ) -> Pod {
     // Add a label to the pod so that we can easily find it.
     let mut labels: BTreeMap<String, String> = BTreeMap::new();
     labels.insert("app".to_owned(), "ytdl".to_owned());
+    if let Some(uid) = get_controller_uid(owner_references.as_deref()) {
+        labels.insert(OWNER_UID_LABEL.to_owned(), uid);
+    }
+
+    let template = pod_template.cloned().unwrap_or_default();
+    // The operator's own labels take precedence over the user's.
+    for (key, value) in template.labels.unwrap_or_default() {
+        labels.entry(key).or_insert(value);
+    }
+    if template.resources.is_some() {
+        container.resources = template.resources;
+    }
+
+    if let Some(proxy) = proxy {
+        container
+            .env
+            .get_or_insert_with(Vec::new)
+            .extend(get_proxy_env(proxy));
+    }
+
+    // Pods inject the same faults as the operator when soak testing.
+    let chaos_env = chaos::get_pod_env();
+    if !chaos_env.is_empty() {
+        container.env.get_or_insert_with(Vec::new).extend(chaos_env);
+    }
+
+    // Without a VPN there is no IP change to wait for, so neither
+    // the init container nor the sidecar are needed.
+    let (init_containers, containers) = if !has_vpn_sidecar(vpn, proxy) {
+        (None, vec![container])
+    } else {
+        (
+            // Create an init container that writes the unmasked public
+            // IP to a shared file. This container must complete before
+            // the others can start, and this is useful when the executor
+            // is trying to figure out the moment the VPN is connected.
+            Some(vec![get_init_container()]),
+            // Main containers will start only after the init container
+            // succeeds. Because all containers in a pod share the same
+            // networking, connecting to a VPN in a sidecar will connect
+            // all other containers as well. The executor will detect
+            // the new/masked IP before starting any downloads.
+            vec![
+                // Each executor will have a VPN sidecar to avoid drawing
+                // too much attention from the video service.
+                // Kubelet will start the VPN container first. If both
+                // images are already available on the node, this should
+                // result in less time waiting for the VPN connection.
+                get_vpn_sidecar(vpn, vpn_region),
+                // Starting the executor container last may reduce VPN
+                // connection wait time.
+                container,
+            ],
+        )
+    };
 
     // The containers have a shared volume mounted at /share
     // that the VPN pod will write a file to when it's ready.
@@ -132,9 +263,10 @@ pub fn masked_pod(
     // containers, so this is the best we can do.
     Pod {
         metadata: ObjectMeta {
-            name: Some(name),
+            generate_name: Some(format!("{}-", name_prefix)),
             namespace: Some(namespace),
             labels: Some(labels),
+            annotations: template.annotations,
             owner_references,
             ..ObjectMeta::default()
         },
@@ -145,27 +277,13 @@ pub fn masked_pod(
             // The pod needs access to the k8s api so it can retrieve
             // e.g. s3 credentials from the configured Secret resources.
             service_account_name: Some(service_account_name),
-            // Create an init container that writes the unmasked public
-            // IP to a shared file. This container must complete before
-            // the others can start, and this is useful when the executor
-            // is trying to figure out the moment the VPN is connected.
-            init_containers: Some(vec![get_init_container()]),
-            // Main containers will start only after the init container
-            // succeeds. Because all containers in a pod share the same
-            // networking, connecting to a VPN in a sidecar will connect
-            // all other containers as well. The executor will detect
-            // the new/masked IP before starting any downloads.
-            containers: vec![
-                // Each executor will have a VPN sidecar to avoid drawing
-                // too much attention from the video service.
-                // Kubelet will start the VPN container first. If both
-                // images are already available on the node, this should
-                // result in less time waiting for the VPN connection.
-                get_vpn_sidecar(),
-                // Starting the executor container last may reduce VPN
-                // connection wait time.
-                container,
-            ],
+            // Placement of the pod as specified by the user.
+            node_selector: template.node_selector,
+            tolerations: template.tolerations,
+            affinity: template.affinity,
+            runtime_class_name: template.runtime_class_name,
+            init_containers,
+            containers,
             // Create an in-memory volume that allows data to be shared
             // between the containers. The init container will write the
             // unmasked public IP to a file in this volume, and the
@@ -185,3 +303,102 @@ pub fn masked_pod(
         ..Pod::default()
     }
 }
+
+/// Mounts the named Secret read-only into the executor container.
+pub fn mount_secret(pod: &mut Pod, volume_name: &str, secret_name: &str, mount_path: &str) {
+    let volume = Volume {
+        name: volume_name.to_owned(),
+        secret: Some(SecretVolumeSource {
+            secret_name: Some(secret_name.to_owned()),
+            ..SecretVolumeSource::default()
+        }),
+        ..Volume::default()
+    };
+    mount_volume(pod, volume, mount_path, true);
+}
+
+/// Adds the volume to the pod and mounts it into the executor container.
+pub fn mount_volume(pod: &mut Pod, volume: Volume, mount_path: &str, read_only: bool) {
+    let spec = match pod.spec.as_mut() {
+        Some(spec) => spec,
+        None => return,
+    };
+    let volume_name = volume.name.clone();
+    spec.volumes.get_or_insert_with(Vec::new).push(volume);
+    if let Some(container) = spec
+        .containers
+        .iter_mut()
+        .find(|container| container.name == EXECUTOR_CONTAINER_NAME)
+    {
+        container
+            .volume_mounts
+            .get_or_insert_with(Vec::new)
+            .push(VolumeMount {
+                name: volume_name,
+                mount_path: mount_path.to_owned(),
+                read_only: Some(read_only),
+                ..VolumeMount::default()
+            });
+    }
+}
+
+/// Returns the uid of the controller among the owner references.
+fn get_controller_uid(owner_references: Option<&[OwnerReference]>) -> Option<String> {
+    owner_references?
+        .iter()
+        .find(|oref| oref.controller == Some(true))
+        .map(|oref| oref.uid.clone())
+}
+
+/// Returns the pods controlled by the resource with the given uid. The
+/// owner references are checked as well as the label, so that a pod
+/// created by a user is never mistaken for one of the operator's.
+pub async fn list_owned_pods(
+    client: Client,
+    namespace: &str,
+    owner_uid: &str,
+) -> Result<Vec<Pod>, Error> {
+    let api: Api<Pod> = Api::namespaced(client, namespace);
+    let lp = ListParams::default().labels(&format!("{}={}", OWNER_UID_LABEL, owner_uid));
+    Ok(api
+        .list(&lp)
+        .await?
+        .items
+        .into_iter()
+        .filter(|pod| {
+            get_controller_uid(pod.metadata.owner_references.as_deref()).as_deref()
+                == Some(owner_uid)
+        })
+        .collect())
+}
+
+/// Returns the pod controlled by the resource with the given uid, or
+/// None if it does not exist. Pods that are being deleted are ignored,
+/// as a replacement no longer has to wait for their names to be freed.
+/// Should more than one exist, e.g. after a stale cache led to a second
+/// one being created, the oldest is used.
+pub async fn get_owned_pod(
+    client: Client,
+    namespace: &str,
+    owner_uid: &str,
+) -> Result<Option<Pod>, Error> {
+    Ok(list_owned_pods(client, namespace, owner_uid)
+        .await?
+        .into_iter()
+        .filter(|pod| pod.metadata.deletion_timestamp.is_none())
+        .min_by_key(|pod| pod.metadata.creation_timestamp.clone()))
+}
+
+/// Deletes every pod controlled by the resource with the given uid.
+/// Pods that were already deleted are ignored.
+pub async fn delete_owned_pods(
+    client: Client,
+    namespace: &str,
+    owner_uid: &str,
+) -> Result<(), Error> {
+    let api: Api<Pod> = Api::namespaced(client.clone(), namespace);
+    for pod in list_owned_pods(client, namespace, owner_uid).await? {
+        delete_opt(&api, &pod.name_any(), PropagationPolicy::Background).await?;
+    }
+    Ok(())
+}
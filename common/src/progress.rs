@@ -0,0 +1,83 @@
+use k8s_openapi::api::core::v1::EnvVar;
+use kube::Resource;
+use std::{env, time::Duration};
+use ytdl_types::DownloadProgress;
+
+use crate::Error;
+
+/// Annotation on the Executor through which the download pod reports
+/// the progress of the current video, as a json-encoded
+/// [`DownloadProgress`]. The controller copies it into the status.
+pub const PROGRESS_ANNOTATION: &str = "ytdl.beebs.dev/progress";
+
+/// Environment variable with the port on which download pods serve the
+/// progress of the current video. If set, the controller scrapes the
+/// pods instead of them annotating their Executor with it.
+pub const PROGRESS_PORT_ENV: &str = "PROGRESS_PORT";
+
+/// Name of the download container's port that serves the progress.
+pub const PROGRESS_PORT_NAME: &str = "progress";
+
+/// How long the controller waits for a download pod's progress.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Returns the download progress reported by the download pod, if any.
+pub fn get_download_progress<K: Resource>(instance: &K) -> Option<DownloadProgress> {
+    instance
+        .meta()
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(PROGRESS_ANNOTATION))
+        .and_then(|value| serde_json::from_str(value).ok())
+}
+
+/// Returns the port on which download pods serve their progress, or
+/// None if they annotate their Executor with it.
+pub fn get_progress_port() -> Result<Option<u16>, Error> {
+    match env::var(PROGRESS_PORT_ENV) {
+        Ok(port) if !port.trim().is_empty() => port.trim().parse().map(Some).map_err(|_| {
+            Error::UserInputError(format!(
+                "{} must be a port number, got {}",
+                PROGRESS_PORT_ENV, port
+            ))
+        }),
+        _ => Ok(None),
+    }
+}
+
+/// Returns the environment that passes the operator's progress port
+/// on to a download pod.
+pub fn get_pod_env() -> Option<EnvVar> {
+    let port = env::var(PROGRESS_PORT_ENV).ok()?;
+    if port.trim().is_empty() {
+        return None;
+    }
+    Some(EnvVar {
+        name: PROGRESS_PORT_ENV.to_owned(),
+        value: Some(port),
+        ..EnvVar::default()
+    })
+}
+
+/// Returns the progress served by the download pod at the IP, which is
+/// None until the pod starts downloading a video.
+pub async fn scrape_progress(pod_ip: &str, port: u16) -> Result<Option<DownloadProgress>, Error> {
+    // IPv6 addresses are bracketed in URLs.
+    let host = if pod_ip.contains(':') {
+        format!("[{}]", pod_ip)
+    } else {
+        pod_ip.to_owned()
+    };
+    // The pod is reached directly, never through the operator's proxy.
+    let body = reqwest::Client::builder()
+        .timeout(SCRAPE_TIMEOUT)
+        .no_proxy()
+        .build()?
+        .get(format!("http://{}:{}/", host, port))
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    Ok(serde_json::from_slice(&body)?)
+}
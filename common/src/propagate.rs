@@ -0,0 +1,50 @@
+//! Propagation of user-defined labels and annotations from a Download
+//! to its Executors and pods, so that tooling keyed on labels (e.g. cost
+//! allocation or monitoring) also covers the resources the operator
+//! creates on the user's behalf. Only keys starting with one of the
+//! configured prefixes are propagated.
+use kube::api::ObjectMeta;
+use std::collections::BTreeMap;
+
+/// Returns the prefixes of the label and annotation keys to propagate,
+/// from the comma-separated `PROPAGATE_PREFIXES` environment variable.
+/// Nothing is propagated if it is unset.
+pub fn get_propagate_prefixes() -> Vec<String> {
+    match std::env::var("PROPAGATE_PREFIXES") {
+        Ok(prefixes) => prefixes
+            .split(',')
+            .map(str::trim)
+            .filter(|prefix| !prefix.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Copies the parent's labels and annotations whose keys start with one
+/// of the prefixes to the child. Keys already set on the child are left
+/// alone, as the operator's own labels take precedence.
+pub fn propagate_metadata(parent: &ObjectMeta, child: &mut ObjectMeta, prefixes: &[String]) {
+    if prefixes.is_empty() {
+        return;
+    }
+    copy_prefixed(&parent.labels, &mut child.labels, prefixes);
+    copy_prefixed(&parent.annotations, &mut child.annotations, prefixes);
+}
+
+fn copy_prefixed(
+    from: &Option<BTreeMap<String, String>>,
+    to: &mut Option<BTreeMap<String, String>>,
+    prefixes: &[String],
+) {
+    for (key, value) in from.iter().flatten() {
+        if prefixes
+            .iter()
+            .any(|prefix| key.starts_with(prefix.as_str()))
+        {
+            to.get_or_insert_with(BTreeMap::new)
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Proxy support for the query and download pods. The pod builder
+//! passes the proxy to the executor container in environment variables,
+//! and the executor uses it for both youtube-dl and its own requests.
+//!
+//! Targets may also name a storage proxy, which the download pod's
+//! uploads go through instead of the VPN or proxy that the traffic to
+//! the video service uses, for split-tunnel network policies.
+use k8s_openapi::{
+    api::core::v1::{EnvVar, EnvVarSource, SecretKeySelector},
+    NamespaceResourceScope,
+};
+use kube::{Api, Client, Resource};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+use ytdl_types::{
+    AzureBlobTarget, GcsTarget, ProxySpec, S3Target, Target, WebDavTarget, WebhookTarget,
+};
+
+use crate::Error;
+
+/// Environment variable with the URL of the proxy.
+pub const PROXY_URL_ENV: &str = "PROXY_URL";
+
+/// Environment variable with the proxy username, if any.
+pub const PROXY_USERNAME_ENV: &str = "PROXY_USERNAME";
+
+/// Environment variable with the proxy password, if any.
+pub const PROXY_PASSWORD_ENV: &str = "PROXY_PASSWORD";
+
+/// Environment variable with the URL of the storage proxy.
+pub const STORAGE_PROXY_URL_ENV: &str = "STORAGE_PROXY_URL";
+
+/// Environment variable with the storage proxy username, if any.
+pub const STORAGE_PROXY_USERNAME_ENV: &str = "STORAGE_PROXY_USERNAME";
+
+/// Environment variable with the storage proxy password, if any.
+pub const STORAGE_PROXY_PASSWORD_ENV: &str = "STORAGE_PROXY_PASSWORD";
+
+/// Environment variables through which the S3 client, which creates
+/// an HTTP client for every request and can't be given a proxy, picks
+/// up the storage proxy. The operator sets them on the executor
+/// container. Every other client is built with its proxy, or none.
+pub const SYSTEM_PROXY_ENVS: &[&str] = &["HTTP_PROXY", "HTTPS_PROXY"];
+
+/// Environment variable with the hosts that bypass the system proxy.
+const NO_PROXY_ENV: &str = "NO_PROXY";
+
+/// URL schemes supported by both youtube-dl and reqwest.
+pub const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+/// Returns the environment variables that pass the proxy to the
+/// executor container. The credentials are read from the Secret.
+pub fn get_proxy_env(proxy: &ProxySpec) -> Vec<EnvVar> {
+    env_from_spec(
+        proxy,
+        [PROXY_URL_ENV, PROXY_USERNAME_ENV, PROXY_PASSWORD_ENV],
+    )
+}
+
+/// Returns the environment variables that pass the storage proxy
+/// to the executor container. The system proxy is set to it for the
+/// S3 client, with the credentials filled in by Kubernetes from the
+/// variables before it, which is why they must be URL-safe.
+pub fn get_storage_proxy_env(proxy: &ProxySpec) -> Vec<EnvVar> {
+    let mut env = env_from_spec(
+        proxy,
+        [
+            STORAGE_PROXY_URL_ENV,
+            STORAGE_PROXY_USERNAME_ENV,
+            STORAGE_PROXY_PASSWORD_ENV,
+        ],
+    );
+    let url = match proxy.secret_ref {
+        Some(_) => proxy.url.replacen(
+            "://",
+            &format!(
+                "://$({}):$({})@",
+                STORAGE_PROXY_USERNAME_ENV, STORAGE_PROXY_PASSWORD_ENV
+            ),
+            1,
+        ),
+        None => proxy.url.clone(),
+    };
+    env.extend(SYSTEM_PROXY_ENVS.iter().map(|name| EnvVar {
+        name: name.to_string(),
+        value: Some(url.clone()),
+        ..EnvVar::default()
+    }));
+    // The VPN sidecar's control server is never proxied.
+    env.push(EnvVar {
+        name: NO_PROXY_ENV.to_owned(),
+        value: Some("localhost,127.0.0.1".to_owned()),
+        ..EnvVar::default()
+    });
+    env
+}
+
+/// Returns the environment variables with the proxy's URL and
+/// credentials under the given names.
+fn env_from_spec(proxy: &ProxySpec, [url, username, password]: [&str; 3]) -> Vec<EnvVar> {
+    let mut env = vec![EnvVar {
+        name: url.to_owned(),
+        value: Some(proxy.url.clone()),
+        ..EnvVar::default()
+    }];
+    if let Some(ref secret) = proxy.secret_ref {
+        for (name, key) in [(username, "username"), (password, "password")] {
+            env.push(EnvVar {
+                name: name.to_owned(),
+                value_from: Some(EnvVarSource {
+                    secret_key_ref: Some(SecretKeySelector {
+                        name: Some(secret.clone()),
+                        key: key.to_owned(),
+                        ..SecretKeySelector::default()
+                    }),
+                    ..EnvVarSource::default()
+                }),
+                ..EnvVar::default()
+            });
+        }
+    }
+    env
+}
+
+/// Returns the proxy URL from the environment with the credentials
+/// embedded, or None if the pod does not use a proxy.
+pub fn get_proxy_url() -> Result<Option<String>, Error> {
+    url_from_env([PROXY_URL_ENV, PROXY_USERNAME_ENV, PROXY_PASSWORD_ENV])
+}
+
+/// Returns the storage proxy URL from the environment with the
+/// credentials embedded, or None if the pod has no storage proxy.
+pub fn get_storage_proxy_url() -> Result<Option<String>, Error> {
+    url_from_env([
+        STORAGE_PROXY_URL_ENV,
+        STORAGE_PROXY_USERNAME_ENV,
+        STORAGE_PROXY_PASSWORD_ENV,
+    ])
+}
+
+/// Returns the proxy URL from the environment variables with the
+/// given names, with the credentials embedded.
+fn url_from_env([url, username, password]: [&str; 3]) -> Result<Option<String>, Error> {
+    let url = match std::env::var(url) {
+        Ok(url) if !url.is_empty() => url,
+        _ => return Ok(None),
+    };
+    let mut url =
+        Url::parse(&url).map_err(|e| Error::UserInputError(format!("invalid proxy url: {}", e)))?;
+    if let Ok(username) = std::env::var(username) {
+        url.set_username(&username)
+            .map_err(|_| Error::UserInputError("proxy url cannot have a username".to_owned()))?;
+    }
+    if let Ok(password) = std::env::var(password) {
+        url.set_password(Some(&password))
+            .map_err(|_| Error::UserInputError("proxy url cannot have a password".to_owned()))?;
+    }
+    Ok(Some(url.to_string()))
+}
+
+/// Returns an HTTP client that uses the proxy from the environment,
+/// if any, so the executor's own requests leave through it as well.
+/// The system proxy, which is the storage proxy in a download pod, is
+/// never used, as these requests go to the video service.
+pub fn get_http_client() -> Result<reqwest::Client, Error> {
+    let builder = match get_proxy_url()? {
+        Some(url) => reqwest::Client::builder().proxy(reqwest::Proxy::all(url)?),
+        None => reqwest::Client::builder().no_proxy(),
+    };
+    Ok(builder.build()?)
+}
+
+/// Returns an HTTP client for the GCS, Azure, and WebDAV targets,
+/// which goes through the storage proxy if the pod has one.
+pub fn get_storage_http_client() -> Result<reqwest::Client, Error> {
+    let builder = match get_storage_proxy_url()? {
+        Some(url) => reqwest::Client::builder().proxy(reqwest::Proxy::all(url)?),
+        None => reqwest::Client::builder(),
+    };
+    Ok(builder.build()?)
+}
+
+/// Returns the storage proxy of the targets referenced by the named
+/// Target. The S3, GCS, Azure, WebDAV, and webhook targets of a Target
+/// must agree on their storage proxy, as the download pod only has one.
+pub async fn get_storage_proxy(
+    client: Client,
+    namespace: &str,
+    target_name: &str,
+) -> Result<Option<ProxySpec>, Error> {
+    let api: Api<Target> = Api::namespaced(client.clone(), namespace);
+    let spec = match api.get_opt(target_name).await? {
+        Some(target) => target.spec,
+        None => return Ok(None),
+    };
+    let mut proxies: Vec<Option<ProxySpec>> = Vec::new();
+    for target_ref in vec![spec.metadata, spec.audiovisual, spec.thumbnail]
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let name = &target_ref.name;
+        let proxy = match target_ref.kind.as_str() {
+            "S3Target" => get_proxy::<S3Target>(&client, namespace, name, |t| t.spec.proxy).await?,
+            "WebhookTarget" => {
+                get_proxy::<WebhookTarget>(&client, namespace, name, |t| t.spec.proxy).await?
+            }
+            "GcsTarget" => {
+                get_proxy::<GcsTarget>(&client, namespace, name, |t| t.spec.proxy).await?
+            }
+            "AzureBlobTarget" => {
+                get_proxy::<AzureBlobTarget>(&client, namespace, name, |t| t.spec.proxy).await?
+            }
+            "WebDavTarget" => {
+                get_proxy::<WebDavTarget>(&client, namespace, name, |t| t.spec.proxy).await?
+            }
+            // Other kinds are only written to by the controller.
+            _ => continue,
+        };
+        if !proxies.contains(&proxy) {
+            proxies.push(proxy);
+        }
+    }
+    match proxies.len() {
+        0 => Ok(None),
+        1 => Ok(proxies.pop().unwrap()),
+        _ => Err(Error::UserInputError(format!(
+            "the targets of Target {} must all use the same storage proxy",
+            target_name
+        ))),
+    }
+}
+
+/// Returns the storage proxy of the named target, or None if it
+/// does not exist or has no storage proxy.
+async fn get_proxy<K>(
+    client: &Client,
+    namespace: &str,
+    name: &str,
+    proxy: fn(K) -> Option<ProxySpec>,
+) -> Result<Option<ProxySpec>, Error>
+where
+    K: Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Debug,
+    <K as Resource>::DynamicType: Default,
+{
+    let api: Api<K> = Api::namespaced(client.clone(), namespace);
+    Ok(api.get_opt(name).await?.and_then(proxy))
+}
@@ -0,0 +1,70 @@
+//! Entries listed by the query engines per
+//! [`DownloadSpec::engine`](ytdl_types::DownloadSpec::engine). Engines
+//! other than youtube-dl list videos without their full metadata, as
+//! `url` entries like those of `--flat-playlist`, which the download
+//! pods resolve before downloading them.
+use serde_json::{json, Value};
+use ytdl_types::{DownloadSpec, QueryEngineKind};
+
+/// Values of `_type` in the info json of an entry that only refers to
+/// the video.
+const URL_TYPES: &[&str] = &["url", "url_transparent"];
+
+/// Returns true if the info json is an entry that only refers to the
+/// video, which needs to be queried before it can be downloaded.
+pub fn is_unresolved(metadata: &Value) -> bool {
+    metadata
+        .get("_type")
+        .and_then(Value::as_str)
+        .map_or(false, |t| URL_TYPES.contains(&t))
+}
+
+/// Returns the URL of the video, preferring the page to the `url` of
+/// an entry, which may be a media URL once the video is resolved.
+pub fn get_video_url(metadata: &Value) -> Option<&str> {
+    metadata
+        .get("webpage_url")
+        .or_else(|| metadata.get("url"))
+        .and_then(Value::as_str)
+}
+
+/// Returns the info json of an entry with the ID that refers to the
+/// video at the URL. The title and Unix timestamp, if known, are
+/// included for the filters and the normalized metadata.
+pub fn url_entry(id: &str, url: &str, title: Option<&str>, timestamp: Option<i64>) -> Value {
+    let mut entry = json!({
+        "_type": "url",
+        "id": id,
+        "url": url,
+        "webpage_url": url,
+    });
+    if let Some(title) = title {
+        entry["title"] = json!(title);
+    }
+    if let Some(timestamp) = timestamp {
+        entry["timestamp"] = json!(timestamp);
+        if let Some(date) = chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0) {
+            entry["upload_date"] = json!(date.format("%Y%m%d").to_string());
+        }
+    }
+    entry
+}
+
+/// Returns the inputs of the Download whose hosts are subject to the
+/// policies, which are each of the URLs of a static input.
+pub fn get_inputs(spec: &DownloadSpec) -> Vec<&str> {
+    match spec.engine.unwrap_or_default() {
+        QueryEngineKind::Static => get_static_urls(&spec.input),
+        _ => vec![spec.input.as_str()],
+    }
+}
+
+/// Returns the video URLs of a static input, which has one per line.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn get_static_urls(input: &str) -> Vec<&str> {
+    input
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
@@ -0,0 +1,207 @@
+//! Redis servers, per [`RedisTargetSpec`](ytdl_types::RedisTargetSpec).
+//! The targets and their Secrets are resolved here, for the download
+//! pods, which write the keys, and for the archives kept in Redis.
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use redis::{ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
+use std::{collections::BTreeMap, time::Duration};
+use ytdl_types::{ContentType, RedisTarget, Target};
+
+use crate::{
+    sql::SSL_MODES,
+    template_key,
+    units::{parse_duration, parse_filesize},
+    Error, DEFAULT_TEMPLATE,
+};
+
+/// Keys in the Secret with the server and the user's credentials.
+pub const HOST_KEY: &str = "host";
+pub const PORT_KEY: &str = "port";
+pub const USERNAME_KEY: &str = "username";
+pub const PASSWORD_KEY: &str = "password";
+
+/// Key in the Secret with the number of the logical database.
+pub const DATABASE_KEY: &str = "database";
+
+/// Key in the Secret that is `"true"` to connect with TLS.
+pub const TLS_KEY: &str = "tls";
+
+/// Keys in the Secret that connected with TLS before `tls`, named as
+/// for SQL targets. Any `sslmode` but `disable` connects with TLS, and
+/// client certificates aren't supported.
+pub const SSL_MODE_KEY: &str = "sslmode";
+pub const SSL_CERT_KEY: &str = "sslcert";
+
+/// Port if the Secret doesn't have one.
+const DEFAULT_PORT: u16 = 6379;
+
+/// Largest file written to a key if the target doesn't set one.
+const DEFAULT_MAX_FILESIZE: u64 = 64 << 20;
+
+/// A Redis server and how the content is written to it.
+#[derive(Debug, Clone)]
+pub struct RedisServer {
+    /// Name of the RedisTarget, for logging.
+    pub name: String,
+
+    /// Address of the server and the user's credentials.
+    pub connection: ConnectionInfo,
+
+    /// Script run instead of `SET`, if any.
+    pub script: Option<String>,
+
+    /// Keys passed to the script after the output's key, rendered
+    /// from the metadata.
+    pub extra_keys: Vec<String>,
+
+    /// Expiry of the output's key, if any.
+    pub ttl: Option<Duration>,
+
+    /// Metadata json passed to the script as `ARGV[2]`, if the content
+    /// isn't the metadata itself.
+    pub metadata: Option<String>,
+
+    /// Largest file written to the key, in bytes.
+    pub max_filesize: u64,
+}
+
+/// A server and the key the content is written to.
+pub type RedisOutput = (RedisServer, String);
+
+/// Returns the Redis outputs of the content for the named Target, with
+/// the keys rendered from the metadata.
+pub async fn get_redis_outputs(
+    client: Client,
+    namespace: &str,
+    target_name: &str,
+    metadata: &serde_json::Value,
+    content: ContentType,
+) -> Result<Vec<RedisOutput>, Error> {
+    let api: Api<Target> = Api::namespaced(client.clone(), namespace);
+    let spec = match api.get_opt(target_name).await? {
+        Some(target) => target.spec,
+        None => return Ok(Vec::new()),
+    };
+    let refs = match content {
+        ContentType::Metadata => spec.metadata,
+        ContentType::Audiovisual => spec.audiovisual,
+        ContentType::Thumbnail => spec.thumbnail,
+    };
+    let targets: Api<RedisTarget> = Api::namespaced(client.clone(), namespace);
+    let mut outputs = Vec::new();
+    for target_ref in refs.unwrap_or_default() {
+        if target_ref.kind != "RedisTarget" {
+            continue;
+        }
+        let spec = targets.get(&target_ref.name).await?.spec;
+        let key = template_key(metadata, spec.key.as_deref().unwrap_or(DEFAULT_TEMPLATE))?;
+        let extra_keys = spec
+            .extra_keys
+            .iter()
+            .flatten()
+            .map(|template| template_key(metadata, template))
+            .collect::<Result<Vec<_>, _>>()?;
+        let server = RedisServer {
+            name: target_ref.name,
+            connection: get_connection_info(client.clone(), namespace, &spec.secret).await?,
+            script: spec.script,
+            extra_keys,
+            ttl: spec.ttl.as_deref().map(parse_duration).transpose()?,
+            metadata: match content {
+                ContentType::Metadata => None,
+                _ => Some(metadata.to_string()),
+            },
+            max_filesize: spec
+                .max_filesize
+                .as_deref()
+                .map(parse_filesize)
+                .transpose()?
+                .unwrap_or(DEFAULT_MAX_FILESIZE),
+        };
+        outputs.push((server, key));
+    }
+    Ok(outputs)
+}
+
+/// Connects to the server of the named RedisTarget.
+pub async fn get_redis_connection(
+    client: Client,
+    namespace: &str,
+    target: &str,
+) -> Result<redis::aio::Connection, Error> {
+    let target = Api::<RedisTarget>::namespaced(client.clone(), namespace)
+        .get(target)
+        .await?;
+    let info = get_connection_info(client, namespace, &target.spec.secret).await?;
+    Ok(redis::Client::open(info)?.get_async_connection().await?)
+}
+
+/// Returns the server and credentials described by the named Secret.
+/// The CA bundle of the target is trusted through `SSL_CERT_FILE`.
+pub async fn get_connection_info(
+    client: Client,
+    namespace: &str,
+    secret: &str,
+) -> Result<ConnectionInfo, Error> {
+    let mut fields: BTreeMap<String, String> = Api::<Secret>::namespaced(client, namespace)
+        .get(secret)
+        .await?
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, String::from_utf8(value.0).ok()?)))
+        .collect();
+    let invalid = |key: &str| {
+        Error::UserInputError(format!("Redis Secret {} has an invalid {}", secret, key))
+    };
+    let host = fields
+        .remove(HOST_KEY)
+        .map(|host| host.trim().to_owned())
+        .ok_or_else(|| {
+            Error::UserInputError(format!("Redis Secret {} has no {} field", secret, HOST_KEY))
+        })?;
+    let port = match fields.remove(PORT_KEY) {
+        Some(port) => port.trim().parse().map_err(|_| invalid(PORT_KEY))?,
+        None => DEFAULT_PORT,
+    };
+    let db = match fields.remove(DATABASE_KEY) {
+        Some(db) if !db.trim().is_empty() => {
+            db.trim().parse().map_err(|_| invalid(DATABASE_KEY))?
+        }
+        _ => 0,
+    };
+    if fields
+        .get(SSL_CERT_KEY)
+        .map_or(false, |cert| !cert.trim().is_empty())
+    {
+        return Err(Error::UserInputError(format!(
+            "Redis Secret {} has an {} field, but client certificates aren't supported",
+            secret, SSL_CERT_KEY
+        )));
+    }
+    let tls = match (fields.remove(TLS_KEY), fields.remove(SSL_MODE_KEY)) {
+        (Some(tls), _) => tls.trim() == "true",
+        (None, Some(ssl_mode)) if SSL_MODES.contains(&ssl_mode.trim()) => {
+            ssl_mode.trim() != "disable"
+        }
+        (None, Some(_)) => return Err(invalid(SSL_MODE_KEY)),
+        (None, None) => false,
+    };
+    let addr = if tls {
+        ConnectionAddr::TcpTls {
+            host,
+            port,
+            insecure: false,
+        }
+    } else {
+        ConnectionAddr::Tcp(host, port)
+    };
+    Ok(ConnectionInfo {
+        addr,
+        redis: RedisConnectionInfo {
+            db,
+            username: fields.remove(USERNAME_KEY).filter(|user| !user.is_empty()),
+            password: fields.remove(PASSWORD_KEY).filter(|pass| !pass.is_empty()),
+        },
+    })
+}
@@ -0,0 +1,266 @@
+//! Replication of a succeeded Download's objects to secondary S3
+//! targets. Objects are copied server-side when the primary and the
+//! replica share an endpoint, which saves streaming large videos through
+//! the controller, and every copy is read back to verify its checksum.
+use kube::{Api, Client, ResourceExt};
+use reqwest::header::{HeaderMap, HeaderValue};
+use s3::bucket::Bucket;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::AsyncWrite;
+use tracing::{info, warn};
+use ytdl_types::{Download, S3Target, S3TargetSpec, StoredObject, Target};
+
+use crate::{
+    get_s3_target_bucket, get_targets,
+    proxy::get_storage_http_client,
+    sse::{add_sse_headers, with_sse},
+    storage_class::{with_storage_class, STORAGE_CLASS_HEADER},
+    Error,
+};
+
+/// Header of a PUT request that makes it a server-side copy.
+const COPY_SOURCE_HEADER: &str = "x-amz-copy-source";
+
+/// Seconds the presigned copy request is valid for.
+const PRESIGN_EXPIRY_SECS: u32 = 60 * 5;
+
+/// Size of the buffer between the download and the upload of an
+/// object that is streamed through the controller.
+const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Copies the objects uploaded by the Download into each of its
+/// replica targets, verifying the checksum of every copy. Does
+/// nothing if the Download has no replication configured.
+pub async fn replicate(
+    client: Client,
+    instance: &Download,
+    objects: &[StoredObject],
+) -> Result<(), Error> {
+    let spec = match instance.spec.replication {
+        Some(ref spec) => spec,
+        None => return Ok(()),
+    };
+    let namespace = instance.namespace().unwrap();
+    let sources = get_source_buckets(client.clone(), instance).await?;
+    let s3_targets: Api<S3Target> = Api::namespaced(client.clone(), &namespace);
+    for name in &spec.targets {
+        let target = s3_targets.get(name).await?;
+        let replica = get_s3_target_bucket(client.clone(), &namespace, &target.spec).await?;
+        for object in objects {
+            let source = sources.get(&object.bucket).ok_or_else(|| {
+                Error::UserInputError(format!(
+                    "bucket {} of object {} is not one of the Download's S3 targets",
+                    object.bucket, object.key
+                ))
+            })?;
+            if is_same_endpoint(source, &replica) && source.name == replica.name {
+                // The replica is the primary, so there is nothing to copy.
+                continue;
+            }
+            copy_object(source, &replica, &object.key, &target.spec).await?;
+            verify_object(&replica, object).await?;
+            info!(
+                bucket = %replica.name,
+                key = %object.key,
+                "Replicated object"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the buckets of the S3Targets the Download stores
+/// audiovisual content and thumbnails in, keyed by their names.
+async fn get_source_buckets(
+    client: Client,
+    instance: &Download,
+) -> Result<HashMap<String, Bucket>, Error> {
+    let namespace = instance.namespace().unwrap();
+    let targets: Api<Target> = Api::namespaced(client.clone(), &namespace);
+    let s3_targets: Api<S3Target> = Api::namespaced(client.clone(), &namespace);
+    let mut buckets = HashMap::new();
+    for name in get_targets(instance) {
+        let target = match targets.get_opt(name).await? {
+            Some(target) => target,
+            None => continue,
+        };
+        let target_refs = target
+            .spec
+            .audiovisual
+            .unwrap_or_default()
+            .into_iter()
+            .chain(target.spec.thumbnail.unwrap_or_default());
+        for target_ref in target_refs {
+            if target_ref.kind != "S3Target" {
+                continue;
+            }
+            let s3_target = s3_targets.get(&target_ref.name).await?;
+            if !buckets.contains_key(&s3_target.spec.bucket) {
+                let bucket =
+                    get_s3_target_bucket(client.clone(), &namespace, &s3_target.spec).await?;
+                buckets.insert(s3_target.spec.bucket, bucket);
+            }
+        }
+    }
+    Ok(buckets)
+}
+
+/// Returns true if both buckets are served by the same endpoint.
+fn is_same_endpoint(a: &Bucket, b: &Bucket) -> bool {
+    a.region().endpoint() == b.region().endpoint()
+}
+
+/// Copies the object from the source to the replica under the same key.
+/// A server-side copy is attempted first if the endpoints match, which
+/// may still fail if the replica's credentials can't read the source.
+/// The copy is encrypted and stored in the storage class per the
+/// replica's target.
+async fn copy_object(
+    source: &Bucket,
+    replica: &Bucket,
+    key: &str,
+    spec: &S3TargetSpec,
+) -> Result<(), Error> {
+    if is_same_endpoint(source, replica) {
+        match server_side_copy(source, replica, key, spec).await {
+            Ok(()) => return Ok(()),
+            Err(e) => warn!(
+                error = %e,
+                key,
+                "Server-side copy failed, streaming the object instead"
+            ),
+        }
+    }
+    let replica = with_sse(replica, spec.sse.as_ref());
+    let replica = with_storage_class(&replica, spec.storage_class.as_deref());
+    stream_copy(source, &replica, key).await
+}
+
+/// Has the endpoint copy the object with a presigned `CopyObject` request.
+async fn server_side_copy(
+    source: &Bucket,
+    replica: &Bucket,
+    key: &str,
+    spec: &S3TargetSpec,
+) -> Result<(), Error> {
+    let copy_source = format!("/{}/{}", source.name, encode_key(key));
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        COPY_SOURCE_HEADER,
+        HeaderValue::from_str(&copy_source)
+            .map_err(|e| Error::UnknownError(format!("invalid copy source: {}", e)))?,
+    );
+    add_sse_headers(&mut headers, spec.sse.as_ref());
+    if let Some(ref storage_class) = spec.storage_class {
+        headers.insert(
+            STORAGE_CLASS_HEADER,
+            HeaderValue::from_str(storage_class)
+                .map_err(|e| Error::UnknownError(format!("invalid storage class: {}", e)))?,
+        );
+    }
+    let url = replica.presign_put(key, PRESIGN_EXPIRY_SECS, Some(headers.clone()))?;
+    let res = get_storage_http_client()?
+        .put(url)
+        .headers(headers)
+        .send()
+        .await?;
+    if !res.status().is_success() {
+        return Err(Error::S3UploadError {
+            status_code: res.status().as_u16(),
+        });
+    }
+    Ok(())
+}
+
+/// Streams the object from the source to the replica.
+async fn stream_copy(source: &Bucket, replica: &Bucket, key: &str) -> Result<(), Error> {
+    let (head, _) = source.head_object(key).await?;
+    let content_type = head
+        .content_type
+        .unwrap_or_else(|| "application/octet-stream".to_owned());
+    let (mut writer, mut reader) = tokio::io::duplex(COPY_BUFFER_SIZE);
+    let download = async move {
+        let result = source.get_object_stream(key, &mut writer).await;
+        // Closing the pipe ends the stream that is uploaded.
+        drop(writer);
+        result
+    };
+    let upload = replica.put_object_stream_with_content_type(&mut reader, key, &content_type);
+    let (download, upload) = tokio::join!(download, upload);
+    let status_code = download?;
+    if status_code != 200 {
+        return Err(Error::S3UploadError { status_code });
+    }
+    let status_code = upload?;
+    if status_code != 200 {
+        return Err(Error::S3UploadError { status_code });
+    }
+    Ok(())
+}
+
+/// Reads the replica back and compares its size and checksum to the
+/// ones recorded by the download pod.
+async fn verify_object(replica: &Bucket, object: &StoredObject) -> Result<(), Error> {
+    let mut writer = HashingWriter::default();
+    replica.get_object_stream(&object.key, &mut writer).await?;
+    let (size, sha256) = writer.finish();
+    if size != object.size || sha256 != object.sha256 {
+        return Err(Error::ChecksumMismatch {
+            bucket: replica.name.clone(),
+            key: object.key.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Percent-encodes the key for the copy source header, keeping
+/// the slashes that separate its segments.
+fn encode_key(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Discards the bytes written to it, keeping only their size and checksum.
+#[derive(Default)]
+struct HashingWriter {
+    hasher: Sha256,
+    size: u64,
+}
+
+impl HashingWriter {
+    /// Returns the size and hex-encoded checksum of the bytes written.
+    fn finish(self) -> (u64, String) {
+        (self.size, hex::encode(self.hasher.finalize()))
+    }
+}
+
+impl AsyncWrite for HashingWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.hasher.update(buf);
+        self.size += buf.len() as u64;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,211 @@
+//! Results of the culled Executors. Before the Download controller
+//! deletes succeeded Executors per [`DownloadSpec::cull`](ytdl_types::DownloadSpec::cull),
+//! it appends a json line for each of their videos to the Download's
+//! results, so that a record of what was downloaded outlives them.
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::{Api, ListParams, ObjectMeta, PostParams},
+    Client, Resource, ResourceExt,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use ytdl_types::{Download, S3Target, StoredObject};
+
+use crate::{get_s3_target_bucket, sse::with_sse, Error};
+
+/// Key in the results ConfigMaps for the results jsonl.
+pub const RESULTS_JSONL_KEY: &str = "results.jsonl";
+
+/// Label on the results ConfigMaps with the uid of their Download.
+pub const RESULTS_LABEL: &str = "ytdl.beebs.dev/results";
+
+/// Size of the results jsonl after which the results continue in a new
+/// ConfigMap, which leaves room under the 1 MiB limit for the rest.
+const MAX_CONFIGMAP_RESULTS_LEN: usize = 900 * 1024;
+
+/// What a single video's Executor downloaded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResultRecord {
+    /// Video ID from the info json.
+    pub id: String,
+
+    /// Name of the Executor that downloaded the video.
+    pub executor: String,
+
+    /// Objects the Executor uploaded. Videos batched into the same
+    /// Executor share its objects.
+    pub objects: Vec<StoredObject>,
+
+    /// RFC 3339 timestamp of when the Executor succeeded.
+    #[serde(rename = "finishedAt")]
+    pub finished_at: String,
+}
+
+/// Appends the records to the Download's results. Videos that are
+/// already in the most recent results are skipped, so that an append
+/// that is retried doesn't record them twice. Does nothing if the
+/// Download does not have results.
+pub async fn append_results(
+    client: Client,
+    instance: &Download,
+    records: &[ResultRecord],
+) -> Result<(), Error> {
+    let spec = match instance.spec.results {
+        Some(ref spec) => spec,
+        None => return Ok(()),
+    };
+    if records.is_empty() {
+        return Ok(());
+    }
+    let namespace = instance.namespace().unwrap();
+    match (&spec.config_map, &spec.s3) {
+        (Some(name), None) => append_configmap(client, instance, &namespace, name, records).await,
+        (None, Some(s3)) => {
+            let target = Api::<S3Target>::namespaced(client.clone(), &namespace)
+                .get(&s3.target)
+                .await?;
+            let bucket = get_s3_target_bucket(client, &namespace, &target.spec).await?;
+            // S3 objects cannot be appended to, so the whole object is
+            // rewritten. Only the Download controller writes to it.
+            let res = bucket.get_object(&s3.key).await?;
+            let mut results = if res.status_code() == 404 {
+                String::new()
+            } else {
+                std::str::from_utf8(res.bytes())?.to_owned()
+            };
+            let lines = to_new_lines(&results, records)?;
+            if lines.is_empty() {
+                return Ok(());
+            }
+            append_lines(&mut results, &lines);
+            let res = with_sse(&bucket, target.spec.sse.as_ref())
+                .put_object(&s3.key, results.as_bytes())
+                .await?;
+            if res.status_code() != 200 {
+                return Err(Error::S3UploadError {
+                    status_code: res.status_code(),
+                });
+            }
+            Ok(())
+        }
+        _ => Err(Error::UserInputError(
+            "results must specify exactly one of configMap or s3".to_owned(),
+        )),
+    }
+}
+
+/// Appends the records to the most recent results ConfigMap, starting
+/// a new one if there is none yet or it is nearly full.
+async fn append_configmap(
+    client: Client,
+    instance: &Download,
+    namespace: &str,
+    name: &str,
+    records: &[ResultRecord],
+) -> Result<(), Error> {
+    let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let selector = format!("{}={}", RESULTS_LABEL, instance.uid().unwrap());
+    let current = api
+        .list(&ListParams::default().labels(&selector))
+        .await?
+        .items
+        .into_iter()
+        .filter_map(|cm| Some((get_chunk_index(name, &cm.name_any())?, cm)))
+        .max_by_key(|(index, _)| *index);
+    let (index, mut cm) = match current {
+        Some(current) => current,
+        None => {
+            let lines = to_new_lines("", records)?;
+            let cm = new_results_configmap(instance, namespace, name, 0, lines);
+            api.create(&PostParams::default(), &cm).await?;
+            return Ok(());
+        }
+    };
+    let results = cm
+        .data
+        .get_or_insert_with(BTreeMap::new)
+        .entry(RESULTS_JSONL_KEY.to_owned())
+        .or_default();
+    let lines = to_new_lines(results, records)?;
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let len: usize = lines.iter().map(|line| line.len() + 1).sum();
+    if !results.is_empty() && results.len() + len > MAX_CONFIGMAP_RESULTS_LEN {
+        let cm = new_results_configmap(instance, namespace, name, index + 1, lines);
+        api.create(&PostParams::default(), &cm).await?;
+        return Ok(());
+    }
+    append_lines(results, &lines);
+    // The resourceVersion is retained for optimistic concurrency.
+    api.replace(&cm.name_any(), &PostParams::default(), &cm)
+        .await?;
+    Ok(())
+}
+
+/// Returns the results ConfigMap with the given index, which is owned
+/// by the Download so that it is garbage collected along with it.
+fn new_results_configmap(
+    instance: &Download,
+    namespace: &str,
+    name: &str,
+    index: usize,
+    lines: Vec<String>,
+) -> ConfigMap {
+    let name = match index {
+        0 => name.to_owned(),
+        index => format!("{}-{}", name, index),
+    };
+    ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(name),
+            namespace: Some(namespace.to_owned()),
+            labels: Some(BTreeMap::from([(
+                RESULTS_LABEL.to_owned(),
+                instance.uid().unwrap(),
+            )])),
+            owner_references: Some(vec![instance.controller_owner_ref(&()).unwrap()]),
+            ..ObjectMeta::default()
+        },
+        data: Some(BTreeMap::from([(
+            RESULTS_JSONL_KEY.to_owned(),
+            lines.join("\n"),
+        )])),
+        ..ConfigMap::default()
+    }
+}
+
+/// Returns the index of the results ConfigMap with the given name,
+/// which is the configured name for the first one and has a numeric
+/// suffix for the rest.
+fn get_chunk_index(name: &str, cm_name: &str) -> Option<usize> {
+    if cm_name == name {
+        return Some(0);
+    }
+    cm_name.strip_prefix(name)?.strip_prefix('-')?.parse().ok()
+}
+
+/// Returns the json lines of the records whose videos are not already
+/// in the results.
+fn to_new_lines(results: &str, records: &[ResultRecord]) -> Result<Vec<String>, Error> {
+    let existing: HashSet<String> = results
+        .split('\n')
+        .filter_map(|line| serde_json::from_str::<ResultRecord>(line).ok())
+        .map(|record| record.id)
+        .collect();
+    records
+        .iter()
+        .filter(|record| !existing.contains(&record.id))
+        .map(|record| Ok(serde_json::to_string(record)?))
+        .collect()
+}
+
+/// Appends the lines to the results jsonl.
+fn append_lines(results: &mut String, lines: &[String]) {
+    for line in lines {
+        if !results.is_empty() && !results.ends_with('\n') {
+            results.push('\n');
+        }
+        results.push_str(line);
+    }
+}
@@ -0,0 +1,201 @@
+//! SFTP servers, per [`SftpTargetSpec`](ytdl_types::SftpTargetSpec).
+//! libssh2 is blocking, so sessions are only used on tokio's blocking
+//! threads. Every lookup or upload opens its own connection, as the
+//! controllers and download pods make few requests to each server.
+use base64::{engine::general_purpose::STANDARD_NO_PAD as BASE64, Engine};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use ssh2::{ErrorCode, HashType, Session, Sftp};
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use ytdl_types::SftpTargetSpec;
+
+use crate::Error;
+
+/// Key in the Secret with the user's OpenSSH private key.
+pub const PRIVATE_KEY_KEY: &str = "ssh-privatekey";
+
+/// Key in the Secret with the user's password.
+pub const PASSWORD_KEY: &str = "password";
+
+/// Port of the server if the spec doesn't set one.
+pub const DEFAULT_PORT: u16 = 22;
+
+/// Prefix of the host key fingerprints printed by `ssh-keygen -l`.
+pub const FINGERPRINT_PREFIX: &str = "SHA256:";
+
+/// Timeout of the connection and of each request on it.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// SFTP status code of a file that does not exist.
+pub const SSH_FX_NO_SUCH_FILE: i32 = 2;
+
+/// A client for a single server. Clones share the credentials.
+#[derive(Clone)]
+pub struct SftpServer {
+    /// Name of the SftpTarget, which stands in for the bucket of the
+    /// files uploaded to it.
+    pub name: String,
+    host: String,
+    port: u16,
+    user: String,
+    credentials: Arc<Credentials>,
+    /// Expected fingerprint of the host key, without the prefix, or
+    /// None if the host key is not verified.
+    fingerprint: Option<String>,
+}
+
+/// The user's credentials from the target's Secret.
+struct Credentials {
+    private_key: Option<String>,
+    password: Option<String>,
+}
+
+impl SftpServer {
+    /// Connects and logs in to the server, verifying its host key, and
+    /// returns the SFTP session. This blocks the thread.
+    pub fn connect(&self) -> Result<Sftp, Error> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| {
+                Error::UserInputError(format!("SFTP host {} has no address", self.host))
+            })?;
+        let tcp = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.set_timeout(TIMEOUT.as_millis() as u32);
+        session.handshake()?;
+        self.verify_host_key(&session)?;
+        self.authenticate(&session)?;
+        Ok(session.sftp()?)
+    }
+
+    /// Returns the size of the file, or None if it does not exist.
+    pub async fn head_object(&self, path: &str) -> Result<Option<u64>, Error> {
+        let server = self.clone();
+        let path = path.to_owned();
+        blocking(move || {
+            let sftp = server.connect()?;
+            match sftp.stat(Path::new(&path)) {
+                Ok(stat) => Ok(Some(stat.size.unwrap_or(0))),
+                Err(e) if e.code() == ErrorCode::SFTP(SSH_FX_NO_SUCH_FILE) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+        .await
+    }
+
+    /// Refuses a server whose host key is not the configured one.
+    fn verify_host_key(&self, session: &Session) -> Result<(), Error> {
+        let expected = match self.fingerprint {
+            Some(ref fingerprint) => fingerprint,
+            None => return Ok(()),
+        };
+        let fingerprint = session
+            .host_key_hash(HashType::Sha256)
+            .map(|hash| BASE64.encode(hash))
+            .ok_or_else(|| {
+                Error::UnknownError(format!("SFTP host {} sent no host key", self.host))
+            })?;
+        if fingerprint != *expected {
+            return Err(Error::HostKeyMismatch {
+                host: self.host.clone(),
+                fingerprint: format!("{}{}", FINGERPRINT_PREFIX, fingerprint),
+            });
+        }
+        Ok(())
+    }
+
+    /// Logs in with the private key, then the password if the key
+    /// is missing or rejected.
+    fn authenticate(&self, session: &Session) -> Result<(), Error> {
+        let mut result = Err(Error::UserInputError(format!(
+            "SFTP credentials need a {} or {} field",
+            PRIVATE_KEY_KEY, PASSWORD_KEY
+        )));
+        if let Some(ref private_key) = self.credentials.private_key {
+            result = session
+                .userauth_pubkey_memory(&self.user, None, private_key, None)
+                .map_err(Error::from);
+        }
+        if let Some(ref password) = self.credentials.password {
+            if !session.authenticated() {
+                result = session
+                    .userauth_password(&self.user, password)
+                    .map_err(Error::from);
+            }
+        }
+        result
+    }
+}
+
+/// Returns the client for the server described by the SftpTarget spec.
+pub async fn get_sftp_server(
+    client: Client,
+    namespace: &str,
+    name: &str,
+    spec: &SftpTargetSpec,
+) -> Result<SftpServer, Error> {
+    let secret = Api::<Secret>::namespaced(client, namespace)
+        .get(&spec.secret)
+        .await?;
+    let mut data = secret.data.unwrap_or_default();
+    let mut field = |key: &str| {
+        data.remove(key)
+            .and_then(|value| String::from_utf8(value.0).ok())
+    };
+    let credentials = Credentials {
+        private_key: field(PRIVATE_KEY_KEY),
+        password: field(PASSWORD_KEY),
+    };
+    let fingerprint = match spec.host_key_fingerprint {
+        Some(ref fingerprint) => Some(parse_fingerprint(fingerprint)?),
+        // Validation requires the fingerprint unless this is set.
+        None if spec.insecure_skip_host_key_verification.unwrap_or(false) => None,
+        None => {
+            return Err(Error::UserInputError(
+                "SFTP host key fingerprint is required".to_owned(),
+            ))
+        }
+    };
+    Ok(SftpServer {
+        name: name.to_owned(),
+        host: spec.host.clone(),
+        port: spec.port.unwrap_or(DEFAULT_PORT),
+        user: spec.user.clone(),
+        credentials: Arc::new(credentials),
+        fingerprint,
+    })
+}
+
+/// Returns the base64 hash of a fingerprint as printed by `ssh-keygen -l`,
+/// without the padding some tools add.
+pub fn parse_fingerprint(fingerprint: &str) -> Result<String, Error> {
+    let hash = fingerprint
+        .strip_prefix(FINGERPRINT_PREFIX)
+        .map(|hash| hash.trim_end_matches('='))
+        .filter(|hash| matches!(BASE64.decode(hash), Ok(hash) if hash.len() == 32))
+        .ok_or_else(|| {
+            Error::UserInputError(format!(
+                "host key fingerprint must be {} followed by a base64 SHA-256 hash",
+                FINGERPRINT_PREFIX
+            ))
+        })?;
+    Ok(hash.to_owned())
+}
+
+/// Runs the blocking function on tokio's blocking threads.
+pub async fn blocking<T, F>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| Error::UnknownError(format!("SFTP task failed: {}", e)))?
+}
@@ -0,0 +1,85 @@
+use kube::Resource;
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// Annotation on the Executor through which the download pod reports
+/// the entities of its batch that it skipped per policy, as a json
+/// array of [`SkipRecord`]. The rest of the batch is still downloaded.
+pub const SKIPPED_ANNOTATION: &str = "ytdl.beebs.dev/skipped";
+
+/// Key in the metadata ConfigMap for the skipped entities jsonl.
+/// Each line is a json-encoded [`SkipRecord`] so that downstream
+/// consumers know which videos are intentionally missing from the
+/// archive, as opposed to having failed.
+pub const SKIPPED_JSONL_KEY: &str = "skipped.jsonl";
+
+/// Policy name for entities excluded by [`DownloadSpec::max_filesize`](ytdl_types::DownloadSpec::max_filesize).
+pub const MAX_FILESIZE_POLICY: &str = "maxFilesize";
+
+/// Policy name for entities excluded by [`DownloadSpec::max_duration`](ytdl_types::DownloadSpec::max_duration).
+pub const MAX_DURATION_POLICY: &str = "maxDuration";
+
+/// Policy name for entities excluded by [`DownloadSpec::date_after`](ytdl_types::DownloadSpec::date_after).
+pub const DATE_AFTER_POLICY: &str = "dateAfter";
+
+/// Policy name for entities excluded by [`DownloadSpec::date_before`](ytdl_types::DownloadSpec::date_before).
+pub const DATE_BEFORE_POLICY: &str = "dateBefore";
+
+/// Policy name for entities excluded by [`DownloadSpec::match_filter`](ytdl_types::DownloadSpec::match_filter).
+pub const MATCH_FILTER_POLICY: &str = "matchFilter";
+
+/// Policy name for entities skipped per [`DownloadSpec::age_restricted`](ytdl_types::DownloadSpec::age_restricted).
+pub const AGE_RESTRICTED_POLICY: &str = "ageRestricted";
+
+/// Policy name for entities skipped per [`DownloadSpec::geo_blocked`](ytdl_types::DownloadSpec::geo_blocked).
+pub const GEO_BLOCKED_POLICY: &str = "geoBlocked";
+
+/// Policy name for entities skipped per [`DownloadSpec::upcoming`](ytdl_types::DownloadSpec::upcoming).
+pub const UPCOMING_POLICY: &str = "upcoming";
+
+/// A single entity that was intentionally not downloaded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SkipRecord {
+    /// Video ID from the info json.
+    pub id: String,
+
+    /// Name of the [`DownloadSpec`](ytdl_types::DownloadSpec) field
+    /// responsible for the skip, e.g. `"maxDuration"`.
+    pub policy: String,
+
+    /// Human-readable explanation of why the entity was skipped.
+    pub reason: String,
+
+    /// RFC 3339 timestamp of when the entity was skipped.
+    pub timestamp: String,
+}
+
+/// Parses the skip records from the skipped jsonl. Lines that fail
+/// to parse are ignored.
+pub fn parse_skip_records(skipped_jsonl: &str) -> Vec<SkipRecord> {
+    skipped_jsonl
+        .split('\n')
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Returns the entities the download pods skipped per policy.
+pub fn get_skipped_entities<K: Resource>(instance: &K) -> Vec<SkipRecord> {
+    instance
+        .meta()
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(SKIPPED_ANNOTATION))
+        .and_then(|value| serde_json::from_str(value).ok())
+        .unwrap_or_default()
+}
+
+/// Encodes the skip records as jsonl.
+pub fn to_skipped_jsonl(records: &[SkipRecord]) -> Result<String, Error> {
+    let lines = records
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lines.join("\n"))
+}
@@ -0,0 +1,158 @@
+//! SQL databases, per [`SqlTargetSpec`](ytdl_types::SqlTargetSpec).
+//! Like [`kafka`](crate::kafka), the targets and their Secrets are
+//! resolved here, so that only the download pods, which write the
+//! metadata, link a database driver.
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use std::collections::BTreeMap;
+use ytdl_types::{SqlTarget, Target};
+
+use crate::Error;
+
+/// Keys in the Secret with the connection parameters.
+pub const USERNAME_KEY: &str = "username";
+pub const PASSWORD_KEY: &str = "password";
+pub const HOST_KEY: &str = "host";
+pub const PORT_KEY: &str = "port";
+pub const DATABASE_KEY: &str = "database";
+pub const SSL_MODE_KEY: &str = "sslmode";
+
+/// Keys in the Secret with the PEM-encoded client certificate and key.
+pub const SSL_CERT_KEY: &str = "sslcert";
+pub const SSL_KEY_KEY: &str = "sslkey";
+
+/// Values accepted for the `sslmode` field, as in libpq.
+pub const SSL_MODES: &[&str] = &[
+    "disable",
+    "allow",
+    "prefer",
+    "require",
+    "verify-ca",
+    "verify-full",
+];
+
+/// Port if the Secret doesn't have one.
+const DEFAULT_PORT: u16 = 5432;
+
+/// SSL mode if the Secret doesn't have one.
+const DEFAULT_SSL_MODE: &str = "prefer";
+
+/// A PostgreSQL database.
+#[derive(Debug, Clone)]
+pub struct SqlDatabase {
+    /// Name of the SqlTarget, for logging.
+    pub name: String,
+
+    /// Hostname of the server.
+    pub host: String,
+
+    /// Port of the server.
+    pub port: u16,
+
+    /// Name of the database.
+    pub database: String,
+
+    /// Credentials of the database user.
+    pub username: String,
+    pub password: String,
+
+    /// One of [`SSL_MODES`].
+    pub ssl_mode: String,
+
+    /// PEM-encoded client certificate and key, if the server
+    /// authenticates clients by certificate.
+    pub ssl_client: Option<(String, String)>,
+}
+
+/// Returns the SQL databases that the metadata is written to for the
+/// named Target.
+pub async fn get_sql_outputs(
+    client: Client,
+    namespace: &str,
+    target_name: &str,
+) -> Result<Vec<SqlDatabase>, Error> {
+    let api: Api<Target> = Api::namespaced(client.clone(), namespace);
+    let refs = match api.get_opt(target_name).await? {
+        Some(target) => target.spec.metadata.unwrap_or_default(),
+        None => return Ok(Vec::new()),
+    };
+    let targets: Api<SqlTarget> = Api::namespaced(client.clone(), namespace);
+    let mut outputs = Vec::new();
+    for target_ref in refs {
+        if target_ref.kind != "SqlTarget" {
+            continue;
+        }
+        let spec = targets.get(&target_ref.name).await?.spec;
+        let database =
+            get_database(client.clone(), namespace, &spec.secret, target_ref.name).await?;
+        outputs.push(database);
+    }
+    Ok(outputs)
+}
+
+/// Returns the database described by the named Secret.
+async fn get_database(
+    client: Client,
+    namespace: &str,
+    secret: &str,
+    name: String,
+) -> Result<SqlDatabase, Error> {
+    let mut fields: BTreeMap<String, String> = Api::<Secret>::namespaced(client, namespace)
+        .get(secret)
+        .await?
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, String::from_utf8(value.0).ok()?)))
+        .collect();
+    let port = match fields.remove(PORT_KEY) {
+        Some(port) => port.trim().parse().map_err(|_| {
+            Error::UserInputError(format!("SQL Secret {} has an invalid {}", secret, PORT_KEY))
+        })?,
+        None => DEFAULT_PORT,
+    };
+    let ssl_mode = match fields.remove(SSL_MODE_KEY) {
+        Some(ssl_mode) if SSL_MODES.contains(&ssl_mode.trim()) => ssl_mode.trim().to_owned(),
+        Some(ssl_mode) => {
+            return Err(Error::UserInputError(format!(
+                "SQL Secret {} has {} {}, expected one of {}",
+                secret,
+                SSL_MODE_KEY,
+                ssl_mode,
+                SSL_MODES.join(", ")
+            )))
+        }
+        None => DEFAULT_SSL_MODE.to_owned(),
+    };
+    let ssl_client = match (fields.remove(SSL_CERT_KEY), fields.remove(SSL_KEY_KEY)) {
+        (Some(cert), Some(key)) => Some((cert, key)),
+        (None, None) => None,
+        _ => {
+            return Err(Error::UserInputError(format!(
+                "SQL Secret {} needs both {} and {}, or neither",
+                secret, SSL_CERT_KEY, SSL_KEY_KEY
+            )))
+        }
+    };
+    Ok(SqlDatabase {
+        name,
+        host: take_field(&mut fields, secret, HOST_KEY)?.trim().to_owned(),
+        port,
+        database: take_field(&mut fields, secret, DATABASE_KEY)?,
+        username: take_field(&mut fields, secret, USERNAME_KEY)?,
+        password: take_field(&mut fields, secret, PASSWORD_KEY)?,
+        ssl_mode,
+        ssl_client,
+    })
+}
+
+/// Removes the field from the Secret's fields, which is required.
+fn take_field(
+    fields: &mut BTreeMap<String, String>,
+    secret: &str,
+    key: &str,
+) -> Result<String, Error> {
+    fields
+        .remove(key)
+        .ok_or_else(|| Error::UserInputError(format!("SQL Secret {} has no {} field", secret, key)))
+}
@@ -0,0 +1,64 @@
+//! Server-side encryption of uploaded objects, per
+//! [`S3TargetSpec::sse`](ytdl_types::S3TargetSpec::sse). The encryption
+//! headers are only valid on the requests that create an object, as S3
+//! rejects them on reads of SSE-S3 and SSE-KMS objects, so they are added
+//! to a copy of the bucket that is only used for uploads.
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use s3::bucket::Bucket;
+use ytdl_types::{SseAlgorithm, SseSpec};
+
+use crate::FieldError;
+
+/// Header with the encryption algorithm.
+const SSE_HEADER: &str = "x-amz-server-side-encryption";
+
+/// Header with the KMS key to encrypt with.
+const SSE_KMS_KEY_ID_HEADER: &str = "x-amz-server-side-encryption-aws-kms-key-id";
+
+/// Returns the headers that request the encryption.
+pub fn get_sse_headers(spec: &SseSpec) -> Vec<(&'static str, String)> {
+    let algorithm = match spec.algorithm {
+        SseAlgorithm::Aes256 => "AES256",
+        SseAlgorithm::AwsKms => "aws:kms",
+    };
+    let mut headers = vec![(SSE_HEADER, algorithm.to_owned())];
+    if let Some(ref key_id) = spec.kms_key_id {
+        headers.push((SSE_KMS_KEY_ID_HEADER, key_id.clone()));
+    }
+    headers
+}
+
+/// Returns a copy of the bucket that requests the encryption for
+/// the objects it uploads, or a plain copy if there is no spec.
+pub fn with_sse(bucket: &Bucket, spec: Option<&SseSpec>) -> Bucket {
+    let mut bucket = bucket.clone();
+    for (name, value) in spec.map(get_sse_headers).unwrap_or_default() {
+        bucket.add_header(name, &value);
+    }
+    bucket
+}
+
+/// Adds the headers that request the encryption to the header map.
+pub fn add_sse_headers(headers: &mut HeaderMap, spec: Option<&SseSpec>) {
+    for (name, value) in spec.map(get_sse_headers).unwrap_or_default() {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+/// Returns the problems with the encryption settings of the S3Target.
+pub fn validate_sse(spec: &SseSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    match spec.kms_key_id {
+        Some(_) if spec.algorithm != SseAlgorithm::AwsKms => errors.push(FieldError::new(
+            "sse.kmsKeyId",
+            "only applies to the aws:kms algorithm",
+        )),
+        Some(ref key_id) if key_id.trim().is_empty() => {
+            errors.push(FieldError::new("sse.kmsKeyId", "must not be empty"))
+        }
+        _ => {}
+    }
+    errors
+}
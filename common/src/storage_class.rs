@@ -0,0 +1,53 @@
+//! Storage class of uploaded objects, per
+//! [`S3TargetSpec::storage_class`](ytdl_types::S3TargetSpec::storage_class),
+//! so that e.g. videos go to cold storage while metadata stays in the
+//! standard class. Like the encryption headers, the storage class is
+//! only sent with the requests that create objects.
+use s3::bucket::Bucket;
+
+use crate::FieldError;
+
+/// Header with the storage class of the uploaded object.
+pub const STORAGE_CLASS_HEADER: &str = "x-amz-storage-class";
+
+/// Storage classes accepted by Amazon S3. Other S3-compatible backends
+/// define their own, so any upper case name is accepted.
+pub const STORAGE_CLASSES: &[&str] = &[
+    "STANDARD",
+    "REDUCED_REDUNDANCY",
+    "STANDARD_IA",
+    "ONEZONE_IA",
+    "INTELLIGENT_TIERING",
+    "GLACIER",
+    "GLACIER_IR",
+    "DEEP_ARCHIVE",
+    "OUTPOSTS",
+];
+
+/// Returns a copy of the bucket that uploads objects in the storage
+/// class, or a plain copy if there is none.
+pub fn with_storage_class(bucket: &Bucket, storage_class: Option<&str>) -> Bucket {
+    let mut bucket = bucket.clone();
+    if let Some(storage_class) = storage_class {
+        bucket.add_header(STORAGE_CLASS_HEADER, storage_class);
+    }
+    bucket
+}
+
+/// Returns the problems with the storage class of the S3Target.
+pub fn validate_storage_class(storage_class: &str) -> Vec<FieldError> {
+    let valid = !storage_class.is_empty()
+        && storage_class
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    if valid {
+        return Vec::new();
+    }
+    vec![FieldError::new(
+        "storageClass",
+        format!(
+            "must be an upper case storage class name such as {}",
+            STORAGE_CLASSES.join(", ")
+        ),
+    )]
+}
@@ -0,0 +1,157 @@
+//! Object stores that rust-s3 can't talk to, i.e. GCS, Azure Blob
+//! Storage, SFTP servers, and WebDAV servers. Their outputs are resolved from the
+//! Executor's Target like the S3 outputs, and the download pods upload
+//! to them from files.
+use kube::{Api, Client};
+use std::collections::BTreeMap;
+use ytdl_types::{AzureBlobTarget, ContentType, GcsTarget, SftpTarget, Target, WebDavTarget};
+
+use crate::{
+    azure::{get_azure_container, AzureContainer},
+    gcs::{get_gcs_bucket, GcsBucket},
+    sftp::{get_sftp_server, SftpServer},
+    template_key,
+    webdav::{get_webdav_server, WebDavServer},
+    Error, DEFAULT_TEMPLATE,
+};
+
+/// A GCS bucket, Azure container, SFTP server, or WebDAV server.
+#[derive(Clone)]
+pub enum Store {
+    Gcs(GcsBucket),
+    Azure(AzureContainer),
+    Sftp(SftpServer),
+    WebDav(WebDavServer),
+}
+
+/// A store and the object name the content is uploaded as, which is
+/// the counterpart of [`Output`](crate::Output).
+pub type StoreOutput = (Store, String);
+
+/// The size and user metadata of an object in a store.
+#[derive(Debug, Clone)]
+pub struct StoreObject {
+    /// Size of the object in bytes.
+    pub size: u64,
+
+    /// User metadata of the object. Files on an SFTP or WebDAV server
+    /// have none.
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Store {
+    /// Returns the name of the bucket or container, or of the
+    /// SftpTarget or WebDavTarget for a server.
+    pub fn name(&self) -> &str {
+        match self {
+            Store::Gcs(bucket) => &bucket.name,
+            Store::Azure(container) => &container.name,
+            Store::Sftp(server) => &server.name,
+            Store::WebDav(server) => &server.name,
+        }
+    }
+
+    /// Returns the name of the service, for logging.
+    pub fn service(&self) -> &'static str {
+        match self {
+            Store::Gcs(_) => "GCS",
+            Store::Azure(_) => "Azure",
+            Store::Sftp(_) => "SFTP",
+            Store::WebDav(_) => "WebDAV",
+        }
+    }
+
+    /// Returns the object, or None if it does not exist.
+    pub async fn head_object(&self, key: &str) -> Result<Option<StoreObject>, Error> {
+        Ok(match self {
+            Store::Gcs(bucket) => bucket.head_object(key).await?.map(|object| StoreObject {
+                size: object.size.parse().unwrap_or(0),
+                metadata: object.metadata.unwrap_or_default(),
+            }),
+            Store::Azure(container) => container.head_object(key).await?.map(|blob| StoreObject {
+                size: blob.size,
+                metadata: blob.metadata,
+            }),
+            Store::Sftp(server) => server.head_object(key).await?.map(|size| StoreObject {
+                size,
+                metadata: BTreeMap::new(),
+            }),
+            Store::WebDav(server) => server.head_object(key).await?.map(|size| StoreObject {
+                size,
+                metadata: BTreeMap::new(),
+            }),
+        })
+    }
+}
+
+/// Returns the GCS, Azure, SFTP, and WebDAV outputs of the content for the named
+/// Target, with the object names rendered from the metadata.
+pub async fn get_store_outputs(
+    client: Client,
+    namespace: &str,
+    target_name: &str,
+    metadata: &serde_json::Value,
+    content: ContentType,
+) -> Result<Vec<StoreOutput>, Error> {
+    let api: Api<Target> = Api::namespaced(client.clone(), namespace);
+    let spec = match api.get_opt(target_name).await? {
+        Some(target) => target.spec,
+        None => return Ok(Vec::new()),
+    };
+    let refs = match content {
+        ContentType::Metadata => spec.metadata,
+        ContentType::Audiovisual => spec.audiovisual,
+        ContentType::Thumbnail => spec.thumbnail,
+    };
+    let mut outputs = Vec::new();
+    for target_ref in refs.unwrap_or_default() {
+        let (store, template) = match target_ref.kind.as_str() {
+            "GcsTarget" => {
+                let api: Api<GcsTarget> = Api::namespaced(client.clone(), namespace);
+                let spec = api.get(&target_ref.name).await?.spec;
+                let bucket = get_gcs_bucket(client.clone(), namespace, &spec).await?;
+                (Store::Gcs(bucket), spec.key)
+            }
+            "AzureBlobTarget" => {
+                let api: Api<AzureBlobTarget> = Api::namespaced(client.clone(), namespace);
+                let spec = api.get(&target_ref.name).await?.spec;
+                let container = get_azure_container(client.clone(), namespace, &spec).await?;
+                (Store::Azure(container), spec.key)
+            }
+            "SftpTarget" => {
+                let api: Api<SftpTarget> = Api::namespaced(client.clone(), namespace);
+                let spec = api.get(&target_ref.name).await?.spec;
+                let server =
+                    get_sftp_server(client.clone(), namespace, &target_ref.name, &spec).await?;
+                (Store::Sftp(server), spec.path)
+            }
+            "WebDavTarget" => {
+                let api: Api<WebDavTarget> = Api::namespaced(client.clone(), namespace);
+                let spec = api.get(&target_ref.name).await?.spec;
+                let server =
+                    get_webdav_server(client.clone(), namespace, &target_ref.name, &spec).await?;
+                (Store::WebDav(server), spec.path)
+            }
+            _ => continue,
+        };
+        let template = template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+        outputs.push((store, template_key(metadata, template)?));
+    }
+    Ok(outputs)
+}
+
+/// Returns true if the request may succeed when sent again.
+pub fn is_transient(e: &Error) -> bool {
+    match e {
+        Error::GcsError { status_code, .. }
+        | Error::AzureError { status_code, .. }
+        | Error::WebDavError { status_code, .. } => {
+            *status_code >= 500 || *status_code == 429 || *status_code == 408
+        }
+        Error::ReqwestError { source } => {
+            source.is_timeout() || source.is_connect() || source.is_request()
+        }
+        Error::IOError { .. } => true,
+        _ => false,
+    }
+}
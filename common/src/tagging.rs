@@ -0,0 +1,88 @@
+//! Object tags, per [`S3TargetSpec::tags`](ytdl_types::S3TargetSpec::tags),
+//! for tag-based lifecycle policies and cost allocation. Tag values are
+//! key templates, so e.g. `channel=%(channel_id)s` tags each object with
+//! the channel it came from. Unlike the other object settings, tags are
+//! applied with PutObjectTagging once the object is uploaded.
+use s3::bucket::Bucket;
+use std::collections::BTreeMap;
+
+use crate::{template_key, validate::validate_template, Error, FieldError};
+
+/// Maximum number of tags S3 allows on an object.
+pub const MAX_TAGS: usize = 10;
+
+/// Maximum length in characters of a tag key.
+const MAX_KEY_LEN: usize = 128;
+
+/// Maximum length in characters of a tag value.
+const MAX_VALUE_LEN: usize = 256;
+
+/// Prefix of the tag keys reserved by AWS.
+const RESERVED_PREFIX: &str = "aws:";
+
+/// Returns the tags with their values rendered from the metadata.
+/// Characters S3 does not allow in tags, which the metadata may well
+/// contain, are replaced, and overlong values are truncated.
+pub fn render_tags(
+    metadata: &serde_json::Value,
+    tags: &BTreeMap<String, String>,
+) -> Result<Vec<(String, String)>, Error> {
+    tags.iter()
+        .map(|(key, template)| {
+            let value = template_key(metadata, template)?
+                .chars()
+                .map(|c| if is_tag_char(c) { c } else { '_' })
+                .take(MAX_VALUE_LEN)
+                .collect();
+            Ok((key.clone(), value))
+        })
+        .collect()
+}
+
+/// Replaces the tags of the object with the given ones.
+pub async fn put_tags(bucket: &Bucket, key: &str, tags: &[(String, String)]) -> Result<(), Error> {
+    let response = bucket.put_object_tagging(key, tags).await?;
+    match response.status_code() {
+        200 => Ok(()),
+        status_code => Err(Error::S3UploadError { status_code }),
+    }
+}
+
+/// Returns the problems with the tags of the S3Target.
+pub fn validate_tags(tags: &BTreeMap<String, String>) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if tags.len() > MAX_TAGS {
+        errors.push(FieldError::new(
+            "tags",
+            format!("must have at most {} tags", MAX_TAGS),
+        ));
+    }
+    for (key, template) in tags {
+        let field = format!("tags.{}", key);
+        if key.is_empty() || key.chars().count() > MAX_KEY_LEN || !key.chars().all(is_tag_char) {
+            errors.push(FieldError::new(
+                &field,
+                format!(
+                    "key must be 1 to {} letters, numbers, spaces, or + - = . _ : / @",
+                    MAX_KEY_LEN
+                ),
+            ));
+        } else if key.starts_with(RESERVED_PREFIX) {
+            errors.push(FieldError::new(
+                &field,
+                format!("key must not start with {}", RESERVED_PREFIX),
+            ));
+        }
+        if template.contains('%') {
+            if let Err(e) = validate_template(template) {
+                errors.push(FieldError::new(&field, e));
+            }
+        }
+    }
+    errors
+}
+
+/// Returns true if S3 allows the character in tag keys and values.
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == ' ' || "+-=._:/@".contains(c)
+}
@@ -0,0 +1,17 @@
+use kube::Resource;
+use ytdl_types::StageTiming;
+
+/// Annotation on the Executor through which the download pod reports
+/// the time it spent in each stage, as a json-encoded [`StageTiming`].
+/// The controller copies it into the status whenever it updates it.
+pub const STAGE_TIMING_ANNOTATION: &str = "ytdl.beebs.dev/stage-timing";
+
+/// Returns the stage timing reported by the download pod, if any.
+pub fn get_stage_timing<K: Resource>(instance: &K) -> Option<StageTiming> {
+    instance
+        .meta()
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(STAGE_TIMING_ANNOTATION))
+        .and_then(|value| serde_json::from_str(value).ok())
+}
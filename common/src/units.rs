@@ -0,0 +1,135 @@
+use chrono::{Duration as DateDuration, NaiveDate, Utc};
+use std::time::Duration;
+
+use crate::Error;
+
+/// Largest offset of a relative date, in days, that a chrono Duration
+/// can hold.
+const MAX_OFFSET_DAYS: i64 = i64::MAX / 1000 / (60 * 60 * 24);
+
+/// Parses a duration string such as `"30s"`, `"5m"`, `"12h"`, or `"2d"`.
+/// A bare number is interpreted as seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, Error> {
+    let value = value.trim();
+    let invalid = || Error::UserInputError(format!("invalid duration: {}", value));
+    let (number, multiplier) = match value.chars().last() {
+        Some('s') => (&value[..value.len() - 1], 1),
+        Some('m') => (&value[..value.len() - 1], 60),
+        Some('h') => (&value[..value.len() - 1], 60 * 60),
+        Some('d') => (&value[..value.len() - 1], 60 * 60 * 24),
+        Some(c) if c.is_ascii_digit() => (value, 1),
+        _ => return Err(invalid()),
+    };
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    number
+        .checked_mul(multiplier)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+/// Parses a file size string in the same format accepted by
+/// youtube-dl's `--max-filesize` (e.g. `"50k"`, `"44.6M"`, `"2G"`)
+/// and returns the number of bytes. A bare number is bytes.
+pub fn parse_filesize(value: &str) -> Result<u64, Error> {
+    let value = value.trim();
+    let invalid = || Error::UserInputError(format!("invalid file size: {}", value));
+    let (number, multiplier) = match value.chars().last() {
+        Some('k') | Some('K') => (&value[..value.len() - 1], 1u64 << 10),
+        Some('m') | Some('M') => (&value[..value.len() - 1], 1u64 << 20),
+        Some('g') | Some('G') => (&value[..value.len() - 1], 1u64 << 30),
+        Some('t') | Some('T') => (&value[..value.len() - 1], 1u64 << 40),
+        Some(c) if c.is_ascii_digit() => (value, 1),
+        _ => return Err(invalid()),
+    };
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    if number < 0.0 {
+        return Err(invalid());
+    }
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parses a date in the same format accepted by youtube-dl's
+/// `--dateafter` and `--datebefore`: either `YYYYMMDD` or a date
+/// relative to today, e.g. `"today-2weeks"` or `"now-1year"`. As
+/// in youtube-dl, a month is 30 days and a year is 365 days.
+pub fn parse_date(value: &str) -> Result<NaiveDate, Error> {
+    let value = value.trim();
+    let invalid = || Error::UserInputError(format!("invalid date: {}", value));
+    if value.len() == 8 && value.chars().all(|c| c.is_ascii_digit()) {
+        return NaiveDate::parse_from_str(value, "%Y%m%d").map_err(|_| invalid());
+    }
+    let today = Utc::now().date_naive();
+    let offset = match value
+        .strip_prefix("today")
+        .or_else(|| value.strip_prefix("now"))
+    {
+        Some(offset) => offset,
+        None => return Err(invalid()),
+    };
+    if offset.is_empty() {
+        return Ok(today);
+    }
+    let sign = match offset.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Err(invalid()),
+    };
+    let offset = &offset[1..];
+    let digits = offset.chars().take_while(char::is_ascii_digit).count();
+    let number: i64 = offset[..digits].parse().map_err(|_| invalid())?;
+    let days = match offset[digits..].trim_end_matches('s') {
+        "day" => 1,
+        "week" => 7,
+        "month" => 30,
+        "year" => 365,
+        _ => return Err(invalid()),
+    };
+    // Duration::days panics beyond its range, which is far beyond the
+    // dates a NaiveDate can hold.
+    let days = number
+        .checked_mul(days)
+        .filter(|days| *days <= MAX_OFFSET_DAYS)
+        .ok_or_else(invalid)?;
+    today
+        .checked_add_signed(DateDuration::days(sign * days))
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(
+            parse_duration("2d").unwrap(),
+            Duration::from_secs(2 * 86_400)
+        );
+    }
+
+    #[test]
+    fn rejects_huge_durations() {
+        assert!(parse_duration("99999999999999999d").is_err());
+        assert!(parse_duration("18446744073709551615m").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        let today = Utc::now().date_naive();
+        assert_eq!(parse_date("today").unwrap(), today);
+        assert_eq!(
+            parse_date("now-2weeks").unwrap(),
+            today - DateDuration::days(14)
+        );
+    }
+
+    #[test]
+    fn rejects_huge_date_offsets() {
+        assert!(parse_date("today-99999999999999days").is_err());
+        assert!(parse_date("today+99999999999999years").is_err());
+        assert!(parse_date("today-9223372036854775807days").is_err());
+        assert!(parse_date("today-99999999999999999999days").is_err());
+    }
+}
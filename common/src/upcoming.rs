@@ -0,0 +1,42 @@
+//! Premieres and scheduled livestreams are listed by the query before
+//! they start, but have no formats to download until then. Depending on
+//! [`DownloadSpec::upcoming`](ytdl_types::DownloadSpec::upcoming), they
+//! are either skipped by the filters or their Executors are deferred
+//! until the scheduled start time.
+use chrono::{DateTime, TimeZone, Utc};
+use tokio::time::Duration;
+
+use crate::Entity;
+
+/// Value of `live_status` in the info json for a video that has yet to start.
+const IS_UPCOMING: &str = "is_upcoming";
+
+/// Returns true if the info json describes a premiere or
+/// livestream that has yet to start.
+pub fn is_upcoming(metadata: &serde_json::Value) -> bool {
+    metadata.get("live_status").and_then(|v| v.as_str()) == Some(IS_UPCOMING)
+}
+
+/// Returns the scheduled start time of an upcoming video, if known.
+pub fn get_release_time(metadata: &serde_json::Value) -> Option<DateTime<Utc>> {
+    if !is_upcoming(metadata) {
+        return None;
+    }
+    let timestamp = metadata.get("release_timestamp")?.as_i64()?;
+    Utc.timestamp_opt(timestamp, 0).single()
+}
+
+/// Returns how long to wait before the batch's Executor may be created,
+/// which is until the last of its upcoming videos starts, or None if
+/// every video can be downloaded now. Upcoming videos without a known
+/// start time are left for the download pod to wait on.
+pub fn get_release_delay(batch: &[Entity]) -> Option<Duration> {
+    let now = Utc::now();
+    batch
+        .iter()
+        .filter_map(|entity| serde_json::from_str(&entity.metadata).ok())
+        .filter_map(|metadata| get_release_time(&metadata))
+        .filter_map(|release| release.signed_duration_since(now).to_std().ok())
+        .filter(|delay| !delay.is_zero())
+        .max()
+}
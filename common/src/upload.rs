@@ -0,0 +1,127 @@
+//! Tuning of the download pods' multipart uploads. Large videos are
+//! uploaded in parts that are retried individually, so the part size
+//! bounds both the memory used by the pod and the bytes sent again
+//! when a request fails over a flaky VPN link. The number of uploads
+//! a pod runs at once bounds the parts held in memory at the same time.
+use k8s_openapi::api::core::v1::EnvVar;
+
+use crate::{units::parse_filesize, Error};
+
+/// Environment variable with the size of each part of a multipart
+/// upload (e.g. `"64M"`), in the format of a youtube-dl file size.
+pub const UPLOAD_PART_SIZE_ENV: &str = "UPLOAD_PART_SIZE";
+
+/// Environment variable with the number of attempts at each upload
+/// request before the upload fails.
+pub const UPLOAD_MAX_ATTEMPTS_ENV: &str = "UPLOAD_MAX_ATTEMPTS";
+
+/// Environment variable with the number of uploads a download pod
+/// runs at once, across the artifacts and outputs of an entity.
+pub const UPLOAD_MAX_CONCURRENT_ENV: &str = "UPLOAD_MAX_CONCURRENT";
+
+/// Default size of each part of a multipart upload.
+const DEFAULT_PART_SIZE: u64 = 8 << 20;
+
+/// Smallest part size S3 accepts for every part but the last.
+const MIN_PART_SIZE: u64 = 5 << 20;
+
+/// Largest part size S3 accepts.
+const MAX_PART_SIZE: u64 = 5 << 30;
+
+/// Default number of attempts at each upload request.
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Default number of uploads a download pod runs at once.
+const DEFAULT_MAX_CONCURRENT: usize = 2;
+
+/// How the download pods upload content to S3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadConfig {
+    /// Size of each part of a multipart upload, in bytes. One part is
+    /// buffered in memory at a time so that it can be sent again.
+    pub part_size: u64,
+
+    /// Number of attempts at each request, at least one.
+    pub max_attempts: u32,
+
+    /// Number of uploads running at once, at least one. Each buffers
+    /// up to a part in memory.
+    pub max_concurrent: usize,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            part_size: DEFAULT_PART_SIZE,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            max_concurrent: DEFAULT_MAX_CONCURRENT,
+        }
+    }
+}
+
+impl UploadConfig {
+    /// Reads the config from the environment, using the defaults for
+    /// unset variables. Fails if a value is invalid or out of the range
+    /// S3 accepts.
+    pub fn from_env() -> Result<Self, Error> {
+        let mut config = UploadConfig::default();
+        if let Some(part_size) = get_env(UPLOAD_PART_SIZE_ENV) {
+            config.part_size = parse_filesize(&part_size)?;
+            if !(MIN_PART_SIZE..=MAX_PART_SIZE).contains(&config.part_size) {
+                return Err(Error::UserInputError(format!(
+                    "{} must be between 5M and 5G, got {}",
+                    UPLOAD_PART_SIZE_ENV, part_size
+                )));
+            }
+        }
+        if let Some(max_attempts) = get_env(UPLOAD_MAX_ATTEMPTS_ENV) {
+            config.max_attempts = max_attempts
+                .parse()
+                .ok()
+                .filter(|max_attempts| *max_attempts > 0)
+                .ok_or_else(|| {
+                    Error::UserInputError(format!(
+                        "{} must be a positive integer, got {}",
+                        UPLOAD_MAX_ATTEMPTS_ENV, max_attempts
+                    ))
+                })?;
+        }
+        if let Some(max_concurrent) = get_env(UPLOAD_MAX_CONCURRENT_ENV) {
+            config.max_concurrent = max_concurrent
+                .parse()
+                .ok()
+                .filter(|max_concurrent| *max_concurrent > 0)
+                .ok_or_else(|| {
+                    Error::UserInputError(format!(
+                        "{} must be a positive integer, got {}",
+                        UPLOAD_MAX_CONCURRENT_ENV, max_concurrent
+                    ))
+                })?;
+        }
+        Ok(config)
+    }
+}
+
+/// Returns the environment that passes the operator's upload
+/// config on to a download pod.
+pub fn get_pod_env() -> Vec<EnvVar> {
+    vec![
+        UPLOAD_PART_SIZE_ENV,
+        UPLOAD_MAX_ATTEMPTS_ENV,
+        UPLOAD_MAX_CONCURRENT_ENV,
+    ]
+    .into_iter()
+    .filter_map(|name| {
+        Some(EnvVar {
+            name: name.to_owned(),
+            value: Some(get_env(name)?),
+            ..EnvVar::default()
+        })
+    })
+    .collect()
+}
+
+/// Returns the value of the environment variable, if set and not empty.
+fn get_env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
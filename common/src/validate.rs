@@ -0,0 +1,840 @@
+//! Static validation of resource specs. These checks are performed by
+//! the admission webhook so that invalid specs are rejected when they
+//! are applied, rather than surfacing later as reconcile failures.
+//! The controllers perform the same checks before creating any pods.
+//! Each function returns a list of problems, which is empty if the
+//! spec is valid.
+use ytdl_types::{
+    AzureBlobTargetSpec, CullPolicy, DownloadSpec, GcsTargetSpec, GeoBlockedPolicy,
+    KafkaTargetSpec, MongoDBTargetSpec, NatsTargetSpec, ProxySpec, QueryEngineKind,
+    RedisTargetSpec, S3TargetSpec, SftpTargetSpec, SqlTargetSpec, TargetRef, TargetSpec,
+    TargetVerifySpec, VolumeTargetSpec, WebDavTargetSpec, WebhookTargetSpec,
+};
+
+use crate::{
+    kafka::SASL_MECHANISMS,
+    match_filter::MatchFilter,
+    metadata_fields::validate_metadata_fields,
+    nats::is_valid_subject,
+    object_headers::validate_object_headers,
+    pod::is_vpn_disabled,
+    proxy::PROXY_SCHEMES,
+    query_engine::get_static_urls,
+    sftp::parse_fingerprint,
+    sse::validate_sse,
+    storage_class::validate_storage_class,
+    tagging::validate_tags,
+    units::{parse_date, parse_duration, parse_filesize},
+    volume::is_relative_path,
+    work_volume::validate_work_volume,
+    Error, FieldError,
+};
+
+/// Checks that the input is what the query engine expects. youtube-dl
+/// accepts anything it has an extractor for, so its input isn't checked.
+fn validate_engine_input(errors: &mut Vec<FieldError>, spec: &DownloadSpec) {
+    match spec.engine.unwrap_or_default() {
+        QueryEngineKind::YoutubeDl | QueryEngineKind::FlatPlaylist => {}
+        QueryEngineKind::Rss => {
+            if !is_http_url(spec.input.trim()) {
+                errors.push(FieldError::new(
+                    "input",
+                    "must be an http or https url when engine is rss",
+                ));
+            }
+        }
+        QueryEngineKind::Static => {
+            let urls = get_static_urls(&spec.input);
+            if urls.is_empty() {
+                errors.push(FieldError::new(
+                    "input",
+                    "must list at least one url when engine is static",
+                ));
+            }
+            for url in urls.into_iter().filter(|url| !is_http_url(url)) {
+                errors.push(FieldError::new(
+                    "input",
+                    format!("{} is not an http or https url", url),
+                ));
+            }
+        }
+    }
+}
+
+fn is_http_url(input: &str) -> bool {
+    reqwest::Url::parse(input).map_or(false, |url| {
+        url.scheme() == "http" || url.scheme() == "https"
+    })
+}
+
+/// Kinds that may be referenced by a [`TargetRef`].
+pub const TARGET_KINDS: &[&str] = &[
+    "S3Target",
+    "WebhookTarget",
+    "SqlTarget",
+    "MongoDBTarget",
+    "RedisTarget",
+    "GcsTarget",
+    "AzureBlobTarget",
+    "VolumeTarget",
+    "SftpTarget",
+    "WebDavTarget",
+    "KafkaTarget",
+    "NatsTarget",
+];
+
+/// Kinds that may only be referenced as metadata targets.
+const METADATA_ONLY_KINDS: &[&str] = &["SqlTarget", "KafkaTarget", "NatsTarget"];
+
+/// Conversion types accepted at the end of a `%(name)s` template field.
+const TEMPLATE_CONVERSIONS: &str = "diouxXeEfFgGcrsa";
+
+/// HTTP methods accepted by [`WebhookTargetSpec::method`].
+const HTTP_METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"];
+
+/// Validates a [`DownloadSpec`]. An empty `targets` list is not checked
+/// here, as it is valid if the namespace has DefaultTargets.
+pub fn validate_download(spec: &DownloadSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if spec.input.trim().is_empty() {
+        errors.push(FieldError::new("input", "must not be empty"));
+    }
+    validate_engine_input(&mut errors, spec);
+    if let Some(ref query_interval) = spec.query_interval {
+        check(&mut errors, "queryInterval", parse_duration(query_interval));
+    }
+    if let Some(ref max_duration) = spec.max_duration {
+        check(&mut errors, "maxDuration", parse_duration(max_duration));
+    }
+    if let Some(ref max_filesize) = spec.max_filesize {
+        check(&mut errors, "maxFilesize", parse_filesize(max_filesize));
+    }
+    if let Some(ref rate_limit) = spec.rate_limit {
+        check(&mut errors, "rateLimit", parse_filesize(rate_limit));
+    }
+    if let Some(ref stall_timeout) = spec.stall_timeout {
+        check(&mut errors, "stallTimeout", parse_duration(stall_timeout));
+    }
+    if let Some(ref download_timeout) = spec.download_timeout {
+        check(
+            &mut errors,
+            "downloadTimeout",
+            parse_duration(download_timeout),
+        );
+    }
+    if let Some(ref date_after) = spec.date_after {
+        check(&mut errors, "dateAfter", parse_date(date_after));
+    }
+    if let Some(ref date_before) = spec.date_before {
+        check(&mut errors, "dateBefore", parse_date(date_before));
+    }
+    if let Some(ref match_filter) = spec.match_filter {
+        check(&mut errors, "matchFilter", MatchFilter::parse(match_filter));
+    }
+    if let (Some(Ok(date_after)), Some(Ok(date_before))) = (
+        spec.date_after.as_deref().map(parse_date),
+        spec.date_before.as_deref().map(parse_date),
+    ) {
+        if date_after > date_before {
+            errors.push(FieldError::new(
+                "dateBefore",
+                "must not be before dateAfter",
+            ));
+        }
+    }
+    if spec.batch_size == Some(0) {
+        errors.push(FieldError::new("batchSize", "must be greater than zero"));
+    }
+    if spec.geo_blocked == Some(GeoBlockedPolicy::RetryOtherRegion)
+        && spec.geo_regions.as_ref().map_or(true, Vec::is_empty)
+    {
+        errors.push(FieldError::new(
+            "geoRegions",
+            "must not be empty when geoBlocked is retryOtherRegion",
+        ));
+    }
+    if spec.geo_blocked == Some(GeoBlockedPolicy::RetryOtherRegion)
+        && is_vpn_disabled(spec.vpn.as_ref())
+    {
+        errors.push(FieldError::new(
+            "vpn.disabled",
+            "must not be true when geoBlocked is retryOtherRegion",
+        ));
+    }
+    if spec.vpn.as_ref().and_then(|vpn| vpn.rotate_ip_every) == Some(0) {
+        errors.push(FieldError::new(
+            "vpn.rotateIpEvery",
+            "must be greater than zero",
+        ));
+    }
+    if let Some(ref proxy) = spec.proxy {
+        check_proxy(&mut errors, proxy);
+        if spec.geo_blocked == Some(GeoBlockedPolicy::RetryOtherRegion) {
+            errors.push(FieldError::new(
+                "proxy",
+                "must not be set when geoBlocked is retryOtherRegion",
+            ));
+        }
+    }
+    if let Some(ref replication) = spec.replication {
+        if replication.targets.is_empty() {
+            errors.push(FieldError::new("replication.targets", "must not be empty"));
+        }
+    }
+    if spec.cull == Some(CullPolicy::Succeeded) {
+        // Culled Executors are only known to be done by their archived
+        // IDs, and their objects are gone along with them.
+        if spec.archive.is_none() {
+            errors.push(FieldError::new(
+                "archive",
+                "must be set when cull is succeeded",
+            ));
+        }
+        if spec.manifest.is_some() {
+            errors.push(FieldError::new(
+                "manifest",
+                "must not be set when cull is succeeded",
+            ));
+        }
+        if spec.replication.is_some() {
+            errors.push(FieldError::new(
+                "replication",
+                "must not be set when cull is succeeded",
+            ));
+        }
+    }
+    if let Some(ref metadata_fields) = spec.metadata_fields {
+        errors.extend(validate_metadata_fields(metadata_fields));
+    }
+    if let Some(ref work_volume) = spec.work_volume {
+        errors.extend(validate_work_volume(work_volume));
+    }
+    if let Some(ref archive) = spec.archive {
+        let backends = [
+            archive.config_map.is_some(),
+            archive.s3.is_some(),
+            archive.redis.is_some(),
+        ];
+        if backends.iter().filter(|&&set| set).count() != 1 {
+            errors.push(FieldError::new(
+                "archive",
+                "must specify exactly one of configMap, s3, or redis",
+            ));
+        }
+        for (field, object) in [
+            ("archive.s3", &archive.s3),
+            ("archive.redis", &archive.redis),
+        ] {
+            if let Some(object) = object {
+                if object.target.trim().is_empty() {
+                    errors.push(FieldError::new(
+                        format!("{}.target", field),
+                        "must not be empty",
+                    ));
+                }
+                if object.key.trim().is_empty() {
+                    errors.push(FieldError::new(
+                        format!("{}.key", field),
+                        "must not be empty",
+                    ));
+                }
+            }
+        }
+    }
+    if let Some(ref results) = spec.results {
+        if results.config_map.is_some() == results.s3.is_some() {
+            errors.push(FieldError::new(
+                "results",
+                "must specify exactly one of configMap or s3",
+            ));
+        }
+        if let Some(ref s3) = results.s3 {
+            if s3.target.trim().is_empty() {
+                errors.push(FieldError::new("results.s3.target", "must not be empty"));
+            }
+            if s3.key.trim().is_empty() {
+                errors.push(FieldError::new("results.s3.key", "must not be empty"));
+            }
+        }
+        if spec.cull != Some(CullPolicy::Succeeded) {
+            // The results are only written when culling.
+            errors.push(FieldError::new(
+                "cull",
+                "must be succeeded when results is set",
+            ));
+        }
+    }
+    for (i, target) in spec.targets.iter().enumerate() {
+        if target.trim().is_empty() {
+            errors.push(FieldError::new(
+                format!("targets[{}]", i),
+                "must not be empty",
+            ));
+        }
+    }
+    errors
+}
+
+/// Validates a [`TargetSpec`], which must reference at least one
+/// target resource of a known kind.
+pub fn validate_target(spec: &TargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let refs = [
+        ("metadata", &spec.metadata),
+        ("audiovisual", &spec.audiovisual),
+        ("thumbnail", &spec.thumbnail),
+    ];
+    if refs
+        .iter()
+        .all(|(_, refs)| refs.as_ref().map_or(true, Vec::is_empty))
+    {
+        errors.push(FieldError::new(
+            "spec",
+            "at least one of metadata, audiovisual, or thumbnail is required",
+        ));
+    }
+    for (content, refs) in refs {
+        for (i, target_ref) in refs.iter().flatten().enumerate() {
+            let field = format!("{}[{}]", content, i);
+            check_target_ref(&mut errors, &field, target_ref);
+            // SQL rows and Kafka and NATS messages only carry the
+            // metadata json.
+            let kind = target_ref.kind.as_str();
+            if METADATA_ONLY_KINDS.contains(&kind) && content != "metadata" {
+                errors.push(FieldError::new(
+                    format!("{}.kind", field),
+                    format!("{} is only valid as a metadata target", kind),
+                ));
+            }
+        }
+    }
+    errors
+}
+
+/// Validates an [`S3TargetSpec`].
+pub fn validate_s3_target(spec: &S3TargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if spec.bucket.trim().is_empty() {
+        errors.push(FieldError::new("bucket", "must not be empty"));
+    }
+    if let Some(ref key) = spec.key {
+        check_template(&mut errors, "key", key);
+    }
+    if let Some(ref endpoint) = spec.endpoint {
+        if let Err(e) = reqwest::Url::parse(endpoint) {
+            errors.push(FieldError::new("endpoint", e.to_string()));
+        }
+    }
+    check_verify(&mut errors, &spec.verify);
+    check_ca_bundle_secret(&mut errors, &spec.ca_bundle_secret);
+    if let Some(ref sse) = spec.sse {
+        errors.extend(validate_sse(sse));
+    }
+    if let Some(ref storage_class) = spec.storage_class {
+        errors.extend(validate_storage_class(storage_class));
+    }
+    if let Some(ref object_headers) = spec.object_headers {
+        errors.extend(validate_object_headers(object_headers));
+    }
+    if let Some(ref tags) = spec.tags {
+        errors.extend(validate_tags(tags));
+    }
+    if let Some(ref proxy) = spec.proxy {
+        check_proxy(&mut errors, proxy);
+    }
+    errors
+}
+
+/// Validates a [`GcsTargetSpec`].
+pub fn validate_gcs_target(spec: &GcsTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if spec.bucket.trim().is_empty() {
+        errors.push(FieldError::new("bucket", "must not be empty"));
+    }
+    if let Some(ref key) = spec.key {
+        check_template(&mut errors, "key", key);
+    }
+    if let Some(ref endpoint) = spec.endpoint {
+        if let Err(e) = reqwest::Url::parse(endpoint) {
+            errors.push(FieldError::new("endpoint", e.to_string()));
+        }
+    }
+    check_verify(&mut errors, &spec.verify);
+    check_ca_bundle_secret(&mut errors, &spec.ca_bundle_secret);
+    if let Some(ref proxy) = spec.proxy {
+        check_proxy(&mut errors, proxy);
+    }
+    errors
+}
+
+/// Validates an [`AzureBlobTargetSpec`].
+pub fn validate_azure_blob_target(spec: &AzureBlobTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    // Container names are 3 to 63 lowercase letters, numbers, and
+    // hyphens, beginning with a letter or number, without consecutive
+    // hyphens.
+    let container = &spec.container;
+    if container.len() < 3
+        || container.len() > 63
+        || !container
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        || container.starts_with('-')
+        || container.ends_with('-')
+        || container.contains("--")
+    {
+        errors.push(FieldError::new(
+            "container",
+            "must be 3 to 63 lowercase letters, numbers, and non-consecutive hyphens",
+        ));
+    }
+    match spec.account {
+        Some(ref account) => {
+            if account.len() < 3
+                || account.len() > 24
+                || !account
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+            {
+                errors.push(FieldError::new(
+                    "account",
+                    "must be 3 to 24 lowercase letters and numbers",
+                ));
+            }
+        }
+        // The system-assigned identity has no connection string to
+        // take the account from.
+        None if spec.secret.is_none() => {
+            errors.push(FieldError::new(
+                "account",
+                "must be set if secret is not set",
+            ));
+        }
+        None => {}
+    }
+    if let Some(ref key) = spec.key {
+        check_template(&mut errors, "key", key);
+    }
+    if let Some(ref endpoint) = spec.endpoint {
+        if let Err(e) = reqwest::Url::parse(endpoint) {
+            errors.push(FieldError::new("endpoint", e.to_string()));
+        }
+    }
+    check_verify(&mut errors, &spec.verify);
+    check_ca_bundle_secret(&mut errors, &spec.ca_bundle_secret);
+    if let Some(ref proxy) = spec.proxy {
+        check_proxy(&mut errors, proxy);
+    }
+    errors
+}
+
+/// Validates a [`VolumeTargetSpec`].
+pub fn validate_volume_target(spec: &VolumeTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if spec.claim_name.trim().is_empty() {
+        errors.push(FieldError::new("claimName", "must not be empty"));
+    }
+    if let Some(ref path) = spec.path {
+        check_template(&mut errors, "path", path);
+        if !is_relative_path(path) {
+            errors.push(FieldError::new(
+                "path",
+                "must be relative and must not contain ..",
+            ));
+        }
+    }
+    if let Some(ref sub_path) = spec.sub_path {
+        if !is_relative_path(sub_path) {
+            errors.push(FieldError::new(
+                "subPath",
+                "must be relative and must not contain ..",
+            ));
+        }
+    }
+    errors
+}
+
+/// Validates an [`SftpTargetSpec`].
+pub fn validate_sftp_target(spec: &SftpTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if spec.host.trim().is_empty() {
+        errors.push(FieldError::new("host", "must not be empty"));
+    }
+    if spec.port == Some(0) {
+        errors.push(FieldError::new("port", "must be between 1 and 65535"));
+    }
+    if spec.user.trim().is_empty() {
+        errors.push(FieldError::new("user", "must not be empty"));
+    }
+    if spec.secret.trim().is_empty() {
+        errors.push(FieldError::new("secret", "must not be empty"));
+    }
+    if let Some(ref path) = spec.path {
+        check_template(&mut errors, "path", path);
+    }
+    match spec.host_key_fingerprint {
+        Some(ref fingerprint) => check(
+            &mut errors,
+            "hostKeyFingerprint",
+            parse_fingerprint(fingerprint),
+        ),
+        None if spec.insecure_skip_host_key_verification.unwrap_or(false) => {}
+        None => errors.push(FieldError::new(
+            "hostKeyFingerprint",
+            "must be set unless insecureSkipHostKeyVerification is true",
+        )),
+    }
+    check_verify(&mut errors, &spec.verify);
+    errors
+}
+
+/// Validates a [`WebDavTargetSpec`].
+pub fn validate_webdav_target(spec: &WebDavTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    match reqwest::Url::parse(&spec.url) {
+        Ok(url) if url.scheme() != "http" && url.scheme() != "https" => {
+            errors.push(FieldError::new("url", "must be an http or https url"));
+        }
+        Ok(_) => {}
+        Err(e) => errors.push(FieldError::new("url", e.to_string())),
+    }
+    if let Some(ref path) = spec.path {
+        check_template(&mut errors, "path", path);
+        if !is_relative_path(path) {
+            errors.push(FieldError::new(
+                "path",
+                "must be relative and must not contain ..",
+            ));
+        }
+    }
+    if spec.secret.trim().is_empty() {
+        errors.push(FieldError::new("secret", "must not be empty"));
+    }
+    check_verify(&mut errors, &spec.verify);
+    check_ca_bundle_secret(&mut errors, &spec.ca_bundle_secret);
+    if let Some(ref proxy) = spec.proxy {
+        check_proxy(&mut errors, proxy);
+    }
+    errors
+}
+
+/// Validates a [`KafkaTargetSpec`].
+pub fn validate_kafka_target(spec: &KafkaTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if spec.brokers.is_empty() {
+        errors.push(FieldError::new("brokers", "must not be empty"));
+    }
+    for (i, broker) in spec.brokers.iter().enumerate() {
+        let valid = match broker.rsplit_once(':') {
+            Some((host, port)) => !host.is_empty() && port.parse::<u16>().map_or(false, |p| p > 0),
+            None => false,
+        };
+        if !valid {
+            errors.push(FieldError::new(
+                format!("brokers[{}]", i),
+                "must be host:port",
+            ));
+        }
+    }
+    // Topic names are 1 to 249 letters, numbers, periods, underscores,
+    // and hyphens, and may not be "." or "..".
+    let topic = &spec.topic;
+    if topic.is_empty()
+        || topic.len() > 249
+        || topic == "."
+        || topic == ".."
+        || !topic
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+    {
+        errors.push(FieldError::new(
+            "topic",
+            "must be 1 to 249 letters, numbers, periods, underscores, and hyphens",
+        ));
+    }
+    if let Some(ref key) = spec.key {
+        check_template(&mut errors, "key", key);
+    }
+    if let Some(ref sasl) = spec.sasl {
+        if let Some(ref mechanism) = sasl.mechanism {
+            if !SASL_MECHANISMS.contains(&mechanism.as_str()) {
+                errors.push(FieldError::new(
+                    "sasl.mechanism",
+                    format!("must be one of {}", SASL_MECHANISMS.join(", ")),
+                ));
+            }
+        }
+        if sasl.secret.trim().is_empty() {
+            errors.push(FieldError::new("sasl.secret", "must not be empty"));
+        }
+    }
+    if let Some(ref secret) = spec.tls.as_ref().and_then(|tls| tls.secret.as_ref()) {
+        if secret.trim().is_empty() {
+            errors.push(FieldError::new("tls.secret", "must not be empty"));
+        }
+    }
+    check_verify(&mut errors, &spec.verify);
+    check_ca_bundle_secret(&mut errors, &spec.ca_bundle_secret);
+    errors
+}
+
+/// Validates a [`NatsTargetSpec`].
+pub fn validate_nats_target(spec: &NatsTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if spec.servers.is_empty() {
+        errors.push(FieldError::new("servers", "must not be empty"));
+    }
+    for (i, server) in spec.servers.iter().enumerate() {
+        match reqwest::Url::parse(server) {
+            Ok(url) if !["nats", "tls", "ws", "wss"].contains(&url.scheme()) => {
+                errors.push(FieldError::new(
+                    format!("servers[{}]", i),
+                    "must be a nats, tls, ws, or wss url",
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => errors.push(FieldError::new(format!("servers[{}]", i), e.to_string())),
+        }
+    }
+    check_template(&mut errors, "subject", &spec.subject);
+    // Template fields are replaced the same way as during verification.
+    if !is_valid_subject(&fill_template(&spec.subject, "test")) {
+        errors.push(FieldError::new(
+            "subject",
+            "must be tokens separated by periods, without wildcards or whitespace",
+        ));
+    }
+    if let Some(ref secret) = spec.secret {
+        if secret.trim().is_empty() {
+            errors.push(FieldError::new("secret", "must not be empty"));
+        }
+    }
+    if let Some(ref stream) = spec.jet_stream.as_ref().and_then(|js| js.stream.as_ref()) {
+        // Stream names are used as subject tokens by the JetStream API.
+        if stream.is_empty() || !is_valid_subject(stream) || stream.contains('.') {
+            errors.push(FieldError::new(
+                "jetStream.stream",
+                "must not be empty or contain periods, wildcards, or whitespace",
+            ));
+        }
+    }
+    check_verify(&mut errors, &spec.verify);
+    check_ca_bundle_secret(&mut errors, &spec.ca_bundle_secret);
+    errors
+}
+
+/// Validates a [`WebhookTargetSpec`].
+pub fn validate_webhook_target(spec: &WebhookTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    check_template(&mut errors, "url", &spec.url);
+    // Template fields are replaced the same way as during verification.
+    if let Err(e) = reqwest::Url::parse(&fill_template(&spec.url, "test")) {
+        errors.push(FieldError::new("url", e.to_string()));
+    }
+    if let Some(ref method) = spec.method {
+        if !HTTP_METHODS.contains(&method.to_uppercase().as_str()) {
+            errors.push(FieldError::new(
+                "method",
+                format!("unsupported http method {}", method),
+            ));
+        }
+    }
+    if let Some(ref timeout) = spec.timeout {
+        check(&mut errors, "timeout", parse_duration(timeout));
+    }
+    check_verify(&mut errors, &spec.verify);
+    check_ca_bundle_secret(&mut errors, &spec.ca_bundle_secret);
+    if let Some(ref proxy) = spec.proxy {
+        check_proxy(&mut errors, proxy);
+    }
+    errors
+}
+
+/// Validates a [`MongoDBTargetSpec`].
+pub fn validate_mongodb_target(spec: &MongoDBTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if spec.secret.trim().is_empty() {
+        errors.push(FieldError::new("secret", "must not be empty"));
+    }
+    if let Some(ref collection) = spec.collection {
+        if collection.trim().is_empty() {
+            errors.push(FieldError::new("collection", "must not be empty"));
+        }
+    }
+    if let Some(ref id) = spec.id {
+        check_template(&mut errors, "id", id);
+    }
+    check_verify(&mut errors, &spec.verify);
+    check_ca_bundle_secret(&mut errors, &spec.ca_bundle_secret);
+    errors
+}
+
+/// Validates a [`SqlTargetSpec`]. The Secret's fields are only checked
+/// when the executor connects, as the webhook doesn't read Secrets.
+pub fn validate_sql_target(spec: &SqlTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if spec.secret.trim().is_empty() {
+        errors.push(FieldError::new("secret", "must not be empty"));
+    }
+    check_verify(&mut errors, &spec.verify);
+    check_ca_bundle_secret(&mut errors, &spec.ca_bundle_secret);
+    errors
+}
+
+/// Validates a [`RedisTargetSpec`].
+pub fn validate_redis_target(spec: &RedisTargetSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if spec.secret.trim().is_empty() {
+        errors.push(FieldError::new("secret", "must not be empty"));
+    }
+    if let Some(ref ttl) = spec.ttl {
+        check(&mut errors, "ttl", parse_duration(ttl));
+    }
+    if let Some(ref max_filesize) = spec.max_filesize {
+        check(&mut errors, "maxFilesize", parse_filesize(max_filesize));
+    }
+    if spec
+        .script
+        .as_deref()
+        .map_or(false, |script| script.trim().is_empty())
+    {
+        errors.push(FieldError::new("script", "must not be empty"));
+    }
+    if let Some(ref key) = spec.key {
+        check_template(&mut errors, "key", key);
+    }
+    for (i, key) in spec.extra_keys.iter().flatten().enumerate() {
+        check_template(&mut errors, &format!("extraKeys[{}]", i), key);
+    }
+    check_verify(&mut errors, &spec.verify);
+    check_ca_bundle_secret(&mut errors, &spec.ca_bundle_secret);
+    errors
+}
+
+/// Validates a youtube-dl output template, e.g. `"%(id)s.%(ext)s"`.
+/// Every `%(` must be followed by a field name, a closing parenthesis,
+/// and a conversion type. A literal percent sign is written as `%%`.
+pub fn validate_template(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("template must not be empty".to_owned());
+    }
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        match chars.next() {
+            Some('%') => continue,
+            Some('(') => {}
+            _ => return Err(format!("invalid template {}: expected %( or %%", template)),
+        }
+        let mut name = String::new();
+        loop {
+            match chars.next() {
+                Some(')') => break,
+                Some(c) => name.push(c),
+                None => return Err(format!("invalid template {}: unclosed %(", template)),
+            }
+        }
+        if name.is_empty() {
+            return Err(format!("invalid template {}: empty field name", template));
+        }
+        // Skip the optional flags, width, and precision.
+        while matches!(chars.peek(), Some(c) if "#0- +.".contains(*c) || c.is_ascii_digit()) {
+            chars.next();
+        }
+        match chars.next() {
+            Some(c) if TEMPLATE_CONVERSIONS.contains(c) => {}
+            _ => {
+                return Err(format!(
+                    "invalid template {}: missing conversion type after %({})",
+                    template, name
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces every field in the template with the given value.
+fn fill_template(template: &str, value: &str) -> String {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("%(") {
+        result.push_str(&rest[..start]);
+        match rest[start..].find(')') {
+            Some(end) => {
+                result.push_str(value);
+                // Skip the closing parenthesis and the conversion type.
+                rest = rest[start + end + 1..].get(1..).unwrap_or_default();
+            }
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Records the error, prefixed with the field name, if there is one.
+fn check<T>(errors: &mut Vec<FieldError>, field: &str, result: Result<T, Error>) {
+    if let Err(e) = result {
+        errors.push(FieldError::new(field, e.to_string()));
+    }
+}
+
+fn check_template(errors: &mut Vec<FieldError>, field: &str, template: &str) {
+    if let Err(e) = validate_template(template) {
+        errors.push(FieldError::new(field, e));
+    }
+}
+
+fn check_verify(errors: &mut Vec<FieldError>, verify: &Option<TargetVerifySpec>) {
+    if let Some(interval) = verify
+        .as_ref()
+        .and_then(|verify| verify.interval.as_deref())
+    {
+        check(errors, "verify.interval", parse_duration(interval));
+    }
+}
+
+fn check_proxy(errors: &mut Vec<FieldError>, proxy: &ProxySpec) {
+    match reqwest::Url::parse(&proxy.url) {
+        Ok(url) if !PROXY_SCHEMES.contains(&url.scheme()) => errors.push(FieldError::new(
+            "proxy.url",
+            format!("unsupported proxy scheme {}", url.scheme()),
+        )),
+        Ok(_) => {}
+        Err(e) => errors.push(FieldError::new("proxy.url", e.to_string())),
+    }
+}
+
+fn check_ca_bundle_secret(errors: &mut Vec<FieldError>, secret: &Option<String>) {
+    if secret
+        .as_ref()
+        .map_or(false, |secret| secret.trim().is_empty())
+    {
+        errors.push(FieldError::new("caBundleSecret", "must not be empty"));
+    }
+}
+
+fn check_target_ref(errors: &mut Vec<FieldError>, field: &str, target_ref: &TargetRef) {
+    if !TARGET_KINDS.contains(&target_ref.kind.as_str()) {
+        errors.push(FieldError::new(
+            format!("{}.kind", field),
+            format!(
+                "unknown target kind {}, expected one of {}",
+                target_ref.kind,
+                TARGET_KINDS.join(", ")
+            ),
+        ));
+    }
+    if target_ref.name.trim().is_empty() {
+        errors.push(FieldError::new(
+            format!("{}.name", field),
+            "must not be empty",
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+//! Filesystem targets, per [`VolumeTargetSpec`](ytdl_types::VolumeTargetSpec).
+//! The claim of each VolumeTarget of the Executor's Target is mounted
+//! into the download pod under [`VOLUME_TARGETS_PATH`], and the content
+//! is written to it as files. The controllers don't mount the claims,
+//! so they only know of the files the Executor recorded.
+use k8s_openapi::api::core::v1::{PersistentVolumeClaimVolumeSource, Pod, Volume};
+use kube::{Api, Client, ResourceExt};
+use std::path::{Component, Path, PathBuf};
+use ytdl_types::{ContentType, Target, VolumeTarget};
+
+use crate::{pod::mount_volume, template_key, Error, DEFAULT_TEMPLATE};
+
+/// Directory the claims are mounted under, one subdirectory per
+/// VolumeTarget.
+pub const VOLUME_TARGETS_PATH: &str = "/targets";
+
+/// Prefix of the names of the claims' volumes in the download pod.
+const VOLUME_NAME_PREFIX: &str = "target";
+
+/// A VolumeTarget's directory in the download pod.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeDir {
+    /// Name of the VolumeTarget, which stands in for the bucket
+    /// of the objects written to it.
+    pub name: String,
+
+    /// Directory the paths are relative to.
+    pub root: PathBuf,
+}
+
+/// A VolumeTarget's directory and the path of the file the content
+/// is written to, which is the counterpart of [`Output`](crate::Output).
+pub type VolumeOutput = (VolumeDir, String);
+
+/// Returns the VolumeTargets referenced by the named Target, for
+/// any type of content, without duplicates.
+pub async fn get_volume_targets(
+    client: Client,
+    namespace: &str,
+    target_name: &str,
+) -> Result<Vec<VolumeTarget>, Error> {
+    let api: Api<Target> = Api::namespaced(client.clone(), namespace);
+    let spec = match api.get_opt(target_name).await? {
+        Some(target) => target.spec,
+        None => return Ok(Vec::new()),
+    };
+    let targets: Api<VolumeTarget> = Api::namespaced(client, namespace);
+    let mut result: Vec<VolumeTarget> = Vec::new();
+    for target_ref in vec![spec.metadata, spec.audiovisual, spec.thumbnail]
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        if target_ref.kind != "VolumeTarget"
+            || result.iter().any(|t| t.name_any() == target_ref.name)
+        {
+            continue;
+        }
+        result.push(targets.get(&target_ref.name).await?);
+    }
+    Ok(result)
+}
+
+/// Returns the volume outputs of the content for the named Target,
+/// with the paths rendered from the metadata.
+pub async fn get_volume_outputs(
+    client: Client,
+    namespace: &str,
+    target_name: &str,
+    metadata: &serde_json::Value,
+    content: ContentType,
+) -> Result<Vec<VolumeOutput>, Error> {
+    let api: Api<Target> = Api::namespaced(client.clone(), namespace);
+    let spec = match api.get_opt(target_name).await? {
+        Some(target) => target.spec,
+        None => return Ok(Vec::new()),
+    };
+    let refs = match content {
+        ContentType::Metadata => spec.metadata,
+        ContentType::Audiovisual => spec.audiovisual,
+        ContentType::Thumbnail => spec.thumbnail,
+    };
+    let targets: Api<VolumeTarget> = Api::namespaced(client, namespace);
+    let mut outputs = Vec::new();
+    for target_ref in refs.unwrap_or_default() {
+        if target_ref.kind != "VolumeTarget" {
+            continue;
+        }
+        let spec = targets.get(&target_ref.name).await?.spec;
+        let template = spec.path.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+        let path = template_key(metadata, template)?;
+        // The metadata is untrusted, so it mustn't escape the directory.
+        if !is_relative_path(&path) {
+            return Err(Error::UserInputError(format!(
+                "path {} of VolumeTarget {} is not relative to the volume",
+                path, target_ref.name
+            )));
+        }
+        let mut root = get_mount_path(&target_ref.name);
+        if let Some(ref sub_path) = spec.sub_path {
+            root.push(sub_path);
+        }
+        let dir = VolumeDir {
+            name: target_ref.name,
+            root,
+        };
+        outputs.push((dir, path));
+    }
+    Ok(outputs)
+}
+
+/// Mounts the claims of the VolumeTargets into the executor container.
+pub fn mount_volume_targets(pod: &mut Pod, targets: &[VolumeTarget]) {
+    for (i, target) in targets.iter().enumerate() {
+        let volume = Volume {
+            name: format!("{}-{}", VOLUME_NAME_PREFIX, i),
+            persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+                claim_name: target.spec.claim_name.clone(),
+                ..PersistentVolumeClaimVolumeSource::default()
+            }),
+            ..Volume::default()
+        };
+        let mount_path = get_mount_path(&target.name_any());
+        mount_volume(pod, volume, &mount_path.to_string_lossy(), false);
+    }
+}
+
+/// Returns true if the path is relative and stays inside the directory
+/// it is relative to.
+pub fn is_relative_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Returns the directory the VolumeTarget's claim is mounted at.
+fn get_mount_path(target_name: &str) -> PathBuf {
+    Path::new(VOLUME_TARGETS_PATH).join(target_name)
+}
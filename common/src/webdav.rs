@@ -0,0 +1,167 @@
+//! WebDAV servers, per [`WebDavTargetSpec`](ytdl_types::WebDavTargetSpec).
+//! Like [`azure`](crate::azure), this is a small client for the requests
+//! the controllers and download pods make: looking up files, creating
+//! the collections they are uploaded to, and uploading them. Requests
+//! are authorized with the HTTP basic auth credentials in the target's
+//! Secret.
+use futures::future::BoxFuture;
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use reqwest::{Body, Method, StatusCode, Url};
+use std::sync::Arc;
+use ytdl_types::WebDavTargetSpec;
+
+use crate::{proxy::get_storage_http_client, Error};
+
+/// Key in the Secret with the user's name.
+pub const USERNAME_KEY: &str = "username";
+
+/// Key in the Secret with the user's password.
+pub const PASSWORD_KEY: &str = "password";
+
+/// Header with the size of a chunked request body, which servers built
+/// on SabreDAV, e.g. Nextcloud and ownCloud, use to reject truncated
+/// uploads.
+const EXPECTED_LENGTH_HEADER: &str = "x-expected-entity-length";
+
+/// A client for a single server. Clones share the credentials.
+#[derive(Clone)]
+pub struct WebDavServer {
+    /// Name of the WebDavTarget, which stands in for the bucket of the
+    /// files uploaded to it.
+    pub name: String,
+    url: Url,
+    http: reqwest::Client,
+    credentials: Arc<(String, String)>,
+}
+
+impl WebDavServer {
+    /// Returns the size of the file, or None if it does not exist.
+    pub async fn head_object(&self, path: &str) -> Result<Option<u64>, Error> {
+        let res = self.request(Method::HEAD, path)?.send().await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let res = check(res).await?;
+        Ok(Some(
+            res.headers()
+                .get(reqwest::header::CONTENT_LENGTH)
+                .and_then(|size| size.to_str().ok())
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0),
+        ))
+    }
+
+    /// Uploads the file with a single PUT request. The body is sent
+    /// with chunked transfer encoding if it is a stream, so its size
+    /// is sent separately. WebDAV servers only replace the file once
+    /// the whole body is received, so an aborted upload leaves no
+    /// partial file behind.
+    pub async fn put_object(
+        &self,
+        path: &str,
+        body: Body,
+        size: u64,
+        content_type: &str,
+    ) -> Result<(), Error> {
+        let res = self
+            .request(Method::PUT, path)?
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .header(EXPECTED_LENGTH_HEADER, size.to_string())
+            .body(body)
+            .send()
+            .await?;
+        check(res).await?;
+        Ok(())
+    }
+
+    /// Creates the collections of the file's path that do not exist.
+    /// The parent collection is only created if the server reports it
+    /// missing, so a file in an existing collection takes one request.
+    pub async fn create_collections(&self, path: &str) -> Result<(), Error> {
+        match path.rsplit_once('/') {
+            Some((parent, _)) if !parent.is_empty() => self.create_collection(parent).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Creates the collection and its missing ancestors.
+    fn create_collection<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let method = Method::from_bytes(b"MKCOL").unwrap();
+            let res = self.request(method.clone(), path)?.send().await?;
+            match res.status() {
+                // The collection already exists.
+                StatusCode::METHOD_NOT_ALLOWED => Ok(()),
+                // The parent collection does not exist.
+                StatusCode::CONFLICT => {
+                    self.create_collections(path).await?;
+                    check(self.request(method, path)?.send().await?).await?;
+                    Ok(())
+                }
+                _ => {
+                    check(res).await?;
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    /// Returns the authorized request for the file or collection at the
+    /// path, relative to the target's URL.
+    fn request(&self, method: Method, path: &str) -> Result<reqwest::RequestBuilder, Error> {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .map_err(|_| Error::UserInputError("invalid WebDAV url".to_owned()))?
+            .pop_if_empty()
+            .extend(path.split('/').filter(|segment| !segment.is_empty()));
+        let (ref username, ref password) = *self.credentials;
+        Ok(self
+            .http
+            .request(method, url)
+            .basic_auth(username, Some(password)))
+    }
+}
+
+/// Returns the client for the server described by the WebDavTarget spec.
+pub async fn get_webdav_server(
+    client: Client,
+    namespace: &str,
+    name: &str,
+    spec: &WebDavTargetSpec,
+) -> Result<WebDavServer, Error> {
+    let url = Url::parse(&spec.url)
+        .map_err(|e| Error::UserInputError(format!("invalid WebDAV url: {}", e)))?;
+    let secret = Api::<Secret>::namespaced(client, namespace)
+        .get(&spec.secret)
+        .await?;
+    let mut data = secret.data.unwrap_or_default();
+    let mut field = |key: &str| {
+        data.remove(key)
+            .and_then(|value| String::from_utf8(value.0).ok())
+            .ok_or_else(|| {
+                Error::UserInputError(format!(
+                    "WebDAV credentials Secret {} has no {} field",
+                    spec.secret, key
+                ))
+            })
+    };
+    let credentials = (field(USERNAME_KEY)?, field(PASSWORD_KEY)?);
+    Ok(WebDavServer {
+        name: name.to_owned(),
+        url,
+        http: get_storage_http_client()?,
+        credentials: Arc::new(credentials),
+    })
+}
+
+/// Returns the response if it succeeded, or its error otherwise.
+async fn check(res: reqwest::Response) -> Result<reqwest::Response, Error> {
+    if res.status().is_success() {
+        return Ok(res);
+    }
+    Err(Error::WebDavError {
+        status_code: res.status().as_u16(),
+        message: res.text().await.unwrap_or_default(),
+    })
+}
@@ -0,0 +1,145 @@
+//! Scratch volume of the download pod, per
+//! [`DownloadSpec::work_volume`](ytdl_types::DownloadSpec::work_volume).
+//! A persistent volume is claimed once per Executor and owned by it, so
+//! every pod the controller recreates for the Executor mounts the same
+//! claim and youtube-dl resumes the partial file the last pod left.
+use k8s_openapi::{
+    api::core::v1::{
+        EmptyDirVolumeSource, PersistentVolumeClaim, PersistentVolumeClaimSpec,
+        PersistentVolumeClaimVolumeSource, Pod, ResourceRequirements, Volume,
+    },
+    apimachinery::pkg::api::resource::Quantity,
+};
+use kube::{
+    api::{Api, ObjectMeta, PostParams, Resource},
+    Client, ResourceExt,
+};
+use std::collections::BTreeMap;
+use ytdl_types::{Executor, WorkVolumeSpec};
+
+use crate::{naming::child_name, pod::mount_volume, Error, FieldError};
+
+/// Directory the work volume is mounted at in the executor container.
+pub const WORK_PATH: &str = "/work";
+
+/// Name of the volume in the download pod.
+const WORK_VOLUME_NAME: &str = "work";
+
+/// Suffixes of the quantities accepted for the volume's size.
+const QUANTITY_SUFFIXES: &[&str] = &[
+    "Ki", "Mi", "Gi", "Ti", "Pi", "Ei", "k", "M", "G", "T", "P", "E",
+];
+
+/// Returns the name of the Executor's PersistentVolumeClaim.
+pub fn get_work_claim_name(executor_name: &str) -> String {
+    child_name(executor_name, WORK_VOLUME_NAME)
+}
+
+/// Returns true if the spec asks for a PersistentVolumeClaim.
+pub fn is_persistent(spec: &WorkVolumeSpec) -> bool {
+    spec.persistent.unwrap_or(false)
+}
+
+/// Creates the Executor's PersistentVolumeClaim if it doesn't exist yet.
+/// The claim is owned by the Executor, so it is garbage collected along
+/// with it, but outlives the Executor's pods.
+pub async fn create_work_claim(
+    client: Client,
+    instance: &Executor,
+    spec: &WorkVolumeSpec,
+) -> Result<(), Error> {
+    let namespace = instance.namespace().unwrap();
+    let name = get_work_claim_name(&instance.name_any());
+    let api: Api<PersistentVolumeClaim> = Api::namespaced(client, &namespace);
+    if api.get_opt(&name).await?.is_some() {
+        return Ok(());
+    }
+    let claim = get_work_claim(instance, spec)?;
+    api.create(&PostParams::default(), &claim).await?;
+    Ok(())
+}
+
+/// Returns the Executor's PersistentVolumeClaim without creating it.
+pub fn get_work_claim(
+    instance: &Executor,
+    spec: &WorkVolumeSpec,
+) -> Result<PersistentVolumeClaim, Error> {
+    let size = spec.size.clone().ok_or_else(|| {
+        Error::UserInputError("workVolume.size is required for a persistent volume".to_owned())
+    })?;
+    Ok(PersistentVolumeClaim {
+        metadata: ObjectMeta {
+            name: Some(get_work_claim_name(&instance.name_any())),
+            namespace: instance.namespace(),
+            owner_references: Some(vec![instance.controller_owner_ref(&()).unwrap()]),
+            ..ObjectMeta::default()
+        },
+        spec: Some(PersistentVolumeClaimSpec {
+            access_modes: Some(vec!["ReadWriteOnce".to_owned()]),
+            storage_class_name: spec.storage_class_name.clone(),
+            resources: Some(ResourceRequirements {
+                requests: Some(BTreeMap::from([("storage".to_owned(), Quantity(size))])),
+                ..ResourceRequirements::default()
+            }),
+            ..PersistentVolumeClaimSpec::default()
+        }),
+        ..PersistentVolumeClaim::default()
+    })
+}
+
+/// Mounts the Executor's work volume into the executor container.
+pub fn mount_work_volume(pod: &mut Pod, executor_name: &str, spec: &WorkVolumeSpec) {
+    let volume = if is_persistent(spec) {
+        Volume {
+            name: WORK_VOLUME_NAME.to_owned(),
+            persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+                claim_name: get_work_claim_name(executor_name),
+                ..PersistentVolumeClaimVolumeSource::default()
+            }),
+            ..Volume::default()
+        }
+    } else {
+        Volume {
+            name: WORK_VOLUME_NAME.to_owned(),
+            empty_dir: Some(EmptyDirVolumeSource {
+                size_limit: spec.size.clone().map(Quantity),
+                ..EmptyDirVolumeSource::default()
+            }),
+            ..Volume::default()
+        }
+    };
+    mount_volume(pod, volume, WORK_PATH, false);
+}
+
+/// Returns the problems with the work volume of the Download.
+pub fn validate_work_volume(spec: &WorkVolumeSpec) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    match spec.size {
+        Some(ref size) if !is_quantity(size) => errors.push(FieldError::new(
+            "workVolume.size",
+            "must be a quantity such as 50Gi",
+        )),
+        None if is_persistent(spec) => errors.push(FieldError::new(
+            "workVolume.size",
+            "is required for a persistent volume",
+        )),
+        _ => {}
+    }
+    if spec.storage_class_name.is_some() && !is_persistent(spec) {
+        errors.push(FieldError::new(
+            "workVolume.storageClassName",
+            "only applies to a persistent volume",
+        ));
+    }
+    errors
+}
+
+/// Returns true if the value is a number with an optional binary or
+/// decimal suffix, which covers the quantities used for storage.
+fn is_quantity(value: &str) -> bool {
+    let number = QUANTITY_SUFFIXES
+        .iter()
+        .find_map(|suffix| value.strip_suffix(suffix))
+        .unwrap_or(value);
+    !number.is_empty() && number.parse::<f64>().map_or(false, |n| n > 0.0)
+}
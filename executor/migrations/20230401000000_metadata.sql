@@ -0,0 +1,56 @@
+-- Schema of the metadata written by SqlTargets. Each video is upserted
+-- by its ID, and its formats and thumbnails are replaced along with it,
+-- so a video that is downloaded again overwrites its rows.
+
+-- One row per video, with the commonly queried fields of the info json
+-- as columns and the whole info json in `metadata`.
+CREATE TABLE IF NOT EXISTS videos (
+    id TEXT PRIMARY KEY,
+    -- Name of the youtube-dl extractor, e.g. "youtube".
+    extractor TEXT,
+    title TEXT,
+    description TEXT,
+    uploader TEXT,
+    channel_id TEXT,
+    -- Upload date as YYYYMMDD, as youtube-dl reports it.
+    upload_date TEXT,
+    -- Length in seconds.
+    duration DOUBLE PRECISION,
+    view_count BIGINT,
+    webpage_url TEXT,
+    metadata JSONB NOT NULL,
+    -- When the row was last written.
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- The formats youtube-dl found for each video, of which the downloaded
+-- one is named by the video's `format_id` in `metadata`. The URLs are
+-- omitted, as they expire.
+CREATE TABLE IF NOT EXISTS formats (
+    video_id TEXT NOT NULL REFERENCES videos (id) ON DELETE CASCADE,
+    format_id TEXT NOT NULL,
+    ext TEXT,
+    format_note TEXT,
+    width INTEGER,
+    height INTEGER,
+    vcodec TEXT,
+    acodec TEXT,
+    -- Size in bytes, exact or approximate.
+    filesize BIGINT,
+    -- Total bitrate in KBit/s.
+    tbr DOUBLE PRECISION,
+    PRIMARY KEY (video_id, format_id)
+);
+
+-- The thumbnails of each video. `thumbnail_id` is the thumbnail's ID
+-- from the info json, or its index if it has none.
+CREATE TABLE IF NOT EXISTS thumbnails (
+    video_id TEXT NOT NULL REFERENCES videos (id) ON DELETE CASCADE,
+    thumbnail_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    width INTEGER,
+    height INTEGER,
+    -- Higher is better.
+    preference INTEGER,
+    PRIMARY KEY (video_id, thumbnail_id)
+);
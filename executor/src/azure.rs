@@ -0,0 +1,109 @@
+use std::{collections::BTreeMap, future::Future};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::warn;
+use ytdl_common::{
+    azure::{block_id, AzureContainer},
+    store::is_transient,
+    upload::UploadConfig,
+    Error,
+};
+
+use crate::{deadline::bounded, upload::INITIAL_BACKOFF};
+
+/// Maximum size of a block of a block blob.
+const MAX_BLOCK_SIZE: u64 = 4000 << 20;
+
+/// Streams the reader to the container as a block blob. The content is
+/// staged in blocks of the operator's part size and committed once the
+/// reader is exhausted, so nothing is visible until the whole blob is
+/// uploaded. Content smaller than a block is uploaded with one request.
+/// Azure discards blocks that are never committed, so an upload still
+/// running at the pod's deadline is simply dropped.
+pub async fn put_object_stream<R: AsyncRead + Unpin>(
+    container: &AzureContainer,
+    reader: &mut R,
+    key: &str,
+    content_type: &str,
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let config = UploadConfig::from_env()?;
+    let block_size = config.part_size.min(MAX_BLOCK_SIZE);
+    let mut chunk = read_block(reader, block_size).await?;
+    if (chunk.len() as u64) < block_size {
+        return bounded(retry(&config, "upload blob", || {
+            container.put_blob(key, chunk.clone(), content_type, metadata)
+        }))
+        .await;
+    }
+    let block_ids = bounded(put_blocks(container, reader, key, chunk, &config)).await?;
+    retry(&config, "commit block list", || {
+        container.put_block_list(key, &block_ids, content_type, metadata)
+    })
+    .await
+}
+
+/// Stages the first chunk and the rest of the reader as the blocks of
+/// the blob, returning their IDs in order.
+async fn put_blocks<R: AsyncRead + Unpin>(
+    container: &AzureContainer,
+    reader: &mut R,
+    key: &str,
+    mut chunk: Vec<u8>,
+    config: &UploadConfig,
+) -> Result<Vec<String>, Error> {
+    let block_size = config.part_size.min(MAX_BLOCK_SIZE);
+    let mut block_ids = Vec::new();
+    while !chunk.is_empty() {
+        let id = block_id(block_ids.len());
+        retry(config, "upload block", || {
+            container.put_block(key, &id, chunk.clone())
+        })
+        .await?;
+        block_ids.push(id);
+        chunk = read_block(reader, block_size).await?;
+    }
+    Ok(block_ids)
+}
+
+/// Reads up to a block's worth of bytes, less only at the end.
+async fn read_block<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    block_size: u64,
+) -> Result<Vec<u8>, Error> {
+    let mut chunk = Vec::with_capacity(block_size as usize);
+    (&mut *reader)
+        .take(block_size)
+        .read_to_end(&mut chunk)
+        .await?;
+    Ok(chunk)
+}
+
+/// Calls `f` until it succeeds, it fails with an error that is not
+/// transient, or the attempts are exhausted. Staging a block or blob
+/// again overwrites it, so every request is safe to retry.
+async fn retry<T, F, Fut>(config: &UploadConfig, what: &str, mut f: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < config.max_attempts && is_transient(&e) => {
+                warn!(
+                    error = %e,
+                    attempt,
+                    backoff = ?backoff,
+                    "Failed to {}, retrying",
+                    what
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Clients of the backends the metadata and content are written to,
+//! other than the object stores. A target's client connects when the
+//! first entity of the batch is written to it, and is reused by the
+//! rest, as the outputs of a batch share their targets. A connection
+//! that isn't replaced by its library when it breaks is dropped once a
+//! request on it fails, so that the next entity connects again.
+use std::{collections::HashMap, fmt::Display, future::Future};
+use tokio::sync::Mutex;
+use ytdl_common::Error;
+
+/// A backend whose client library the common crate doesn't link.
+#[derive(Debug, Clone, Copy)]
+pub enum Backend {
+    Sql,
+    MongoDb,
+    Kafka,
+    Nats,
+}
+
+impl Backend {
+    /// Converts an error of the backend's client library.
+    pub fn error<E: Display>(self, e: E) -> Error {
+        let message = e.to_string();
+        match self {
+            Backend::Sql => Error::SqlError { message },
+            Backend::MongoDb => Error::MongoDbError { message },
+            Backend::Kafka => Error::KafkaError { message },
+            Backend::Nats => Error::NatsError { message },
+        }
+    }
+}
+
+/// Extension for converting the errors of a backend's client library.
+pub trait OrBackend<T> {
+    fn or_backend(self, backend: Backend) -> Result<T, Error>;
+}
+
+impl<T, E: Display> OrBackend<T> for Result<T, E> {
+    fn or_backend(self, backend: Backend) -> Result<T, Error> {
+        self.map_err(|e| backend.error(e))
+    }
+}
+
+/// The clients of a backend's targets, by the name of the target.
+/// Clients are cheap to clone and share their connections.
+pub struct Clients<C> {
+    clients: Mutex<HashMap<String, C>>,
+}
+
+impl<C> Default for Clients<C> {
+    fn default() -> Self {
+        Clients {
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<C: Clone> Clients<C> {
+    /// Returns the client of the named target, connecting if there
+    /// is none yet.
+    pub async fn get<F, Fut>(&self, name: &str, connect: F) -> Result<C, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<C, Error>>,
+    {
+        let mut clients = self.clients.lock().await;
+        if let Some(client) = clients.get(name) {
+            return Ok(client.clone());
+        }
+        let client = connect().await?;
+        clients.insert(name.to_owned(), client.clone());
+        Ok(client)
+    }
+
+    /// Drops the named target's client if the result of its request is
+    /// an error, as the connection may be broken, and returns it.
+    pub async fn evict_on_error<T>(
+        &self,
+        name: &str,
+        result: Result<T, Error>,
+    ) -> Result<T, Error> {
+        if result.is_err() {
+            self.clients.lock().await.remove(name);
+        }
+        result
+    }
+}
@@ -0,0 +1,218 @@
+use kube::client::Client;
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{atomic::AtomicU64, Arc},
+    time::Instant,
+};
+use tokio::{fs, process::Command};
+use tracing::info;
+use ytdl_common::{chaos, get_video_output, Error};
+use ytdl_types::{Executor, StoredObject};
+
+use crate::{
+    manifest::hash_file,
+    schedule::upload_slot,
+    sniff::{correct_object, sniff_file},
+    timing::{self, Stage},
+    upload::{put_object_stream, with_checksum},
+    work::{download_file, get_file_name, get_work_dir},
+};
+
+/// Directory the video is downloaded to before it is split,
+/// unless the Executor has a work volume.
+const CHAPTERS_DIR: &str = "/tmp/chapters";
+
+/// A single chapter from the video's info json.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    /// One-based position of the chapter in the video.
+    pub number: usize,
+
+    /// Title of the chapter, or an empty string if it has none.
+    pub title: String,
+
+    /// Offset (seconds) of the start of the chapter.
+    pub start: f64,
+
+    /// Offset (seconds) of the end of the chapter.
+    pub end: f64,
+}
+
+/// Returns the chapters from the video's info json, which is
+/// empty if youtube-dl did not report any.
+pub fn get_chapters(metadata: &serde_json::Value) -> Vec<Chapter> {
+    metadata
+        .get("chapters")
+        .and_then(|chapters| chapters.as_array())
+        .map(|chapters| {
+            chapters
+                .iter()
+                .filter_map(|chapter| {
+                    Some((
+                        chapter.get("start_time")?.as_f64()?,
+                        chapter.get("end_time")?.as_f64()?,
+                        chapter
+                            .get("title")
+                            .and_then(|title| title.as_str())
+                            .unwrap_or("")
+                            .to_owned(),
+                    ))
+                })
+                .filter(|(start, end, _)| end > start)
+                .enumerate()
+                .map(|(i, (start, end, title))| Chapter {
+                    number: i + 1,
+                    title,
+                    start,
+                    end,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the info json with the chapter's template variables,
+/// `%(chapter_number)s` and `%(chapter_title)s`, added to it.
+/// Slashes are removed from the title so it can't add levels
+/// to the object key.
+fn chapter_metadata(metadata: &serde_json::Value, chapter: &Chapter) -> serde_json::Value {
+    let mut metadata = metadata.clone();
+    if let Some(object) = metadata.as_object_mut() {
+        object.insert(
+            "chapter_number".to_owned(),
+            chapter.number.to_string().into(),
+        );
+        object.insert(
+            "chapter_title".to_owned(),
+            chapter.title.replace('/', "_").into(),
+        );
+    }
+    metadata
+}
+
+/// Downloads the whole video to a file, then cuts it into the chapters
+/// with ffmpeg and uploads each one to the key templated from the
+/// chapter's variables. Streams are copied rather than re-encoded, so
+/// chapter boundaries are rounded to the nearest keyframe.
+pub async fn download_chapters(
+    client: Client,
+    metadata: &serde_json::Value,
+    chapters: &[Chapter],
+    command: &str,
+    instance: &Executor,
+    downloaded: Arc<AtomicU64>,
+) -> Result<Vec<StoredObject>, Error> {
+    // Template the keys up front so a template missing the chapter
+    // variables fails before anything is downloaded.
+    let mut outputs = Vec::with_capacity(chapters.len());
+    let mut keys = HashSet::new();
+    for chapter in chapters {
+        let output = get_video_output(
+            client.clone(),
+            &chapter_metadata(metadata, chapter),
+            instance,
+        )
+        .await?
+        .ok_or_else(|| {
+            Error::UnknownError("video output requested but no output spec provided".to_owned())
+        })?;
+        if !keys.insert(output.1.clone()) {
+            return Err(Error::UserInputError(format!(
+                "video key {} is used by more than one chapter, the key template must include %(chapter_number)s or %(chapter_title)s",
+                output.1
+            )));
+        }
+        outputs.push(output);
+    }
+    let dir = get_work_dir(instance, CHAPTERS_DIR);
+    fs::create_dir_all(&dir).await?;
+    let started = Instant::now();
+    let source = download_file(
+        command,
+        instance,
+        &dir,
+        &get_file_name(metadata),
+        &downloaded,
+    )
+    .await?;
+    timing::add(Stage::Download, started.elapsed());
+    let mut objects = Vec::with_capacity(chapters.len());
+    let ext = source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("mkv")
+        .to_owned();
+    for (chapter, (bucket, key)) in chapters.iter().zip(outputs) {
+        info!(
+            chapter = chapter.number,
+            title = %chapter.title,
+            bucket = %bucket.name,
+            key = %key,
+            "Uploading chapter"
+        );
+        let path = dir.join(format!("chapter-{}.{}", chapter.number, ext));
+        let started = Instant::now();
+        cut_chapter(&source, &path, chapter).await?;
+        let (key, content_type) = correct_object(key, sniff_file(&path).await?);
+        let (size, sha256) = hash_file(&path).await?;
+        chaos::s3_fault()?;
+        let status_code = {
+            // Only keep the file open for the duration of the upload.
+            let mut body = fs::File::open(&path).await?;
+            let _slot = upload_slot().await;
+            put_object_stream(
+                &with_checksum(&bucket, &sha256),
+                &mut body,
+                &key,
+                content_type,
+            )
+            .await?
+        };
+        // Each chapter is removed as soon as it's uploaded to
+        // minimize the disk space needed for long videos.
+        let _ = fs::remove_file(&path).await;
+        timing::add(Stage::Upload, started.elapsed());
+        if status_code != 200 {
+            return Err(Error::S3UploadError { status_code });
+        }
+        objects.push(StoredObject {
+            bucket: bucket.name.clone(),
+            key,
+            size,
+            sha256,
+        });
+    }
+    let _ = fs::remove_file(&source).await;
+    info!(
+        chapters = chapters.len(),
+        "Chapter uploads completed successfully"
+    );
+    Ok(objects)
+}
+
+/// Copies the chapter's streams from the source video into a new file.
+async fn cut_chapter(source: &Path, path: &Path, chapter: &Chapter) -> Result<(), Error> {
+    let status = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y", "-ss"])
+        .arg(chapter.start.to_string())
+        .arg("-t")
+        .arg((chapter.end - chapter.start).to_string())
+        .arg("-i")
+        .arg(source)
+        .args(["-map", "0", "-c", "copy"])
+        .arg(path)
+        .status()
+        .await?;
+    if status.success() {
+        return Ok(());
+    }
+    match status.code() {
+        Some(exit_code) => Err(Error::FfmpegError { exit_code }),
+        // The process was killed by a signal, e.g. the OOM killer.
+        None => Err(Error::UnknownError(format!(
+            "ffmpeg was terminated: {}",
+            status
+        ))),
+    }
+}
@@ -0,0 +1,53 @@
+//! The download pod's deadline, passed by the controller. Work that is
+//! still running when it passes fails with [`Error::DeadlineExceeded`],
+//! giving the uploads a chance to clean up their partial state before
+//! the executor exits and reports the timeout.
+use lazy_static::lazy_static;
+use std::{future::Future, time::Duration};
+use tokio::time::Instant;
+use tracing::warn;
+use ytdl_common::{deadline::get_deadline, Error};
+
+lazy_static! {
+    /// Instant the deadline passes, if the pod has one. An invalid
+    /// deadline is ignored, leaving the pod's own as the only one.
+    static ref DEADLINE: Option<Instant> = match get_deadline() {
+        Ok(deadline) => deadline.map(|deadline| {
+            let remaining = (deadline - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO);
+            Instant::now() + remaining
+        }),
+        Err(e) => {
+            warn!(error = %e, "Ignoring the download deadline");
+            None
+        }
+    };
+}
+
+/// Returns an error if the deadline has passed.
+pub fn check() -> Result<(), Error> {
+    match *DEADLINE {
+        Some(deadline) if Instant::now() >= deadline => Err(Error::DeadlineExceeded),
+        _ => Ok(()),
+    }
+}
+
+/// Resolves once the deadline passes, and never if there is none.
+pub async fn expired() -> Error {
+    match *DEADLINE {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => futures::future::pending().await,
+    }
+    Error::DeadlineExceeded
+}
+
+/// Runs the future until it completes or the deadline passes, in which
+/// case it is dropped. Callers clean up whatever it left behind.
+pub async fn bounded<T, F>(future: F) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    tokio::select! {
+        result = future => result,
+        error = expired() => Err(error),
+    }
+}
@@ -1,164 +1,581 @@
-use image::{imageops::FilterType, DynamicImage, ImageFormat};
-use kube::client::Client;
+use kube::{client::Client, ResourceExt};
 use s3::bucket::Bucket;
-use scopeguard::defer;
-use std::{env, ffi::OsStr, path::Path, process::Stdio};
-use tokio::process::Command;
-use tokio::{fs, io::BufReader};
-use ytdl_common::{get_thumbnail_output, get_video_output, Error, Output};
-use ytdl_types::{Executor, ThumbnailStorageSpec};
+use std::{
+    collections::BTreeMap,
+    env,
+    process::{ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::process::{ChildStderr, Command};
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+};
+use tracing::{info, warn};
+use ytdl_common::{
+    auth::get_auth_args,
+    chaos,
+    cookies::get_cookies_file,
+    failure::{classify_output, ExitCode, FailureReason},
+    get_job_metadata, get_thumbnail_output, get_video_output,
+    kafka::get_kafka_outputs,
+    mongodb::get_mongodb_outputs,
+    nats::get_nats_outputs,
+    object_headers::with_object_headers,
+    pod::has_vpn_sidecar,
+    progress::get_progress_port,
+    proxy::{get_proxy_url, SYSTEM_PROXY_ENVS},
+    query_engine::{get_video_url, is_unresolved},
+    redis::get_redis_outputs,
+    skip::{SkipRecord, AGE_RESTRICTED_POLICY, GEO_BLOCKED_POLICY},
+    sql::get_sql_outputs,
+    sse::with_sse,
+    storage_class::with_storage_class,
+    store::get_store_outputs,
+    tagging::{put_tags, render_tags},
+    upcoming::is_upcoming,
+    volume::get_volume_outputs,
+    wants_content, Error, Output,
+};
+use ytdl_types::{
+    AgeRestrictedPolicy, ContentType, Executor, GeoBlockedPolicy, S3OutputSpec, StoredObject,
+};
+
+use crate::{
+    chapters::{download_chapters, get_chapters},
+    deadline,
+    egress::{CountingReader, EgressReporter},
+    exit::{Fatal, OrExit},
+    kafka::produce_metadata,
+    manifest::{report_objects, report_skipped, HashingReader},
+    mongodb, nats,
+    pipeline::{
+        ConvertImage, Entity, FetchThumbnail, FetchVideo, Pipeline, S3Sink, Sink, SniffContainer,
+        Tagging,
+    },
+    progress::{self, PROGRESS_TEMPLATE},
+    redis,
+    schedule::upload_slot,
+    sniff::{correct_object, sniff, SNIFF_LEN},
+    sql::upsert_metadata,
+    stall::{get_stall_timeout, stalled, MAX_STALL_RETRIES},
+    thumbnail::{get_thumbnail_options, ThumbnailOptions},
+    timing::{self, Stage},
+    upload::put_object_stream,
+};
 
 /// Path for the metadata info json file. youtube-dl can only
 /// load this from a file, and it's convenient to write it out
 /// for debugging purposes (e.g. `cat /info.json`).
 const INFO_JSON_PATH: &str = "/info.json";
 
-pub async fn download(client: Client, command: &str, dl_video: bool, dl_thumbnail: bool) {
+/// What the download pod was created to do, per the controller's
+/// checks of the Executor's outputs.
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadOptions {
+    /// Download the video to its outputs.
+    pub download_video: bool,
+
+    /// Download the thumbnail to its outputs.
+    pub download_thumbnail: bool,
+
+    /// Publish the metadata to the metadata targets.
+    pub publish_metadata: bool,
+}
+
+pub async fn download(
+    client: Client,
+    command: &str,
+    options: DownloadOptions,
+) -> Result<(), Fatal> {
     // Parse the resource from the environment.
-    let instance: Executor =
-        get_resource().expect("failed to get Executor resource from environment");
+    let instance: Executor = get_resource().or_exit(
+        ExitCode::Config,
+        "failed to get Executor resource from environment",
+    )?;
 
-    // Write the video metadata to a file so youtube-dl
-    // won't query the video service again.
-    fs::write(INFO_JSON_PATH, &instance.spec.metadata)
+    // Wait for the VPN to connect before starting the download.
+    if !has_vpn_sidecar(instance.spec.vpn.as_ref(), instance.spec.proxy.as_ref()) {
+        info!("Environment parsed, pod has no VPN sidecar");
+    } else {
+        info!("Environment parsed, waiting for VPN to connect");
+        let started = Instant::now();
+        crate::ready::wait_for_vpn()
+            .await
+            .or_exit(ExitCode::Vpn, "vpn failed to connect")?;
+        timing::add(Stage::VpnWait, started.elapsed());
+    }
+
+    // Bytes downloaded are reported for per-namespace egress accounting.
+    let mut egress = EgressReporter::new(client.clone(), &instance)
         .await
-        .expect("failed to write video info json to file");
+        .or_exit(ExitCode::Download, "failed to initialize egress reporter")?;
 
-    // Parse the video metadata json from the spec.
-    let metadata: serde_json::Value = instance
+    // Progress of the current video is served for the controller to
+    // scrape, if it's configured to, and reported in the background
+    // otherwise.
+    match get_progress_port().or_exit(ExitCode::Config, "failed to get progress port")? {
+        Some(port) => {
+            tokio::spawn(async move {
+                if let Err(e) = progress::serve(port).await {
+                    warn!(error = %e, "Failed to serve download progress");
+                }
+            });
+        }
+        None => {
+            tokio::spawn(progress::report_periodically(
+                client.clone(),
+                instance.clone(),
+            ));
+        }
+    }
+
+    // Number of entities after which the VPN is reconnected, if any.
+    let rotate_ip_every = instance
         .spec
-        .metadata
+        .vpn
+        .as_ref()
+        .and_then(|vpn| vpn.rotate_ip_every)
+        .filter(|_| has_vpn_sidecar(instance.spec.vpn.as_ref(), instance.spec.proxy.as_ref()))
+        .filter(|every| *every > 0)
+        .map(|every| every as usize);
+
+    // Objects uploaded so far, reported for the Download's manifest.
+    let mut objects: Vec<StoredObject> = Vec::new();
+
+    // Entities of the batch skipped per policy so far.
+    let mut skipped: Vec<SkipRecord> = Vec::new();
+
+    // Download each entity in the batch sequentially, reusing
+    // the same VPN connection for all of them unless rotating.
+    let batch = get_job_metadata(&instance);
+    for (i, metadata) in batch.iter().enumerate() {
+        if let Some(every) = rotate_ip_every.filter(|every| i > 0 && i % every == 0) {
+            info!(entity = i + 1, every, "Rotating VPN exit IP");
+            let started = Instant::now();
+            crate::ready::rotate_ip()
+                .await
+                .or_exit(ExitCode::Vpn, "failed to rotate vpn exit ip")?;
+            timing::add(Stage::VpnWait, started.elapsed());
+        }
+        // Don't start an entity the pod has no time left for.
+        deadline::check().or_exit(ExitCode::Download, "failed to process batch")?;
+        info!(entity = i + 1, total = batch.len(), "Processing entity");
+        let result = download_entity(
+            client.clone(),
+            command,
+            &instance,
+            metadata,
+            options,
+            &mut egress,
+        )
+        .await;
+        let uploaded = match result {
+            Ok(uploaded) => uploaded,
+            Err(fatal) => {
+                // A single entity is skipped by the controller, which marks
+                // the whole Executor as Skipped. In a batch, only the entity
+                // is skipped and the rest of the batch is still downloaded.
+                let record = match get_skip_record(&instance, metadata, &fatal.error) {
+                    Some(record) if batch.len() > 1 => record,
+                    _ => return Err(fatal),
+                };
+                info!(id = %record.id, policy = %record.policy, "Skipping entity");
+                skipped.push(record);
+                report_skipped(client.clone(), &instance, &skipped)
+                    .await
+                    .or_exit(ExitCode::Upload, "failed to report skipped entity")?;
+                continue;
+            }
+        };
+        if !uploaded.is_empty() {
+            objects.extend(uploaded);
+            report_objects(client.clone(), &instance, &objects).await;
+        }
+        timing::report(client.clone(), &instance).await;
+    }
+    Ok(())
+}
+
+/// Returns the record of the entity that failed with the error, if the
+/// Executor's policy is to skip such entities.
+fn get_skip_record(instance: &Executor, info_json: &str, error: &Error) -> Option<SkipRecord> {
+    let (policy, reason) = match error {
+        Error::AgeRestricted(_)
+            if instance.spec.age_restricted.unwrap_or_default() == AgeRestrictedPolicy::Skip =>
+        {
+            (AGE_RESTRICTED_POLICY, "skipped age-restricted video")
+        }
+        Error::GeoBlocked(_)
+            if instance.spec.geo_blocked.unwrap_or_default() == GeoBlockedPolicy::Skip =>
+        {
+            (GEO_BLOCKED_POLICY, "skipped geo-blocked video")
+        }
+        _ => return None,
+    };
+    let info: serde_json::Value = serde_json::from_str(info_json).ok()?;
+    Some(SkipRecord {
+        id: info.get("id")?.as_str()?.to_owned(),
+        policy: policy.to_owned(),
+        reason: format!("{}: {}", reason, error),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+/// Downloads the video and/or thumbnail for a single entity, publishes
+/// its metadata, and returns the objects that were uploaded.
+async fn download_entity(
+    client: Client,
+    command: &str,
+    instance: &Executor,
+    info_json: &str,
+    options: DownloadOptions,
+    egress: &mut EgressReporter,
+) -> Result<Vec<StoredObject>, Fatal> {
+    // Parse the video metadata json from the spec.
+    let mut metadata: serde_json::Value = info_json
         .parse()
-        .expect("failed to parse video info json");
+        .map_err(Error::from)
+        .or_exit(ExitCode::Config, "failed to parse video info json")?;
 
-    // Get the extra args from the spec.
-    let extra: &Option<Vec<String>> = &instance.spec.extra;
+    // A premiere that was upcoming when queried has no formats
+    // in its metadata, so it is queried again now that it started.
+    // Entries listed without their metadata by a query engine other
+    // than youtube-dl are queried for the first time.
+    let info_json = if is_upcoming(&metadata) || is_unresolved(&metadata) {
+        let info_json = refresh_metadata(command, &metadata)
+            .await
+            .or_exit(ExitCode::Download, "failed to query video")?;
+        metadata = info_json.parse().map_err(Error::from).or_exit(
+            ExitCode::Download,
+            "failed to parse refreshed video info json",
+        )?;
+        info_json
+    } else {
+        info_json.to_owned()
+    };
 
-    // Determine what we need to do, download-wise, and
-    // get the output objects at the same time.
-    let outputs = get_outputs(client, &metadata, &instance, dl_video, dl_thumbnail)
+    // Write the video metadata to a file so youtube-dl
+    // won't query the video service again.
+    fs::write(INFO_JSON_PATH, &info_json)
         .await
-        .expect("failed to get outputs");
+        .map_err(Error::from)
+        .or_exit(ExitCode::Config, "failed to write video info json to file")?;
 
-    // Wait for the VPN to connect before starting the download.
-    println!("Environment parsed, waiting for VPN to connect");
-    crate::ready::wait_for_vpn()
-        .await
-        .expect("vpn failed to connect");
-
-    // Start the download(s).
-    match outputs {
-        // Download both video and thumbnail concurrently.
-        (Some(video_output), Some(thumbnail_output)) => {
-            let thumbnail_opts = get_thumbnail_options(&instance, &thumbnail_output.1)
-                .expect("thumbnail output options");
-            println!("Downloading video and thumbnail");
-            let result = tokio::join!(
-                download_video(&metadata, video_output.0, video_output.1, &command, extra),
-                download_thumbnail(
-                    &metadata,
-                    thumbnail_opts,
-                    thumbnail_output.0,
-                    thumbnail_output.1
-                ),
-            );
-            result.0.expect("failed to download video");
-            result.1.expect("failed to download thumbnail");
-        }
-        // Download the video only.
-        (Some(video_output), None) => {
-            println!("Downloading video");
-            download_video(&metadata, video_output.0, video_output.1, &command, extra)
+    // Never invoke youtube-dl or fetch the thumbnail if the
+    // corresponding content type was excluded by the user.
+    let dl_video =
+        options.download_video && wants_content(&instance.spec.content, ContentType::Audiovisual);
+    let dl_thumbnail =
+        options.download_thumbnail && wants_content(&instance.spec.content, ContentType::Thumbnail);
+    if !dl_video && !dl_thumbnail {
+        // Only the metadata is published, as the Executor doesn't want
+        // the content or an earlier pod already stored it.
+        info!("No content to download");
+        if options.publish_metadata {
+            publish_metadata(client, instance, &metadata, &info_json, &[])
                 .await
-                .expect("failed to download video");
+                .or_exit(ExitCode::Upload, "failed to publish metadata")?;
         }
-        // Download the thumbnail only.
-        (None, Some(thumbnail_output)) => {
-            let thumbnail_opts = get_thumbnail_options(&instance, &thumbnail_output.1)
-                .expect("thumbnail output options");
-            println!("Downloading thumbnail");
-            download_thumbnail(
-                &metadata,
-                thumbnail_opts,
-                thumbnail_output.0,
-                thumbnail_output.1,
-            )
+        return Ok(vec![]);
+    }
+
+    // Determine what we need to do, download-wise, and
+    // get the output objects at the same time.
+    let outputs = get_outputs(client.clone(), &metadata, instance, dl_video, dl_thumbnail)
+        .await
+        .or_exit(ExitCode::Config, "failed to get outputs")?;
+    let tagging = get_tagging(instance, &metadata, &outputs)
+        .or_exit(ExitCode::Config, "failed to render object tags")?;
+    let (video_output, thumbnail_output) = configure_outputs(instance, outputs);
+
+    let entity = Entity {
+        command,
+        instance,
+        metadata: &metadata,
+        // Bytes downloaded for this entity, including partial downloads
+        // that failed, as those still count towards the egress.
+        downloaded: Arc::new(AtomicU64::new(0)),
+    };
+
+    // Download the video and thumbnail concurrently.
+    let (video_result, thumbnail_result) = tokio::join!(
+        async {
+            if !dl_video {
+                return Ok(Vec::new());
+            }
+            store_video(client.clone(), &entity, video_output, tagging.0).await
+        },
+        async {
+            if !dl_thumbnail {
+                return Ok(Vec::new());
+            }
+            store_thumbnail(client.clone(), &entity, thumbnail_output, tagging.1).await
+        },
+    );
+
+    // Report the bytes before a failure terminates the pod.
+    egress.add(entity.downloaded.load(Ordering::Relaxed)).await;
+    let mut objects = video_result.or_exit(ExitCode::Download, "failed to download video")?;
+    objects.extend(thumbnail_result.or_exit(ExitCode::Download, "failed to download thumbnail")?);
+    if options.publish_metadata {
+        publish_metadata(client, instance, &metadata, &info_json, &objects)
             .await
-            .expect("failed to download thumbnail");
-        }
-        (None, None) => {
-            // The operator should never create an executor pod
-            // without specifying at least one of the options.
-            panic!("no download options specified");
+            .or_exit(ExitCode::Upload, "failed to publish metadata")?;
+    }
+    Ok(objects)
+}
+
+/// Upserts the metadata into the SQL and MongoDB targets, produces the
+/// metadata json to the Kafka targets, and publishes it, or a completion event
+/// with the uploaded objects, to the NATS targets once the content is
+/// stored, so that consumers never see a video before its files.
+async fn publish_metadata(
+    client: Client,
+    instance: &Executor,
+    metadata: &serde_json::Value,
+    info_json: &str,
+    objects: &[StoredObject],
+) -> Result<(), Error> {
+    if !wants_content(&instance.spec.content, ContentType::Metadata) {
+        return Ok(());
+    }
+    let namespace = get_namespace(instance)?;
+    let target = &instance.spec.output;
+    let databases = get_sql_outputs(client.clone(), &namespace, target).await?;
+    upsert_metadata(&databases, metadata).await?;
+    let content = ContentType::Metadata;
+    let collections =
+        get_mongodb_outputs(client.clone(), &namespace, target, metadata, content).await?;
+    mongodb::upsert_metadata(&collections, metadata).await?;
+    let keys = get_redis_outputs(client.clone(), &namespace, target, metadata, content).await?;
+    redis::set_metadata(&keys, info_json).await?;
+    let kafka = get_kafka_outputs(client.clone(), &namespace, target, metadata).await?;
+    produce_metadata(&kafka, info_json).await?;
+    let subjects = get_nats_outputs(client, &namespace, target, metadata).await?;
+    nats::publish(&subjects, instance, metadata, info_json, objects).await
+}
+
+/// Downloads the video and uploads it to its outputs. Without a work
+/// volume, the video is streamed straight to its S3 output, and split
+/// into one object per chapter if the user asked for it. Otherwise it
+/// is saved to a file first, so that it is downloaded once however many
+/// outputs there are, and a failed upload is retried without
+/// downloading the video again.
+async fn store_video(
+    client: Client,
+    entity: &Entity<'_>,
+    output: Option<Output>,
+    tagging: Tagging,
+) -> Result<Vec<StoredObject>, Error> {
+    let instance = entity.instance;
+    let mut pipeline = Pipeline::new("video", FetchVideo).validate(SniffContainer);
+    let mut objects = Vec::new();
+    if let Some((bucket, key)) = output {
+        let chapters = if instance.spec.split_chapters.unwrap_or(false) {
+            get_chapters(entity.metadata)
+        } else {
+            vec![]
+        };
+        if !chapters.is_empty() {
+            info!(chapters = chapters.len(), "Splitting video by chapter");
+            objects = download_chapters(
+                client.clone(),
+                entity.metadata,
+                &chapters,
+                entity.command,
+                instance,
+                entity.downloaded.clone(),
+            )
+            .await?;
+            tag_objects(&tagging, &objects).await?;
+        } else if instance.spec.work_volume.is_none() {
+            let object = download_video(
+                entity.metadata,
+                bucket,
+                key,
+                entity.command,
+                instance,
+                entity.downloaded.clone(),
+            )
+            .await?;
+            tag_objects(&tagging, std::slice::from_ref(&object)).await?;
+            objects.push(object);
+        } else {
+            pipeline = pipeline.output(S3Sink::new(bucket, tagging), key);
         }
     }
+    let namespace = get_namespace(instance)?;
+    let target = &instance.spec.output;
+    let content = ContentType::Audiovisual;
+    let stores =
+        get_store_outputs(client.clone(), &namespace, target, entity.metadata, content).await?;
+    for (store, key) in stores {
+        pipeline = pipeline.output(store, key);
+    }
+    let volumes =
+        get_volume_outputs(client.clone(), &namespace, target, entity.metadata, content).await?;
+    for (dir, path) in volumes {
+        pipeline = pipeline.output(dir, path);
+    }
+    let collections =
+        get_mongodb_outputs(client.clone(), &namespace, target, entity.metadata, content).await?;
+    for (collection, id) in collections {
+        pipeline = pipeline.output(collection, id);
+    }
+    let keys = get_redis_outputs(client, &namespace, target, entity.metadata, content).await?;
+    for (server, key) in keys {
+        pipeline = pipeline.output(server, key);
+    }
+    objects.extend(pipeline.run(entity).await?);
+    Ok(objects)
 }
 
-/// A struct containing the processing options when downloading
-/// thumbnails. To prevent a bucket from receiving thumbnails of
-/// mixed formats, the user must specify and output format for
-/// all thumbnails. If the user does not specify a format, the
-/// format can be inferred from the output key. If the format
-/// cannot be inferred at all, the download will fail.
-/// If only one of the resize dimensions is set, the image
-/// will be resized proportionally, keeping the aspect ratio.
-struct ThumbnailOptions {
-    /// Output format for the thumbnail. Conversion is enforced
-    /// to normalize the format across all thumbnails.
-    format: ImageFormat,
-
-    /// Sampling filter to use when resizing.
-    filter: FilterType,
-
-    /// Maximum width (pixels) of the thumbnail image.
-    width: Option<u32>,
-
-    /// Maximum height (pixels) of the thumbnail image.
-    height: Option<u32>,
+/// Downloads the thumbnail, converts it to the output format, and
+/// uploads it to its outputs.
+async fn store_thumbnail(
+    client: Client,
+    entity: &Entity<'_>,
+    output: Option<Output>,
+    tagging: Tagging,
+) -> Result<Vec<StoredObject>, Error> {
+    let instance = entity.instance;
+    let namespace = get_namespace(instance)?;
+    let target = &instance.spec.output;
+    let content = ContentType::Thumbnail;
+    let stores =
+        get_store_outputs(client.clone(), &namespace, target, entity.metadata, content).await?;
+    let volumes =
+        get_volume_outputs(client.clone(), &namespace, target, entity.metadata, content).await?;
+    let collections =
+        get_mongodb_outputs(client.clone(), &namespace, target, entity.metadata, content).await?;
+    let keys = get_redis_outputs(client, &namespace, target, entity.metadata, content).await?;
+    let mut outputs: Vec<(Box<dyn Sink>, String)> = Vec::new();
+    if let Some((bucket, key)) = output {
+        outputs.push((Box::new(S3Sink::new(bucket, tagging)), key));
+    }
+    for (store, key) in stores {
+        outputs.push((Box::new(store), key));
+    }
+    for (dir, path) in volumes {
+        outputs.push((Box::new(dir), path));
+    }
+    for (collection, id) in collections {
+        outputs.push((Box::new(collection), id));
+    }
+    for (server, key) in keys {
+        outputs.push((Box::new(server), key));
+    }
+    // Each output gets the format inferred from its own key if the spec
+    // doesn't set one, so the thumbnail is converted once per distinct
+    // format, falling back to the format of the downloaded thumbnail.
+    let mut groups: Vec<(ThumbnailOptions, Vec<(Box<dyn Sink>, String)>)> = Vec::new();
+    for (sink, key) in outputs {
+        let options = get_thumbnail_options(instance, &key)?;
+        match groups
+            .iter_mut()
+            .find(|(group, _)| group.format == options.format)
+        {
+            Some((_, group)) => group.push((sink, key)),
+            None => groups.push((options, vec![(sink, key)])),
+        }
+    }
+    let mut objects = Vec::new();
+    for (options, outputs) in groups {
+        let mut pipeline =
+            Pipeline::new("thumbnail", FetchThumbnail).transform(ConvertImage::new(options));
+        for (sink, key) in outputs {
+            pipeline = pipeline.output(sink, key);
+        }
+        objects.extend(pipeline.run(entity).await?);
+    }
+    Ok(objects)
 }
 
-/// Returns a struct containing download and processing options
-/// for the thumbnail. The options are determined by the spec
-/// and the output key is used to infer output format if it's
-/// not specified explicitly in the spec.
-fn get_thumbnail_options(instance: &Executor, key: &str) -> Result<ThumbnailOptions, Error> {
-    // All of the thumbnail output options are specified in a single
-    // section of the spec that addresses thumbnail storage.
-    let thumbnail: &ThumbnailStorageSpec = instance.spec.output.thumbnail.as_ref().unwrap();
-    // Determine the sampling filter to use when resizing.
-    let filter: FilterType = match thumbnail.filter {
-        // User can override the filter in the spec.
-        Some(ref filter) => parse_filter_type(filter).ok_or_else(|| {
-            Error::UserInputError(format!("unsupported image filter: {}", filter))
-        })?,
-        // Default filter is the highest quality.
-        None => FilterType::Lanczos3,
-    };
-    // Determine the output image format, which may be
-    // different from the downloaded thumbnail and will
-    // necessitate conversion.
-    let format: ImageFormat = match thumbnail.format {
-        // Prefer the overridden format in the spec.
-        Some(ref format) => ImageFormat::from_extension(format).ok_or_else(|| {
-            Error::UserInputError(format!("unsupported thumbnail format: {}", format))
-        })?,
-        // Default to the format inferred from the output key.
-        None => match get_format_from_filename(key) {
-            // Output S3 key was a valid image extension.
-            Some(format) => format,
-            // Image format cannot be inferred from spec.
-            None => return Err(Error::UserInputError(
-                "thumbnail output format not specified and could not be inferred from output key"
-                    .to_owned(),
-            )),
-        },
+/// Returns the tagging of the video and thumbnail outputs. The buckets
+/// are kept without the headers the uploads are configured with, which
+/// don't apply to PutObjectTagging.
+fn get_tagging(
+    instance: &Executor,
+    metadata: &serde_json::Value,
+    (video, thumbnail): &(Option<Output>, Option<Output>),
+) -> Result<(Tagging, Tagging), Error> {
+    let output = &instance.spec.output;
+    let video_tags = output
+        .video
+        .as_ref()
+        .and_then(|video| video.s3.as_ref())
+        .and_then(|s3| s3.tags.as_ref());
+    let thumbnail_tags = output
+        .thumbnail
+        .as_ref()
+        .and_then(|thumbnail| thumbnail.s3.as_ref())
+        .and_then(|s3| s3.tags.as_ref());
+    let tagging = |output: &Option<Output>,
+                   tags: Option<&BTreeMap<String, String>>|
+     -> Result<Tagging, Error> {
+        match (output, tags) {
+            (Some((bucket, _)), Some(tags)) if !tags.is_empty() => {
+                Ok(Some((bucket.clone(), render_tags(metadata, tags)?)))
+            }
+            _ => Ok(None),
+        }
     };
-    Ok(ThumbnailOptions {
-        format,
-        filter,
-        width: thumbnail.width,
-        height: thumbnail.height,
-    })
+    Ok((
+        tagging(video, video_tags)?,
+        tagging(thumbnail, thumbnail_tags)?,
+    ))
+}
+
+/// Tags the uploaded objects, if the output has tags.
+async fn tag_objects(tagging: &Tagging, objects: &[StoredObject]) -> Result<(), Error> {
+    if let Some((bucket, tags)) = tagging {
+        for object in objects {
+            put_tags(bucket, &object.key, tags).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Maximum interval at which youtube-dl checks whether an upcoming
+/// video has started, for premieres that start late.
+const WAIT_FOR_VIDEO_INTERVAL: &str = "60";
+
+/// Queries the full metadata of the video, waiting for it to start if
+/// it's upcoming. Returns the refreshed info json.
+async fn refresh_metadata(command: &str, metadata: &serde_json::Value) -> Result<String, Error> {
+    let webpage_url = get_video_url(metadata)
+        .ok_or_else(|| Error::UserInputError("metadata has no webpage_url or url".to_owned()))?;
+    info!(url = webpage_url, "Querying the video's metadata");
+    let proxy = get_proxy_url()?;
+    let cookies = get_cookies_file()?;
+    let mut args = vec!["-j", "--wait-for-video", WAIT_FOR_VIDEO_INTERVAL];
+    if let Some(ref proxy) = proxy {
+        args.push("--proxy");
+        args.push(proxy);
+    }
+    if let Some(ref cookies) = cookies {
+        args.push("--cookies");
+        args.push(cookies);
+    }
+    let auth = get_auth_args()?;
+    args.extend(auth.iter().map(String::as_str));
+    args.push(webpage_url);
+    let output = youtube_dl(command)
+        .args(&args[..])
+        .stderr(Stdio::inherit())
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(youtube_dl_error(output.status));
+    }
+    String::from_utf8(output.stdout)
+        .map(|info_json| info_json.trim_end().to_owned())
+        .map_err(|e| Error::UnknownError(format!("info json is not valid utf-8: {}", e)))
 }
 
 /// Parses the Executor resource from the environment.
@@ -166,19 +583,17 @@ fn get_resource() -> Result<Executor, Error> {
     Ok(serde_json::from_str(&env::var("RESOURCE")?)?)
 }
 
-/// Error code for missing video output spec. The operator
-/// should never ask an Executor pod to download a video
-/// without providing an output spec. This is considered
-/// an unreachable error.
-const NO_VIDEO_OUTPUT: &str = "video output requested but no output spec provided";
-
-/// Error code for missing thumbnail output spec. The operator
-/// should never ask an Executor pod to download a thumbnail
-/// without providing an output spec. This is considered
-/// an unreachable error.
-const NO_THUMBNAIL_OUTPUT: &str = "thumbnail output requested but no output spec provided";
+/// Returns the namespace of the Executor, which the resource in the
+/// environment must have.
+fn get_namespace(instance: &Executor) -> Result<String, Error> {
+    instance
+        .namespace()
+        .ok_or_else(|| Error::UserInputError("Executor resource has no namespace".to_owned()))
+}
 
-/// Returns the output objects for the executor.
+/// Returns the S3 output objects for the executor. An output is
+/// missing if the content is only stored in GCS, Azure, SFTP, WebDAV,
+/// volume, or MongoDB targets.
 async fn get_outputs(
     client: Client,
     metadata: &serde_json::Value,
@@ -194,240 +609,270 @@ async fn get_outputs(
                 get_video_output(client.clone(), &metadata, &instance),
                 get_thumbnail_output(client.clone(), &metadata, &instance),
             );
-            let video_output = result.0?.expect(NO_VIDEO_OUTPUT);
-            let thumbnail_output = result.1?.expect(NO_THUMBNAIL_OUTPUT);
-            Ok((Some(video_output), Some(thumbnail_output)))
+            Ok((result.0?, result.1?))
         }
         // Operator is asking this executor to download just the video.
         (true, false) => {
-            let video_output = get_video_output(client, metadata, instance)
-                .await?
-                .expect(NO_VIDEO_OUTPUT);
-            Ok((Some(video_output), None))
+            let video_output = get_video_output(client, metadata, instance).await?;
+            Ok((video_output, None))
         }
         // Operator is asking this executor to download just the thumbnail.
         (false, true) => {
-            let thumbnail_output = get_thumbnail_output(client, metadata, instance)
-                .await?
-                .expect(NO_THUMBNAIL_OUTPUT);
-            Ok((None, Some(thumbnail_output)))
+            let thumbnail_output = get_thumbnail_output(client, metadata, instance).await?;
+            Ok((None, thumbnail_output))
         }
         // Operator is asking this executor to download nothing.
         // This is an unreachable branch because the operator
         // should never create an executor pod without specifying
         // at least one of the download options.
-        (false, false) => {
-            panic!("no download options specified");
-        }
+        (false, false) => Err(Error::UserInputError(
+            "no download options specified".to_owned(),
+        )),
+    }
+}
+
+/// Has the output buckets encrypt the objects they upload, store them
+/// in the storage class, and send their headers per the output specs.
+fn configure_outputs(
+    instance: &Executor,
+    (video, thumbnail): (Option<Output>, Option<Output>),
+) -> (Option<Output>, Option<Output>) {
+    let output = &instance.spec.output;
+    let video_spec = output.video.as_ref().and_then(|video| video.s3.as_ref());
+    let thumbnail_spec = output
+        .thumbnail
+        .as_ref()
+        .and_then(|thumbnail| thumbnail.s3.as_ref());
+    let configure = |spec: Option<&S3OutputSpec>, (bucket, key): Output| {
+        let bucket = with_sse(&bucket, spec.and_then(|spec| spec.sse.as_ref()));
+        let storage_class = spec.and_then(|spec| spec.storage_class.as_deref());
+        let bucket = with_storage_class(&bucket, storage_class);
+        let headers = spec.and_then(|spec| spec.object_headers.as_ref());
+        (with_object_headers(&bucket, headers), key)
+    };
+    (
+        video.map(|output| configure(video_spec, output)),
+        thumbnail.map(|output| configure(thumbnail_spec, output)),
+    )
+}
+
+/// Returns the youtube-dl command without the storage proxy in its
+/// environment, so that it reaches the video service through the
+/// pod's VPN or proxy rather than the proxy used for uploads.
+pub(crate) fn youtube_dl(command: &str) -> Command {
+    let mut command = Command::new(command);
+    for name in SYSTEM_PROXY_ENVS {
+        command.env_remove(name);
     }
+    command
 }
 
 /// Builds the AV download command for youtube-dl.
 /// Other commands (e.g. yt-dlp) are injected here.
-fn build_args(extra: &Option<Vec<String>>) -> Vec<&str> {
+pub(crate) fn build_args<'a>(
+    instance: &'a Executor,
+    proxy: Option<&'a str>,
+    cookies: Option<&'a str>,
+    auth: &'a [String],
+) -> Vec<&'a str> {
     let mut cmd = vec![
         "--load-info-json",
         INFO_JSON_PATH,
+        // Print machine-readable progress, one update per line.
+        "--newline",
+        "--progress",
+        "--progress-template",
+        PROGRESS_TEMPLATE,
     ];
-    if let Some(ref extra) = extra {
-        extra.iter().for_each(|arg| cmd.push(&arg));
+    if let Some(proxy) = proxy {
+        cmd.push("--proxy");
+        cmd.push(proxy);
+    }
+    if let Some(cookies) = cookies {
+        cmd.push("--cookies");
+        cmd.push(cookies);
+    }
+    cmd.extend(auth.iter().map(String::as_str));
+    if let Some(ref max_filesize) = instance.spec.max_filesize {
+        // Safeguard in case the metadata did not report a size.
+        cmd.push("--max-filesize");
+        cmd.push(max_filesize);
+    }
+    if let Some(ref rate_limit) = instance.spec.rate_limit {
+        cmd.push("--limit-rate");
+        cmd.push(rate_limit);
+    }
+    if let Some(ref extra_args) = instance.spec.extra_args {
+        cmd.extend(extra_args.iter().map(String::as_str));
     }
     cmd
 }
 
 /// Downloads the video and uploads it to the specified output.
+/// youtube-dl is restarted if it stalls, in which case the video is
+/// streamed again from the beginning, as a stream cannot be resumed.
 async fn download_video(
     metadata: &serde_json::Value,
     bucket: Bucket,
     key: String,
     command: &str,
-    extra: &Option<Vec<String>>,
-) -> Result<(), Error> {
+    instance: &Executor,
+    downloaded: Arc<AtomicU64>,
+) -> Result<StoredObject, Error> {
     // We pass the webpage_url value as the query to youtub-dl.
     let webpage_url: &str = metadata
         .get("webpage_url")
-        .ok_or_else(|| Error::UserInputError("metadata is missing webpage_url".to_owned()))?
+        .ok_or_else(|| Error::UserInputError("metadata has no webpage_url or url".to_owned()))?
         .as_str()
         .ok_or_else(|| Error::UserInputError("metadata webpage_url is not a string".to_owned()))?;
-    println!(
-        "Downloading video {} -> s3://{}/{}",
-        webpage_url, &bucket.name, &key
+    info!(
+        url = webpage_url,
+        bucket = %bucket.name,
+        key = %key,
+        "Downloading video"
     );
-    let mut child = Command::new(command)
-        .args(&build_args(extra)[..])
+    chaos::s3_fault()?;
+    let stall_timeout = get_stall_timeout(instance)?;
+    // The upload runs for as long as youtube-dl does.
+    let _slot = upload_slot().await;
+    let mut stalls = 0;
+    loop {
+        let result = stream_video(
+            &bucket,
+            key.clone(),
+            command,
+            instance,
+            downloaded.clone(),
+            stall_timeout,
+        )
+        .await;
+        match result {
+            Err(Error::Stalled { seconds }) if stalls < MAX_STALL_RETRIES => {
+                stalls += 1;
+                warn!(seconds, stalls, "youtube-dl stalled, restarting");
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Runs youtube-dl once, streaming its output to the bucket. youtube-dl
+/// is killed if no bytes are received for the duration of the timeout.
+async fn stream_video(
+    bucket: &Bucket,
+    key: String,
+    command: &str,
+    instance: &Executor,
+    downloaded: Arc<AtomicU64>,
+    stall_timeout: Option<Duration>,
+) -> Result<StoredObject, Error> {
+    let proxy = get_proxy_url()?;
+    let cookies = get_cookies_file()?;
+    let auth = get_auth_args()?;
+    let started = Instant::now();
+    let mut args = build_args(instance, proxy.as_deref(), cookies.as_deref(), &auth);
+    // Write the video to stdout, which moves the progress to stderr.
+    args.push("--output");
+    args.push("-");
+    let mut child = youtube_dl(command)
+        .args(&args[..])
         .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
+        .stderr(Stdio::piped())
         .spawn()?;
     let stdout = child
         .stdout
         .take()
         .ok_or_else(|| Error::UnknownError("failed to get child process stdout".to_owned()))?;
-    let mut reader = BufReader::new(stdout);
-    let status_code = bucket.put_object_stream(&mut reader, &key).await?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| Error::UnknownError("failed to get child process stderr".to_owned()))?;
+    // Watch stderr concurrently with the upload so known
+    // failure modes can be reported to the controller.
+    let stderr = tokio::spawn(watch_stderr(stderr));
+    // Bytes received from youtube-dl, sampled to detect a stall.
+    let progress = Arc::new(AtomicU64::new(0));
+    let mut stdout = CountingReader::new(stdout, progress.clone());
+    let transfer = async {
+        // Sniff the real container from the head of the stream, as the
+        // extension youtube-dl reported may not survive remuxing.
+        let mut head = Vec::with_capacity(SNIFF_LEN);
+        (&mut stdout)
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut head)
+            .await?;
+        timing::add(Stage::Query, started.elapsed());
+        let started = Instant::now();
+        let (key, content_type) = correct_object(key, sniff(&head));
+        let reader = std::io::Cursor::new(head).chain(stdout);
+        let mut reader =
+            BufReader::new(HashingReader::new(CountingReader::new(reader, downloaded)));
+        let upload = put_object_stream(bucket, &mut reader, &key, content_type).await;
+        timing::add(Stage::Download, started.elapsed());
+        Ok::<_, Error>((key, upload, reader.into_inner().finish()))
+    };
+    let (key, upload, (size, sha256)) = tokio::select! {
+        result = transfer => result?,
+        error = stalled(|| progress.load(Ordering::Relaxed), stall_timeout) => {
+            // Closing youtube-dl's stderr also ends the watcher.
+            let _ = child.kill().await;
+            return Err(error);
+        }
+    };
+    if let Err(Error::DeadlineExceeded) = upload {
+        // youtube-dl would otherwise run until its next write fails.
+        let _ = child.kill().await;
+    }
+    let status = child.wait().await?;
+    if let Ok(Some((reason, line))) = stderr.await {
+        match reason {
+            FailureReason::AgeRestricted => return Err(Error::AgeRestricted(line)),
+            FailureReason::GeoBlocked => return Err(Error::GeoBlocked(line)),
+            FailureReason::DeadlineExceeded | FailureReason::Unknown => {}
+        }
+    }
+    let status_code = upload?;
     if status_code != 200 {
         return Err(Error::S3UploadError { status_code });
     }
-    let status = child.wait().await?;
     if status.success() {
         // Upload completed and youtube-dl exited successfully.
-        println!("Video download completed successfully");
-        return Ok(());
-    }
-    let exit_code = status
-        .code()
-        .expect("youtube-dl failed with no exit status");
-    Err(Error::YoutubeDlError { exit_code })
-}
-
-/// Returns the default thumbnail url from the video infojson.
-fn get_thumbnail_url(metadata: &serde_json::Value) -> Result<String, Error> {
-    Ok(metadata
-        .get("thumbnail")
-        .ok_or_else(|| Error::UserInputError("metadata is missing thumbnail".to_owned()))?
-        .as_str()
-        .ok_or_else(|| Error::UserInputError("metadata thumbnail is not a string".to_owned()))?
-        .to_owned())
-}
-
-/// Converts the HTTP response Content-Type header
-/// to the corresponding image format enum value.
-fn mimetype_to_format(mimetype: &str) -> Result<ImageFormat, Error> {
-    Ok(match mimetype {
-        "image/jpeg" => ImageFormat::Jpeg,
-        "image/png" => ImageFormat::Png,
-        "image/gif" => ImageFormat::Gif,
-        "image/webp" => ImageFormat::WebP,
-        "image/tiff" => ImageFormat::Tiff,
-        "image/bmp" => ImageFormat::Bmp,
-        "image/x-icon" => ImageFormat::Ico,
-        _ => {
-            return Err(Error::UserInputError(format!(
-                "unsupported thumbnail mimetype {}",
-                mimetype
-            )))
-        }
-    })
-}
-
-/// Returns the FilterType enum value for the given filter name.
-/// The matching is case insensitive.
-fn parse_filter_type(value: &str) -> Option<FilterType> {
-    match value.to_lowercase().as_str() {
-        "lanczos3" => Some(FilterType::Lanczos3),
-        "triangle" => Some(FilterType::Triangle),
-        "catmullrom" => Some(FilterType::CatmullRom),
-        "gaussian" => Some(FilterType::Gaussian),
-        "nearest" => Some(FilterType::Nearest),
-        _ => None,
-    }
-}
-
-/// Downloads the thumbnail image from the given url and
-/// returns the response body as a DynamicImage object.
-async fn get_image_from_url(url: &str) -> Result<DynamicImage, Error> {
-    // Start the HTTP request and wait for the response.
-    let res = reqwest::get(url).await?;
-    // Check the response status code before starting the upload.
-    if !res.status().is_success() {
-        // Non-2xx status code.
-        return Err(Error::ThumbnailDownloadError {
-            status_code: res.status().as_u16(),
+        info!("Video download completed successfully");
+        return Ok(StoredObject {
+            bucket: bucket.name.clone(),
+            key,
+            size,
+            sha256,
         });
     }
-    // Determine the format with the response mimetype header.
-    let source_format = mimetype_to_format(
-        res.headers()
-            .get("content-type")
-            .ok_or_else(|| {
-                Error::UserInputError(
-                    "thumbnail response is missing content-type header".to_owned(),
-                )
-            })?
-            .to_str()
-            .unwrap(),
-    )?;
-    // Decode the image from the response body.
-    Ok(image::load_from_memory_with_format(
-        res.bytes().await?.as_ref(),
-        source_format,
-    )?)
+    Err(youtube_dl_error(status))
 }
 
-/// Downloads the thumbnail to the destination bucket.
-async fn download_thumbnail(
-    metadata: &serde_json::Value,
-    options: ThumbnailOptions,
-    bucket: Bucket,
-    key: String,
-) -> Result<(), Error> {
-    // Get the thumbnail URL from the info json.
-    let thumbnail_url = get_thumbnail_url(metadata)?;
-    println!(
-        "Downloading thumbnail {} -> s3://{}/{}",
-        &thumbnail_url, &bucket.name, &key
-    );
-    // Download and parse the thumbnail image.
-    let img = get_image_from_url(&thumbnail_url).await?;
-    // Resize the image if necessary.
-    let img = resize_image(img, options.filter, options.width, options.height);
-    // Save the image to a temporary file.
-    let out_path = format!("/tmp/{}", key);
-    img.save_with_format(&out_path, options.format)?;
-    defer! {
-        // Garbage collect the temporary file.
-        let _ = std::fs::remove_file(&out_path);
-    }
-    let status_code = {
-        // Only keep the file open for the duration of the upload.
-        let mut body = fs::File::open(&out_path).await?;
-        // Stream the file contents to S3.
-        bucket.put_object_stream(&mut body, &key).await?
-    };
-    if status_code != 200 {
-        return Err(Error::S3UploadError { status_code });
+/// Returns the error of a youtube-dl process that failed.
+pub(crate) fn youtube_dl_error(status: ExitStatus) -> Error {
+    match status.code() {
+        Some(exit_code) => Error::YoutubeDlError { exit_code },
+        // The process was killed by a signal, e.g. the OOM killer.
+        None => Error::UnknownError(format!("youtube-dl was terminated: {}", status)),
     }
-    println!("Thumbnail download completed successfully");
-    Ok(())
 }
 
-/// Resizes the image using the specified filter and dimensions.
-/// If only one dimension is specified, the other dimension is
-/// calculated to maintain the aspect ratio.
-fn resize_image(
-    img: DynamicImage,
-    filter: FilterType,
-    width: Option<u32>,
-    height: Option<u32>,
-) -> DynamicImage {
-    match (width, height) {
-        // Resize both dimensions to the exact specified size.
-        (Some(width), Some(height)) => img.resize(width, height, filter),
-        // Resize the width to the specified size and maintain the
-        // aspect ratio.
-        (Some(width), None) => {
-            let aspect = img.width() as f32 / img.height() as f32;
-            let height = (width as f32 / aspect) as u32;
-            img.resize(width, height, filter)
+/// Echoes the child process's stderr to the log and returns
+/// the first line that indicates a known failure mode, if any.
+/// Progress lines are recorded for the controller instead.
+pub(crate) async fn watch_stderr(stderr: ChildStderr) -> Option<(FailureReason, String)> {
+    let mut lines = BufReader::new(stderr).lines();
+    let mut failure = None;
+    while let Ok(Some(line)) = lines.next_line().await {
+        if progress::record(&line) {
+            continue;
         }
-        // Resize the height to the specified size and maintain the
-        // aspect ratio.
-        (None, Some(height)) => {
-            let aspect = img.width() as f32 / img.height() as f32;
-            let width = (height as f32 * aspect) as u32;
-            img.resize(width, height, filter)
+        info!(target: "youtube-dl", "{}", line);
+        if failure.is_none() {
+            failure = classify_output(&line).map(|reason| (reason, line));
         }
-        // Don't resize the image.
-        (None, None) => img,
     }
-}
-
-/// Returns the ImageFormat enum value based on the file extension
-/// of the given filename/path.
-fn get_format_from_filename(filename: &str) -> Option<ImageFormat> {
-    Path::new(filename)
-        .extension()
-        .and_then(OsStr::to_str)
-        .and_then(ImageFormat::from_extension)
+    failure
 }
 
 /*
@@ -444,7 +889,7 @@ async fn download_raw_thumbnail(
     bucket: Bucket,
     key: String,
 ) -> Result<(), Error> {
-    let res = reqwest::get(thumbnail_url).await?;
+    let res = get_http_client()?.get(thumbnail_url).send().await?;
     // Check the response status code before starting the upload.
     if !res.status().is_success() {
         // Non-2xx status code.
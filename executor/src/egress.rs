@@ -0,0 +1,103 @@
+use kube::{
+    api::{Api, Patch, PatchParams},
+    client::Client,
+    ResourceExt,
+};
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+use tracing::warn;
+use ytdl_common::{
+    egress::{get_bytes_downloaded, BYTES_DOWNLOADED_ANNOTATION},
+    Error,
+};
+use ytdl_types::Executor;
+
+/// Wraps a reader and counts the number of bytes read through it,
+/// which is how the bytes streamed from youtube-dl are measured
+/// without buffering the video.
+pub struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R> CountingReader<R> {
+    /// Wraps the reader, adding the bytes read to `count`.
+    pub fn new(inner: R, count: Arc<AtomicU64>) -> Self {
+        CountingReader { inner, count }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = (buf.filled().len() - before) as u64;
+        self.count.fetch_add(read, Ordering::Relaxed);
+        result
+    }
+}
+
+/// Tracks the bytes downloaded by this pod and reports them to the
+/// controller for per-namespace egress accounting.
+pub struct EgressReporter {
+    client: Client,
+    name: String,
+    namespace: String,
+
+    /// Bytes reported by previous attempts of the download pod.
+    base: u64,
+
+    /// Bytes downloaded by this pod so far.
+    downloaded: u64,
+}
+
+impl EgressReporter {
+    /// Creates a reporter for the Executor. The Executor is fetched
+    /// again so bytes reported by previously failed download pods
+    /// are carried over instead of being overwritten.
+    pub async fn new(client: Client, instance: &Executor) -> Result<Self, Error> {
+        let name = instance.name_any();
+        let namespace = instance.namespace().unwrap();
+        let api: Api<Executor> = Api::namespaced(client.clone(), &namespace);
+        let base = get_bytes_downloaded(&api.get(&name).await?);
+        Ok(EgressReporter {
+            client,
+            name,
+            namespace,
+            base,
+            downloaded: 0,
+        })
+    }
+
+    /// Adds the bytes to the running total and reports the new total.
+    /// Failure to report is logged but does not fail the download.
+    pub async fn add(&mut self, bytes: u64) {
+        if bytes == 0 {
+            return;
+        }
+        self.downloaded += bytes;
+        let total = self.base + self.downloaded;
+        let patch = Patch::Merge(serde_json::json!({
+            "metadata": {
+                "annotations": {
+                    BYTES_DOWNLOADED_ANNOTATION: total.to_string(),
+                },
+            },
+        }));
+        let api: Api<Executor> = Api::namespaced(self.client.clone(), &self.namespace);
+        if let Err(e) = api.patch(&self.name, &PatchParams::default(), &patch).await {
+            warn!(error = %e, total, "Failed to report bytes downloaded");
+        }
+    }
+}
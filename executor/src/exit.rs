@@ -0,0 +1,82 @@
+//! Fatal errors of the executor. Rather than panicking, errors are
+//! propagated to `main` along with the stage they occurred in, which
+//! becomes the process's [`ExitCode`], so that the controller can tell
+//! a misconfiguration it shouldn't retry from a transient failure.
+use tracing::error;
+use ytdl_common::{
+    failure::{ExitCode, Failure},
+    Error,
+};
+
+/// An error that ends the executor.
+#[derive(Debug)]
+pub struct Fatal {
+    /// Exit code of the stage the error occurred in.
+    pub code: ExitCode,
+
+    /// What the executor was doing, for the log.
+    pub context: &'static str,
+
+    /// The underlying error.
+    pub error: Error,
+}
+
+impl Fatal {
+    /// Records the error as the container's termination message so the
+    /// controller can classify the failure, then exits with its code.
+    pub fn exit(self) -> ! {
+        if let Err(e) = Failure::from(&self.error).write() {
+            error!(error = %e, "Failed to write termination message");
+        }
+        error!(error = %self.error, code = self.code.code(), "{}", self.context);
+        std::process::exit(self.code.code())
+    }
+}
+
+/// Extension for attaching the stage to an error.
+pub trait OrExit<T> {
+    /// Fails with the exit code of the stage, unless the error is one
+    /// that belongs to a specific stage wherever it occurs.
+    fn or_exit(self, code: ExitCode, context: &'static str) -> Result<T, Fatal>;
+}
+
+impl<T> OrExit<T> for Result<T, Error> {
+    fn or_exit(self, code: ExitCode, context: &'static str) -> Result<T, Fatal> {
+        self.map_err(|error| Fatal {
+            code: classify(&error, code),
+            context,
+            error,
+        })
+    }
+}
+
+/// Returns the exit code of an error that occurred in the given stage.
+/// The video and its uploads run concurrently, so their errors are
+/// told apart by kind, and invalid input is a configuration error
+/// wherever it's found, e.g. a key template the metadata can't fill.
+fn classify(error: &Error, stage: ExitCode) -> ExitCode {
+    match error {
+        Error::UserInputError(_)
+        | Error::EnvError { .. }
+        | Error::ValidationError(_)
+        | Error::HostKeyMismatch { .. } => ExitCode::Config,
+        Error::VPNError(_) => ExitCode::Vpn,
+        Error::YoutubeDlError { .. }
+        | Error::Stalled { .. }
+        | Error::AgeRestricted(_)
+        | Error::GeoBlocked(_)
+        | Error::ThumbnailDownloadError { .. } => ExitCode::Download,
+        Error::S3UploadError { .. }
+        | Error::ChecksumMismatch { .. }
+        | Error::GcsError { .. }
+        | Error::AzureError { .. }
+        | Error::WebDavError { .. }
+        | Error::SshError { .. }
+        | Error::KafkaError { .. }
+        | Error::NatsError { .. }
+        | Error::SqlError { .. }
+        | Error::MongoDbError { .. }
+        | Error::RedisError { .. } => ExitCode::Upload,
+        _ => stage,
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::warn;
+use ytdl_common::{
+    gcs::{GcsBucket, UploadStatus},
+    store::is_transient,
+    upload::UploadConfig,
+    Error,
+};
+
+use crate::{deadline::bounded, upload::INITIAL_BACKOFF};
+
+/// Chunks of a resumable upload, except the last, must be a multiple
+/// of this size.
+const CHUNK_ALIGNMENT: u64 = 256 << 10;
+
+/// Streams the reader to the bucket with a resumable upload. The
+/// content is sent in chunks of the operator's part size, and a chunk
+/// that fails with a transient error is resumed from the bytes GCS
+/// persisted, with exponential backoff between attempts. An upload
+/// that fails, or is still running at the pod's deadline, is cancelled.
+pub async fn put_object_stream<R: AsyncRead + Unpin>(
+    bucket: &GcsBucket,
+    reader: &mut R,
+    key: &str,
+    content_type: &str,
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let config = UploadConfig::from_env()?;
+    let session = bucket.start_upload(key, content_type, metadata).await?;
+    let result = bounded(put_chunks(bucket, reader, &session, &config)).await;
+    if result.is_err() {
+        // Don't leave the persisted bytes behind until the session expires.
+        if let Err(e) = bucket.cancel_upload(&session).await {
+            warn!(error = %e, key, "Failed to cancel resumable upload");
+        }
+    }
+    result
+}
+
+/// Sends the reader in chunks to the upload session until GCS reports
+/// the upload is done.
+async fn put_chunks<R: AsyncRead + Unpin>(
+    bucket: &GcsBucket,
+    reader: &mut R,
+    session: &str,
+    config: &UploadConfig,
+) -> Result<(), Error> {
+    let chunk_size = config.part_size - config.part_size % CHUNK_ALIGNMENT;
+    let mut chunk = Vec::with_capacity(chunk_size as usize);
+    let mut offset = 0;
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        // Top the chunk up after the bytes GCS persisted are dropped.
+        let missing = chunk_size - chunk.len() as u64;
+        (&mut *reader).take(missing).read_to_end(&mut chunk).await?;
+        // A short chunk means the reader is exhausted.
+        let total = if (chunk.len() as u64) < chunk_size {
+            Some(offset + chunk.len() as u64)
+        } else {
+            None
+        };
+        let status = match bucket.put_chunk(session, &chunk, offset, total).await {
+            Ok(status) => {
+                backoff = INITIAL_BACKOFF;
+                attempt = 1;
+                status
+            }
+            Err(e) if attempt < config.max_attempts && is_transient(&e) => {
+                warn!(
+                    error = %e,
+                    attempt,
+                    backoff = ?backoff,
+                    "Failed to upload chunk, resuming"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+                match bucket.query_upload(session).await {
+                    Ok(status) => status,
+                    // Send the whole chunk again.
+                    Err(e) if is_transient(&e) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        match status {
+            UploadStatus::Done => return Ok(()),
+            UploadStatus::Incomplete(persisted) => {
+                if persisted < offset || persisted > offset + chunk.len() as u64 {
+                    return Err(Error::UnknownError(format!(
+                        "GCS persisted {} bytes of the upload, expected {} to {}",
+                        persisted,
+                        offset,
+                        offset + chunk.len() as u64
+                    )));
+                }
+                chunk.drain(..(persisted - offset) as usize);
+                offset = persisted;
+            }
+        }
+    }
+}
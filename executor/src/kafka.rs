@@ -0,0 +1,65 @@
+use lazy_static::lazy_static;
+use rdkafka::{
+    config::ClientConfig,
+    producer::{FutureProducer, FutureRecord},
+    util::Timeout,
+};
+use tracing::info;
+use ytdl_common::{
+    kafka::{KafkaOutput, KafkaTopic},
+    Error,
+};
+
+use crate::{
+    backend::{Backend, Clients, OrBackend},
+    deadline::bounded,
+};
+
+/// Time librdkafka spends delivering a message, retries included,
+/// before the message fails.
+const MESSAGE_TIMEOUT_MS: &str = "60000";
+
+lazy_static! {
+    /// Producers of the clusters, by the name of the KafkaTarget.
+    static ref PRODUCERS: Clients<FutureProducer> = Clients::default();
+}
+
+/// Produces the metadata json as a message to each of the topics,
+/// waiting for the brokers to acknowledge it. A message still being
+/// delivered at the pod's deadline is abandoned, although
+/// the brokers may have received it.
+pub async fn produce_metadata(outputs: &[KafkaOutput], info_json: &str) -> Result<(), Error> {
+    for (topic, key) in outputs {
+        let producer = PRODUCERS
+            .get(&topic.name, || async { create_producer(topic) })
+            .await?;
+        let record = FutureRecord::to(&topic.topic).key(key).payload(info_json);
+        let (partition, offset) = bounded(async {
+            producer
+                .send(record, Timeout::Never)
+                .await
+                .map_err(|(e, _)| Backend::Kafka.error(e))
+        })
+        .await?;
+        info!(
+            name = %topic.name,
+            topic = %topic.topic,
+            key = %key,
+            partition,
+            offset,
+            "Produced metadata message"
+        );
+    }
+    Ok(())
+}
+
+/// Returns a producer for the topic's cluster. librdkafka reconnects
+/// to the brokers by itself, so the producer is kept for the pod.
+fn create_producer(topic: &KafkaTopic) -> Result<FutureProducer, Error> {
+    let mut config = ClientConfig::new();
+    for (property, value) in &topic.config {
+        config.set(property, value);
+    }
+    config.set("message.timeout.ms", MESSAGE_TIMEOUT_MS);
+    config.create().or_backend(Backend::Kafka)
+}
@@ -1,11 +1,43 @@
 use clap::{Parser, Subcommand};
 use kube::client::Client;
 use std::env;
-use ytdl_common::Error;
+use tracing::warn;
+use ytdl_common::{
+    chaos::{self, ChaosConfig},
+    failure::ExitCode,
+    Error,
+};
 
+use crate::exit::{Fatal, OrExit};
+
+mod azure;
+mod backend;
+mod chapters;
+mod deadline;
 mod download;
+mod egress;
+mod exit;
+mod gcs;
+mod kafka;
+mod manifest;
+mod mongodb;
+mod nats;
+mod pipeline;
+mod progress;
 mod query;
 pub mod ready;
+mod redis;
+mod schedule;
+mod sftp;
+mod sniff;
+mod sql;
+mod stall;
+mod store;
+mod thumbnail;
+mod timing;
+mod upload;
+mod webdav;
+mod work;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -25,6 +57,9 @@ enum Command {
 
         #[arg(long, default_value_t = false)]
         download_thumbnail: bool,
+
+        #[arg(long, default_value_t = false)]
+        publish_metadata: bool,
     },
 }
 
@@ -38,25 +73,47 @@ fn get_command() -> String {
 
 #[tokio::main]
 async fn main() {
+    ytdl_common::logging::init();
+    // Faults are only injected when the operator is soak testing.
+    chaos::configure(ChaosConfig::from_env());
+    if let Err(fatal) = run().await {
+        fatal.exit();
+    }
+}
+
+/// Runs the command, returning the error that ends the executor
+/// with the exit code of the stage it occurred in.
+async fn run() -> Result<(), Fatal> {
+    // Trust the targets' CA bundles before any client is created.
+    ytdl_common::ca_bundle::install_ca_bundles()
+        .or_exit(ExitCode::Config, "failed to install the CA bundles")?;
     let client: Client = Client::try_default()
         .await
-        .expect("Expected a valid KUBECONFIG environment variable.");
+        .map_err(Error::from)
+        .or_exit(ExitCode::Config, "failed to create the Kubernetes client")?;
     // Get the youtube-dl command to use from the spec.
     let command = get_command();
     // Parse command line options.
     let cli = Cli::parse();
     match cli.command {
-        Some(Command::Query) => {
-            query::query(client, &command).await.unwrap();
-        }
+        Some(Command::Query) => query::query(client, &command)
+            .await
+            .or_exit(ExitCode::Download, "failed to query"),
         Some(Command::Download {
             download_video,
             download_thumbnail,
+            publish_metadata,
         }) => {
-            download::download(client, &command, download_video, download_thumbnail).await;
+            let options = download::DownloadOptions {
+                download_video,
+                download_thumbnail,
+                publish_metadata,
+            };
+            download::download(client, &command, options).await
         }
         None => {
-            println!("No command specified");
+            warn!("No command specified");
+            Ok(())
         }
     }
 }
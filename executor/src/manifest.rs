@@ -0,0 +1,120 @@
+use kube::{
+    api::{Api, Patch, PatchParams},
+    client::Client,
+    ResourceExt,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, ReadBuf},
+};
+use tracing::warn;
+use ytdl_common::{
+    manifest::OBJECTS_ANNOTATION,
+    skip::{SkipRecord, SKIPPED_ANNOTATION},
+    Error,
+};
+use ytdl_types::{Executor, StoredObject};
+
+/// Wraps a reader and computes the size and SHA-256 checksum of
+/// everything read through it, so streamed uploads can be listed
+/// in the manifest without buffering the video.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<R> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        HashingReader {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    /// Returns the size and hex-encoded checksum of the bytes read.
+    pub fn finish(self) -> (u64, String) {
+        (self.size, hex::encode(self.hasher.finalize()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = &buf.filled()[before..];
+        self.size += read.len() as u64;
+        self.hasher.update(read);
+        result
+    }
+}
+
+/// Returns the size and hex-encoded checksum of the file.
+pub async fn hash_file(path: &Path) -> Result<(u64, String), Error> {
+    let mut reader = HashingReader::new(fs::File::open(path).await?);
+    let mut buf = vec![0; 64 * 1024];
+    while reader.read(&mut buf).await? > 0 {}
+    Ok(reader.finish())
+}
+
+/// Reports the objects uploaded so far to the controller, which lists
+/// them in the Download's manifest. Failure to report is logged but
+/// does not fail the download.
+pub async fn report_objects(client: Client, instance: &Executor, objects: &[StoredObject]) {
+    if objects.is_empty() {
+        return;
+    }
+    let value = match serde_json::to_string(objects) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!(error = %e, "Failed to serialize uploaded objects");
+            return;
+        }
+    };
+    let patch = Patch::Merge(serde_json::json!({
+        "metadata": {
+            "annotations": {
+                OBJECTS_ANNOTATION: value,
+            },
+        },
+    }));
+    let api: Api<Executor> = Api::namespaced(client, &instance.namespace().unwrap());
+    if let Err(e) = api
+        .patch(&instance.name_any(), &PatchParams::default(), &patch)
+        .await
+    {
+        warn!(error = %e, "Failed to report uploaded objects");
+    }
+}
+
+/// Reports the entities of the batch that were skipped per policy by
+/// annotating the Executor with them. Unlike the objects, a failure is
+/// returned, as the controller would count the entities as downloaded.
+pub async fn report_skipped(
+    client: Client,
+    instance: &Executor,
+    skipped: &[SkipRecord],
+) -> Result<(), Error> {
+    let patch = Patch::Merge(serde_json::json!({
+        "metadata": {
+            "annotations": {
+                SKIPPED_ANNOTATION: serde_json::to_string(skipped)?,
+            },
+        },
+    }));
+    let api: Api<Executor> = Api::namespaced(client, &instance.namespace().unwrap());
+    api.patch(&instance.name_any(), &PatchParams::default(), &patch)
+        .await?;
+    Ok(())
+}
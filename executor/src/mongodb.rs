@@ -0,0 +1,191 @@
+use futures::io::AsyncWriteExt;
+use lazy_static::lazy_static;
+use mongodb::{
+    bson::{doc, spec::BinarySubtype, to_document, Binary, Bson, Document},
+    error::{ErrorKind, GridFsErrorKind},
+    gridfs::GridFsBucket,
+    options::{
+        ClientOptions, Credential, GridFsBucketOptions, GridFsUploadOptions, ReplaceOptions,
+        ServerAddress, Tls, TlsOptions,
+    },
+    Client, Collection, Database,
+};
+use std::path::Path;
+use tokio::fs;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tracing::info;
+use ytdl_common::{
+    mongodb::{MongoCollection, MongoConnection, MongoOutput},
+    Error,
+};
+use ytdl_types::StoredObject;
+
+use crate::{
+    backend::{Backend, Clients, OrBackend},
+    deadline::bounded,
+    manifest::hash_file,
+};
+
+/// Largest file stored in a document's `payload`. Larger files are
+/// stored in GridFS, as a document is limited to 16 MiB, which has to
+/// leave room for the document's other fields.
+const MAX_PAYLOAD_SIZE: u64 = 15 * 1024 * 1024;
+
+lazy_static! {
+    /// Databases of the deployments, by the name of the MongoDbTarget.
+    static ref DATABASES: Clients<Database> = Clients::default();
+}
+
+/// Upserts the metadata json as a document into each of the
+/// collections.
+pub async fn upsert_metadata(
+    outputs: &[MongoOutput],
+    metadata: &serde_json::Value,
+) -> Result<(), Error> {
+    for (collection, id) in outputs {
+        let mut document = to_document(metadata).or_backend(Backend::MongoDb)?;
+        document.insert("_id", id.as_str());
+        bounded(async {
+            let database = get_database(collection).await?;
+            replace_document(&database.collection(&collection.collection), id, document).await
+        })
+        .await?;
+        info!(
+            name = %collection.name,
+            database = %collection.database,
+            collection = %collection.collection,
+            id = %id,
+            "Upserted metadata document"
+        );
+    }
+    Ok(())
+}
+
+/// Stores the file as the document with the `_id`, or as the GridFS
+/// file with the `_id` if it's too large for a document. Whichever of
+/// the two it isn't stored as is removed, so that a file stored again
+/// with a different size is only found in one place.
+pub async fn put_file(
+    collection: &MongoCollection,
+    path: &Path,
+    id: &str,
+    content_type: &str,
+) -> Result<StoredObject, Error> {
+    let (size, sha256) = hash_file(path).await?;
+    let database = get_database(collection).await?;
+    let documents = database.collection(&collection.collection);
+    let bucket = database.gridfs_bucket(
+        GridFsBucketOptions::builder()
+            .bucket_name(collection.collection.clone())
+            .build(),
+    );
+    if size <= MAX_PAYLOAD_SIZE {
+        let payload = fs::read(path).await?;
+        let document = doc! {
+            "_id": id,
+            "payload": Binary {
+                subtype: BinarySubtype::Generic,
+                bytes: payload,
+            },
+            "contentType": content_type,
+            "size": size as i64,
+            "sha256": &sha256,
+        };
+        replace_document(&documents, id, document).await?;
+        delete_gridfs_file(&bucket, id).await?;
+    } else {
+        // GridFS files can't be replaced, so an earlier one is deleted.
+        delete_gridfs_file(&bucket, id).await?;
+        let options = GridFsUploadOptions::builder()
+            .metadata(doc! {
+                "contentType": content_type,
+                "sha256": &sha256,
+            })
+            .build();
+        let mut upload =
+            bucket.open_upload_stream_with_id(Bson::String(id.to_owned()), id, options);
+        let mut body = fs::File::open(path).await?.compat();
+        futures::io::copy(&mut body, &mut upload)
+            .await
+            .or_backend(Backend::MongoDb)?;
+        upload.close().await.or_backend(Backend::MongoDb)?;
+        documents
+            .delete_one(doc! { "_id": id }, None)
+            .await
+            .or_backend(Backend::MongoDb)?;
+    }
+    Ok(StoredObject {
+        bucket: collection.name.clone(),
+        key: id.to_owned(),
+        size,
+        sha256,
+    })
+}
+
+/// Inserts the document, or replaces the one with the same `_id`.
+async fn replace_document(
+    documents: &Collection<Document>,
+    id: &str,
+    document: Document,
+) -> Result<(), Error> {
+    let options = ReplaceOptions::builder().upsert(true).build();
+    documents
+        .replace_one(doc! { "_id": id }, document, options)
+        .await
+        .or_backend(Backend::MongoDb)?;
+    Ok(())
+}
+
+/// Deletes the GridFS file with the `_id`, if there is one.
+async fn delete_gridfs_file(bucket: &GridFsBucket, id: &str) -> Result<(), Error> {
+    match bucket.delete(Bson::String(id.to_owned())).await {
+        Ok(()) => Ok(()),
+        Err(e) => match *e.kind {
+            ErrorKind::GridFs(GridFsErrorKind::FileNotFound { .. }) => Ok(()),
+            _ => Err(Backend::MongoDb.error(e)),
+        },
+    }
+}
+
+/// Returns the database of the collection. The driver's client pools
+/// its connections and replaces broken ones, so it's kept for the pod.
+async fn get_database(collection: &MongoCollection) -> Result<Database, Error> {
+    DATABASES
+        .get(&collection.name, || connect(collection))
+        .await
+}
+
+/// Returns the database of the collection with a new client, which
+/// connects when it's first used. The CA bundles of the targets are
+/// trusted through `SSL_CERT_FILE`.
+async fn connect(collection: &MongoCollection) -> Result<Database, Error> {
+    let options = match collection.connection {
+        // A `mongodb+srv` connection string's hosts are resolved here.
+        MongoConnection::Uri(ref uri) => ClientOptions::parse(uri).await,
+        MongoConnection::Host {
+            ref host,
+            port,
+            ref credentials,
+            tls,
+        } => {
+            let mut options = ClientOptions::default();
+            options.hosts = vec![ServerAddress::Tcp {
+                host: host.clone(),
+                port: Some(port),
+            }];
+            if let Some((ref username, ref password)) = credentials {
+                let mut credential = Credential::default();
+                credential.username = Some(username.clone());
+                credential.password = Some(password.clone());
+                options.credential = Some(credential);
+            }
+            if tls {
+                options.tls = Some(Tls::Enabled(TlsOptions::default()));
+            }
+            Ok(options)
+        }
+    };
+    let client =
+        Client::with_options(options.or_backend(Backend::MongoDb)?).or_backend(Backend::MongoDb)?;
+    Ok(client.database(&collection.database))
+}
@@ -0,0 +1,113 @@
+use async_nats::{jetstream, ConnectOptions, HeaderMap, ServerAddr};
+use kube::ResourceExt;
+use lazy_static::lazy_static;
+use std::time::Duration;
+use tracing::info;
+use ytdl_common::{
+    nats::{NatsCredentials, NatsSubject},
+    Error,
+};
+use ytdl_types::{Executor, NatsPayload, StoredObject};
+
+use crate::{
+    backend::{Backend, Clients, OrBackend},
+    deadline::bounded,
+};
+
+/// Timeout of the connection to the servers.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Header with the stream a JetStream message is expected to be
+/// captured by.
+const EXPECTED_STREAM_HEADER: &str = "Nats-Expected-Stream";
+
+lazy_static! {
+    /// Clients of the clusters, by the name of the NatsTarget.
+    static ref CLIENTS: Clients<async_nats::Client> = Clients::default();
+}
+
+/// Publishes the metadata json or completion event to each of the
+/// subjects. Core NATS messages are flushed before
+/// returning, and JetStream messages are acknowledged by the stream.
+pub async fn publish(
+    outputs: &[NatsSubject],
+    instance: &Executor,
+    metadata: &serde_json::Value,
+    info_json: &str,
+    objects: &[StoredObject],
+) -> Result<(), Error> {
+    for output in outputs {
+        let payload = match output.payload {
+            NatsPayload::Metadata => info_json.as_bytes().to_vec(),
+            NatsPayload::Event => serde_json::to_vec(&serde_json::json!({
+                "namespace": instance.namespace(),
+                "executor": instance.name_any(),
+                "id": metadata.get("id"),
+                "objects": objects,
+            }))?,
+        };
+        bounded(publish_message(output, payload)).await?;
+        info!(
+            name = %output.name,
+            subject = %output.subject,
+            jet_stream = output.jet_stream.is_some(),
+            "Published {:?} message",
+            output.payload
+        );
+    }
+    Ok(())
+}
+
+/// Publishes the payload to the subject.
+async fn publish_message(output: &NatsSubject, payload: Vec<u8>) -> Result<(), Error> {
+    let client = CLIENTS.get(&output.name, || connect(output)).await?;
+    let subject = output.subject.clone();
+    match output.jet_stream {
+        Some(ref jet_stream) => {
+            let mut headers = HeaderMap::new();
+            if let Some(ref stream) = jet_stream.stream {
+                headers.insert(EXPECTED_STREAM_HEADER, stream.as_str());
+            }
+            jetstream::new(client)
+                .publish_with_headers(subject, headers, payload.into())
+                .await
+                .or_backend(Backend::Nats)?
+                .await
+                .or_backend(Backend::Nats)?;
+        }
+        None => {
+            client
+                .publish(subject, payload.into())
+                .await
+                .or_backend(Backend::Nats)?;
+            client.flush().await.or_backend(Backend::Nats)?;
+        }
+    }
+    Ok(())
+}
+
+/// Connects to the servers of the subject. The client reconnects by
+/// itself, so it's kept for the pod.
+async fn connect(output: &NatsSubject) -> Result<async_nats::Client, Error> {
+    let options = match output.credentials {
+        NatsCredentials::None => ConnectOptions::new(),
+        NatsCredentials::Creds(ref creds) => {
+            ConnectOptions::with_credentials(creds).or_backend(Backend::Nats)?
+        }
+        NatsCredentials::Token(ref token) => ConnectOptions::with_token(token.clone()),
+        NatsCredentials::UserAndPassword(ref username, ref password) => {
+            ConnectOptions::with_user_and_password(username.clone(), password.clone())
+        }
+    };
+    let servers = output
+        .servers
+        .iter()
+        .map(|server| server.parse())
+        .collect::<Result<Vec<ServerAddr>, _>>()
+        .map_err(|e| Error::UserInputError(format!("invalid NATS server url: {}", e)))?;
+    options
+        .connection_timeout(CONNECTION_TIMEOUT)
+        .connect(servers)
+        .await
+        .or_backend(Backend::Nats)
+}
@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use std::{path::PathBuf, sync::atomic::Ordering};
+use tokio::fs;
+use tracing::info;
+use ytdl_common::{proxy::get_http_client, Error};
+
+use super::{Artifact, Entity, Fetch};
+use crate::{
+    thumbnail::{format_to_mimetype, get_thumbnail_url, mimetype_to_format},
+    work::{download_file, get_file_name, get_work_dir},
+};
+
+/// Directory files are fetched to, unless the Executor has a work volume.
+const FETCH_DIR: &str = "/tmp";
+
+/// Fetches the whole video with youtube-dl, into the work volume if the
+/// Executor has one.
+pub struct FetchVideo;
+
+#[async_trait]
+impl Fetch for FetchVideo {
+    async fn fetch(&self, entity: &Entity<'_>) -> Result<Artifact, Error> {
+        let dir = get_work_dir(entity.instance, FETCH_DIR);
+        fs::create_dir_all(&dir).await?;
+        let path = download_file(
+            entity.command,
+            entity.instance,
+            &dir,
+            &get_file_name(entity.metadata),
+            &entity.downloaded,
+        )
+        .await?;
+        Ok(Artifact::new(path, "application/octet-stream"))
+    }
+
+    fn resumable(&self, entity: &Entity<'_>) -> bool {
+        entity.instance.spec.work_volume.is_some()
+    }
+}
+
+/// Fetches the default thumbnail in the format the video service
+/// serves it in.
+pub struct FetchThumbnail;
+
+#[async_trait]
+impl Fetch for FetchThumbnail {
+    async fn fetch(&self, entity: &Entity<'_>) -> Result<Artifact, Error> {
+        let url = get_thumbnail_url(entity.metadata)?;
+        info!(url = %url, "Fetching thumbnail");
+        let res = get_http_client()?.get(&url).send().await?;
+        if !res.status().is_success() {
+            // Non-2xx status code.
+            return Err(Error::ThumbnailDownloadError {
+                status_code: res.status().as_u16(),
+            });
+        }
+        // Determine the format with the response mimetype header.
+        let source_format = mimetype_to_format(
+            res.headers()
+                .get("content-type")
+                .ok_or_else(|| {
+                    Error::UserInputError(
+                        "thumbnail response is missing content-type header".to_owned(),
+                    )
+                })?
+                .to_str()
+                .map_err(|e| {
+                    Error::UserInputError(format!("invalid thumbnail content-type header: {}", e))
+                })?,
+        )?;
+        let body = res.bytes().await?;
+        entity
+            .downloaded
+            .fetch_add(body.len() as u64, Ordering::Relaxed);
+        let path = PathBuf::from(format!(
+            "{}/{}.thumbnail",
+            FETCH_DIR,
+            get_file_name(entity.metadata)
+        ));
+        fs::write(&path, &body).await?;
+        Ok(Artifact::new(path, format_to_mimetype(source_format)))
+    }
+}
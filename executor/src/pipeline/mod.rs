@@ -0,0 +1,251 @@
+//! The stages each artifact of an entity goes through: it is fetched
+//! to a local file, validated, transformed, and stored to each of its
+//! outputs. Every stage is a trait, so a new artifact type only needs
+//! a [`Fetch`] and a new kind of target only needs a [`Sink`].
+//!
+//! Videos streamed straight from youtube-dl to S3, or split by chapter,
+//! never exist as a whole file and are uploaded outside the pipeline.
+use async_trait::async_trait;
+use std::{
+    path::PathBuf,
+    sync::{atomic::AtomicU64, Arc},
+    time::Instant,
+};
+use tokio::fs;
+use tracing::info;
+use ytdl_common::Error;
+use ytdl_types::{Executor, StoredObject};
+
+use crate::{
+    schedule::upload_slot,
+    sniff::{correct_object, Container},
+    timing::{self, Stage},
+};
+
+mod fetch;
+mod sink;
+mod transform;
+mod validate;
+
+pub use fetch::{FetchThumbnail, FetchVideo};
+pub use sink::{S3Sink, Tagging};
+pub use transform::ConvertImage;
+pub use validate::SniffContainer;
+
+/// The entity the artifacts are fetched for.
+pub struct Entity<'a> {
+    /// youtube-dl command, e.g. `yt-dlp`.
+    pub command: &'a str,
+
+    /// The Executor the pod belongs to.
+    pub instance: &'a Executor,
+
+    /// The entity's info json.
+    pub metadata: &'a serde_json::Value,
+
+    /// Bytes downloaded for the entity, for egress accounting.
+    pub downloaded: Arc<AtomicU64>,
+}
+
+/// A fetched artifact, stored in a local file until every output has it.
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    /// Path of the file with the content.
+    pub path: PathBuf,
+
+    /// MIME type of the content.
+    pub content_type: &'static str,
+
+    /// Media container of the content, if it was sniffed. Keys with
+    /// a media extension are corrected to match it.
+    pub container: Option<Container>,
+}
+
+impl Artifact {
+    /// Returns the artifact at the path with the MIME type.
+    pub fn new(path: PathBuf, content_type: &'static str) -> Self {
+        Artifact {
+            path,
+            content_type,
+            container: None,
+        }
+    }
+
+    /// Returns the key and Content-Type of the artifact's object
+    /// for the output key.
+    fn object(&self, key: String) -> (String, &'static str) {
+        match self.container {
+            Some(_) => correct_object(key, self.container),
+            None => (key, self.content_type),
+        }
+    }
+}
+
+/// Fetches the artifact of the entity to a local file.
+#[async_trait]
+pub trait Fetch: Send + Sync {
+    async fn fetch(&self, entity: &Entity<'_>) -> Result<Artifact, Error>;
+
+    /// Whether the fetched file is kept if the pipeline fails, so that
+    /// a pod recreated after the failure doesn't fetch it again.
+    fn resumable(&self, _entity: &Entity<'_>) -> bool {
+        false
+    }
+}
+
+/// Checks the fetched artifact, failing the pipeline if it is unfit for
+/// storage, and records what it learns about the content.
+#[async_trait]
+pub trait Validate: Send + Sync {
+    async fn validate(&self, entity: &Entity<'_>, artifact: &mut Artifact) -> Result<(), Error>;
+}
+
+/// Converts the artifact, e.g. to the output format.
+#[async_trait]
+pub trait Transform: Send + Sync {
+    async fn transform(&self, entity: &Entity<'_>, artifact: Artifact) -> Result<Artifact, Error>;
+}
+
+/// A bucket or container the artifact is uploaded to.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// Name of the bucket or container, for logging.
+    fn name(&self) -> &str;
+
+    /// Name of the service, for logging.
+    fn service(&self) -> &'static str;
+
+    /// Uploads the artifact as the object with the key and Content-Type.
+    async fn store(
+        &self,
+        artifact: &Artifact,
+        key: &str,
+        content_type: &str,
+    ) -> Result<StoredObject, Error>;
+}
+
+/// Outputs of different kinds are boxed to be collected before they
+/// are added to a pipeline.
+#[async_trait]
+impl Sink for Box<dyn Sink> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn service(&self) -> &'static str {
+        (**self).service()
+    }
+
+    async fn store(
+        &self,
+        artifact: &Artifact,
+        key: &str,
+        content_type: &str,
+    ) -> Result<StoredObject, Error> {
+        (**self).store(artifact, key, content_type).await
+    }
+}
+
+/// The stages of a single artifact and the outputs it is stored to.
+pub struct Pipeline {
+    /// Name of the artifact, for logging.
+    name: &'static str,
+    fetch: Box<dyn Fetch>,
+    validators: Vec<Box<dyn Validate>>,
+    transforms: Vec<Box<dyn Transform>>,
+    outputs: Vec<(Box<dyn Sink>, String)>,
+}
+
+impl Pipeline {
+    /// Returns a pipeline fetching the named artifact, without outputs.
+    pub fn new(name: &'static str, fetch: impl Fetch + 'static) -> Self {
+        Pipeline {
+            name,
+            fetch: Box::new(fetch),
+            validators: Vec::new(),
+            transforms: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Adds a validation, which runs in the order added.
+    pub fn validate(mut self, validate: impl Validate + 'static) -> Self {
+        self.validators.push(Box::new(validate));
+        self
+    }
+
+    /// Adds a transformation, which runs in the order added after
+    /// the validations.
+    pub fn transform(mut self, transform: impl Transform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Adds an output the artifact is stored to as the key.
+    pub fn output(mut self, sink: impl Sink + 'static, key: String) -> Self {
+        self.outputs.push((Box::new(sink), key));
+        self
+    }
+
+    /// Runs the stages and returns the stored objects. Nothing is fetched
+    /// if there are no outputs. The file is removed once it is stored.
+    /// If a stage fails, it is only kept if the fetch is resumable, e.g.
+    /// a video in the work volume, which a pod recreated after the
+    /// failure doesn't download again.
+    pub async fn run(self, entity: &Entity<'_>) -> Result<Vec<StoredObject>, Error> {
+        if self.outputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        info!(artifact = self.name, "Fetching artifact");
+        let started = Instant::now();
+        let mut artifact = self.fetch.fetch(entity).await?;
+        timing::add(Stage::Download, started.elapsed());
+        let fetched = artifact.path.clone();
+        match self.store(entity, &mut artifact).await {
+            Ok(objects) => {
+                let _ = fs::remove_file(&artifact.path).await;
+                info!(artifact = self.name, "Artifact stored successfully");
+                Ok(objects)
+            }
+            Err(e) => {
+                if !self.fetch.resumable(entity) {
+                    let _ = fs::remove_file(&fetched).await;
+                    let _ = fs::remove_file(&artifact.path).await;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Validates and transforms the fetched artifact and stores it to
+    /// each of the outputs. The artifact is left as the last transform
+    /// returned it, so that the file can be removed.
+    async fn store(
+        &self,
+        entity: &Entity<'_>,
+        artifact: &mut Artifact,
+    ) -> Result<Vec<StoredObject>, Error> {
+        for validate in &self.validators {
+            validate.validate(entity, artifact).await?;
+        }
+        for transform in &self.transforms {
+            *artifact = transform.transform(entity, artifact.clone()).await?;
+        }
+        let mut objects = Vec::with_capacity(self.outputs.len());
+        for (sink, key) in &self.outputs {
+            let (key, content_type) = artifact.object(key.clone());
+            info!(
+                bucket = %sink.name(),
+                key = %key,
+                "Uploading {} to {}",
+                self.name,
+                sink.service()
+            );
+            let _slot = upload_slot().await;
+            let started = Instant::now();
+            objects.push(sink.store(artifact, &key, content_type).await?);
+            timing::add(Stage::Upload, started.elapsed());
+        }
+        Ok(objects)
+    }
+}
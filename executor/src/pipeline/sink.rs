@@ -0,0 +1,173 @@
+use async_trait::async_trait;
+use s3::bucket::Bucket;
+use tokio::fs;
+use ytdl_common::{
+    chaos, mongodb::MongoCollection, redis::RedisServer, store::Store, tagging::put_tags,
+    volume::VolumeDir, Error,
+};
+use ytdl_types::StoredObject;
+
+use super::{Artifact, Sink};
+use crate::{
+    deadline::bounded,
+    manifest::hash_file,
+    mongodb, redis,
+    store::put_file,
+    upload::{put_object_stream, with_checksum},
+};
+
+/// An output's bucket and the tags rendered for its objects, if the
+/// output spec has any.
+pub type Tagging = Option<(Bucket, Vec<(String, String)>)>;
+
+/// An S3 output. The bucket is configured with the output's headers,
+/// and objects are tagged once they are uploaded.
+pub struct S3Sink {
+    bucket: Bucket,
+    tagging: Tagging,
+}
+
+impl S3Sink {
+    pub fn new(bucket: Bucket, tagging: Tagging) -> Self {
+        S3Sink { bucket, tagging }
+    }
+}
+
+#[async_trait]
+impl Sink for S3Sink {
+    fn name(&self) -> &str {
+        &self.bucket.name
+    }
+
+    fn service(&self) -> &'static str {
+        "S3"
+    }
+
+    async fn store(
+        &self,
+        artifact: &Artifact,
+        key: &str,
+        content_type: &str,
+    ) -> Result<StoredObject, Error> {
+        let (size, sha256) = hash_file(&artifact.path).await?;
+        chaos::s3_fault()?;
+        let status_code = {
+            // Only keep the file open for the duration of the upload.
+            let mut body = fs::File::open(&artifact.path).await?;
+            let bucket = with_checksum(&self.bucket, &sha256);
+            put_object_stream(&bucket, &mut body, key, content_type).await?
+        };
+        if status_code != 200 {
+            return Err(Error::S3UploadError { status_code });
+        }
+        if let Some((ref bucket, ref tags)) = self.tagging {
+            put_tags(bucket, key, tags).await?;
+        }
+        Ok(StoredObject {
+            bucket: self.bucket.name.clone(),
+            key: key.to_owned(),
+            size,
+            sha256,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for Store {
+    fn name(&self) -> &str {
+        Store::name(self)
+    }
+
+    fn service(&self) -> &'static str {
+        Store::service(self)
+    }
+
+    async fn store(
+        &self,
+        artifact: &Artifact,
+        key: &str,
+        content_type: &str,
+    ) -> Result<StoredObject, Error> {
+        put_file(self, &artifact.path, key, content_type).await
+    }
+}
+
+#[async_trait]
+impl Sink for MongoCollection {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn service(&self) -> &'static str {
+        "MongoDB"
+    }
+
+    async fn store(
+        &self,
+        artifact: &Artifact,
+        key: &str,
+        content_type: &str,
+    ) -> Result<StoredObject, Error> {
+        mongodb::put_file(self, &artifact.path, key, content_type).await
+    }
+}
+
+#[async_trait]
+impl Sink for RedisServer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn service(&self) -> &'static str {
+        "Redis"
+    }
+
+    async fn store(
+        &self,
+        artifact: &Artifact,
+        key: &str,
+        _content_type: &str,
+    ) -> Result<StoredObject, Error> {
+        redis::put_file(self, &artifact.path, key).await
+    }
+}
+
+#[async_trait]
+impl Sink for VolumeDir {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn service(&self) -> &'static str {
+        "volume"
+    }
+
+    async fn store(
+        &self,
+        artifact: &Artifact,
+        key: &str,
+        _content_type: &str,
+    ) -> Result<StoredObject, Error> {
+        let (size, sha256) = hash_file(&artifact.path).await?;
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        // The file is copied next to its destination and renamed, so
+        // that a partial file never appears under the final name.
+        let mut partial = path.clone().into_os_string();
+        partial.push(".part");
+        let copy = async { Ok::<_, Error>(fs::copy(&artifact.path, &partial).await?) };
+        if let Err(e) = bounded(copy).await {
+            let _ = fs::remove_file(&partial).await;
+            return Err(e);
+        }
+        fs::rename(&partial, &path).await?;
+        Ok(StoredObject {
+            bucket: self.name.clone(),
+            key: key.to_owned(),
+            size,
+            sha256,
+        })
+    }
+}
@@ -0,0 +1,41 @@
+use async_trait::async_trait;
+use tokio::fs;
+use ytdl_common::Error;
+
+use super::{Artifact, Entity, Transform};
+use crate::thumbnail::{format_to_mimetype, mimetype_to_format, resize_image, ThumbnailOptions};
+
+/// Resizes the image, if the options have dimensions, and converts it
+/// to the output format. An image that needs neither is passed through
+/// as downloaded.
+pub struct ConvertImage {
+    options: ThumbnailOptions,
+}
+
+impl ConvertImage {
+    pub fn new(options: ThumbnailOptions) -> Self {
+        ConvertImage { options }
+    }
+}
+
+#[async_trait]
+impl Transform for ConvertImage {
+    async fn transform(&self, _entity: &Entity<'_>, artifact: Artifact) -> Result<Artifact, Error> {
+        let options = &self.options;
+        let source_format = mimetype_to_format(artifact.content_type)?;
+        let format = options.format.unwrap_or(source_format);
+        if format == source_format && options.width.is_none() && options.height.is_none() {
+            return Ok(artifact);
+        }
+        let body = fs::read(&artifact.path).await?;
+        let img = image::load_from_memory_with_format(&body, source_format)?;
+        let img = resize_image(img, options.filter, options.width, options.height);
+        let path = artifact.path.with_extension(format.extensions_str()[0]);
+        if let Err(e) = img.save_with_format(&path, format) {
+            let _ = fs::remove_file(&path).await;
+            return Err(e.into());
+        }
+        let _ = fs::remove_file(&artifact.path).await;
+        Ok(Artifact::new(path, format_to_mimetype(format)))
+    }
+}
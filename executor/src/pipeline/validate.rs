@@ -0,0 +1,18 @@
+use async_trait::async_trait;
+use ytdl_common::Error;
+
+use super::{Artifact, Entity, Validate};
+use crate::sniff::sniff_file;
+
+/// Sniffs the real container of the video, as the extension youtube-dl
+/// reported may not survive remuxing. The keys of the outputs are
+/// corrected to match it.
+pub struct SniffContainer;
+
+#[async_trait]
+impl Validate for SniffContainer {
+    async fn validate(&self, _entity: &Entity<'_>, artifact: &mut Artifact) -> Result<(), Error> {
+        artifact.container = sniff_file(&artifact.path).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use kube::{
+    api::{Api, Patch, PatchParams},
+    client::Client,
+    ResourceExt,
+};
+use std::{sync::Mutex, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+use tracing::{info, warn};
+use ytdl_common::{progress::PROGRESS_ANNOTATION, Error};
+use ytdl_types::{DownloadProgress, Executor};
+
+/// Prefix of the lines youtube-dl prints with the progress template,
+/// which distinguishes them from the rest of its output.
+const PROGRESS_PREFIX: &str = "ytdl-progress ";
+
+/// Template for youtube-dl's `--progress-template`, which prints the
+/// progress of the download as a json object on each line.
+pub const PROGRESS_TEMPLATE: &str = "download:ytdl-progress %(progress)j";
+
+/// Interval at which the latest progress is reported to the controller.
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Progress of the video currently being downloaded.
+static PROGRESS: Mutex<Option<DownloadProgress>> = Mutex::new(None);
+
+/// Parses a line printed with the [`PROGRESS_TEMPLATE`]. yt-dlp reports
+/// most of the fields of its progress dict as floats, and any may be null.
+fn parse_line(line: &str) -> Option<DownloadProgress> {
+    let raw: serde_json::Value = serde_json::from_str(line.strip_prefix(PROGRESS_PREFIX)?).ok()?;
+    let field = |name: &str| raw.get(name).and_then(serde_json::Value::as_f64);
+    let downloaded_bytes = field("downloaded_bytes").unwrap_or(0.0) as u64;
+    let total_bytes = field("total_bytes")
+        .or_else(|| field("total_bytes_estimate"))
+        .map(|total| total as u64)
+        .filter(|total| *total > 0);
+    let percent = total_bytes.map(|total| {
+        let percent = downloaded_bytes as f64 * 100.0 / total as f64;
+        (percent.min(100.0) * 10.0).round() / 10.0
+    });
+    Some(DownloadProgress {
+        percent,
+        downloaded_bytes,
+        total_bytes,
+        bytes_per_second: field("speed").map(f64::round),
+        eta_seconds: field("eta").map(|eta| eta.max(0.0) as u64),
+    })
+}
+
+/// Records the progress if the line is one of youtube-dl's progress
+/// lines, returning true if it was so that it isn't logged.
+pub fn record(line: &str) -> bool {
+    if !line.starts_with(PROGRESS_PREFIX) {
+        return false;
+    }
+    if let Some(progress) = parse_line(line) {
+        *PROGRESS.lock().unwrap() = Some(progress);
+    }
+    true
+}
+
+/// Records the progress lines of the child process's output, which
+/// youtube-dl prints to stdout when it isn't streaming the video there.
+pub async fn watch<R: AsyncRead + Unpin>(output: R) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        record(&line);
+    }
+}
+
+/// Reports the latest progress to the controller whenever it changes,
+/// which records it in the status. Failure to report is logged but
+/// does not fail the download. Runs until the pod exits.
+pub async fn report_periodically(client: Client, instance: Executor) {
+    let api: Api<Executor> = Api::namespaced(client, &instance.namespace().unwrap());
+    let mut reported = None;
+    loop {
+        tokio::time::sleep(REPORT_INTERVAL).await;
+        let progress = PROGRESS.lock().unwrap().clone();
+        if progress.is_none() || progress == reported {
+            continue;
+        }
+        let value = match serde_json::to_string(&progress) {
+            Ok(value) => value,
+            Err(e) => {
+                warn!(error = %e, "Failed to serialize download progress");
+                continue;
+            }
+        };
+        let patch = Patch::Merge(serde_json::json!({
+            "metadata": {
+                "annotations": {
+                    PROGRESS_ANNOTATION: value,
+                },
+            },
+        }));
+        match api
+            .patch(&instance.name_any(), &PatchParams::default(), &patch)
+            .await
+        {
+            Ok(_) => reported = progress,
+            Err(e) => warn!(error = %e, "Failed to report download progress"),
+        }
+    }
+}
+
+/// Serves the latest progress as json on the port, for the controller
+/// to scrape, until the pod exits. Every request gets the progress
+/// whatever its method and path, as nothing else is served, so only
+/// enough of it is read to respond.
+pub async fn serve(port: u16) -> Result<(), Error> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    info!(port, "Serving download progress");
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            let mut request = [0u8; 1024];
+            if stream.read(&mut request).await.is_err() {
+                return;
+            }
+            let body = serde_json::to_string(&*PROGRESS.lock().unwrap())
+                .unwrap_or_else(|_| "null".to_owned());
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
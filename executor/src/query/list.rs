@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+use std::vec::IntoIter;
+use ytdl_common::{
+    query_engine::{get_static_urls, url_entry},
+    Error,
+};
+
+use super::QueryEngine;
+
+/// Lists the video URLs of a static input without querying them. Each
+/// video's ID is its URL, as nothing else is known about it until the
+/// download pod queries it.
+pub struct StaticList {
+    lines: IntoIter<String>,
+}
+
+impl StaticList {
+    pub fn new(input: &str) -> Self {
+        let lines: Vec<String> = get_static_urls(input)
+            .into_iter()
+            .map(|url| url_entry(url, url, None, None).to_string())
+            .collect();
+        StaticList {
+            lines: lines.into_iter(),
+        }
+    }
+}
+
+#[async_trait]
+impl QueryEngine for StaticList {
+    async fn next_line(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.lines.next())
+    }
+
+    async fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn cancel(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
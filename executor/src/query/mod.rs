@@ -0,0 +1,325 @@
+//! Lists the videos of a Download's input with the query engine of
+//! [`DownloadSpec::engine`](ytdl_types::DownloadSpec::engine) and
+//! creates their Executors. Each engine only produces lines of info
+//! json, so a new kind of source only needs a [`QueryEngine`].
+use async_trait::async_trait;
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::{ListParams, ObjectMeta, PostParams},
+    client::Client,
+    runtime::watcher,
+    Api, Resource, ResourceExt,
+};
+use std::{
+    collections::BTreeMap,
+    env,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tokio::time::{self, Duration};
+use tracing::{debug, info, warn};
+use ytdl_common::{
+    archive::load_archive,
+    create_executor,
+    filter::check_filters,
+    get_assigned_ids, get_batch_size, get_executor, get_executor_name,
+    normalize::{to_normalized_jsonl, NORMALIZED_JSONL_KEY},
+    pod::has_vpn_sidecar,
+    skip::{to_skipped_jsonl, SkipRecord, SKIPPED_JSONL_KEY},
+    upcoming::get_release_delay,
+    Entity, Error, INFO_JSONL_KEY, METADATA_LABEL,
+};
+use ytdl_types::{Download, QueryEngineKind};
+
+mod list;
+mod rss;
+mod youtube_dl;
+
+use list::StaticList;
+use rss::RssFeed;
+use youtube_dl::YoutubeDl;
+
+/// A source of the videos to download, which lists each as a line
+/// of info json.
+#[async_trait]
+pub trait QueryEngine: Send {
+    /// Returns the next line of output, or None once there are no more.
+    async fn next_line(&mut self) -> Result<Option<String>, Error>;
+
+    /// Checks that the query succeeded, once every line is read.
+    async fn finish(&mut self) -> Result<(), Error>;
+
+    /// Stops the query before every line is read.
+    async fn cancel(&mut self) -> Result<(), Error>;
+}
+
+/// Starts the query engine of the Download on its input.
+async fn start_engine(
+    command: &str,
+    instance: &Download,
+    kind: QueryEngineKind,
+) -> Result<Box<dyn QueryEngine>, Error> {
+    Ok(match kind {
+        QueryEngineKind::YoutubeDl => Box::new(YoutubeDl::spawn(command, instance, false)?),
+        QueryEngineKind::FlatPlaylist => Box::new(YoutubeDl::spawn(command, instance, true)?),
+        QueryEngineKind::Rss => Box::new(RssFeed::fetch(instance.spec.input.trim()).await?),
+        QueryEngineKind::Static => Box::new(StaticList::new(&instance.spec.input)),
+    })
+}
+
+/// Try to reconcile the Executor associated with this batch of
+/// json metadata. The Executor is named after the first entity.
+async fn reconcile_executor(
+    client: Client,
+    instance: &Download,
+    batch: Vec<Entity>,
+) -> Result<(), Error> {
+    let id = batch[0].id.clone();
+    if get_executor(
+        client.clone(),
+        &get_executor_name(instance, &batch),
+        instance.namespace().as_ref().unwrap(),
+    )
+    .await?
+    .is_none()
+    {
+        // Create the Executor from these lines of output.
+        info!(%id, entities = batch.len(), "Creating Executor");
+        create_executor(client, instance, batch).await?;
+    }
+    Ok(())
+}
+
+/// Creates an Executor for the accumulated entities, if any, and
+/// clears the batch. Failures are logged but do not stop the query.
+/// While the Download is suspended, the batch is dropped and the
+/// controller creates its Executor from the metadata once resumed.
+/// Batches with upcoming videos are dropped as well, and the
+/// controller creates them once they start.
+async fn flush_batch(
+    client: Client,
+    instance: &Download,
+    batch: &mut Vec<Entity>,
+    suspended: &AtomicBool,
+) {
+    if batch.is_empty() {
+        return;
+    }
+    let id = batch[0].id.clone();
+    if suspended.load(Ordering::SeqCst) {
+        debug!(%id, "Download is suspended, deferring Executor creation");
+        batch.clear();
+        return;
+    }
+    if let Some(delay) = get_release_delay(batch) {
+        debug!(%id, ?delay, "Batch has upcoming videos, deferring Executor creation");
+        batch.clear();
+        return;
+    }
+    if let Err(err) = reconcile_executor(client, instance, batch.drain(..).collect()).await {
+        warn!(%id, error = %err, "Failed to create Executor");
+    }
+}
+
+/// Watches the Download resource and resolves once it is deleted or
+/// marked for deletion. The query is cancelled at that point so no
+/// more Executors are created for a Download that is going away.
+/// Changes to [`DownloadSpec::suspend`](ytdl_types::DownloadSpec::suspend)
+/// are stored in `suspended`.
+async fn wait_for_cancel(client: Client, instance: &Download, suspended: &AtomicBool) {
+    let api: Api<Download> = Api::namespaced(client, instance.namespace().as_ref().unwrap());
+    let lp = ListParams::default().fields(&format!("metadata.name={}", instance.name_any()));
+    let mut stream = watcher(api, lp).boxed();
+    while let Some(event) = stream.next().await {
+        match event {
+            Ok(watcher::Event::Deleted(_)) => return,
+            Ok(watcher::Event::Applied(download)) => {
+                if download.metadata.deletion_timestamp.is_some() {
+                    return;
+                }
+                suspended.store(download.spec.suspend.unwrap_or(false), Ordering::SeqCst);
+            }
+            // An empty list means the resource no longer exists.
+            Ok(watcher::Event::Restarted(downloads)) => {
+                if downloads
+                    .iter()
+                    .all(|download| download.metadata.deletion_timestamp.is_some())
+                {
+                    return;
+                }
+                if let Some(download) = downloads.first() {
+                    suspended.store(download.spec.suspend.unwrap_or(false), Ordering::SeqCst);
+                }
+            }
+            Err(err) => {
+                // The watcher will retry on the next poll.
+                warn!(error = %err, "Failed to watch Download for deletion");
+                time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
+
+/// Parses the Download resource from the environment.
+fn get_resource() -> Result<Download, Error> {
+    Ok(serde_json::from_str(&env::var("RESOURCE")?)?)
+}
+
+/// Queries the video metadata from the given url and creates
+/// Executor resources as needed.
+pub async fn query(client: Client, command: &str) -> Result<(), Error> {
+    let instance: Download = get_resource()?;
+
+    // Wait for the VPN to connect before starting the query.
+    if !has_vpn_sidecar(instance.spec.vpn.as_ref(), instance.spec.proxy.as_ref()) {
+        info!("Environment parsed, pod has no VPN sidecar");
+    } else {
+        info!("Environment parsed, waiting for VPN to connect");
+        crate::ready::wait_for_vpn().await?;
+    }
+
+    // IDs that were already downloaded or that already have an
+    // Executor, which are left out of the batches. The archive is only
+    // read once, as the controller catches any batches archived later.
+    let mut done = get_assigned_ids(client.clone(), &instance).await?;
+    if let Some(archive) = load_archive(client.clone(), &instance).await? {
+        done.extend(archive);
+    }
+
+    // Start the query engine.
+    let kind = instance.spec.engine.unwrap_or_default();
+    info!(engine = %kind, "Starting query");
+    let mut engine = start_engine(command, &instance, kind).await?;
+
+    // Entities are grouped into batches in the same order that the
+    // Download controller will group the remaining lines of info.jsonl.
+    let batch_size = get_batch_size(&instance);
+    let mut batch: Vec<Entity> = Vec::with_capacity(batch_size);
+
+    // Read the output line-by-line.
+    let mut lines = Vec::new();
+    let mut skipped = Vec::new();
+    let suspended = AtomicBool::new(instance.spec.suspend.unwrap_or(false));
+    let cancel = wait_for_cancel(client.clone(), &instance, &suspended);
+    tokio::pin!(cancel);
+    loop {
+        let line = tokio::select! {
+            line = engine.next_line() => match line? {
+                Some(line) => line,
+                None => break,
+            },
+            _ = &mut cancel => {
+                // Stop the engine and bail before creating any more
+                // Executors. The controller deletes this pod shortly.
+                info!("Download was deleted, cancelling query");
+                engine.cancel().await?;
+                return Ok(());
+            }
+        };
+
+        // Immediately dump the line to the log.
+        debug!(target: "query", "{}", line);
+
+        // Try and parse the line as json.
+        let info_json: serde_json::Value = match serde_json::from_str(&line) {
+            Ok(info_json) => info_json,
+            Err(err) => {
+                // Ignore this line.
+                warn!(error = %err, "Failed to parse json");
+                continue;
+            }
+        };
+
+        // All info json should have an "id" field.
+        let id: &str = match info_json["id"].as_str() {
+            Some(id) => id,
+            None => {
+                // Ignore this line.
+                warn!("Failed to parse id from json");
+                continue;
+            }
+        };
+
+        // Add the entity to the current batch unless it is excluded
+        // by the Download's filters or has already been batched.
+        match check_filters(&instance, &info_json)? {
+            Some(skip) => {
+                info!(id, policy = skip.policy, reason = %skip.reason, "Skipping entity");
+                skipped.push(SkipRecord {
+                    id: id.to_owned(),
+                    policy: skip.policy.to_owned(),
+                    reason: skip.reason,
+                    timestamp: chrono::Utc::now().to_rfc3339(),
+                });
+            }
+            None if done.contains(id) => debug!(id, "Entity already batched or archived"),
+            None => batch.push(Entity {
+                id: id.to_owned(),
+                metadata: line.clone(),
+            }),
+        }
+
+        // Add the line to the final output ConfigMap, as we know it's valid json.
+        lines.push(line);
+
+        // Try and create an Executor once the batch is full.
+        if batch.len() >= batch_size {
+            flush_batch(client.clone(), &instance, &mut batch, &suspended).await;
+        }
+    }
+
+    // Create an Executor for the remaining partial batch.
+    flush_batch(client.clone(), &instance, &mut batch, &suspended).await;
+
+    // Check that the engine listed every video.
+    engine.finish().await?;
+
+    // Upload the metadata as a ConfigMap.
+    info!(lines = lines.len(), "Creating metadata ConfigMap");
+    publish_metadata(client, &instance, lines, &skipped).await?;
+
+    // All done.
+    info!(input = %instance.spec.input, engine = %kind, "Successfully queried metadata");
+    Ok(())
+}
+
+async fn publish_metadata(
+    client: Client,
+    instance: &Download,
+    lines: Vec<String>,
+    skipped: &[SkipRecord],
+) -> Result<(), Error> {
+    let namespace = instance.namespace().unwrap();
+    let api: Api<ConfigMap> = Api::namespaced(client, &namespace);
+    let cm = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(instance.name_any()),
+            namespace: Some(namespace),
+            // The Download controller watches the ConfigMaps it owns,
+            // so it reconciles the Download as soon as this is created.
+            labels: Some(BTreeMap::from([(
+                METADATA_LABEL.to_owned(),
+                "true".to_owned(),
+            )])),
+            owner_references: Some(vec![instance.controller_owner_ref(&()).unwrap()]),
+            ..Default::default()
+        },
+        data: Some({
+            let mut data = BTreeMap::new();
+            data.insert(INFO_JSONL_KEY.to_owned(), lines.join("\n"));
+            // The normalized metadata is stored alongside the raw info
+            // json for consumers that need a stable schema.
+            data.insert(
+                NORMALIZED_JSONL_KEY.to_owned(),
+                to_normalized_jsonl(&lines)?,
+            );
+            // Entities excluded by the filters are recorded so that
+            // consumers know they are intentionally missing.
+            data.insert(SKIPPED_JSONL_KEY.to_owned(), to_skipped_jsonl(skipped)?);
+            data
+        }),
+        ..Default::default()
+    };
+    api.create(&PostParams::default(), &cm).await?;
+    Ok(())
+}
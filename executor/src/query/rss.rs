@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use std::vec::IntoIter;
+use tracing::{info, warn};
+use ytdl_common::{proxy::get_http_client, query_engine::url_entry, Error};
+
+use super::QueryEngine;
+
+/// Lists the entries of an RSS or Atom feed, such as a YouTube
+/// channel's, as the videos at their links. The feed is fetched once,
+/// as it's small, and the entries' IDs are those of the feed.
+pub struct RssFeed {
+    lines: IntoIter<String>,
+}
+
+impl RssFeed {
+    /// Fetches and parses the feed at the URL through the pod's proxy.
+    pub async fn fetch(url: &str) -> Result<Self, Error> {
+        let body = get_http_client()?
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let feed = feed_rs::parser::parse(body.as_ref())
+            .map_err(|e| Error::UserInputError(format!("failed to parse feed {}: {}", url, e)))?;
+        let mut lines = Vec::with_capacity(feed.entries.len());
+        for entry in feed.entries {
+            let link = match entry.links.first() {
+                Some(link) => link.href.as_str(),
+                None => {
+                    warn!(id = %entry.id, "Feed entry has no link, skipping");
+                    continue;
+                }
+            };
+            let title = entry.title.as_ref().map(|title| title.content.as_str());
+            let timestamp = entry
+                .published
+                .or(entry.updated)
+                .map(|date| date.timestamp());
+            lines.push(url_entry(&entry.id, link, title, timestamp).to_string());
+        }
+        info!(url, entries = lines.len(), "Fetched feed");
+        Ok(RssFeed {
+            lines: lines.into_iter(),
+        })
+    }
+}
+
+#[async_trait]
+impl QueryEngine for RssFeed {
+    async fn next_line(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.lines.next())
+    }
+
+    async fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn cancel(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use async_trait::async_trait;
+use std::process::Stdio;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader, Lines},
+    process::{Child, ChildStdout, Command},
+};
+use ytdl_common::{auth::get_auth_args, cookies::get_cookies_file, proxy::get_proxy_url, Error};
+use ytdl_types::Download;
+
+use super::QueryEngine;
+
+/// Queries the input with youtube-dl, which prints the info json of
+/// each video on its own line.
+pub struct YoutubeDl {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+impl YoutubeDl {
+    /// Starts youtube-dl on the Download's input. With `flat`, the
+    /// videos of a playlist or channel are only listed.
+    pub fn spawn(command: &str, instance: &Download, flat: bool) -> Result<Self, Error> {
+        let proxy = get_proxy_url()?;
+        let cookies = get_cookies_file()?;
+        let auth = get_auth_args()?;
+        let args = build_args(
+            &instance.spec.input,
+            flat,
+            instance.spec.ignore_errors.unwrap_or(false),
+            proxy.as_deref(),
+            instance.spec.date_after.as_deref(),
+            instance.spec.date_before.as_deref(),
+            instance.spec.match_filter.as_deref(),
+            cookies.as_deref(),
+            &auth,
+        );
+        let mut child = Command::new(command)
+            .args(&args[..])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| Error::UnknownError("failed to get child process stdout".to_owned()))?;
+        Ok(YoutubeDl {
+            child,
+            lines: BufReader::new(stdout).lines(),
+        })
+    }
+}
+
+#[async_trait]
+impl QueryEngine for YoutubeDl {
+    async fn next_line(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.lines.next_line().await?)
+    }
+
+    async fn finish(&mut self) -> Result<(), Error> {
+        let status = self.child.wait().await?;
+        if !status.success() {
+            return Err(Error::UnknownError(format!(
+                "youtube-dl exited with status code {}",
+                status.code().unwrap_or(-1)
+            )));
+        }
+        Ok(())
+    }
+
+    async fn cancel(&mut self) -> Result<(), Error> {
+        Ok(self.child.kill().await?)
+    }
+}
+
+fn build_args<'a>(
+    url: &'a str,
+    flat: bool,
+    ignore_errors: bool,
+    proxy: Option<&'a str>,
+    date_after: Option<&'a str>,
+    date_before: Option<&'a str>,
+    match_filter: Option<&'a str>,
+    cookies: Option<&'a str>,
+    auth: &'a [String],
+) -> Vec<&'a str> {
+    let mut args = vec!["-j"];
+    if flat {
+        args.push("--flat-playlist");
+    }
+    if ignore_errors {
+        args.push("--ignore-errors");
+    }
+    if let Some(date_after) = date_after {
+        args.push("--dateafter");
+        args.push(date_after);
+    }
+    if let Some(date_before) = date_before {
+        args.push("--datebefore");
+        args.push(date_before);
+    }
+    if let Some(match_filter) = match_filter {
+        args.push("--match-filter");
+        args.push(match_filter);
+    }
+    if let Some(cookies) = cookies {
+        args.push("--cookies");
+        args.push(cookies);
+    }
+    args.extend(auth.iter().map(String::as_str));
+    if let Some(proxy) = proxy {
+        args.push("--proxy");
+        args.push(proxy);
+    }
+    args.push(url);
+    args
+}
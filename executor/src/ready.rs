@@ -3,6 +3,7 @@ use std::{
     time::{Duration, SystemTime},
 };
 use tokio::{fs, time};
+use tracing::{info, warn};
 use ytdl_common::pod::{IP_FILE_PATH, IP_SERVICE};
 
 use crate::Error;
@@ -19,11 +20,11 @@ const TIMEOUT: Duration = Duration::from_secs(12);
 pub async fn wait_for_vpn() -> Result<(), Error> {
     // Get the unmasked IP address from the shared dir.
     let ip = wait_for_initial_ip().await?;
-    println!("Unmasked public IP: {}", &ip);
+    info!(%ip, "Unmasked public IP");
     // Probe the public IP until it changes.
-    println!("Waiting for public IP to change...");
+    info!("Waiting for public IP to change...");
     let ip = wait_for_ip_change(&ip).await?;
-    println!("VPN connected. Masked public IP: {}", &ip);
+    info!(%ip, "VPN connected");
     Ok(())
 }
 
@@ -60,6 +61,34 @@ async fn wait_for_initial_ip() -> Result<String, Error> {
     }
 }
 
+/// Address of gluetun's HTTP control server. The sidecar shares the
+/// pod's network namespace, so it is reachable on localhost.
+const CONTROL_SERVER: &str = "http://127.0.0.1:8000";
+
+/// Reconnects the VPN through gluetun's control server so that the
+/// following downloads use a different exit IP. Rotation is best
+/// effort: if the provider assigns the same IP again, a warning is
+/// logged and the downloads continue.
+pub async fn rotate_ip() -> Result<(), Error> {
+    let ip = get_public_ip().await?;
+    info!(%ip, "Rotating public IP");
+    // The system proxy is the storage proxy, which can't reach localhost.
+    let client = reqwest::Client::builder().no_proxy().build()?;
+    for status in ["stopped", "running"] {
+        client
+            .put(format!("{}/v1/openvpn/status", CONTROL_SERVER))
+            .json(&serde_json::json!({ "status": status }))
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+    match wait_for_ip_change(&ip).await {
+        Ok(ip) => info!(%ip, "Public IP rotated"),
+        Err(e) => warn!(error = %e, "Public IP did not change after reconnecting"),
+    }
+    Ok(())
+}
+
 /// Waits for the public IP address to change then returns
 /// the new IP address.
 async fn wait_for_ip_change(current: &str) -> Result<String, Error> {
@@ -85,6 +114,8 @@ async fn wait_for_ip_change(current: &str) -> Result<String, Error> {
 /// an external service (e.g. https://api.ipify.org).
 /// This should be the same service used by the init
 /// container to write the contents of /shared/ip
+/// The system proxy is bypassed, as it is the storage proxy.
 async fn get_public_ip() -> Result<String, Error> {
-    Ok(reqwest::get(IP_SERVICE).await?.text().await?)
+    let client = reqwest::Client::builder().no_proxy().build()?;
+    Ok(client.get(IP_SERVICE).send().await?.text().await?)
 }
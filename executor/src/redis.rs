@@ -0,0 +1,220 @@
+use lazy_static::lazy_static;
+use redis::{aio::MultiplexedConnection, AsyncCommands, Script};
+use std::path::Path;
+use tokio::fs;
+use tracing::info;
+use ytdl_common::{
+    redis::{RedisOutput, RedisServer},
+    Error,
+};
+use ytdl_types::StoredObject;
+
+use crate::{backend::Clients, deadline::bounded, manifest::hash_file};
+
+lazy_static! {
+    /// Connections to the servers, by the name of the RedisTarget.
+    static ref CONNECTIONS: Clients<MultiplexedConnection> = Clients::default();
+}
+
+/// Sets the key of each of the outputs to the metadata json.
+pub async fn set_metadata(outputs: &[RedisOutput], info_json: &str) -> Result<(), Error> {
+    for (server, key) in outputs {
+        bounded(write(server, key, info_json.as_bytes())).await?;
+        info!(name = %server.name, key = %key, "Set metadata key");
+    }
+    Ok(())
+}
+
+/// Sets the key to the contents of the file, which is read into memory.
+/// Files larger than the target's `maxFilesize` are refused.
+pub async fn put_file(server: &RedisServer, path: &Path, key: &str) -> Result<StoredObject, Error> {
+    let size = fs::metadata(path).await?.len();
+    if size > server.max_filesize {
+        return Err(Error::UserInputError(format!(
+            "file size {} exceeds maxFilesize {} of RedisTarget {}",
+            size, server.max_filesize, server.name
+        )));
+    }
+    let (size, sha256) = hash_file(path).await?;
+    let value = fs::read(path).await?;
+    write(server, key, &value).await?;
+    Ok(StoredObject {
+        bucket: server.name.clone(),
+        key: key.to_owned(),
+        size,
+        sha256,
+    })
+}
+
+/// Writes the value to the key over the server's connection, which is
+/// dropped if the write fails, as it doesn't reconnect by itself.
+async fn write(server: &RedisServer, key: &str, value: &[u8]) -> Result<(), Error> {
+    let mut con = CONNECTIONS
+        .get(&server.name, || async {
+            Ok(redis::Client::open(server.connection.clone())?
+                .get_multiplexed_async_connection()
+                .await?)
+        })
+        .await?;
+    let result = write_value(&mut con, server, key, value).await;
+    CONNECTIONS.evict_on_error(&server.name, result).await
+}
+
+/// Sets the key to the value with `SET`, or runs the target's script
+/// with the key and extra keys as `KEYS` and the value and metadata as
+/// `ARGV`. The key expires after the TTL, if the target has one.
+async fn write_value(
+    con: &mut MultiplexedConnection,
+    server: &RedisServer,
+    key: &str,
+    value: &[u8],
+) -> Result<(), Error> {
+    // SETEX and EXPIRE take whole seconds, and zero is rejected.
+    let ttl = server.ttl.map(|ttl| ttl.as_secs().max(1) as usize);
+    match server.script {
+        Some(ref script) => {
+            let script = Script::new(script);
+            let mut invocation = script.key(key);
+            for extra_key in &server.extra_keys {
+                invocation.key(extra_key);
+            }
+            invocation.arg(value);
+            if let Some(ref metadata) = server.metadata {
+                invocation.arg(metadata);
+            }
+            invocation.invoke_async::<_, redis::Value>(con).await?;
+            if let Some(ttl) = ttl {
+                // Does nothing if the script didn't create the key.
+                con.expire::<_, ()>(key, ttl).await?;
+            }
+        }
+        None => match ttl {
+            Some(ttl) => con.set_ex::<_, _, ()>(key, value, ttl).await?,
+            None => con.set::<_, _, ()>(key, value).await?,
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::{ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
+    use std::{process::Command, time::Duration};
+
+    /// A Redis container, removed when dropped.
+    struct Container {
+        id: String,
+        port: u16,
+    }
+
+    impl Container {
+        /// Runs a Redis container with its port published on a free
+        /// host port.
+        fn start() -> Self {
+            let output = Command::new("docker")
+                .args([
+                    "run",
+                    "--rm",
+                    "-d",
+                    "-p",
+                    "127.0.0.1::6379",
+                    "redis:7-alpine",
+                ])
+                .output()
+                .expect("docker is installed");
+            assert!(output.status.success(), "docker run failed");
+            let id = String::from_utf8(output.stdout).unwrap().trim().to_owned();
+            let output = Command::new("docker")
+                .args(["port", &id, "6379"])
+                .output()
+                .expect("docker is installed");
+            let port = String::from_utf8(output.stdout)
+                .unwrap()
+                .lines()
+                .next()
+                .and_then(|addr| addr.rsplit(':').next())
+                .and_then(|port| port.trim().parse().ok())
+                .expect("container has a published port");
+            Container { id, port }
+        }
+
+        /// Returns a server on the container with the target's settings.
+        fn server(
+            &self,
+            name: &str,
+            script: Option<&str>,
+            extra_keys: &[&str],
+            ttl: Option<Duration>,
+        ) -> RedisServer {
+            RedisServer {
+                name: name.to_owned(),
+                connection: ConnectionInfo {
+                    addr: ConnectionAddr::Tcp("127.0.0.1".to_owned(), self.port),
+                    redis: RedisConnectionInfo::default(),
+                },
+                script: script.map(str::to_owned),
+                extra_keys: extra_keys.iter().map(|key| key.to_string()).collect(),
+                ttl,
+                metadata: Some("{\"id\":\"abc\"}".to_owned()),
+                max_filesize: 1 << 20,
+            }
+        }
+
+        /// Connects to the container once it accepts connections.
+        async fn connect(&self) -> redis::aio::Connection {
+            let client = redis::Client::open(format!("redis://127.0.0.1:{}", self.port)).unwrap();
+            for _ in 0..50 {
+                if let Ok(con) = client.get_async_connection().await {
+                    return con;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            panic!("redis container didn't accept connections");
+        }
+    }
+
+    impl Drop for Container {
+        fn drop(&mut self) {
+            let _ = Command::new("docker").args(["rm", "-f", &self.id]).output();
+        }
+    }
+
+    /// Writes keys with `SET`, a script with extra keys, and a TTL to a
+    /// Redis container. Run with `cargo test -- --ignored` where docker
+    /// is available.
+    #[tokio::test]
+    #[ignore]
+    async fn writes_keys() {
+        let container = Container::start();
+        let mut con = container.connect().await;
+
+        let server = container.server("set", None, &[], None);
+        write(&server, "set", b"value").await.unwrap();
+        let value: String = con.get("set").await.unwrap();
+        assert_eq!(value, "value");
+        let ttl: i64 = con.ttl("set").await.unwrap();
+        assert_eq!(ttl, -1);
+
+        let script = "redis.call('SET', KEYS[1], ARGV[1]) \
+                      redis.call('SET', KEYS[2], ARGV[2])";
+        let server = container.server("script", Some(script), &["extra"], None);
+        write(&server, "script", b"value").await.unwrap();
+        let value: String = con.get("script").await.unwrap();
+        assert_eq!(value, "value");
+        let value: String = con.get("extra").await.unwrap();
+        assert_eq!(value, "{\"id\":\"abc\"}");
+
+        let ttl = Some(Duration::from_secs(60));
+        let server = container.server("ttl", None, &[], ttl);
+        write(&server, "ttl", b"value").await.unwrap();
+        let ttl: i64 = con.ttl("ttl").await.unwrap();
+        assert!(ttl > 0 && ttl <= 60, "ttl is {}", ttl);
+
+        let ttl = Some(Duration::from_secs(60));
+        let server = container.server("script-ttl", Some(script), &["extra"], ttl);
+        write(&server, "script-ttl", b"value").await.unwrap();
+        let ttl: i64 = con.ttl("script-ttl").await.unwrap();
+        assert!(ttl > 0 && ttl <= 60, "ttl is {}", ttl);
+    }
+}
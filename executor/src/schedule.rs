@@ -0,0 +1,28 @@
+//! Scheduling of the download pod's uploads. The artifacts of an entity
+//! are downloaded concurrently, and each upload buffers up to a part in
+//! memory, so only [`UploadConfig::max_concurrent`] uploads run at once
+//! to keep the memory of small pods bounded. The rest wait their turn.
+use lazy_static::lazy_static;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::debug;
+use ytdl_common::upload::UploadConfig;
+
+lazy_static! {
+    /// Slots of the uploads running at once. An invalid config fails
+    /// the uploads themselves, so the default is used here.
+    static ref UPLOADS: Semaphore =
+        Semaphore::new(UploadConfig::from_env().unwrap_or_default().max_concurrent);
+}
+
+/// Waits for a free upload slot, which is held until the permit is
+/// dropped. Uploads never wait for a slot while holding one, so they
+/// can't deadlock.
+pub async fn upload_slot() -> SemaphorePermit<'static> {
+    if UPLOADS.available_permits() == 0 {
+        debug!("Waiting for an upload slot");
+    }
+    UPLOADS
+        .acquire()
+        .await
+        .expect("upload semaphore is never closed")
+}
@@ -0,0 +1,123 @@
+use ssh2::{ErrorCode, RenameFlags, Sftp};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::mpsc,
+};
+use ytdl_common::{
+    sftp::{SftpServer, SSH_FX_NO_SUCH_FILE},
+    upload::UploadConfig,
+    Error,
+};
+
+use crate::deadline::bounded;
+
+/// Chunks of the content sent to the blocking writer, with None
+/// marking the end of the content.
+type Chunks = mpsc::Receiver<Option<Vec<u8>>>;
+
+/// Streams the reader to the server as the file at the path, creating
+/// its directories as needed. The content is written next to the file
+/// and renamed once complete, so a partial upload, e.g. one still
+/// running at the pod's deadline, never appears under the final name.
+/// The reader is read a part at a time, as configured by the operator,
+/// while the previous part is written.
+pub async fn put_object_stream<R: AsyncRead + Unpin>(
+    server: &SftpServer,
+    reader: &mut R,
+    path: &str,
+) -> Result<(), Error> {
+    let config = UploadConfig::from_env()?;
+    let (tx, rx) = mpsc::channel(1);
+    // libssh2 is blocking, so the file is written on another thread
+    // while the reader is read.
+    let writer = {
+        let server = server.clone();
+        let path = PathBuf::from(path);
+        tokio::task::spawn_blocking(move || write_file(&server, &path, rx))
+    };
+    let sent = bounded(send_chunks(reader, tx, config.part_size)).await;
+    let written = writer
+        .await
+        .map_err(|e| Error::UnknownError(format!("SFTP upload failed: {}", e)))?;
+    // An aborted upload fails the writer too, but the reason is here.
+    sent?;
+    written
+}
+
+/// Sends the reader to the writer a chunk at a time, followed by the
+/// end marker. Stops early if the writer failed.
+async fn send_chunks<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    tx: mpsc::Sender<Option<Vec<u8>>>,
+    chunk_size: u64,
+) -> Result<(), Error> {
+    loop {
+        let mut chunk = Vec::with_capacity(chunk_size as usize);
+        (&mut *reader)
+            .take(chunk_size)
+            .read_to_end(&mut chunk)
+            .await?;
+        if chunk.is_empty() {
+            break;
+        }
+        if tx.send(Some(chunk)).await.is_err() {
+            // The writer's error is returned instead.
+            return Ok(());
+        }
+    }
+    let _ = tx.send(None).await;
+    Ok(())
+}
+
+/// Writes the chunks to the file at the path, renaming it into place
+/// after the end marker. The partial file is removed if the chunks
+/// stop before the end marker or the upload fails.
+fn write_file(server: &SftpServer, path: &Path, mut chunks: Chunks) -> Result<(), Error> {
+    let sftp = server.connect()?;
+    if let Some(parent) = path.parent() {
+        create_dirs(&sftp, parent)?;
+    }
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let result = (|| -> Result<(), Error> {
+        let mut file = sftp.create(&partial)?;
+        loop {
+            match chunks.blocking_recv() {
+                Some(Some(chunk)) => file.write_all(&chunk)?,
+                Some(None) => break,
+                None => return Err(Error::UnknownError("SFTP upload was aborted".to_owned())),
+            }
+        }
+        // Close the file before it is renamed.
+        drop(file);
+        let flags = RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE;
+        sftp.rename(&partial, path, Some(flags))?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = sftp.unlink(&partial);
+    }
+    result
+}
+
+/// Creates the directory and its missing ancestors.
+fn create_dirs(sftp: &Sftp, dir: &Path) -> Result<(), Error> {
+    if dir.as_os_str().is_empty() {
+        return Ok(());
+    }
+    match sftp.stat(dir) {
+        Ok(_) => return Ok(()),
+        Err(e) if e.code() == ErrorCode::SFTP(SSH_FX_NO_SUCH_FILE) => {}
+        Err(e) => return Err(e.into()),
+    }
+    if let Some(parent) = dir.parent() {
+        create_dirs(sftp, parent)?;
+    }
+    sftp.mkdir(dir, 0o755)?;
+    Ok(())
+}
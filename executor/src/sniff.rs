@@ -0,0 +1,148 @@
+//! Detection of the real container of youtube-dl's output. The `ext`
+//! in the info json is youtube-dl's guess before any remuxing, so an
+//! object templated with `%(ext)s` can end up as e.g. a `.webm` that
+//! actually contains mp4. The head of the stream is sniffed before the
+//! upload starts so the key and Content-Type can be corrected.
+use std::path::Path;
+use tokio::{fs, io::AsyncReadExt};
+use tracing::info;
+use ytdl_common::Error;
+
+/// Number of bytes read from the head of the stream for sniffing.
+pub const SNIFF_LEN: usize = 4096;
+
+/// Known media containers, identified by their magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    /// ISO base media file format (mp4, m4a, mov, 3gp).
+    Mp4,
+    /// Matroska with the `webm` doctype.
+    Webm,
+    /// Matroska with any other doctype.
+    Matroska,
+    Flv,
+    Ogg,
+    Mp3,
+    Wav,
+}
+
+impl Container {
+    /// Extensions that correctly describe the container.
+    /// The first one is used when the key is corrected.
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Container::Mp4 => &["mp4", "m4a", "m4v", "mov", "3gp"],
+            Container::Webm => &["webm", "weba", "mkv", "mka"],
+            Container::Matroska => &["mkv", "mka"],
+            Container::Flv => &["flv"],
+            Container::Ogg => &["ogg", "oga", "ogv", "opus"],
+            Container::Mp3 => &["mp3"],
+            Container::Wav => &["wav"],
+        }
+    }
+
+    /// MIME type for the object's Content-Type.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "video/mp4",
+            Container::Webm => "video/webm",
+            Container::Matroska => "video/x-matroska",
+            Container::Flv => "video/x-flv",
+            Container::Ogg => "application/ogg",
+            Container::Mp3 => "audio/mpeg",
+            Container::Wav => "audio/wav",
+        }
+    }
+}
+
+/// Returns the container the stream's first bytes belong to.
+pub fn sniff(head: &[u8]) -> Option<Container> {
+    if head.len() >= 8 && &head[4..8] == b"ftyp" {
+        return Some(Container::Mp4);
+    }
+    if head.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        // The doctype is near the start of the EBML header.
+        let is_webm = head.windows(4).take(64).any(|w| w == b"webm");
+        return Some(if is_webm {
+            Container::Webm
+        } else {
+            Container::Matroska
+        });
+    }
+    if head.starts_with(b"FLV") {
+        return Some(Container::Flv);
+    }
+    if head.starts_with(b"OggS") {
+        return Some(Container::Ogg);
+    }
+    if head.len() >= 12 && head.starts_with(b"RIFF") && &head[8..12] == b"WAVE" {
+        return Some(Container::Wav);
+    }
+    // An ID3 tag or an MPEG layer III frame header.
+    if head.starts_with(b"ID3") || (head.len() >= 2 && head[0] == 0xFF && head[1] & 0xE6 == 0xE2) {
+        return Some(Container::Mp3);
+    }
+    None
+}
+
+/// Returns the container of the file at the path.
+pub async fn sniff_file(path: &Path) -> Result<Option<Container>, Error> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    fs::File::open(path)
+        .await?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)
+        .await?;
+    Ok(sniff(&head))
+}
+
+/// Returns the corrected key and the Content-Type for an object whose
+/// head is in the given container, if it could be determined.
+pub fn correct_object(key: String, container: Option<Container>) -> (String, &'static str) {
+    match container {
+        Some(container) => {
+            let corrected = correct_key(&key, container);
+            if corrected != key {
+                info!(
+                    key = %key,
+                    corrected = %corrected,
+                    container = ?container,
+                    "Correcting object key extension to match the container"
+                );
+            }
+            (corrected, container.mime_type())
+        }
+        None => (key, "application/octet-stream"),
+    }
+}
+
+/// Returns the key with its extension replaced if the extension is
+/// a media extension that doesn't describe the container. Keys with
+/// no extension, or one that isn't a known media extension, are
+/// left alone since they were chosen deliberately by the user.
+fn correct_key(key: &str, container: Container) -> String {
+    let ext = match Path::new(key).extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.to_lowercase(),
+        None => return key.to_owned(),
+    };
+    if container.extensions().contains(&ext.as_str()) || !is_media_extension(&ext) {
+        return key.to_owned();
+    }
+    let stem = &key[..key.len() - ext.len()];
+    format!("{}{}", stem, container.extensions()[0])
+}
+
+/// Returns true if the extension belongs to any known container.
+fn is_media_extension(ext: &str) -> bool {
+    [
+        Container::Mp4,
+        Container::Webm,
+        Container::Matroska,
+        Container::Flv,
+        Container::Ogg,
+        Container::Mp3,
+        Container::Wav,
+    ]
+    .iter()
+    .any(|container| container.extensions().contains(&ext))
+}
@@ -0,0 +1,249 @@
+use lazy_static::lazy_static;
+use serde_json::Value;
+use sqlx::{
+    migrate::Migrator,
+    postgres::{PgConnectOptions, PgSslMode},
+    types::Json,
+    Connection, PgConnection, Postgres, Transaction,
+};
+use std::{convert::TryFrom, sync::Arc};
+use tokio::sync::Mutex;
+use tracing::info;
+use ytdl_common::{sql::SqlDatabase, Error};
+
+use crate::{
+    backend::{Backend, Clients, OrBackend},
+    deadline::bounded,
+};
+
+/// Migrations of the schema, which is documented in them. They are
+/// applied when the pod first connects, which does nothing once they're
+/// applied. The migrator holds an advisory lock, so pods connecting at
+/// the same time don't apply them twice.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+lazy_static! {
+    /// Connections to the databases, by the name of the SqlTarget.
+    static ref CONNECTIONS: Clients<Arc<Mutex<PgConnection>>> = Clients::default();
+}
+
+/// Inserts the video, or updates it if it was downloaded before.
+const UPSERT_VIDEO: &str = "
+INSERT INTO videos (
+    id, extractor, title, description, uploader, channel_id,
+    upload_date, duration, view_count, webpage_url, metadata, updated_at
+)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, now())
+ON CONFLICT (id) DO UPDATE SET
+    extractor = EXCLUDED.extractor,
+    title = EXCLUDED.title,
+    description = EXCLUDED.description,
+    uploader = EXCLUDED.uploader,
+    channel_id = EXCLUDED.channel_id,
+    upload_date = EXCLUDED.upload_date,
+    duration = EXCLUDED.duration,
+    view_count = EXCLUDED.view_count,
+    webpage_url = EXCLUDED.webpage_url,
+    metadata = EXCLUDED.metadata,
+    updated_at = EXCLUDED.updated_at";
+
+const INSERT_FORMAT: &str = "
+INSERT INTO formats (
+    video_id, format_id, ext, format_note, width, height, vcodec, acodec, filesize, tbr
+)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+ON CONFLICT DO NOTHING";
+
+const INSERT_THUMBNAIL: &str = "
+INSERT INTO thumbnails (video_id, thumbnail_id, url, width, height, preference)
+VALUES ($1, $2, $3, $4, $5, $6)
+ON CONFLICT DO NOTHING";
+
+/// Upserts the metadata into each of the databases, keyed by the video
+/// ID.
+pub async fn upsert_metadata(outputs: &[SqlDatabase], metadata: &Value) -> Result<(), Error> {
+    if outputs.is_empty() {
+        return Ok(());
+    }
+    let id = get_str(metadata, "id")
+        .ok_or_else(|| Error::UserInputError("video metadata has no id".to_owned()))?;
+    for output in outputs {
+        bounded(upsert(output, id, metadata)).await?;
+        info!(
+            name = %output.name,
+            host = %output.host,
+            database = %output.database,
+            id,
+            "Upserted metadata"
+        );
+    }
+    Ok(())
+}
+
+/// Replaces the video's rows in a single transaction, so that readers
+/// never see a video without its formats and thumbnails.
+async fn upsert(output: &SqlDatabase, id: &str, metadata: &Value) -> Result<(), Error> {
+    let conn = CONNECTIONS
+        .get(&output.name, || async {
+            Ok(Arc::new(Mutex::new(connect(output).await?)))
+        })
+        .await?;
+    let result = async {
+        let mut conn = conn.lock().await;
+        let mut tx = conn.begin().await.or_backend(Backend::Sql)?;
+        upsert_video(&mut tx, id, metadata).await?;
+        replace_formats(&mut tx, id, metadata).await?;
+        replace_thumbnails(&mut tx, id, metadata).await?;
+        tx.commit().await.or_backend(Backend::Sql)
+    }
+    .await;
+    CONNECTIONS.evict_on_error(&output.name, result).await
+}
+
+/// Connects to the database and applies the migrations.
+async fn connect(output: &SqlDatabase) -> Result<PgConnection, Error> {
+    let options = get_connect_options(output)?;
+    let mut conn = PgConnection::connect_with(&options)
+        .await
+        .or_backend(Backend::Sql)?;
+    MIGRATOR.run(&mut conn).await.or_backend(Backend::Sql)?;
+    Ok(conn)
+}
+
+/// Returns the options for connecting to the database. The CA bundles
+/// of the targets are trusted through `SSL_CERT_FILE`.
+fn get_connect_options(output: &SqlDatabase) -> Result<PgConnectOptions, Error> {
+    let ssl_mode: PgSslMode = output.ssl_mode.parse().map_err(|e| {
+        Error::UserInputError(format!("invalid sslmode {}: {}", output.ssl_mode, e))
+    })?;
+    let mut options = PgConnectOptions::new()
+        .host(&output.host)
+        .port(output.port)
+        .database(&output.database)
+        .username(&output.username)
+        .password(&output.password)
+        .ssl_mode(ssl_mode);
+    if let Some((ref cert, ref key)) = output.ssl_client {
+        options = options
+            .ssl_client_cert_from_pem(cert)
+            .ssl_client_key_from_pem(key);
+    }
+    Ok(options)
+}
+
+async fn upsert_video(
+    tx: &mut Transaction<'_, Postgres>,
+    id: &str,
+    metadata: &Value,
+) -> Result<(), Error> {
+    sqlx::query(UPSERT_VIDEO)
+        .bind(id)
+        .bind(get_str(metadata, "extractor"))
+        .bind(get_str(metadata, "title"))
+        .bind(get_str(metadata, "description"))
+        .bind(get_str(metadata, "uploader"))
+        .bind(get_str(metadata, "channel_id"))
+        .bind(get_str(metadata, "upload_date"))
+        .bind(metadata.get("duration").and_then(Value::as_f64))
+        .bind(metadata.get("view_count").and_then(Value::as_i64))
+        .bind(get_str(metadata, "webpage_url"))
+        .bind(Json(metadata))
+        .execute(&mut **tx)
+        .await
+        .or_backend(Backend::Sql)?;
+    Ok(())
+}
+
+/// Replaces the video's formats with those in the metadata. Formats
+/// without an ID are skipped, as they can't be told apart.
+async fn replace_formats(
+    tx: &mut Transaction<'_, Postgres>,
+    id: &str,
+    metadata: &Value,
+) -> Result<(), Error> {
+    sqlx::query("DELETE FROM formats WHERE video_id = $1")
+        .bind(id)
+        .execute(&mut **tx)
+        .await
+        .or_backend(Backend::Sql)?;
+    for format in get_array(metadata, "formats") {
+        let format_id = match get_str(format, "format_id") {
+            Some(format_id) => format_id,
+            None => continue,
+        };
+        let filesize = format
+            .get("filesize")
+            .or_else(|| format.get("filesize_approx"))
+            .and_then(Value::as_f64)
+            .map(|filesize| filesize as i64);
+        sqlx::query(INSERT_FORMAT)
+            .bind(id)
+            .bind(format_id)
+            .bind(get_str(format, "ext"))
+            .bind(get_str(format, "format_note"))
+            .bind(get_i32(format, "width"))
+            .bind(get_i32(format, "height"))
+            .bind(get_str(format, "vcodec"))
+            .bind(get_str(format, "acodec"))
+            .bind(filesize)
+            .bind(format.get("tbr").and_then(Value::as_f64))
+            .execute(&mut **tx)
+            .await
+            .or_backend(Backend::Sql)?;
+    }
+    Ok(())
+}
+
+/// Replaces the video's thumbnails with those in the metadata.
+/// Thumbnails without a URL are skipped.
+async fn replace_thumbnails(
+    tx: &mut Transaction<'_, Postgres>,
+    id: &str,
+    metadata: &Value,
+) -> Result<(), Error> {
+    sqlx::query("DELETE FROM thumbnails WHERE video_id = $1")
+        .bind(id)
+        .execute(&mut **tx)
+        .await
+        .or_backend(Backend::Sql)?;
+    for (i, thumbnail) in get_array(metadata, "thumbnails").iter().enumerate() {
+        let url = match get_str(thumbnail, "url") {
+            Some(url) => url,
+            None => continue,
+        };
+        let thumbnail_id = match thumbnail.get("id") {
+            Some(Value::String(thumbnail_id)) => thumbnail_id.clone(),
+            Some(Value::Number(thumbnail_id)) => thumbnail_id.to_string(),
+            _ => i.to_string(),
+        };
+        sqlx::query(INSERT_THUMBNAIL)
+            .bind(id)
+            .bind(thumbnail_id)
+            .bind(url)
+            .bind(get_i32(thumbnail, "width"))
+            .bind(get_i32(thumbnail, "height"))
+            .bind(get_i32(thumbnail, "preference"))
+            .execute(&mut **tx)
+            .await
+            .or_backend(Backend::Sql)?;
+    }
+    Ok(())
+}
+
+fn get_str<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(Value::as_str)
+}
+
+fn get_i32(value: &Value, key: &str) -> Option<i32> {
+    value
+        .get(key)
+        .and_then(Value::as_i64)
+        .and_then(|value| i32::try_from(value).ok())
+}
+
+fn get_array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
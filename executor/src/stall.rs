@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+use ytdl_common::{defaults::DEFAULT_STALL_TIMEOUT, units::parse_duration, Error};
+use ytdl_types::Executor;
+
+/// Number of times a stalled youtube-dl process is killed and
+/// restarted before the download fails.
+pub const MAX_STALL_RETRIES: u32 = 3;
+
+/// Longest interval at which progress is sampled.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Returns the window without any bytes downloaded after which
+/// youtube-dl is considered stalled, or None if detection is disabled.
+pub fn get_stall_timeout(instance: &Executor) -> Result<Option<Duration>, Error> {
+    let timeout = parse_duration(
+        instance
+            .spec
+            .stall_timeout
+            .as_deref()
+            .unwrap_or(DEFAULT_STALL_TIMEOUT),
+    )?;
+    Ok(Some(timeout).filter(|timeout| !timeout.is_zero()))
+}
+
+/// Resolves once `progress` has not changed for the duration of the
+/// window, and never if the window is None. `progress` should return
+/// a value that grows as bytes are downloaded, e.g. a byte count.
+pub async fn stalled<F: FnMut() -> u64>(mut progress: F, window: Option<Duration>) -> Error {
+    let window = match window {
+        Some(window) => window,
+        None => return futures::future::pending().await,
+    };
+    let mut last = progress();
+    let mut last_change = Instant::now();
+    loop {
+        tokio::time::sleep(POLL_INTERVAL.min(window)).await;
+        let current = progress();
+        if current != last {
+            last = current;
+            last_change = Instant::now();
+        } else if last_change.elapsed() >= window {
+            return Error::Stalled {
+                seconds: window.as_secs(),
+            };
+        }
+    }
+}
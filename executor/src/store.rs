@@ -0,0 +1,36 @@
+use std::{collections::BTreeMap, path::Path};
+use tokio::fs;
+use ytdl_common::{manifest::CHECKSUM_METADATA_KEY, store::Store, Error};
+use ytdl_types::StoredObject;
+
+use crate::{azure, gcs, manifest::hash_file, sftp, webdav};
+
+/// Uploads the file to the store, storing its checksum as metadata
+/// where the store supports it.
+pub async fn put_file(
+    store: &Store,
+    path: &Path,
+    key: &str,
+    content_type: &str,
+) -> Result<StoredObject, Error> {
+    let (size, sha256) = hash_file(path).await?;
+    let metadata = BTreeMap::from([(CHECKSUM_METADATA_KEY.to_owned(), sha256.clone())]);
+    let mut body = fs::File::open(path).await?;
+    match store {
+        Store::Gcs(bucket) => {
+            gcs::put_object_stream(bucket, &mut body, key, content_type, &metadata).await?
+        }
+        Store::Azure(container) => {
+            azure::put_object_stream(container, &mut body, key, content_type, &metadata).await?
+        }
+        Store::Sftp(server) => sftp::put_object_stream(server, &mut body, key).await?,
+        // The file is reopened for each attempt.
+        Store::WebDav(server) => webdav::put_file(server, path, key, size, content_type).await?,
+    }
+    Ok(StoredObject {
+        bucket: store.name().to_owned(),
+        key: key.to_owned(),
+        size,
+        sha256,
+    })
+}
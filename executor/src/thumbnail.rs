@@ -0,0 +1,167 @@
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
+use std::{ffi::OsStr, path::Path};
+use ytdl_common::Error;
+use ytdl_types::{Executor, ThumbnailStorageSpec};
+
+/// A struct containing the processing options when downloading
+/// thumbnails. To prevent a bucket from receiving thumbnails of
+/// mixed formats, the user may specify an output format for
+/// all thumbnails. If the user does not specify a format, the
+/// format is inferred from the output key. If the format
+/// cannot be inferred at all, the thumbnail is stored in the
+/// format the video service serves it in.
+/// If only one of the resize dimensions is set, the image
+/// will be resized proportionally, keeping the aspect ratio.
+pub struct ThumbnailOptions {
+    /// Output format for the thumbnail, or None to keep the
+    /// format of the downloaded thumbnail. Conversion is enforced
+    /// to normalize the format across all thumbnails.
+    pub format: Option<ImageFormat>,
+
+    /// Sampling filter to use when resizing.
+    pub filter: FilterType,
+
+    /// Maximum width (pixels) of the thumbnail image.
+    pub width: Option<u32>,
+
+    /// Maximum height (pixels) of the thumbnail image.
+    pub height: Option<u32>,
+}
+
+/// Returns a struct containing download and processing options
+/// for the thumbnail. The options are determined by the spec
+/// and the output key is used to infer output format if it's
+/// not specified explicitly in the spec. Without a thumbnail
+/// section in the spec, the thumbnail is not resized.
+pub fn get_thumbnail_options(instance: &Executor, key: &str) -> Result<ThumbnailOptions, Error> {
+    // All of the thumbnail output options are specified in a single
+    // section of the spec that addresses thumbnail storage, which
+    // may be omitted if only the key implies a thumbnail.
+    let thumbnail: Option<&ThumbnailStorageSpec> = instance.spec.output.thumbnail.as_ref();
+    // Determine the sampling filter to use when resizing.
+    let filter: FilterType = match thumbnail.and_then(|thumbnail| thumbnail.filter.as_ref()) {
+        // User can override the filter in the spec.
+        Some(filter) => parse_filter_type(filter).ok_or_else(|| {
+            Error::UserInputError(format!("unsupported image filter: {}", filter))
+        })?,
+        // Default filter is the highest quality.
+        None => FilterType::Lanczos3,
+    };
+    // Determine the output image format, which may be
+    // different from the downloaded thumbnail and will
+    // necessitate conversion.
+    let format = match thumbnail.and_then(|thumbnail| thumbnail.format.as_ref()) {
+        // Prefer the overridden format in the spec.
+        Some(format) => Some(ImageFormat::from_extension(format).ok_or_else(|| {
+            Error::UserInputError(format!("unsupported thumbnail format: {}", format))
+        })?),
+        // Default to the format inferred from the output key, or
+        // the downloaded thumbnail's if the key has no image
+        // extension, e.g. with the default template.
+        None => get_format_from_filename(key),
+    };
+    Ok(ThumbnailOptions {
+        format,
+        filter,
+        width: thumbnail.and_then(|thumbnail| thumbnail.width),
+        height: thumbnail.and_then(|thumbnail| thumbnail.height),
+    })
+}
+
+/// Returns the default thumbnail url from the video infojson.
+pub fn get_thumbnail_url(metadata: &serde_json::Value) -> Result<String, Error> {
+    Ok(metadata
+        .get("thumbnail")
+        .ok_or_else(|| Error::UserInputError("metadata is missing thumbnail".to_owned()))?
+        .as_str()
+        .ok_or_else(|| Error::UserInputError("metadata thumbnail is not a string".to_owned()))?
+        .to_owned())
+}
+
+/// Converts the HTTP response Content-Type header
+/// to the corresponding image format enum value.
+pub fn mimetype_to_format(mimetype: &str) -> Result<ImageFormat, Error> {
+    Ok(match mimetype {
+        "image/jpeg" => ImageFormat::Jpeg,
+        "image/png" => ImageFormat::Png,
+        "image/gif" => ImageFormat::Gif,
+        "image/webp" => ImageFormat::WebP,
+        "image/tiff" => ImageFormat::Tiff,
+        "image/bmp" => ImageFormat::Bmp,
+        "image/x-icon" => ImageFormat::Ico,
+        _ => {
+            return Err(Error::UserInputError(format!(
+                "unsupported thumbnail mimetype {}",
+                mimetype
+            )))
+        }
+    })
+}
+
+/// Returns the mimetype of the image format, which is the
+/// Content-Type of the uploaded thumbnail.
+pub fn format_to_mimetype(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::Png => "image/png",
+        ImageFormat::Gif => "image/gif",
+        ImageFormat::WebP => "image/webp",
+        ImageFormat::Tiff => "image/tiff",
+        ImageFormat::Bmp => "image/bmp",
+        ImageFormat::Ico => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Returns the FilterType enum value for the given filter name.
+/// The matching is case insensitive.
+fn parse_filter_type(value: &str) -> Option<FilterType> {
+    match value.to_lowercase().as_str() {
+        "lanczos3" => Some(FilterType::Lanczos3),
+        "triangle" => Some(FilterType::Triangle),
+        "catmullrom" => Some(FilterType::CatmullRom),
+        "gaussian" => Some(FilterType::Gaussian),
+        "nearest" => Some(FilterType::Nearest),
+        _ => None,
+    }
+}
+
+/// Resizes the image using the specified filter and dimensions.
+/// If only one dimension is specified, the other dimension is
+/// calculated to maintain the aspect ratio.
+pub fn resize_image(
+    img: DynamicImage,
+    filter: FilterType,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> DynamicImage {
+    match (width, height) {
+        // Resize both dimensions to the exact specified size.
+        (Some(width), Some(height)) => img.resize(width, height, filter),
+        // Resize the width to the specified size and maintain the
+        // aspect ratio.
+        (Some(width), None) => {
+            let aspect = img.width() as f32 / img.height() as f32;
+            let height = (width as f32 / aspect) as u32;
+            img.resize(width, height, filter)
+        }
+        // Resize the height to the specified size and maintain the
+        // aspect ratio.
+        (None, Some(height)) => {
+            let aspect = img.width() as f32 / img.height() as f32;
+            let width = (height as f32 * aspect) as u32;
+            img.resize(width, height, filter)
+        }
+        // Don't resize the image.
+        (None, None) => img,
+    }
+}
+
+/// Returns the ImageFormat enum value based on the file extension
+/// of the given filename/path.
+fn get_format_from_filename(filename: &str) -> Option<ImageFormat> {
+    Path::new(filename)
+        .extension()
+        .and_then(OsStr::to_str)
+        .and_then(ImageFormat::from_extension)
+}
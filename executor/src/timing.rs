@@ -0,0 +1,67 @@
+use kube::{
+    api::{Api, Patch, PatchParams},
+    client::Client,
+    ResourceExt,
+};
+use std::{sync::Mutex, time::Duration};
+use tracing::warn;
+use ytdl_common::timing::STAGE_TIMING_ANNOTATION;
+use ytdl_types::{Executor, StageTiming};
+
+/// Stages of the download pod's work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    VpnWait,
+    Query,
+    Download,
+    Upload,
+}
+
+/// Time spent in each stage by this pod so far. The video and the
+/// thumbnail are downloaded concurrently, hence the lock.
+static TIMING: Mutex<StageTiming> = Mutex::new(StageTiming {
+    vpn_wait_seconds: 0.0,
+    query_seconds: 0.0,
+    download_seconds: 0.0,
+    upload_seconds: 0.0,
+});
+
+/// Adds the duration to the time spent in the stage.
+pub fn add(stage: Stage, duration: Duration) {
+    let seconds = duration.as_secs_f64();
+    let mut timing = TIMING.lock().unwrap();
+    match stage {
+        Stage::VpnWait => timing.vpn_wait_seconds += seconds,
+        Stage::Query => timing.query_seconds += seconds,
+        Stage::Download => timing.download_seconds += seconds,
+        Stage::Upload => timing.upload_seconds += seconds,
+    }
+}
+
+/// Reports the time spent in each stage so far to the controller,
+/// which records it in the status. Failure to report is logged but
+/// does not fail the download.
+pub async fn report(client: Client, instance: &Executor) {
+    let timing = TIMING.lock().unwrap().clone();
+    let value = match serde_json::to_string(&timing) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!(error = %e, "Failed to serialize stage timing");
+            return;
+        }
+    };
+    let patch = Patch::Merge(serde_json::json!({
+        "metadata": {
+            "annotations": {
+                STAGE_TIMING_ANNOTATION: value,
+            },
+        },
+    }));
+    let api: Api<Executor> = Api::namespaced(client, &instance.namespace().unwrap());
+    if let Err(e) = api
+        .patch(&instance.name_any(), &PatchParams::default(), &patch)
+        .await
+    {
+        warn!(error = %e, "Failed to report stage timing");
+    }
+}
@@ -0,0 +1,169 @@
+use s3::{bucket::Bucket, error::S3Error, serde_types::Part};
+use std::{future::Future, time::Duration};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::warn;
+use ytdl_common::{
+    manifest::CHECKSUM_METADATA_KEY, object_headers::CONTENT_TYPE_HEADER, upload::UploadConfig,
+    Error,
+};
+
+use crate::deadline::bounded;
+
+/// Delay before the first retry, doubled after each attempt.
+pub(crate) const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Streams the reader to the bucket. Content larger than a single
+/// part is uploaded with a multipart upload, and each request that
+/// fails with a transient error (5xx, throttling, timeout) is retried
+/// with exponential backoff, so a hiccup only costs the failed part
+/// rather than the whole video. The part size and number of attempts
+/// are configured by the operator. A multipart upload still running at
+/// the pod's deadline is aborted. Returns the final status code.
+pub async fn put_object_stream<R: AsyncRead + Unpin>(
+    bucket: &Bucket,
+    reader: &mut R,
+    key: &str,
+    content_type: &str,
+) -> Result<u16, Error> {
+    let config = UploadConfig::from_env()?;
+    // A Content-Type among the bucket's headers overrides the detected one.
+    let content_type = bucket
+        .extra_headers
+        .get(CONTENT_TYPE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or(content_type);
+    let chunk = read_chunk(reader, &config).await?;
+    if (chunk.len() as u64) < config.part_size {
+        // The content fits in a single request.
+        let res = bounded(retry(&config, "put object", || {
+            bucket.put_object_with_content_type(key, &chunk, content_type)
+        }))
+        .await?;
+        return Ok(res.status_code());
+    }
+    let upload_id = retry(&config, "initiate multipart upload", || {
+        bucket.initiate_multipart_upload(key, content_type)
+    })
+    .await?
+    .upload_id;
+    // The bucket's headers describe the object, e.g. its encryption,
+    // and S3 only accepts them on the request that creates it.
+    let bucket = &without_headers(bucket);
+    match bounded(put_parts(
+        bucket,
+        reader,
+        key,
+        content_type,
+        &upload_id,
+        chunk,
+        &config,
+    ))
+    .await
+    {
+        Ok(parts) => {
+            let res = retry(&config, "complete multipart upload", || {
+                bucket.complete_multipart_upload(key, &upload_id, parts.clone())
+            })
+            .await?;
+            Ok(res.status_code())
+        }
+        Err(e) => {
+            // Don't leave the uploaded parts behind to be billed for.
+            if let Err(e) = bucket.abort_upload(key, &upload_id).await {
+                warn!(error = %e, key, "Failed to abort multipart upload");
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Returns a copy of the bucket that stores the checksum as user
+/// metadata of the objects it uploads, for content hashed beforehand.
+pub fn with_checksum(bucket: &Bucket, sha256: &str) -> Bucket {
+    let mut bucket = bucket.clone();
+    bucket.add_header(&format!("x-amz-meta-{}", CHECKSUM_METADATA_KEY), sha256);
+    bucket
+}
+
+/// Returns a copy of the bucket without its extra headers.
+fn without_headers(bucket: &Bucket) -> Bucket {
+    let mut bucket = bucket.clone();
+    bucket.extra_headers.clear();
+    bucket
+}
+
+/// Uploads the first chunk and the rest of the reader as the
+/// parts of the multipart upload, returning the uploaded parts.
+async fn put_parts<R: AsyncRead + Unpin>(
+    bucket: &Bucket,
+    reader: &mut R,
+    key: &str,
+    content_type: &str,
+    upload_id: &str,
+    mut chunk: Vec<u8>,
+    config: &UploadConfig,
+) -> Result<Vec<Part>, Error> {
+    let mut parts = Vec::new();
+    while !chunk.is_empty() {
+        let part_number = parts.len() as u32 + 1;
+        let part = retry(config, "upload part", || {
+            bucket.put_multipart_chunk(chunk.clone(), key, part_number, upload_id, content_type)
+        })
+        .await?;
+        parts.push(part);
+        chunk = read_chunk(reader, config).await?;
+    }
+    Ok(parts)
+}
+
+/// Reads up to a part's worth of bytes, less only at the end.
+async fn read_chunk<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    config: &UploadConfig,
+) -> Result<Vec<u8>, Error> {
+    let mut chunk = Vec::with_capacity(config.part_size as usize);
+    (&mut *reader)
+        .take(config.part_size)
+        .read_to_end(&mut chunk)
+        .await?;
+    Ok(chunk)
+}
+
+/// Calls `f` until it succeeds, it fails with an error that is not
+/// transient, or the attempts are exhausted.
+async fn retry<T, F, Fut>(config: &UploadConfig, what: &str, mut f: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, S3Error>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < config.max_attempts && is_transient(&e) => {
+                warn!(
+                    error = %e,
+                    attempt,
+                    backoff = ?backoff,
+                    "Failed to {}, retrying",
+                    what
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Returns true if the request may succeed when sent again.
+fn is_transient(e: &S3Error) -> bool {
+    match e {
+        S3Error::Http(status, _) => *status >= 500 || *status == 429,
+        S3Error::Reqwest(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+        S3Error::Io(_) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,48 @@
+use reqwest::Body;
+use std::path::Path;
+use tokio::fs;
+use tokio_util::io::ReaderStream;
+use tracing::warn;
+use ytdl_common::{store::is_transient, upload::UploadConfig, webdav::WebDavServer, Error};
+
+use crate::{deadline::bounded, upload::INITIAL_BACKOFF};
+
+/// Uploads the file to the server at the path, creating its collections
+/// as needed. The file is streamed as the body of a single chunked PUT,
+/// so it is never buffered in memory. The server only stores the file
+/// once the whole body is received, so an upload still running at the
+/// pod's deadline is simply dropped. PUT replaces the file, so a failed
+/// upload is retried from the start of the file.
+pub async fn put_file(
+    server: &WebDavServer,
+    file: &Path,
+    path: &str,
+    size: u64,
+    content_type: &str,
+) -> Result<(), Error> {
+    let config = UploadConfig::from_env()?;
+    bounded(async {
+        server.create_collections(path).await?;
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            let body = Body::wrap_stream(ReaderStream::new(fs::File::open(file).await?));
+            match server.put_object(path, body, size, content_type).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < config.max_attempts && is_transient(&e) => {
+                    warn!(
+                        error = %e,
+                        attempt,
+                        backoff = ?backoff,
+                        "Failed to upload file, retrying",
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    })
+    .await
+}
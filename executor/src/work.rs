@@ -0,0 +1,154 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio::fs;
+use tracing::warn;
+use ytdl_common::{
+    auth::get_auth_args, cookies::get_cookies_file, failure::FailureReason, naming::normalize_id,
+    proxy::get_proxy_url, work_volume::WORK_PATH, Error,
+};
+use ytdl_types::Executor;
+
+use crate::{
+    deadline,
+    download::{build_args, watch_stderr, youtube_dl, youtube_dl_error},
+    progress,
+    stall::{get_stall_timeout, stalled, MAX_STALL_RETRIES},
+};
+
+/// Returns the directory videos are downloaded to, which is the work
+/// volume if the Executor has one and the fallback otherwise.
+pub fn get_work_dir(instance: &Executor, fallback: &str) -> PathBuf {
+    match instance.spec.work_volume {
+        Some(_) => PathBuf::from(WORK_PATH),
+        None => PathBuf::from(fallback),
+    }
+}
+
+/// Returns the name of the video's file, without the extension. Files
+/// are named after the video so that a pod recreated for the same batch
+/// resumes each video from its own partial file.
+pub fn get_file_name(metadata: &serde_json::Value) -> String {
+    metadata
+        .get("id")
+        .and_then(|id| id.as_str())
+        .map(normalize_id)
+        .unwrap_or_else(|| "video".to_owned())
+}
+
+/// Downloads the whole video into the directory and returns the path
+/// of the file youtube-dl wrote. If youtube-dl stalls it is restarted,
+/// resuming from the partial file.
+pub async fn download_file(
+    command: &str,
+    instance: &Executor,
+    dir: &Path,
+    name: &str,
+    downloaded: &AtomicU64,
+) -> Result<PathBuf, Error> {
+    let stall_timeout = get_stall_timeout(instance)?;
+    let mut stalls = 0;
+    loop {
+        match run_download(command, instance, dir, name, stall_timeout).await {
+            Err(Error::Stalled { seconds }) if stalls < MAX_STALL_RETRIES => {
+                stalls += 1;
+                warn!(seconds, stalls, "youtube-dl stalled, resuming the download");
+            }
+            result => {
+                result?;
+                break;
+            }
+        }
+    }
+    // The extension is chosen by youtube-dl, so find the file.
+    let prefix = format!("{}.", name);
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name.starts_with(&prefix) && !file_name.ends_with(".part") {
+            downloaded.fetch_add(entry.metadata().await?.len(), Ordering::Relaxed);
+            return Ok(entry.path());
+        }
+    }
+    Err(Error::UnknownError(
+        "youtube-dl exited successfully but the video file was not found".to_owned(),
+    ))
+}
+
+/// Runs youtube-dl once, writing the video into the directory.
+/// youtube-dl is killed if the directory does not grow for the
+/// duration of the timeout.
+async fn run_download(
+    command: &str,
+    instance: &Executor,
+    dir: &Path,
+    name: &str,
+    stall_timeout: Option<Duration>,
+) -> Result<(), Error> {
+    let proxy = get_proxy_url()?;
+    let cookies = get_cookies_file()?;
+    let auth = get_auth_args()?;
+    let output = format!("{}/{}.%(ext)s", dir.display(), name);
+    let mut args = build_args(instance, proxy.as_deref(), cookies.as_deref(), &auth);
+    args.push("--output");
+    args.push(&output);
+    // Resume the partial file left by a stalled attempt or,
+    // on a persistent work volume, by a previous pod.
+    args.push("--continue");
+    let mut child = youtube_dl(command)
+        .args(&args[..])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // youtube-dl prints its progress to stdout when writing a file.
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| Error::UnknownError("failed to get child process stdout".to_owned()))?;
+    tokio::spawn(progress::watch(stdout));
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| Error::UnknownError("failed to get child process stderr".to_owned()))?;
+    let stderr = tokio::spawn(watch_stderr(stderr));
+    let status = tokio::select! {
+        status = child.wait() => status?,
+        error = stalled(|| get_dir_size(dir), stall_timeout) => {
+            let _ = child.kill().await;
+            return Err(error);
+        }
+        // The partial file is left for the next pod to resume.
+        error = deadline::expired() => {
+            let _ = child.kill().await;
+            return Err(error);
+        }
+    };
+    if let Ok(Some((reason, line))) = stderr.await {
+        match reason {
+            FailureReason::AgeRestricted => return Err(Error::AgeRestricted(line)),
+            FailureReason::GeoBlocked => return Err(Error::GeoBlocked(line)),
+            FailureReason::DeadlineExceeded | FailureReason::Unknown => {}
+        }
+    }
+    if !status.success() {
+        return Err(youtube_dl_error(status));
+    }
+    Ok(())
+}
+
+/// Returns the total size of the files in the directory,
+/// which grows as youtube-dl writes the video.
+fn get_dir_size(dir: &Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
@@ -0,0 +1,281 @@
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use k8s_openapi::{api::core::v1::ConfigMap, NamespaceResourceScope};
+use kube::{
+    api::{ListParams, Patch, PatchParams, PostParams},
+    Api, Client, Resource, ResourceExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    fs::File,
+    io::{Read, Write},
+};
+use tracing::{info, warn};
+use ytdl_common::{results::RESULTS_LABEL, Error, INFO_JSONL_KEY};
+use ytdl_types::{
+    AzureBlobTarget, DefaultTargets, Download, Executor, GcsTarget, HostPolicy, KafkaTarget,
+    MongoDBTarget, NatsTarget, RedisTarget, S3Target, SftpTarget, SqlTarget, Target, VolumeTarget,
+    WebDavTarget, WebhookTarget,
+};
+
+use crate::util::get_watch_apis;
+
+/// Identifies a resource by kind, namespace, and name. Used to
+/// point owner references at the recreated resources, which are
+/// assigned new UIDs by the destination cluster.
+type ResourceKey = (String, String, String);
+
+/// Exports all ytdl resources, including their status objects, and
+/// the Downloads' metadata, archive, and results ConfigMaps to a
+/// gzipped tarball. Each kind is stored as a json array in its own file.
+pub async fn export(client: Client, namespaces: &[String], path: &str) -> Result<(), Error> {
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    // Targets are exported first as they are referenced by the Downloads.
+    entries.push(export_kind::<Target>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<S3Target>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<WebhookTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<SqlTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<MongoDBTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<RedisTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<GcsTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<AzureBlobTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<VolumeTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<SftpTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<WebDavTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<KafkaTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<NatsTarget>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<DefaultTargets>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<HostPolicy>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<Download>(client.clone(), namespaces).await?);
+    entries.push(export_kind::<Executor>(client.clone(), namespaces).await?);
+    entries.push(export_configmaps(client, namespaces).await?);
+    let mut builder =
+        tar::Builder::new(GzEncoder::new(File::create(path)?, Compression::default()));
+    for (name, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, &name, &data[..])?;
+    }
+    builder.into_inner()?.finish()?.flush()?;
+    info!(path, "Export complete");
+    Ok(())
+}
+
+/// Imports the resources from a tarball created by [`export`]. Resources
+/// that already exist are left untouched. Status objects are restored so
+/// the controllers resume where they left off instead of starting over.
+pub async fn import(client: Client, path: &str) -> Result<(), Error> {
+    let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        files.insert(name, data);
+    }
+    // Owners are imported before the resources that reference them.
+    let mut uids: HashMap<ResourceKey, String> = HashMap::new();
+    import_kind::<Target>(client.clone(), &files, &mut uids).await?;
+    import_kind::<S3Target>(client.clone(), &files, &mut uids).await?;
+    import_kind::<WebhookTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<SqlTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<MongoDBTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<RedisTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<GcsTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<AzureBlobTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<VolumeTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<SftpTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<WebDavTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<KafkaTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<NatsTarget>(client.clone(), &files, &mut uids).await?;
+    import_kind::<DefaultTargets>(client.clone(), &files, &mut uids).await?;
+    import_kind::<HostPolicy>(client.clone(), &files, &mut uids).await?;
+    import_kind::<Download>(client.clone(), &files, &mut uids).await?;
+    import_kind::<Executor>(client.clone(), &files, &mut uids).await?;
+    import_kind::<ConfigMap>(client, &files, &mut uids).await?;
+    info!(path, "Import complete");
+    Ok(())
+}
+
+/// Returns the name of the file in the tarball for the given kind.
+fn get_file_name<K>() -> String
+where
+    K: Resource,
+    <K as Resource>::DynamicType: Default,
+{
+    format!("{}.json", K::plural(&Default::default()))
+}
+
+/// Lists all resources of the given kind in the watched namespaces.
+/// Kinds whose CRD is not installed are exported as an empty list.
+async fn export_kind<K>(client: Client, namespaces: &[String]) -> Result<(String, Vec<u8>), Error>
+where
+    K: Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Serialize + Debug,
+    <K as Resource>::DynamicType: Default,
+{
+    let mut items: Vec<K> = Vec::new();
+    for api in get_watch_apis::<K>(client.clone(), namespaces) {
+        match api.list(&ListParams::default()).await {
+            Ok(list) => items.extend(list.items),
+            Err(kube::Error::Api(ae)) if ae.code == 404 => {
+                warn!(kind = %K::kind(&Default::default()), "CRD is not installed, skipping");
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    info!(kind = %K::kind(&Default::default()), count = items.len(), "Exported resources");
+    Ok((get_file_name::<K>(), serde_json::to_vec_pretty(&items)?))
+}
+
+/// Exports the ConfigMaps of each Download. The ones that cache the
+/// info jsonl are named after their Download and save the new cluster
+/// from having to query every Download again. The archive, which may
+/// be shared by several Downloads, and the results of the culled
+/// Executors are exported so that nothing is downloaded twice.
+async fn export_configmaps(
+    client: Client,
+    namespaces: &[String],
+) -> Result<(String, Vec<u8>), Error> {
+    let mut items: Vec<ConfigMap> = Vec::new();
+    let mut exported: HashSet<(String, String)> = HashSet::new();
+    let mut add = |cm: ConfigMap| {
+        if exported.insert((cm.namespace().unwrap_or_default(), cm.name_any())) {
+            items.push(cm);
+        }
+    };
+    for api in get_watch_apis::<Download>(client.clone(), namespaces) {
+        for download in api.list(&ListParams::default()).await?.items {
+            let cm_api: Api<ConfigMap> =
+                Api::namespaced(client.clone(), download.namespace().as_ref().unwrap());
+            if let Some(cm) = cm_api.get_opt(&download.name_any()).await? {
+                let has_metadata = cm
+                    .data
+                    .as_ref()
+                    .map_or(false, |data| data.contains_key(INFO_JSONL_KEY));
+                if has_metadata {
+                    add(cm);
+                }
+            }
+            let archive = download
+                .spec
+                .archive
+                .as_ref()
+                .and_then(|archive| archive.config_map.as_deref());
+            if let Some(name) = archive {
+                if let Some(cm) = cm_api.get_opt(name).await? {
+                    add(cm);
+                }
+            }
+            if download.spec.results.is_some() {
+                let selector = format!("{}={}", RESULTS_LABEL, download.uid().unwrap());
+                for cm in cm_api
+                    .list(&ListParams::default().labels(&selector))
+                    .await?
+                {
+                    add(cm);
+                }
+            }
+        }
+    }
+    info!(
+        kind = "ConfigMap",
+        count = items.len(),
+        "Exported resources"
+    );
+    Ok((
+        get_file_name::<ConfigMap>(),
+        serde_json::to_vec_pretty(&items)?,
+    ))
+}
+
+/// Recreates the resources of the given kind from the tarball, then
+/// restores their status objects.
+async fn import_kind<K>(
+    client: Client,
+    files: &HashMap<String, Vec<u8>>,
+    uids: &mut HashMap<ResourceKey, String>,
+) -> Result<(), Error>
+where
+    K: Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Serialize + Debug,
+    <K as Resource>::DynamicType: Default,
+{
+    let kind = K::kind(&Default::default()).into_owned();
+    let data = match files.get(&get_file_name::<K>()) {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+    let items: Vec<serde_json::Value> = serde_json::from_slice(data)?;
+    let mut count = 0;
+    for mut item in items {
+        // The status subresource is ignored on creation.
+        let status = item.as_object_mut().and_then(|item| item.remove("status"));
+        let mut resource: K = serde_json::from_value(item)?;
+        let name = resource.name_any();
+        let namespace = resource.namespace().unwrap();
+        prepare_metadata(&mut resource, uids);
+        let api: Api<K> = Api::namespaced(client.clone(), &namespace);
+        let created = match api.create(&PostParams::default(), &resource).await {
+            Ok(created) => created,
+            Err(kube::Error::Api(ae)) if ae.code == 409 => {
+                warn!(%kind, %namespace, %name, "Resource already exists, skipping");
+                api.get(&name).await?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(uid) = created.uid() {
+            uids.insert((kind.clone(), namespace, name.clone()), uid);
+        }
+        if let Some(status) = status {
+            let patch = Patch::Merge(serde_json::json!({ "status": status }));
+            api.patch_status(&name, &PatchParams::default(), &patch)
+                .await?;
+        }
+        count += 1;
+    }
+    info!(%kind, count, "Imported resources");
+    Ok(())
+}
+
+/// Removes the cluster-assigned fields from the resource's metadata
+/// and points its owner references at the recreated owners. Owner
+/// references to resources that were not imported are dropped.
+fn prepare_metadata<K: Resource>(resource: &mut K, uids: &HashMap<ResourceKey, String>) {
+    let namespace = resource.namespace().unwrap();
+    let meta = resource.meta_mut();
+    meta.uid = None;
+    meta.resource_version = None;
+    meta.creation_timestamp = None;
+    meta.deletion_timestamp = None;
+    meta.generation = None;
+    meta.managed_fields = None;
+    meta.self_link = None;
+    // Finalizers are added back by the controllers as needed.
+    meta.finalizers = None;
+    if let Some(owner_references) = meta.owner_references.take() {
+        let owner_references: Vec<_> = owner_references
+            .into_iter()
+            .filter_map(|mut oref| {
+                let key = (oref.kind.clone(), namespace.clone(), oref.name.clone());
+                oref.uid = uids.get(&key)?.clone();
+                Some(oref)
+            })
+            .collect();
+        meta.owner_references = Some(owner_references).filter(|orefs| !orefs.is_empty());
+    }
+    // The results ConfigMaps are found by the uid of their Download.
+    let owner_uid = meta
+        .owner_references
+        .iter()
+        .flatten()
+        .find(|oref| oref.controller == Some(true))
+        .map(|oref| oref.uid.clone());
+    if let (Some(labels), Some(uid)) = (meta.labels.as_mut(), owner_uid) {
+        if let Some(label) = labels.get_mut(RESULTS_LABEL) {
+            *label = uid;
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Benchmarking harness for the controllers. Creates a fleet of fake
+//! Downloads in a disposable cluster (e.g. kind) where the operator is
+//! running, and measures how quickly the Download controller reconciles
+//! them and how many requests reach the API server meanwhile.
+use futures::StreamExt;
+use k8s_openapi::http;
+use kube::{
+    api::{DeleteParams, ListParams, ObjectMeta, PostParams},
+    runtime::watcher::{self, watcher},
+    Api, Client, ResourceExt,
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+use ytdl_common::Error;
+use ytdl_types::{Download, DownloadSpec};
+
+/// Label on the fake Downloads, whose value identifies the run.
+pub const BENCH_LABEL: &str = "ytdl.beebs.dev/bench";
+
+/// Options for a benchmark run.
+pub struct BenchOptions {
+    /// Namespace to create the Downloads in.
+    pub namespace: String,
+
+    /// Number of Downloads to create.
+    pub count: usize,
+
+    /// Maximum number of concurrent create requests.
+    pub parallelism: usize,
+
+    /// Create the Downloads unsuspended, so the controller creates a
+    /// query pod for each. By default they are suspended, which limits
+    /// the run to the controller and the API server.
+    pub unsuspended: bool,
+
+    /// Time to wait for every Download to be reconciled.
+    pub timeout: Duration,
+
+    /// Leave the Downloads in place after the run.
+    pub keep: bool,
+}
+
+/// Runs the benchmark and logs the results.
+pub async fn run(client: Client, options: BenchOptions) -> Result<(), Error> {
+    let run_id = chrono::Utc::now().format("%Y%m%d%H%M%S").to_string();
+    let api: Api<Download> = Api::namespaced(client.clone(), &options.namespace);
+    let selector = format!("{}={}", BENCH_LABEL, run_id);
+
+    let mut stream = watcher(api.clone(), ListParams::default().labels(&selector)).boxed();
+    let before = get_api_request_counts(&client).await;
+    let start = Instant::now();
+
+    // The Downloads are created while watching, as the controller may
+    // reconcile a Download before its create request even returns.
+    let create_all = async {
+        let results: Vec<Result<(String, Instant), Error>> =
+            futures::stream::iter(0..options.count)
+                .map(|i| create(&api, &run_id, i, options.unsuspended))
+                .buffer_unordered(options.parallelism.max(1))
+                .collect()
+                .await;
+        info!(
+            count = options.count,
+            seconds = start.elapsed().as_secs_f64(),
+            "Created Downloads"
+        );
+        results
+    };
+
+    // A Download counts as reconciled once the controller writes
+    // its first status.
+    let watch_all = async {
+        let mut reconciled: HashMap<String, Instant> = HashMap::with_capacity(options.count);
+        let deadline = tokio::time::sleep(options.timeout);
+        tokio::pin!(deadline);
+        while reconciled.len() < options.count {
+            let event = tokio::select! {
+                event = stream.next() => event,
+                _ = &mut deadline => {
+                    warn!(
+                        reconciled = reconciled.len(),
+                        count = options.count,
+                        "Timed out waiting for the Downloads to be reconciled"
+                    );
+                    break;
+                }
+            };
+            let objs = match event {
+                Some(Ok(watcher::Event::Applied(obj))) => vec![obj],
+                Some(Ok(watcher::Event::Restarted(objs))) => objs,
+                Some(Ok(watcher::Event::Deleted(_))) => continue,
+                Some(Err(e)) => {
+                    warn!(error = %e, "Watch error");
+                    continue;
+                }
+                None => break,
+            };
+            for obj in objs {
+                if obj.status.as_ref().and_then(|s| s.phase).is_some() {
+                    reconciled
+                        .entry(obj.name_any())
+                        .or_insert_with(Instant::now);
+                }
+            }
+        }
+        reconciled
+    };
+    let (results, reconciled) = tokio::join!(create_all, watch_all);
+
+    // Latency is measured from the return of the create request.
+    let mut latencies: HashMap<String, Duration> = HashMap::with_capacity(options.count);
+    for result in results {
+        let (name, created) = result?;
+        if let Some(at) = reconciled.get(&name) {
+            latencies.insert(name, at.saturating_duration_since(created));
+        }
+    }
+    let elapsed = start.elapsed();
+    let after = get_api_request_counts(&client).await;
+    report(&options, &latencies, elapsed, before, after);
+
+    if !options.keep {
+        info!(selector = %selector, "Deleting Downloads");
+        api.delete_collection(
+            &DeleteParams::default(),
+            &ListParams::default().labels(&selector),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Creates the i-th fake Download. The input is never queried
+/// unless the Download is unsuspended.
+async fn create(
+    api: &Api<Download>,
+    run_id: &str,
+    i: usize,
+    unsuspended: bool,
+) -> Result<(String, Instant), Error> {
+    let name = format!("bench-{}-{}", run_id, i);
+    let download = Download {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            labels: Some([(BENCH_LABEL.to_owned(), run_id.to_owned())].into()),
+            ..ObjectMeta::default()
+        },
+        spec: DownloadSpec {
+            input: format!("https://example.com/bench/{}", i),
+            suspend: Some(!unsuspended),
+            ..DownloadSpec::default()
+        },
+        status: None,
+    };
+    api.create(&PostParams::default(), &download).await?;
+    Ok((name, Instant::now()))
+}
+
+/// Logs the throughput, latency percentiles, and API request rates.
+fn report(
+    options: &BenchOptions,
+    latencies: &HashMap<String, Duration>,
+    elapsed: Duration,
+    before: Option<ApiRequestCounts>,
+    after: Option<ApiRequestCounts>,
+) {
+    let mut sorted: Vec<Duration> = latencies.values().copied().collect();
+    sorted.sort();
+    let percentile = |p: f64| -> f64 {
+        if sorted.is_empty() {
+            return 0.0;
+        }
+        let i = ((sorted.len() as f64 * p).ceil() as usize).clamp(1, sorted.len()) - 1;
+        sorted[i].as_secs_f64()
+    };
+    let seconds = elapsed.as_secs_f64();
+    info!(
+        count = options.count,
+        reconciled = latencies.len(),
+        seconds,
+        throughput = latencies.len() as f64 / seconds,
+        p50 = percentile(0.5),
+        p90 = percentile(0.9),
+        p99 = percentile(0.99),
+        max = percentile(1.0),
+        "Reconcile results"
+    );
+    match (before, after) {
+        (Some(before), Some(after)) => info!(
+            total_qps = (after.total - before.total) / seconds,
+            ytdl_qps = (after.ytdl - before.ytdl) / seconds,
+            "API server request rates, including the benchmark's own requests"
+        ),
+        _ => warn!("API server metrics are unavailable, request rates were not measured"),
+    }
+}
+
+/// Cumulative request counts from the API server's metrics.
+struct ApiRequestCounts {
+    /// All requests.
+    total: f64,
+
+    /// Requests for resources in the `ytdl.beebs.dev` group.
+    ytdl: f64,
+}
+
+/// Scrapes the API server's `apiserver_request_total` counters. Returns
+/// None if the metrics can't be read, e.g. for lack of permission to
+/// the `/metrics` endpoint.
+async fn get_api_request_counts(client: &Client) -> Option<ApiRequestCounts> {
+    let request = http::Request::get("/metrics").body(vec![]).ok()?;
+    let text = match client.request_text(request).await {
+        Ok(text) => text,
+        Err(e) => {
+            warn!(error = %e, "Failed to scrape API server metrics");
+            return None;
+        }
+    };
+    let mut counts = ApiRequestCounts {
+        total: 0.0,
+        ytdl: 0.0,
+    };
+    for line in text.lines() {
+        if !line.starts_with("apiserver_request_total{") {
+            continue;
+        }
+        let value: f64 = match line.rsplit(' ').next().and_then(|v| v.parse().ok()) {
+            Some(value) => value,
+            None => continue,
+        };
+        counts.total += value;
+        if line.contains("group=\"ytdl.beebs.dev\"") {
+            counts.ytdl += value;
+        }
+    }
+    Some(counts)
+}
@@ -1,15 +1,32 @@
-use crate::util::MANAGER_NAME;
-use k8s_openapi::api::core::v1::{Container, EnvVar, Pod, VolumeMount};
+use crate::{events, util::MANAGER_NAME};
+use k8s_openapi::api::core::v1::{ConfigMap, Container, EnvVar, Pod, VolumeMount};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
 use kube::{
-    api::{Api, DeleteParams, Patch, PatchParams, PostParams, Resource},
-    Client, CustomResourceExt,
+    api::{Api, Patch, PatchParams, PostParams, PropagationPolicy, Resource},
+    runtime::events::EventType,
+    Client, CustomResourceExt, ResourceExt,
 };
 use ytdl_common::{
-    pod::{masked_pod, SHARED_PATH, SHARED_VOLUME_NAME},
-    Error, DEFAULT_EXECUTOR_IMAGE,
+    auth::mount_auth,
+    compliance::METADATA_ONLY_EXTRACTORS_ENV,
+    condition::{set_condition, set_health_conditions, Health, SPEC_VALID},
+    cookies::mount_cookies,
+    delete::delete_opt,
+    failure::EXECUTOR_CONTAINER_NAME,
+    format_field_errors,
+    history::{record_pod_start, record_transition},
+    pod::{delete_owned_pods, masked_pod, SHARED_PATH, SHARED_VOLUME_NAME},
+    propagate::{get_propagate_prefixes, propagate_metadata},
+    skip::{to_skipped_jsonl, SkipRecord, SKIPPED_JSONL_KEY},
+    Error, FieldError, DEFAULT_EXECUTOR_IMAGE,
 };
-use ytdl_types::{Download, DownloadPhase, DownloadStatus};
+use ytdl_types::{
+    Condition, CulledTotals, Download, DownloadCounts, DownloadPhase, DownloadStatus,
+    DownloadSummary, Executor,
+};
+
+/// Annotation with the JSON summary of a completed Download.
+pub const SUMMARY_ANNOTATION: &str = "ytdl.beebs.dev/summary";
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ProgressOptions {
@@ -21,10 +38,41 @@ pub struct FailureOptions {
     pub message: String,
 }
 
-/// Deletes the query pod for the given Download.
-pub async fn delete_query_pod(client: Client, name: &str, namespace: &str) -> Result<(), Error> {
-    let api: Api<Pod> = Api::namespaced(client, namespace);
-    api.delete(name, &DeleteParams::default()).await?;
+/// Deletes the query pod for the given Download. Does nothing if
+/// the pod was already deleted.
+pub async fn delete_query_pod(client: Client, instance: &Download) -> Result<(), Error> {
+    delete_owned_pods(
+        client,
+        &instance.namespace().unwrap(),
+        &instance.uid().unwrap(),
+    )
+    .await
+}
+
+/// Deletes the metadata ConfigMap for the given Download. With the
+/// foreground policy, the ConfigMap is only removed from the API once
+/// its own dependents are gone. Does nothing if it was already deleted.
+pub async fn delete_metadata_configmap(
+    client: Client,
+    name: &str,
+    namespace: &str,
+    foreground: bool,
+) -> Result<(), Error> {
+    let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let policy = if foreground {
+        PropagationPolicy::Foreground
+    } else {
+        PropagationPolicy::Background
+    };
+    delete_opt(&api, name, policy).await?;
+    Ok(())
+}
+
+/// Deletes the Download, which happens once its TTL after finishing has
+/// elapsed. The child resources are garbage collected by Kubernetes.
+pub async fn delete_download(client: Client, name: &str, namespace: &str) -> Result<(), Error> {
+    let api: Api<Download> = Api::namespaced(client, namespace);
+    delete_opt(&api, name, PropagationPolicy::Background).await?;
     Ok(())
 }
 
@@ -48,11 +96,27 @@ pub async fn create_query_pod(
     instance: &Download,
     service_account_name: String,
 ) -> Result<(), Error> {
+    let pod = get_query_pod(name, namespace, instance, service_account_name)?;
+    let api: Api<Pod> = Api::namespaced(client, namespace);
+    api.create(&PostParams::default(), &pod).await?;
+    Ok(())
+}
+
+/// Returns the query pod for the given Download without creating it.
+pub fn get_query_pod(
+    name: &str,
+    namespace: &str,
+    instance: &Download,
+    service_account_name: String,
+) -> Result<Pod, Error> {
     // Determine the executor image.
     let image = get_executor_image(instance);
 
+    // Prefixes of the labels and annotations to propagate.
+    let prefixes = get_propagate_prefixes();
+
     let container = Container {
-        name: "executor".to_owned(),
+        name: EXECUTOR_CONTAINER_NAME.to_owned(),
         image: Some(image),
         args: Some(vec!["query".to_owned()]),
         // TODO: inject the imagePullPolicy from the helm chart.
@@ -65,6 +129,20 @@ pub async fn create_query_pod(
                 value: Some(serde_json::to_string(instance)?),
                 ..EnvVar::default()
             },
+            // The query pod propagates the same labels and
+            // annotations to the Executors it creates.
+            EnvVar {
+                name: "PROPAGATE_PREFIXES".to_owned(),
+                value: Some(prefixes.join(",")),
+                ..EnvVar::default()
+            },
+            // The query pod enforces the same metadata-only policy
+            // on the Executors it creates.
+            EnvVar {
+                name: METADATA_ONLY_EXTRACTORS_ENV.to_owned(),
+                value: std::env::var(METADATA_ONLY_EXTRACTORS_ENV).ok(),
+                ..EnvVar::default()
+            },
         ]),
         // Pass the full resource as an environment variable.
         // We need the shared volume mounted as it contains
@@ -88,31 +166,51 @@ pub async fn create_query_pod(
     let oref = instance.controller_owner_ref(&()).unwrap();
 
     // Build the full Pod resource with the VPN sidecar.
-    let pod: Pod = masked_pod(
+    let mut pod: Pod = masked_pod(
         name.to_owned(),
         namespace.to_owned(),
         Some(vec![oref]),
         service_account_name,
         container,
+        instance.spec.vpn.as_ref(),
+        None,
+        instance.spec.proxy.as_ref(),
+        instance.spec.pod_template.as_ref(),
     );
-    let api: Api<Pod> = Api::namespaced(client, namespace);
-    api.create(&PostParams::default(), &pod).await?;
-    Ok(())
+
+    // Mount the cookies for authenticated queries.
+    if let Some(ref secret) = instance.spec.cookies_secret {
+        mount_cookies(&mut pod, secret);
+    }
+
+    // Mount the account credentials for sites that require logging in.
+    if let Some(ref secret) = instance.spec.auth_secret {
+        mount_auth(&mut pod, secret);
+    }
+
+    // Inherit the Download's user-defined labels and annotations.
+    propagate_metadata(&instance.metadata, &mut pod.metadata, &prefixes);
+    Ok(pod)
 }
 
 /// Updates the Download's status object to reflect download progress.
 pub async fn download_progress(
     client: Client,
     instance: &Download,
-    succeeded: usize,
-    total: usize,
+    counts: DownloadCounts,
+    percent: f64,
 ) -> Result<(), Error> {
-    patch_status(client, instance, |status| {
+    let total = counts.queued + counts.running + counts.succeeded + counts.failed + counts.skipped;
+    patch_status(client, instance, move |status| {
         status.message = Some(format!(
             "download in progress ({}/{} succeeded)",
-            succeeded, total
+            counts.succeeded, total
         ));
         status.phase = Some(DownloadPhase::Downloading);
+        status.total_videos = Some(total);
+        status.downloaded_videos = Some(counts.succeeded);
+        status.counts = Some(counts);
+        status.percent = Some(percent);
     })
     .await?;
     Ok(())
@@ -120,10 +218,7 @@ pub async fn download_progress(
 
 /// Updates the Download's status object to signal it is waiting
 /// for other queries to finish before it proceeds.
-pub async fn throttled(
-    client: Client,
-    instance: &Download,
-) -> Result<(), Error> {
+pub async fn throttled(client: Client, instance: &Download) -> Result<(), Error> {
     patch_status(client, instance, |status| {
         status.message = Some("waiting for other queries to finish".to_owned());
         status.phase = Some(DownloadPhase::Throttled);
@@ -132,16 +227,140 @@ pub async fn throttled(
     Ok(())
 }
 
+/// Overwrites the list of skipped entities in the metadata ConfigMap,
+/// which has the same name as the Download.
+pub async fn record_skipped(
+    client: Client,
+    instance: &Download,
+    records: &[SkipRecord],
+) -> Result<(), Error> {
+    let name = instance.metadata.name.as_deref().unwrap();
+    let namespace = instance.metadata.namespace.as_deref().unwrap();
+    let patch = Patch::Merge(serde_json::json!({
+        "data": {
+            SKIPPED_JSONL_KEY: to_skipped_jsonl(records)?,
+        },
+    }));
+    let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    api.patch(name, &PatchParams::default(), &patch).await?;
+    Ok(())
+}
+
+/// Sets the SpecValid condition. If the spec is invalid, the
+/// field errors are listed in the condition's message.
+pub async fn spec_validity(
+    client: Client,
+    instance: &Download,
+    errors: &[FieldError],
+) -> Result<(), Error> {
+    let generation = instance.metadata.generation;
+    let condition = if errors.is_empty() {
+        Condition {
+            type_: SPEC_VALID.to_owned(),
+            status: "True".to_owned(),
+            reason: Some("Valid".to_owned()),
+            message: None,
+            last_transition_time: None,
+            observed_generation: generation,
+        }
+    } else {
+        Condition {
+            type_: SPEC_VALID.to_owned(),
+            status: "False".to_owned(),
+            reason: Some("Invalid".to_owned()),
+            message: Some(format_field_errors(errors)),
+            last_transition_time: None,
+            observed_generation: generation,
+        }
+    };
+    patch_status(client, instance, move |status| {
+        set_condition(status.conditions.get_or_insert_with(Vec::new), condition);
+    })
+    .await?;
+    Ok(())
+}
+
+/// Records the targets inherited from the namespace's DefaultTargets.
+pub async fn inherit_targets(
+    client: Client,
+    instance: &Download,
+    targets: Vec<String>,
+) -> Result<(), Error> {
+    patch_status(client, instance, move |status| {
+        status.targets = Some(targets);
+    })
+    .await?;
+    Ok(())
+}
+
 /// Updates the Download's status object to signal complete success.
 pub async fn succeeded(
     client: Client,
     instance: &Download,
-) -> Result<(), Error> {
-    patch_status(client, instance, |status| {
+    mut summary: DownloadSummary,
+) -> Result<DownloadSummary, Error> {
+    let now = chrono::Utc::now();
+    if let Some(ref created) = instance.metadata.creation_timestamp {
+        summary.wall_seconds = now.signed_duration_since(created.0).num_seconds().max(0) as u64;
+    }
+    summary.average_bytes_per_second = summary.total_bytes / summary.wall_seconds.max(1);
+    let result = summary.clone();
+    patch_status(client, instance, move |status| {
         status.message = Some("all downloads have succeeded".to_owned());
         status.phase = Some(DownloadPhase::Succeeded);
+        status.completion_time = Some(now.to_rfc3339());
+        status.total_videos = Some(summary.succeeded + summary.skipped);
+        status.downloaded_videos = Some(summary.succeeded);
+        status.counts = Some(DownloadCounts {
+            succeeded: summary.succeeded,
+            skipped: summary.skipped,
+            ..DownloadCounts::default()
+        });
+        status.percent = Some(100.0);
+        status.summary = Some(summary);
     })
     .await?;
+    Ok(result)
+}
+
+/// Writes the summary of the completed Download to its annotation and
+/// publishes a completion Event, so that external automation can react
+/// to completed Downloads with a single watch.
+pub async fn publish_summary(
+    client: Client,
+    instance: &Download,
+    summary: &DownloadSummary,
+) -> Result<(), Error> {
+    let patch = serde_json::json!({
+        "metadata": {
+            "annotations": {
+                SUMMARY_ANNOTATION: serde_json::to_string(summary)?,
+            }
+        }
+    });
+    let api: Api<Download> = Api::namespaced(client.clone(), &instance.namespace().unwrap());
+    api.patch(
+        &instance.name_any(),
+        &PatchParams::default(),
+        &Patch::Merge(&patch),
+    )
+    .await?;
+    events::publish(
+        client,
+        instance,
+        EventType::Normal,
+        "Completed",
+        Some(format!(
+            "downloaded {} videos ({} bytes) in {}s at {} bytes/s, skipped {}, {} failures",
+            summary.succeeded,
+            summary.total_bytes,
+            summary.wall_seconds,
+            summary.average_bytes_per_second,
+            summary.skipped,
+            summary.failures,
+        )),
+    )
+    .await;
     Ok(())
 }
 
@@ -162,10 +381,7 @@ pub async fn query_progress(
 
 /// Updates the Download's phase to Pending, which indicates
 /// the resource made its initial appearance to the operator.
-pub async fn pending(
-    client: Client,
-    instance: &Download,
-) -> Result<(), Error> {
+pub async fn pending(client: Client, instance: &Download) -> Result<(), Error> {
     patch_status(client, instance, |status| {
         status.message = Some("the resource first appeared to the controller".to_owned());
         status.phase = Some(DownloadPhase::Pending);
@@ -174,15 +390,82 @@ pub async fn pending(
     Ok(())
 }
 
+/// Updates the Download's phase to Suspended, which indicates
+/// reconciliation is paused per the spec.
+pub async fn suspended(client: Client, instance: &Download) -> Result<(), Error> {
+    patch_status(client, instance, |status| {
+        status.message = Some("reconciliation is suspended".to_owned());
+        status.phase = Some(DownloadPhase::Suspended);
+    })
+    .await?;
+    Ok(())
+}
+
 /// Update the Download's phase to Starting, which indicates
 /// the query pod is initializing.
-pub async fn query_starting(
+pub async fn query_starting(client: Client, instance: &Download) -> Result<(), Error> {
+    patch_status(client, instance, |status| {
+        status.message = Some("the query pod is starting".to_owned());
+        status.phase = Some(DownloadPhase::QueryStarting);
+    })
+    .await?;
+    Ok(())
+}
+
+/// Marks the Download's status as QueryStarting after its query pod
+/// was created, and records the pod's creation. Returns the number of
+/// query pods created within the last hour.
+pub async fn query_pod_created(client: Client, instance: &Download) -> Result<usize, Error> {
+    let mut starts = 0;
+    patch_status(client, instance, |status| {
+        status.message = Some("the query pod is starting".to_owned());
+        status.phase = Some(DownloadPhase::QueryStarting);
+        starts = record_pod_start(status.pod_starts.get_or_insert_with(Vec::new));
+    })
+    .await?;
+    Ok(starts)
+}
+
+/// Adds the totals of the Executors that are about to be deleted to the
+/// culled totals, and lists them as being culled. The patch is only
+/// applied to the version of the Download they were counted from.
+pub async fn culling(
     client: Client,
     instance: &Download,
+    names: Vec<String>,
+    totals: CulledTotals,
 ) -> Result<(), Error> {
+    let resource_version = instance.resource_version();
+    patch_status_from(client, instance, resource_version, move |status| {
+        let culled = status.culled.get_or_insert_with(CulledTotals::default);
+        culled.resources += totals.resources;
+        culled.succeeded += totals.succeeded;
+        culled.failures += totals.failures;
+        culled.total_bytes += totals.total_bytes;
+        status.culling = Some(names);
+    })
+    .await?;
+    Ok(())
+}
+
+/// Deletes the named Executors. Executors that were already deleted
+/// are ignored.
+pub async fn delete_executors(
+    client: Client,
+    namespace: &str,
+    names: &[String],
+) -> Result<(), Error> {
+    let api: Api<Executor> = Api::namespaced(client, namespace);
+    for name in names {
+        delete_opt(&api, name, PropagationPolicy::Background).await?;
+    }
+    Ok(())
+}
+
+/// Clears the list of Executors being culled once they are all gone.
+pub async fn cull_finished(client: Client, instance: &Download) -> Result<(), Error> {
     patch_status(client, instance, |status| {
-        status.message = Some("the query pod is starting".to_owned());
-        status.phase = Some(DownloadPhase::QueryStarting);
+        status.culling = None;
     })
     .await?;
     Ok(())
@@ -209,32 +492,79 @@ async fn patch_status(
     client: Client,
     instance: &Download,
     f: impl FnOnce(&mut DownloadStatus),
+) -> Result<Download, Error> {
+    patch_status_from(client, instance, None, f).await
+}
+
+/// Patches the status like [`patch_status`], failing with a conflict if
+/// the resource version is given and the resource has since changed.
+async fn patch_status_from(
+    client: Client,
+    instance: &Download,
+    resource_version: Option<String>,
+    f: impl FnOnce(&mut DownloadStatus),
 ) -> Result<Download, Error> {
     let name = instance.metadata.name.as_deref().unwrap();
     let namespace = instance.metadata.namespace.as_deref().unwrap();
-    let patch = Patch::Apply({
-        let mut status = instance.status.clone().unwrap_or_default();
-        f(&mut status);
-        status.last_updated = Some(chrono::Utc::now().to_rfc3339());
-        serde_json::json!({
-            "apiVersion": "vpn.beebs.dev/v1",
-            "kind": Download::crd().spec.names.kind.clone(),
-            "status": status,
-        })
+    let old_phase = instance.status.as_ref().and_then(|status| status.phase);
+    let mut status = instance.status.clone().unwrap_or_default();
+    f(&mut status);
+    status.last_updated = Some(chrono::Utc::now().to_rfc3339());
+    let new_phase = status.phase;
+    let message = status.message.clone();
+    if let Some(phase) = new_phase.filter(|phase| Some(*phase) != old_phase) {
+        record_transition(
+            status.phase_history.get_or_insert_with(Vec::new),
+            &phase.to_string(),
+            message.as_deref(),
+        );
+    }
+    if let Some(phase) = new_phase {
+        let health = match phase {
+            DownloadPhase::Succeeded => Health::Ready,
+            DownloadPhase::Suspended => Health::Suspended,
+            DownloadPhase::ErrQueryFailed | DownloadPhase::ErrDownloadFailed => Health::Stalled,
+            _ => Health::Reconciling,
+        };
+        set_health_conditions(
+            status.conditions.get_or_insert_with(Vec::new),
+            health,
+            &phase.to_string(),
+            message.as_deref(),
+            instance.metadata.generation,
+        );
+    }
+    let mut patch = serde_json::json!({
+        "apiVersion": "vpn.beebs.dev/v1",
+        "kind": Download::crd().spec.names.kind.clone(),
+        "status": status,
     });
-    let api: Api<Download> = Api::namespaced(client, namespace);
-    Ok(api
+    if let Some(resource_version) = resource_version {
+        // The API server rejects the patch if the version is stale.
+        patch["metadata"] = serde_json::json!({ "resourceVersion": resource_version });
+    }
+    let patch = Patch::Apply(patch);
+    let api: Api<Download> = Api::namespaced(client.clone(), namespace);
+    let result = api
         .patch_status(name, &PatchParams::apply(MANAGER_NAME), &patch)
-        .await?)
+        .await?;
+    if let Some(phase) = new_phase.filter(|phase| Some(*phase) != old_phase) {
+        // Record the phase transition as an Event so the history
+        // survives subsequent status updates.
+        let type_ = match phase {
+            DownloadPhase::ErrQueryFailed | DownloadPhase::ErrDownloadFailed => EventType::Warning,
+            _ => EventType::Normal,
+        };
+        events::publish(client, instance, type_, &phase.to_string(), message).await;
+    }
+    Ok(result)
 }
 
 pub mod finalizer {
     use super::*;
-    use kube::api::{Patch, PatchParams};
-    use serde_json::{json, Value};
 
-    /// Adds a finalizer record into an `Download` kind of resource. If the finalizer already exists,
-    /// this action has no effect.
+    /// Adds the finalizer to a `Download` kind of resource, leaving the finalizers of other
+    /// controllers in place. If the finalizer already exists, this action has no effect.
     ///
     /// # Arguments:
     /// - `client` - Kubernetes client to modify the `Download` resource with.
@@ -244,17 +574,11 @@ pub mod finalizer {
     /// Note: Does not check for resource's existence for simplicity.
     pub async fn add(client: Client, name: &str, namespace: &str) -> Result<Download, Error> {
         let api: Api<Download> = Api::namespaced(client, namespace);
-        let finalizer: Value = json!({
-            "metadata": {
-                "finalizers": ["ytdl.beebs.dev/finalizer"]
-            }
-        });
-        let patch: Patch<&Value> = Patch::Merge(&finalizer);
-        Ok(api.patch(name, &PatchParams::default(), &patch).await?)
+        crate::finalizer::add(&api, name).await
     }
 
-    /// Removes all finalizers from an `Download` resource. If there are no finalizers already, this
-    /// action has no effect.
+    /// Removes the finalizer from a `Download` resource, leaving the finalizers of other
+    /// controllers in place. If the finalizer is already gone, this action has no effect.
     ///
     /// # Arguments:
     /// - `client` - Kubernetes client to modify the `Download` resource with.
@@ -264,12 +588,6 @@ pub mod finalizer {
     /// Note: Does not check for resource's existence for simplicity.
     pub async fn delete(client: Client, name: &str, namespace: &str) -> Result<Download, Error> {
         let api: Api<Download> = Api::namespaced(client, namespace);
-        let finalizer: Value = json!({
-            "metadata": {
-                "finalizers": null
-            }
-        });
-        let patch: Patch<&Value> = Patch::Merge(&finalizer);
-        Ok(api.patch(name, &PatchParams::default(), &patch).await?)
+        crate::finalizer::remove(&api, name).await
     }
 }
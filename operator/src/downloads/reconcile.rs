@@ -1,23 +1,59 @@
-use futures::stream::StreamExt;
+use futures::stream::{self, StreamExt};
 use k8s_openapi::api::core::v1::{ConfigMap, Pod, PodStatus};
 use kube::Resource;
 use kube::ResourceExt;
 use kube::{
-    api::ListParams, client::Client, runtime::controller::Action, runtime::Controller, Api,
+    api::ListParams,
+    client::Client,
+    runtime::{controller::Action, events::EventType, Controller},
+    Api,
 };
-use std::sync::Arc;
-use tokio::time::Duration;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{debug, error, info, instrument, warn};
 
 use super::action::{self, ProgressOptions};
+use crate::{
+    drain, events,
+    index::OwnerIndex,
+    metrics,
+    util::{
+        get_concurrency, get_foreground_configmap_deletion, get_host_policy, get_watch_apis,
+        RequeueIntervals,
+    },
+};
 use ytdl_common::{
-    check_pod_scheduling_error, create_executor, get_download_phase, get_executor,
-    get_executor_service_account_name, Entity, Error, IMMEDIATELY, INFO_JSONL_KEY,
+    archive::{append_archive, is_archived, ArchiveCache},
+    chaos, check_pod_scheduling_error,
+    compliance::MetadataOnlyPolicy,
+    condition::{get_condition, SPEC_VALID},
+    create_executor,
+    egress::get_bytes_downloaded,
+    extra_args::ExtraArgsPolicy,
+    failure::{get_pod_exit_code, get_pod_failure},
+    filter::check_filters,
+    format_field_errors, get_batch_size, get_download_phase, get_executor_service_account_name,
+    get_job_entities, get_remaining_ttl,
+    host_policy::validate_host_policies,
+    manifest::{get_stored_objects, publish_manifest},
+    pod::get_owned_pod,
+    replication::replicate,
+    results::{append_results, ResultRecord},
+    skip::{get_skipped_entities, parse_skip_records, SkipRecord, SKIPPED_JSONL_KEY},
+    upcoming::get_release_delay,
+    validate::validate_download,
+    Entity, Error, FieldError, IMMEDIATELY, INFO_JSONL_KEY, METADATA_LABEL,
+};
+use ytdl_types::{
+    CullPolicy, CulledTotals, DefaultTargets, Download, DownloadCounts, DownloadPhase,
+    DownloadSummary, Executor, ExecutorPhase, StoredObject,
 };
-use ytdl_types::{Download, DownloadPhase, ExecutorPhase};
-use crate::util::get_concurrency;
 
-pub async fn main() {
-    println!("Initializing Download controller...");
+pub async fn main(namespaces: Vec<String>) {
+    info!("Initializing Download controller...");
 
     // First, a Kubernetes client must be obtained using the `kube` crate
     // The client will later be moved to the custom controller
@@ -30,12 +66,34 @@ pub async fn main() {
     let service_account_name = get_executor_service_account_name()
         .expect("Expected a valid executor service account name.");
 
+    // Fail fast on a finalizer name the API server would reject.
+    crate::finalizer::get_finalizer().expect("Expected a valid finalizer name.");
+
+    // Keep the per-phase gauges up to date for the metrics server.
+    let apis = get_watch_apis::<Download>(kubernetes_client.clone(), &namespaces);
+    metrics::spawn_phase_gauges(apis, "Download", |instance: &Download| {
+        instance
+            .status
+            .as_ref()
+            .and_then(|status| status.phase)
+            .map(|phase| phase.to_string())
+    });
+
+    // Cache the Executors so each Download's children are looked up
+    // without any requests to the API server.
+    let executors = OwnerIndex::new(get_watch_apis::<Executor>(
+        kubernetes_client.clone(),
+        &namespaces,
+    ));
+    executors.wait_until_synced().await;
+
     // Preparation of resources used by the `kube_runtime::Controller`
-    let crd_api: Api<Download> = Api::all(kubernetes_client.clone());
     let context: Arc<ContextData> = Arc::new(ContextData::new(
         kubernetes_client.clone(),
         service_account_name,
+        executors,
         get_concurrency(),
+        RequeueIntervals::from_env(),
     ));
 
     // The controller comes from the `kube_runtime` crate and manages the reconciliation process.
@@ -44,30 +102,56 @@ pub async fn main() {
     // - `kube::api::ListParams` to select the `Download` resources with. Can be used for Download filtering `Download` resources before reconciliation,
     // - `reconcile` function with reconciliation logic to be called each time a resource of `Download` kind is created/updated/deleted,
     // - `on_error` function to call whenever reconciliation fails.
-    println!("Starting Download controller...");
-    Controller::new(crd_api.clone(), ListParams::default())
-        .run(reconcile, on_error, context)
+    // One Controller is run for each watched namespace, or a single
+    // cluster-wide Controller if no namespaces are specified.
+    info!(?namespaces, "Starting Download controller...");
+    let mut stores = Vec::new();
+    let configmaps = get_watch_apis::<ConfigMap>(kubernetes_client.clone(), &namespaces);
+    let controllers = get_watch_apis::<Download>(kubernetes_client.clone(), &namespaces)
+        .into_iter()
+        .zip(configmaps)
+        .map(|(api, configmaps)| {
+            // Reconcile the Download as soon as the query pod creates
+            // or updates its metadata ConfigMap, rather than waiting
+            // for the next requeue.
+            let controller = Controller::new(api, ListParams::default())
+                .owns(configmaps, ListParams::default().labels(METADATA_LABEL));
+            stores.push(controller.store());
+            controller.run(reconcile, on_error, context.clone()).boxed()
+        })
+        .collect::<Vec<_>>();
+    // Report the size of the caches for the metrics server.
+    metrics::spawn_cache_gauges(stores, "Download");
+    stream::select_all(controllers)
         .for_each(|reconciliation_result| async move {
             match reconciliation_result {
                 Ok(video_resource) => {
-                    println!("Reconciliation successful. Resource: {:?}", video_resource);
+                    debug!(resource = ?video_resource, "Reconciliation successful");
                 }
                 Err(reconciliation_err) => {
-                    eprintln!("Reconciliation error: {:?}", reconciliation_err)
+                    metrics::controller_error("Download", &reconciliation_err);
+                    warn!(error = ?reconciliation_err, "Reconciliation error")
                 }
             }
         })
         .await;
 }
 
-
-
 /// Context injected with each `reconcile` and `on_error` method invocation.
 struct ContextData {
     /// Kubernetes client to make Kubernetes API requests with. Required for K8S resource management.
     client: Client,
     concurrency: usize,
     service_account_name: String,
+
+    /// Cache of the child Executors, indexed by the uid of their Download.
+    executors: OwnerIndex<Executor>,
+
+    /// Cache of the Downloads' archives.
+    archives: ArchiveCache,
+
+    /// Intervals after which resources are requeued.
+    intervals: RequeueIntervals,
 }
 
 impl ContextData {
@@ -79,12 +163,17 @@ impl ContextData {
     pub fn new(
         client: Client,
         service_account_name: String,
+        executors: OwnerIndex<Executor>,
         concurrency: usize,
+        intervals: RequeueIntervals,
     ) -> Self {
         ContextData {
             client,
             service_account_name,
+            executors,
+            archives: ArchiveCache::default(),
             concurrency,
+            intervals,
         }
     }
 }
@@ -96,6 +185,13 @@ struct QueryFailureOptions {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+struct CullOptions {
+    names: Vec<String>,
+    totals: CulledTotals,
+    results: Vec<ResultRecord>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 enum ReconcileAction {
     // The resource first appeared to the controller and requires
     // its phase to be set to "Pending" to indicate that reconciliation
@@ -105,6 +201,27 @@ enum ReconcileAction {
     // Delete all child resources.
     Delete,
 
+    // The Download succeeded and its ttlSecondsAfterFinished elapsed.
+    Expire,
+
+    // Wait for the remaining ttlSecondsAfterFinished before deleting
+    // the Download.
+    AwaitTtl(Duration),
+
+    // Pause reconciliation per the spec by setting the Suspended phase.
+    Suspend,
+
+    // The spec failed validation. The problems are surfaced in the
+    // SpecValid condition and no pods are created until it is fixed.
+    InvalidSpec(Vec<FieldError>),
+
+    // The spec passed validation, which is recorded in the SpecValid
+    // condition.
+    SpecValid,
+
+    // Record the targets inherited from the namespace's DefaultTargets.
+    InheritTargets(Vec<String>),
+
     CreateQueryPod,
 
     DeleteQueryPod,
@@ -113,11 +230,33 @@ enum ReconcileAction {
 
     QueryProgress(ProgressOptions),
 
-    CreateExecutor(Entity),
+    CreateExecutor(Vec<Entity>),
+
+    // Report the number of videos in each state and the overall
+    // percentage of the Download that is complete.
+    DownloadProgress(DownloadCounts, f64),
+
+    // Write the full list of skipped entities to the metadata ConfigMap.
+    RecordSkipped(Vec<SkipRecord>),
+
+    // Add the IDs of successfully downloaded entities to the archive.
+    RecordArchive(Vec<String>),
 
-    DownloadProgress { succeeded: usize, total: usize },
+    // Record the results of the named succeeded Executors, whose
+    // entities are archived, and add their totals to the culled totals
+    // before deleting them.
+    Cull(CullOptions),
 
-    Succeeded,
+    // Delete the culled Executors that are still in the cache.
+    DeleteCulled(Vec<String>),
+
+    // Every culled Executor is gone, so stop skipping them.
+    CullFinished,
+
+    // Mark the Download as Succeeded and record the summary, whose
+    // wall time and average speed are filled in upon writing. The
+    // objects uploaded by the Executors go into the manifest.
+    Succeeded(DownloadSummary, Vec<StoredObject>),
 
     /*
     // Create the pod to download the video and/or thumbnail. Subsequent
@@ -141,7 +280,24 @@ enum ReconcileAction {
 }
 
 /// Main reconciliation loop for the `Download` resource.
+#[instrument(
+    skip_all,
+    fields(
+        name = %instance.name_any(),
+        namespace = ?instance.namespace(),
+        generation = ?instance.metadata.generation,
+    )
+)]
 async fn reconcile(instance: Arc<Download>, context: Arc<ContextData>) -> Result<Action, Error> {
+    // Observe the duration of this reconciliation.
+    let _timer = metrics::reconcile_started("Download");
+
+    // Nothing is written once the leader Lease is being handed over.
+    let _writer = match drain::writer().await {
+        Some(writer) => writer,
+        None => return Ok(Action::await_change()),
+    };
+
     // The `Client` is shared -> a clone from the reference is obtained.
     let client: Client = context.client.clone();
 
@@ -161,8 +317,20 @@ async fn reconcile(instance: Arc<Download>, context: Arc<ContextData>) -> Result
     // Name of the Download resource is used to name the subresources as well.
     let name = instance.name_any();
 
+    // Simulate slow API responses and the loss of the query pod.
+    chaos::api_delay_fault().await;
+    if chaos::pod_deletion_fault() {
+        action::delete_query_pod(client.clone(), &instance).await?;
+    }
+
     // Read phase of the reconciliation loop.
-    let action = determine_action(client.clone(), &instance).await?;
+    let action = determine_action(
+        client.clone(),
+        &instance,
+        &context.executors,
+        &context.archives,
+    )
+    .await?;
 
     if action != ReconcileAction::NoOp {
         // This log line is useful for debugging purposes.
@@ -171,7 +339,7 @@ async fn reconcile(instance: Arc<Download>, context: Arc<ContextData>) -> Result
         // deserve their own enum entries may come down to
         // how badly you want to see them in the log, and
         // that alone is a perfectly valid reason to do so.
-        println!("{}/{} ACTION: {:?}", namespace, name, action);
+        info!(action = ?action, "Reconciling");
     }
 
     // Write phase of the reconciliation loop.
@@ -183,9 +351,68 @@ async fn reconcile(instance: Arc<Download>, context: Arc<ContextData>) -> Result
             // Requeue the resource to be immediately reconciled again.
             Ok(Action::requeue(IMMEDIATELY))
         }
+        ReconcileAction::Expire => {
+            // Delete the Download, which also garbage collects its Executors.
+            action::delete_download(client, &name, &namespace).await?;
+
+            // The deletion is handled by the next reconciliation.
+            Ok(Action::await_change())
+        }
+        ReconcileAction::AwaitTtl(remaining) => {
+            // Check again once the TTL has elapsed.
+            Ok(Action::requeue(remaining))
+        }
+        ReconcileAction::Suspend => {
+            // Update the phase to reflect that reconciliation is paused.
+            action::suspended(client, &instance).await?;
+
+            // Nothing is done until the spec is changed.
+            Ok(Action::await_change())
+        }
+        ReconcileAction::InvalidSpec(errors) => {
+            // Surface the field errors in the SpecValid condition.
+            action::spec_validity(client.clone(), &instance, &errors).await?;
+
+            // Also record them in the resource's Event history.
+            events::publish(
+                client,
+                &instance,
+                EventType::Warning,
+                "InvalidSpec",
+                Some(format_field_errors(&errors)),
+            )
+            .await;
+
+            // Wait for the user to fix the spec.
+            Ok(Action::await_change())
+        }
+        ReconcileAction::SpecValid => {
+            // Clear any previous validation errors.
+            action::spec_validity(client, &instance, &[]).await?;
+
+            // Requeue immediately to proceed with reconciliation.
+            Ok(Action::requeue(IMMEDIATELY))
+        }
+        ReconcileAction::InheritTargets(targets) => {
+            // Record the inherited targets in the status object.
+            action::inherit_targets(client, &instance, targets).await?;
+
+            // Requeue immediately to proceed with reconciliation.
+            Ok(Action::requeue(IMMEDIATELY))
+        }
         ReconcileAction::Delete => {
             // Delete the query pod.
-            action::delete_query_pod(client.clone(), &name, &namespace).await?;
+            action::delete_query_pod(client.clone(), &instance).await?;
+
+            // Delete the metadata ConfigMap. It would otherwise be garbage
+            // collected, but only after the Download is gone.
+            action::delete_metadata_configmap(
+                client.clone(),
+                &name,
+                &namespace,
+                get_foreground_configmap_deletion(),
+            )
+            .await?;
 
             // Delete all of the child Executors.
             // Executors are garbage collected using owner references.
@@ -200,15 +427,19 @@ async fn reconcile(instance: Arc<Download>, context: Arc<ContextData>) -> Result
         }
         ReconcileAction::DeleteQueryPod => {
             // Delete just the query pod.
-            action::delete_query_pod(client, &name, &namespace).await?;
+            action::delete_query_pod(client, &instance).await?;
 
             // Requeue immediately to proceed with reconciliation.
             Ok(Action::requeue(IMMEDIATELY))
         }
         ReconcileAction::CreateQueryPod => {
+            if drain::is_draining() {
+                // Leave the query to the replica that replaces this one.
+                return Ok(Action::requeue(context.intervals.throttled));
+            }
             if is_throttled(client.clone(), context.concurrency).await? {
                 action::throttled(client, &instance).await?;
-                return Ok(Action::requeue(Duration::from_secs(15)));
+                return Ok(Action::requeue(context.intervals.throttled));
             }
             // TODO: reserve a slot in the semaphore
 
@@ -218,36 +449,46 @@ async fn reconcile(instance: Arc<Download>, context: Arc<ContextData>) -> Result
 
             // Create the executor pod that queries the info jsonl and
             // creates child Executor resources for each entity.
-            action::create_query_pod(
+            if let Err(e) = action::create_query_pod(
                 client.clone(),
                 &name,
                 &namespace,
                 &instance,
                 context.service_account_name.clone(),
             )
-            .await?;
+            .await
+            {
+                // Surface the failure in the resource's Event history.
+                events::publish(
+                    client,
+                    &instance,
+                    EventType::Warning,
+                    "PodCreateFailed",
+                    Some(format!("failed to create query pod: {}", e)),
+                )
+                .await;
+                return Err(e);
+            }
 
             // Update the Download's status to reflect the starting query.
-            action::query_starting(client, &instance).await?;
+            let starts = action::query_pod_created(client.clone(), &instance).await?;
+
+            // Warn if the query pod keeps getting recreated.
+            events::check_pod_restarts(client, &instance, "Download", starts).await;
 
             // Requeue after a short delay to give the pod time to schedule/start.
-            Ok(Action::requeue(Duration::from_secs(5)))
+            Ok(Action::requeue(context.intervals.starting))
         }
         ReconcileAction::QueryFailure(options) => {
             // Update the Download's status to include the failure message.
-            action::query_failure(
-                client.clone(),
-                &instance,
-                options.message,
-            )
-            .await?;
+            action::query_failure(client.clone(), &instance, options.message).await?;
 
             if options.recreate {
                 // Delete the query pod so it can be recreated.
-                action::delete_query_pod(client, &name, &namespace).await?;
+                action::delete_query_pod(client, &instance).await?;
                 // Display the error message for a short while before
                 // requeueing as a form of back-off.
-                return Ok(Action::requeue(Duration::from_secs(5)));
+                return Ok(Action::requeue(context.intervals.failure));
             }
 
             // Don't requeue until the resource is changed.
@@ -256,45 +497,106 @@ async fn reconcile(instance: Arc<Download>, context: Arc<ContextData>) -> Result
         ReconcileAction::QueryProgress(opts) => {
             match opts.start_time {
                 // Update the Download's status to reflect the progress of the query.
-                Some(start_time) => {
-                    action::query_progress(client, &instance, start_time)
-                        .await?
-                }
+                Some(start_time) => action::query_progress(client, &instance, start_time).await?,
                 // Query pod start time is not yet available.
-                None => {
-                    action::query_starting(client, &instance).await?
-                }
+                None => action::query_starting(client, &instance).await?,
             }
             // Requeue after a short delay to check query progress again.
-            Ok(Action::requeue(Duration::from_secs(3)))
+            Ok(Action::requeue(context.intervals.progress))
         }
-        ReconcileAction::DownloadProgress { succeeded, total } => {
+        ReconcileAction::DownloadProgress(counts, percent) => {
             // Update the status object to show download progress.
-            action::download_progress(
-                client,
-                &instance,
-                succeeded,
-                total,
-            )
-            .await?;
+            action::download_progress(client, &instance, counts, percent).await?;
 
             // Requeue after a short delay to check download progress again.
-            Ok(Action::requeue(Duration::from_secs(3)))
+            Ok(Action::requeue(context.intervals.progress))
         }
-        ReconcileAction::CreateExecutor(entity) => {
+        ReconcileAction::CreateExecutor(batch) => {
+            if drain::is_draining() {
+                // Leave the Executor to the replica that replaces this one.
+                return Ok(Action::requeue(context.intervals.throttled));
+            }
+
             // Apply the finalizer first. This way the Download resource
             // won't be deleted before the child Executor is deleted.
             let instance = action::finalizer::add(client.clone(), &name, &namespace).await?;
 
-            // Create the child Executor from the entity.
-            create_executor(client, &instance, entity.id, entity.metadata).await?;
+            // Create the child Executor from the batch of entities. The
+            // cache may not reflect one the query pod already created.
+            match create_executor(client, &instance, batch).await {
+                Err(Error::KubeError {
+                    source: kube::Error::Api(ae),
+                }) if ae.code == 409 => {}
+                result => result?,
+            }
 
             // Requeue without delay as there may be other Executors to create.
             Ok(Action::requeue(IMMEDIATELY))
         }
-        ReconcileAction::Succeeded => {
+        ReconcileAction::RecordSkipped(records) => {
+            // Record the skipped entities alongside the metadata.
+            action::record_skipped(client, &instance, &records).await?;
+
+            // Requeue immediately to finish reconciliation.
+            Ok(Action::requeue(IMMEDIATELY))
+        }
+        ReconcileAction::RecordArchive(ids) => {
+            // Keep future queries from downloading these entities again.
+            append_archive(client, &instance, &ids).await?;
+
+            // Requeue immediately to finish reconciliation.
+            Ok(Action::requeue(IMMEDIATELY))
+        }
+        ReconcileAction::Cull(options) => {
+            // Record the results and totals first, so that they are never
+            // lost. The patch fails if the status changed since it was
+            // read, which keeps the same Executors from being counted twice.
+            append_results(client.clone(), &instance, &options.results).await?;
+            let count = options.names.len();
+            match action::culling(client.clone(), &instance, options.names, options.totals).await {
+                Err(Error::KubeError {
+                    source: kube::Error::Api(ae),
+                }) if ae.code == 409 => return Ok(Action::requeue(IMMEDIATELY)),
+                result => result?,
+            }
+            events::publish(
+                client,
+                &instance,
+                EventType::Normal,
+                "Culled",
+                Some(format!("culling {} succeeded Executors", count)),
+            )
+            .await;
+
+            // Requeue immediately to delete the Executors.
+            Ok(Action::requeue(IMMEDIATELY))
+        }
+        ReconcileAction::DeleteCulled(names) => {
+            // Delete the Executors, whose pods are garbage collected.
+            action::delete_executors(client, &namespace, &names).await?;
+
+            // Check again once the cache reflects the deletions.
+            Ok(Action::requeue(context.intervals.progress))
+        }
+        ReconcileAction::CullFinished => {
+            // Count the remaining Executors as usual.
+            action::cull_finished(client, &instance).await?;
+
+            // Requeue immediately to proceed with reconciliation.
+            Ok(Action::requeue(IMMEDIATELY))
+        }
+        ReconcileAction::Succeeded(summary, objects) => {
+            // Replicate the objects and publish the manifest first, as
+            // the Download is not reconciled again once it's marked as
+            // Succeeded.
+            replicate(client.clone(), &instance, &objects).await?;
+            publish_manifest(client.clone(), &instance, objects).await?;
+
             // Update the status object to show that the downloads are complete.
-            action::succeeded(client, &instance).await?;
+            let summary = action::succeeded(client.clone(), &instance, summary).await?;
+
+            // Publish the summary so external automation can react to it.
+            action::publish_summary(client, &instance, &summary).await?;
 
             // Requeue only when the resource changes.
             Ok(Action::await_change())
@@ -325,6 +627,34 @@ fn needs_pending(instance: &Download) -> bool {
     instance.status.is_none() || instance.status.as_ref().unwrap().phase.is_none()
 }
 
+/// Returns true if the Download omits its targets and has yet
+/// to inherit them from the namespace's DefaultTargets.
+fn needs_default_targets(instance: &Download) -> bool {
+    instance.spec.targets.is_empty()
+        && instance
+            .status
+            .as_ref()
+            .map_or(true, |status| status.targets.is_none())
+}
+
+/// Returns the union of the targets of all DefaultTargets resources
+/// in the Download's namespace, ordered by resource name.
+async fn get_default_targets(client: Client, instance: &Download) -> Result<Vec<String>, Error> {
+    let api: Api<DefaultTargets> = Api::namespaced(client, &instance.namespace().unwrap());
+    let mut defaults = api.list(&ListParams::default()).await?.items;
+    defaults.sort_by_key(|defaults| defaults.name_any());
+    let mut targets: Vec<String> = Vec::new();
+    for target in defaults
+        .into_iter()
+        .flat_map(|defaults| defaults.spec.targets)
+    {
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    Ok(targets)
+}
+
 /// Returns the ConfigMap that stores the info jsonl for the query.
 async fn get_metadata_configmap(
     client: Client,
@@ -339,13 +669,14 @@ async fn get_metadata_configmap(
 }
 
 /// Returns the query pod if it exists, or None if it does not.
+/// The pod is found by its owner rather than by name.
 async fn get_query_pod(client: Client, instance: &Download) -> Result<Option<Pod>, Error> {
-    let pod_api: Api<Pod> = Api::namespaced(client, &instance.namespace().unwrap());
-    match pod_api.get(&instance.name_any()).await {
-        Ok(pod) => Ok(Some(pod)),
-        Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(None),
-        Err(e) => Err(e.into()),
-    }
+    get_owned_pod(
+        client,
+        &instance.namespace().unwrap(),
+        &instance.uid().unwrap(),
+    )
+    .await
 }
 
 /// Determine the action given that query pod exists.
@@ -405,12 +736,22 @@ async fn determine_query_pod_action(
             Ok(ReconcileAction::DeleteQueryPod)
         }
         _ => {
-            // Report error, delete pod, and re-create.
-            // TODO: find way to extract a verbose error message from the pod.
-            let message = format!("query pod is in phase {}", phase);
+            // Report error, delete pod, and re-create, unless the exit
+            // code says the query is misconfigured, which no retry fixes.
+            let mut message = format!("query pod is in phase {}", phase);
+            if let Some(failure) = get_pod_failure(&pod) {
+                message = format!("{}: {}", message, failure.message);
+            }
+            let recreate = match get_pod_exit_code(&pod) {
+                Some(code) => {
+                    message = format!("{} ({})", message, code);
+                    code.is_retryable()
+                }
+                None => true,
+            };
             Ok(ReconcileAction::QueryFailure(QueryFailureOptions {
                 message,
-                recreate: true,
+                recreate,
             }))
         }
     }
@@ -432,10 +773,7 @@ async fn determine_query_action(
     }
 }
 
-fn parse_id(line: &str) -> Result<String, Error> {
-    // Parse the video metadata json.
-    let info: serde_json::Value = serde_json::from_str(line)?;
-
+fn parse_id(info: &serde_json::Value) -> Result<String, Error> {
     // Get the ID field. This is used to name the Executor.
     Ok(info
         .get("id")
@@ -445,84 +783,378 @@ fn parse_id(line: &str) -> Result<String, Error> {
         .to_owned())
 }
 
+/// Parses the entities from info.jsonl that should be downloaded.
+/// Lines that fail to parse are skipped, as they could be error
+/// messages or something, as are entities excluded by the filters.
+/// The query pod skips the same lines, so the entities it batched are
+/// found among these.
+fn parse_entities(instance: &Download, info_jsonl: &str) -> Result<Vec<Entity>, Error> {
+    let mut entities = Vec::new();
+    for line in info_jsonl.split('\n') {
+        // Parse the video metadata json.
+        let info: serde_json::Value = match serde_json::from_str(line) {
+            Ok(info) => info,
+            Err(_) => continue,
+        };
+        let id = match parse_id(&info) {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+        if check_filters(instance, &info)?.is_some() {
+            // The entity is intentionally excluded.
+            continue;
+        }
+        entities.push(Entity {
+            id,
+            metadata: line.to_owned(),
+        });
+    }
+    Ok(entities)
+}
+
+/// Maximum number of Executors culled at once, which bounds the size
+/// of the list of Executors being culled in the Download's status.
+const MAX_CULL: usize = 100;
+
 async fn determine_executor_action(
     client: Client,
     instance: &Download,
+    executors: &OwnerIndex<Executor>,
+    archives: &ArchiveCache,
     info_jsonl: &str,
+    skipped_jsonl: Option<&str>,
 ) -> Result<ReconcileAction, Error> {
     // Keep track of child Executor population status.
     let mut total = 0;
     let mut succeeded = 0;
+    let mut skipped = 0;
+    let mut counts = DownloadCounts::default();
 
-    // Reconcile the Executors for each line in info.jsonl.
-    for line in info_jsonl.split('\n') {
-        // Attempt to parse the line into json. If it fails,
-        // skip it and go to the next line.
-        let id = match parse_id(line) {
-            Ok(v) => v,
-            Err(_) => {
-                // Skip this line if we can't parse it.
-                // Could be an error message or something.
+    // Videos completed so far, including the downloaded fraction
+    // of the videos that are in progress.
+    let mut completed = 0.0;
+
+    // Totals for the summary written upon completion.
+    let mut total_bytes = 0;
+    let mut failures = 0;
+
+    // Objects uploaded by the succeeded Executors, for the manifest.
+    let mut objects = Vec::new();
+
+    // Executors deleted per the cull policy count as if they still
+    // existed, and the succeeded ones that can be deleted next.
+    let culled = instance
+        .status
+        .as_ref()
+        .and_then(|status| status.culled.clone())
+        .unwrap_or_default();
+    total += culled.succeeded as usize;
+    succeeded += culled.succeeded as usize;
+    counts.succeeded += culled.succeeded;
+    total_bytes += culled.total_bytes;
+    failures += culled.failures;
+    let cull = instance.spec.cull.unwrap_or_default() == CullPolicy::Succeeded;
+    let mut cullable = CullOptions {
+        names: Vec::new(),
+        totals: CulledTotals::default(),
+        results: Vec::new(),
+    };
+
+    // Skip records already stored in the metadata ConfigMap,
+    // which initially only contains the ones from the filters.
+    let mut skip_records = skipped_jsonl.map(parse_skip_records).unwrap_or_default();
+    let mut skip_records_changed = false;
+
+    // Parse the entities that need to be downloaded.
+    let entities = parse_entities(instance, info_jsonl)?;
+
+    // IDs that were already downloaded, and the IDs of succeeded
+    // Executors that have yet to be added to the archive.
+    let archive = archives.load(client, instance).await?;
+    let mut unarchived: Vec<String> = Vec::new();
+
+    // The Executors are looked up in the cache by their owner's uid.
+    let uid = instance.uid().unwrap();
+
+    // Existing Executors keep the entities they were created with, so
+    // that the batches don't shift when new videos appear in the input.
+    // Only the entities without an Executor are batched anew.
+    let mut assigned: HashMap<String, Arc<Executor>> = HashMap::new();
+    for executor in executors.list(&uid) {
+        for entity in get_job_entities(&executor) {
+            assigned.insert(entity.id, executor.clone());
+        }
+    }
+    let mut batches: Vec<(Vec<Entity>, Option<Arc<Executor>>)> = Vec::new();
+    let mut batched: HashSet<String> = HashSet::new();
+    let mut unassigned: Vec<Entity> = Vec::new();
+    for entity in entities {
+        match assigned.get(&entity.id) {
+            Some(executor) => {
+                if batched.insert(executor.name_any()) {
+                    batches.push((get_job_entities(executor), Some(executor.clone())));
+                }
+            }
+            None if archive
+                .as_ref()
+                .map_or(false, |archive| archive.contains(&entity.id)) =>
+            {
+                // Already downloaded, possibly by a different Download.
+            }
+            None => unassigned.push(entity),
+        }
+    }
+    batches.extend(
+        unassigned
+            .chunks(get_batch_size(instance))
+            .map(|batch| (batch.to_vec(), None)),
+    );
+
+    // Reconcile the Executors for each batch of entities.
+    for (batch, executor) in batches.iter() {
+        let batch = batch.as_slice();
+        let executor = match executor {
+            Some(executor) => executor.clone(),
+            None if get_release_delay(batch).is_some() => {
+                // The batch has upcoming videos, so the Executor is
+                // created on a later reconciliation once they start.
+                total += batch.len();
+                counts.queued += batch.len() as u32;
                 continue;
             }
-        };
-
-        // Get the Executor for the entity.
-        let executor_name = format!("{}-{}", instance.name_any(), id);
-        let executor = match get_executor(
-            client.clone(),
-            &executor_name,
-            instance.namespace().as_ref().unwrap(),
-        )
-        .await
-        {
-            Ok(Some(executor)) => executor,
-            Ok(None) => {
+            None => {
                 // Executor does not exist, create it.
-                return Ok(ReconcileAction::CreateExecutor(Entity {
-                    id,
-                    metadata: line.to_owned(),
-                }));
-            }
-            Err(e) => {
-                return Err(e);
+                return Ok(ReconcileAction::CreateExecutor(batch.to_vec()));
             }
         };
 
-        // Increment the total number of Executors.
-        total += 1;
+        let executor_name = executor.name_any();
+
+        // Increment the total number of videos.
+        total += batch.len();
+        if let Some(ref status) = executor.status {
+            total_bytes += status.bytes_accounted.unwrap_or(0);
+            failures += status.retries.unwrap_or(0);
+        }
 
         // Check the status of the Executor.
+        let videos = batch.len() as u32;
         match executor.status {
             Some(ref status) => match status.phase {
-                Some(phase) => if phase == ExecutorPhase::Succeeded {
-                    // Increment the number of succeeded Executors.
-                    succeeded += 1;
+                Some(ExecutorPhase::Succeeded) => {
+                    // Every video in the batch has been downloaded, but
+                    // for those the download pod skipped per policy.
+                    let batch_skipped: Vec<SkipRecord> = get_skipped_entities(executor.as_ref())
+                        .into_iter()
+                        .filter(|record| batch.iter().any(|entity| entity.id == record.id))
+                        .collect();
+                    let is_skipped =
+                        |entity: &Entity| batch_skipped.iter().any(|record| record.id == entity.id);
+                    let skipped_videos = batch.iter().filter(|entity| is_skipped(entity)).count();
+                    succeeded += batch.len() - skipped_videos;
+                    counts.succeeded += videos - skipped_videos as u32;
+                    skipped += skipped_videos;
+                    counts.skipped += skipped_videos as u32;
+                    objects.extend(get_stored_objects(executor.as_ref()));
+                    if let Some(ref archive) = archive {
+                        // The skipped videos are left out of the archive,
+                        // so the Executor is never culled.
+                        unarchived.extend(
+                            batch
+                                .iter()
+                                .filter(|entity| !archive.contains(&entity.id))
+                                .filter(|entity| !is_skipped(entity))
+                                .map(|entity| entity.id.clone()),
+                        );
+                    }
+                    for record in batch_skipped.iter() {
+                        if !skip_records.iter().any(|existing| existing.id == record.id) {
+                            skip_records.push(record.clone());
+                            skip_records_changed = true;
+                        }
+                    }
+                    if cull
+                        && cullable.names.len() < MAX_CULL
+                        && is_archived(archive.as_deref(), batch)
+                        && get_bytes_downloaded(executor.as_ref())
+                            <= status.bytes_accounted.unwrap_or(0)
+                    {
+                        // Its result is folded into the archive and its
+                        // egress is accounted for, so it can go.
+                        if instance.spec.results.is_some() {
+                            let stored = get_stored_objects(executor.as_ref());
+                            let finished_at = status.completion_time.clone().unwrap_or_default();
+                            cullable
+                                .results
+                                .extend(batch.iter().map(|entity| ResultRecord {
+                                    id: entity.id.clone(),
+                                    executor: executor_name.clone(),
+                                    objects: stored.clone(),
+                                    finished_at: finished_at.clone(),
+                                }));
+                        }
+                        cullable.names.push(executor_name);
+                        cullable.totals.resources += 1;
+                        cullable.totals.succeeded += videos;
+                        cullable.totals.failures += status.retries.unwrap_or(0);
+                        cullable.totals.total_bytes += status.bytes_accounted.unwrap_or(0);
+                    }
                 }
-                _ => {}
+                Some(ExecutorPhase::Skipped) => {
+                    // The batch was intentionally skipped per policy.
+                    skipped += batch.len();
+                    counts.skipped += videos;
+                    for entity in batch {
+                        if skip_records.iter().any(|record| record.id == entity.id) {
+                            continue;
+                        }
+                        skip_records.push(SkipRecord {
+                            id: entity.id.clone(),
+                            policy: status.skip_policy.clone().unwrap_or_default(),
+                            reason: status.message.clone().unwrap_or_default(),
+                            timestamp: status.last_updated.clone().unwrap_or_default(),
+                        });
+                        skip_records_changed = true;
+                    }
+                }
+                Some(ExecutorPhase::Running) => {
+                    // Only the video currently being downloaded reports
+                    // its progress, the rest of the batch is queued.
+                    counts.running += videos;
+                    completed += status
+                        .progress
+                        .as_ref()
+                        .and_then(|progress| progress.percent)
+                        .unwrap_or(0.0)
+                        / 100.0;
+                }
+                Some(ExecutorPhase::Failed) => counts.failed += videos,
+                _ => counts.queued += videos,
             },
-            _ => {}
+            _ => counts.queued += videos,
         }
     }
-    if succeeded != total {
-        // Not all Executors have succeeded, report the progress.
-        return Ok(ReconcileAction::DownloadProgress { succeeded, total });
+    if !unarchived.is_empty() {
+        // Archive the downloads as soon as they succeed.
+        return Ok(ReconcileAction::RecordArchive(unarchived));
+    }
+    if !cullable.names.is_empty() {
+        // Delete the archived Executors, keeping only their results.
+        return Ok(ReconcileAction::Cull(cullable));
+    }
+    if succeeded + skipped != total {
+        // Not all Executors have finished, report the progress.
+        completed += (succeeded + skipped) as f64;
+        let percent = (completed * 1000.0 / total as f64).round() / 10.0;
+        return Ok(ReconcileAction::DownloadProgress(counts, percent));
+    }
+    if skip_records_changed {
+        // Make sure the skip list is complete before succeeding.
+        return Ok(ReconcileAction::RecordSkipped(skip_records));
     }
     match get_download_phase(instance)? {
         // Nothing to do, we're already in the Succeeded phase.
         DownloadPhase::Succeeded => Ok(ReconcileAction::NoOp),
         // Mark the phase as Succeeded.
-        _ => Ok(ReconcileAction::Succeeded),
+        _ => Ok(ReconcileAction::Succeeded(
+            DownloadSummary {
+                succeeded: succeeded as u32,
+                skipped: skipped as u32,
+                failures,
+                total_bytes,
+                ..DownloadSummary::default()
+            },
+            objects,
+        )),
     }
 }
 
+/// Returns the action to finish culling the Executors listed in the
+/// status, if any. The list is only cleared once none of them are in
+/// the cache, so that they are never counted along with their totals.
+fn determine_culling_action(
+    instance: &Download,
+    executors: &OwnerIndex<Executor>,
+) -> Option<ReconcileAction> {
+    let culling = instance.status.as_ref()?.culling.as_ref()?;
+    let uid = instance.uid().unwrap();
+    let remaining: Vec<String> = culling
+        .iter()
+        .filter(|name| executors.get(&uid, name).is_some())
+        .cloned()
+        .collect();
+    if remaining.is_empty() {
+        return Some(ReconcileAction::CullFinished);
+    }
+    Some(ReconcileAction::DeleteCulled(remaining))
+}
+
+/// Returns the action to update the SpecValid condition, if it does
+/// not reflect the current spec. An invalid spec that was already
+/// reported results in NoOp, as there is nothing to do until the
+/// user fixes it.
+fn determine_validation_action(
+    instance: &Download,
+    errors: Vec<FieldError>,
+) -> Option<ReconcileAction> {
+    let condition = get_condition(
+        instance
+            .status
+            .as_ref()
+            .and_then(|status| status.conditions.as_deref()),
+        SPEC_VALID,
+    );
+    let up_to_date = condition.map_or(false, |condition| {
+        condition.observed_generation == instance.metadata.generation
+    });
+    if errors.is_empty() {
+        // Only record a valid spec if it was previously invalid, to
+        // avoid an extra status update for every new Download.
+        return match condition {
+            Some(condition) if condition.status != "True" => Some(ReconcileAction::SpecValid),
+            _ => None,
+        };
+    }
+    if up_to_date && condition.map_or(false, |condition| condition.status == "False") {
+        return Some(ReconcileAction::NoOp);
+    }
+    Some(ReconcileAction::InvalidSpec(errors))
+}
+
 /// The "read" phase of the reconciliation loop.
-async fn determine_action(client: Client, instance: &Download) -> Result<ReconcileAction, Error> {
+async fn determine_action(
+    client: Client,
+    instance: &Download,
+    executors: &OwnerIndex<Executor>,
+    archives: &ArchiveCache,
+) -> Result<ReconcileAction, Error> {
     if instance.meta().deletion_timestamp.is_some() {
         // We only want to garbage collect child resources.
         return Ok(ReconcileAction::Delete);
     };
 
+    // Validate the spec before any pods are created, so that mistakes
+    // are reported up front instead of failing deep inside a pod. The
+    // cluster's policies are enforced here too, as the webhook may be
+    // bypassed.
+    let mut errors = validate_download(&instance.spec);
+    errors.extend(
+        MetadataOnlyPolicy::from_env().validate(&instance.namespace().unwrap(), &instance.spec),
+    );
+    errors.extend(ExtraArgsPolicy::from_env().validate(&instance.spec));
+    errors.extend(
+        validate_host_policies(
+            client.clone(),
+            &instance.namespace().unwrap(),
+            &instance.spec,
+            &get_host_policy(),
+        )
+        .await?,
+    );
+    if let Some(action) = determine_validation_action(instance, errors) {
+        return Ok(action);
+    }
+
     // Make sure the status object exists with a phase.
     // If not, create it and set the phase to Pending.
     // This allows us to access the status and phase
@@ -532,6 +1164,45 @@ async fn determine_action(client: Client, instance: &Download) -> Result<Reconci
         return Ok(ReconcileAction::Pending);
     }
 
+    // Existing child resources are left alone while suspended, but
+    // no new ones are created.
+    let suspended = get_download_phase(instance)? == DownloadPhase::Suspended;
+    if instance.spec.suspend.unwrap_or(false) {
+        if suspended {
+            return Ok(ReconcileAction::NoOp);
+        }
+        return Ok(ReconcileAction::Suspend);
+    } else if suspended {
+        // Resume reconciliation from the beginning.
+        return Ok(ReconcileAction::Pending);
+    }
+
+    if get_download_phase(instance)? == DownloadPhase::Succeeded {
+        let remaining = get_remaining_ttl(
+            instance
+                .status
+                .as_ref()
+                .and_then(|status| status.completion_time.as_deref()),
+            instance.spec.ttl_seconds_after_finished,
+        );
+        if let Some(remaining) = remaining {
+            // The Executors are not reconciled again, as they may have
+            // already been cleaned up per their own TTL.
+            if remaining.is_zero() {
+                return Ok(ReconcileAction::Expire);
+            }
+            return Ok(ReconcileAction::AwaitTtl(remaining));
+        }
+    }
+
+    if needs_default_targets(instance) {
+        // Inherit the namespace's default targets, if there are any.
+        let targets = get_default_targets(client.clone(), instance).await?;
+        if !targets.is_empty() {
+            return Ok(ReconcileAction::InheritTargets(targets));
+        }
+    }
+
     // First step is to reconcile the metadata ConfigMap.
     let metadata: ConfigMap = match get_metadata_configmap(client.clone(), instance).await {
         // ConfigMap exists. All we need to do now is manage
@@ -557,23 +1228,43 @@ async fn determine_action(client: Client, instance: &Download) -> Result<Reconci
         .get(INFO_JSONL_KEY)
         .ok_or_else(|| Error::UnknownError("metadata ConfigMap has no info.jsonl".to_owned()))?;
 
+    // Finish culling before counting the Executors, as the cache may
+    // still have the ones whose totals were already recorded.
+    if let Some(action) = determine_culling_action(instance, executors) {
+        return Ok(action);
+    }
+
     // The rest of this controller and the query executor
     // itself share code for creating child Executors from
     // `youtube-dl -j` jsonl output. This allows downloads
     // to start before the query is finished, which may take
     // a long time for huge channels or playlists.
-    determine_executor_action(client, instance, info_jsonl).await
+    determine_executor_action(
+        client,
+        instance,
+        executors,
+        archives,
+        info_jsonl,
+        data.get(SKIPPED_JSONL_KEY).map(String::as_str),
+    )
+    .await
 }
 
 /// Actions to be taken when a reconciliation fails - for whatever reason.
-/// Prints out the error to `stderr` and requeues the resource for another reconciliation after
-/// five seconds.
+/// Logs the error and requeues the resource for another reconciliation after
+/// the configured error backoff.
 ///
 /// # Arguments
 /// - `instance`: The erroneous resource.
 /// - `error`: A reference to the `kube::Error` that occurred during reconciliation.
-/// - `_context`: Unused argument. Context Data "injected" automatically by kube-rs.
-fn on_error(instance: Arc<Download>, error: &Error, _context: Arc<ContextData>) -> Action {
-    eprintln!("Reconciliation error:\n{:?}.\n{:?}", error, instance);
-    Action::requeue(Duration::from_secs(5))
+/// - `context`: Context Data "injected" automatically by kube-rs.
+fn on_error(instance: Arc<Download>, error: &Error, context: Arc<ContextData>) -> Action {
+    metrics::reconcile_failed("Download");
+    error!(
+        name = %instance.name_any(),
+        namespace = ?instance.namespace(),
+        error = ?error,
+        "Reconciliation error"
+    );
+    Action::requeue(context.intervals.error)
 }
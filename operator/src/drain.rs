@@ -0,0 +1,97 @@
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::{RwLock, RwLockReadGuard},
+};
+use tracing::{error, info};
+
+/// Set once the operator begins draining. This is never reset, as
+/// a draining replica is expected to be replaced.
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// Set once the replica stops writing, before it hands over the leader
+/// Lease. Only set while the write lock of [`WRITERS`] is held.
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// Reconciliations in flight each hold a read guard, so that the
+    /// writes stop once the write lock is taken.
+    static ref WRITERS: RwLock<()> = RwLock::new(());
+}
+
+/// Returns `true` if the operator is draining, in which case no new
+/// pods or Executors are created. Existing pods are still monitored
+/// so that their statuses are recorded before the operator exits.
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::SeqCst)
+}
+
+/// Puts the operator into drain mode.
+fn start() {
+    if !DRAINING.swap(true, Ordering::SeqCst) {
+        info!("Draining, no new pods will be created");
+    }
+}
+
+/// Returns the permit to write on behalf of the leader, which is held
+/// for the duration of a reconciliation, or None once the replica has
+/// stopped writing.
+pub async fn writer() -> Option<RwLockReadGuard<'static, ()>> {
+    let guard = WRITERS.read().await;
+    if STOPPED.load(Ordering::SeqCst) {
+        return None;
+    }
+    Some(guard)
+}
+
+/// Waits for the reconciliations in flight to finish, after which no
+/// writer is granted again, so that the replica can hand over the
+/// leader Lease without two replicas writing at once.
+pub async fn stop_writers() {
+    let _guard = WRITERS.write().await;
+    STOPPED.store(true, Ordering::SeqCst);
+    info!("Stopped reconciling");
+}
+
+/// Starts draining when the process receives SIGTERM, which is how
+/// Kubernetes stops the old pods during an upgrade. Without leader
+/// election, the controller keeps recording statuses for the remainder
+/// of the pod's `terminationGracePeriodSeconds`. With it, the replica
+/// stops reconciling before the leader Lease is handed over to the new
+/// pods.
+pub fn spawn_signal_handler() {
+    tokio::spawn(async {
+        let mut sigterm = match signal(SignalKind::terminate()) {
+            Ok(sigterm) => sigterm,
+            Err(e) => {
+                error!(error = %e, "Failed to install SIGTERM handler");
+                return;
+            }
+        };
+        sigterm.recv().await;
+        start();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn no_writer_after_stop() {
+        let writer = writer().await.expect("writers are granted before the stop");
+        let stop = tokio::spawn(stop_writers());
+        // The stop waits for the reconciliation in flight.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!stop.is_finished());
+        drop(writer);
+        timeout(Duration::from_secs(1), stop)
+            .await
+            .expect("stop finishes once the writer is dropped")
+            .unwrap();
+        assert!(super::writer().await.is_none());
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{
+    metrics,
+    util::{get_restart_alert_threshold, MANAGER_NAME},
+};
+use kube::{
+    runtime::events::{Event, EventType, Recorder, Reporter},
+    Client, Resource, ResourceExt,
+};
+use tracing::warn;
+
+/// Publishes a Kubernetes Event for the given resource so that its
+/// history is visible with `kubectl describe`. Status messages are
+/// overwritten with each update, whereas Events are retained. Errors
+/// are logged and otherwise ignored, as failing to publish an Event
+/// should never fail reconciliation.
+pub async fn publish<K>(
+    client: Client,
+    instance: &K,
+    type_: EventType,
+    reason: &str,
+    note: Option<String>,
+) where
+    K: Resource<DynamicType = ()>,
+{
+    let reporter = Reporter {
+        controller: MANAGER_NAME.to_owned(),
+        instance: std::env::var("HOSTNAME").ok(),
+    };
+    let recorder = Recorder::new(client, reporter, instance.object_ref(&()));
+    let result = recorder
+        .publish(Event {
+            type_,
+            reason: reason.to_owned(),
+            note,
+            action: "Reconcile".to_owned(),
+            secondary: None,
+        })
+        .await;
+    if let Err(e) = result {
+        warn!(reason, error = %e, "Failed to publish event");
+    }
+}
+
+/// Publishes a warning Event and increments the alert metric if more
+/// pods were created for the resource within the last hour than the
+/// threshold allows. Such a resource is flapping, e.g. its pods keep
+/// failing and are recreated, and would otherwise churn silently.
+pub async fn check_pod_restarts<K>(client: Client, instance: &K, kind: &str, starts: usize)
+where
+    K: Resource<DynamicType = ()>,
+{
+    let threshold = get_restart_alert_threshold();
+    if threshold == 0 || starts <= threshold {
+        return;
+    }
+    metrics::pod_restart_alert(kind, &instance.namespace().unwrap_or_default());
+    publish(
+        client,
+        instance,
+        EventType::Warning,
+        "PodFlapping",
+        Some(format!(
+            "{} pods were created within the last hour, exceeding the threshold of {}",
+            starts, threshold
+        )),
+    )
+    .await;
+}
@@ -1,17 +1,38 @@
-use crate::util::MANAGER_NAME;
+use crate::{events, util::MANAGER_NAME};
 use k8s_openapi::{
-    api::core::v1::{Container, EnvVar, Pod, VolumeMount},
-    apimachinery::pkg::apis::meta::v1::Time,
+    api::core::v1::{ConfigMap, Container, ContainerPort, EnvVar, Pod, VolumeMount},
+    apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time},
 };
 use kube::{
-    api::{Api, DeleteParams, Patch, PatchParams, PostParams, Resource},
-    Client, CustomResourceExt,
+    api::{Api, Patch, PatchParams, PostParams, PropagationPolicy, Resource},
+    runtime::events::EventType,
+    Client, CustomResourceExt, ResourceExt,
 };
+use std::{collections::BTreeMap, time::Duration};
 use ytdl_common::{
-    pod::{masked_pod, SHARED_PATH, SHARED_VOLUME_NAME},
+    auth::mount_auth,
+    ca_bundle::{get_ca_bundle_secrets, mount_ca_bundles},
+    condition::{set_health_conditions, Health},
+    cookies::mount_cookies,
+    deadline::{get_deadline_env, get_download_timeout, set_active_deadline},
+    delete::delete_opt,
+    failure::EXECUTOR_CONTAINER_NAME,
+    history::{record_pod_start, record_transition},
+    manifest::get_stored_objects,
+    pod::{delete_owned_pods, masked_pod, SHARED_PATH, SHARED_VOLUME_NAME},
+    progress::{self, get_progress_port, PROGRESS_PORT_NAME},
+    propagate::{get_propagate_prefixes, propagate_metadata},
+    proxy::{get_storage_proxy, get_storage_proxy_env},
+    timing::get_stage_timing,
+    upload,
+    volume::{get_volume_targets, mount_volume_targets},
+    work_volume::{create_work_claim, is_persistent, mount_work_volume},
     Error, DEFAULT_EXECUTOR_IMAGE,
 };
-use ytdl_types::{Executor, ExecutorPhase, ExecutorStatus};
+use ytdl_types::{
+    AgeRestrictedPolicy, DownloadProgress, Executor, ExecutorPhase, ExecutorStatus, ProxySpec,
+    VolumeTarget,
+};
 
 /// Returns the image to use for the executor container.
 /// It may be overridden by the user in the spec, but
@@ -25,6 +46,29 @@ pub fn get_executor_image(instance: &Executor) -> String {
         .to_owned()
 }
 
+/// Returns the VPN server region to use for the download pod,
+/// which is only set after the video was found to be geo-blocked.
+fn get_vpn_region(instance: &Executor) -> Option<String> {
+    let index = instance.status.as_ref()?.geo_region_index? as usize;
+    instance.spec.geo_regions.as_ref()?.get(index).cloned()
+}
+
+/// Returns the Secret with the cookies for the download pod, if any.
+/// With allowWithCookies, the cookies are only used once the video
+/// hit an age gate.
+fn get_cookies_secret(instance: &Executor) -> Option<&str> {
+    let secret = instance.spec.cookies_secret.as_deref()?;
+    let with_cookies = instance
+        .status
+        .as_ref()
+        .and_then(|status| status.with_cookies)
+        .unwrap_or(false);
+    match instance.spec.age_restricted.unwrap_or_default() {
+        AgeRestrictedPolicy::AllowWithCookies if !with_cookies => None,
+        _ => Some(secret),
+    }
+}
+
 /// A central tenet of this project is to only access
 /// the external video service from within pods that
 /// have VPN sidecars. Thus, both the video and the
@@ -36,16 +80,21 @@ pub struct DownloadPodOptions {
 
     // If true, download the thumbnail to the storage backend.
     pub download_thumbnail: bool,
+
+    // If true, publish the metadata to the metadata targets.
+    pub publish_metadata: bool,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ProgressOptions {
     pub start_time: Option<Time>,
+    pub progress: Option<DownloadProgress>,
 }
 
 /// Returns the arguments to pass to the executor container's
 /// default command. This is used to configure the executor
-/// to download the video and/or thumbnail.
+/// to download the video and/or thumbnail, and to publish
+/// the metadata.
 fn get_executor_args(options: DownloadPodOptions) -> Vec<String> {
     let mut args = vec!["download".to_owned()];
     if options.download_video {
@@ -54,6 +103,9 @@ fn get_executor_args(options: DownloadPodOptions) -> Vec<String> {
     if options.download_thumbnail {
         args.push("--download-thumbnail".to_owned());
     }
+    if options.publish_metadata {
+        args.push("--publish-metadata".to_owned());
+    }
     args
 }
 
@@ -70,6 +122,70 @@ pub async fn create_pod(
     service_account_name: String,
     options: DownloadPodOptions,
 ) -> Result<(), Error> {
+    let targets = get_pod_targets(client.clone(), namespace, instance).await?;
+
+    // Claim the scratch volume first if it's persistent so
+    // that the partial download outlives this pod.
+    if let Some(ref work_volume) = instance.spec.work_volume {
+        if is_persistent(work_volume) {
+            create_work_claim(client.clone(), instance, work_volume).await?;
+        }
+    }
+
+    let pod = get_download_pod(
+        name,
+        namespace,
+        instance,
+        service_account_name,
+        options,
+        &targets,
+    )?;
+
+    // Create the pod.
+    let pod_api: Api<Pod> = Api::namespaced(client, namespace);
+    pod_api.create(&PostParams::default(), &pod).await?;
+    Ok(())
+}
+
+/// The parts of the download pod that depend on the targets of the
+/// Executor, which are looked up in the cluster.
+#[derive(Debug, Default, Clone)]
+pub struct PodTargets {
+    /// Proxy the uploads go through, if any.
+    pub storage_proxy: Option<ProxySpec>,
+
+    /// Secrets with the CA bundles of the targets the pod uploads to.
+    pub ca_bundles: Vec<String>,
+
+    /// Volume targets the pod writes to.
+    pub volume_targets: Vec<VolumeTarget>,
+}
+
+/// Looks up the parts of the download pod that depend on the
+/// Executor's targets.
+pub async fn get_pod_targets(
+    client: Client,
+    namespace: &str,
+    instance: &Executor,
+) -> Result<PodTargets, Error> {
+    let output = &instance.spec.output;
+    Ok(PodTargets {
+        storage_proxy: get_storage_proxy(client.clone(), namespace, output).await?,
+        ca_bundles: get_ca_bundle_secrets(client.clone(), namespace, output).await?,
+        volume_targets: get_volume_targets(client, namespace, output).await?,
+    })
+}
+
+/// Returns the download pod for the given Executor without creating
+/// it or the claim of its work volume.
+pub fn get_download_pod(
+    name: &str,
+    namespace: &str,
+    instance: &Executor,
+    service_account_name: String,
+    options: DownloadPodOptions,
+    targets: &PodTargets,
+) -> Result<Pod, Error> {
     // Inject the spec as an environment variable.
     let resource: String = serde_json::to_string(instance)?;
 
@@ -81,19 +197,42 @@ pub async fn create_pod(
     // arguments.
     let args = get_executor_args(options);
 
+    // The executor aborts by the deadline, which is computed now.
+    let timeout = get_download_timeout(instance)?;
+    let deadline_env = timeout.map(get_deadline_env).transpose()?;
+
     let container = Container {
-        name: "executor".to_owned(),
+        name: EXECUTOR_CONTAINER_NAME.to_owned(),
         image: Some(image),
         // TODO: inject the imagePullPolicy from the helm chart.
         // There needs to be an ExecutorOptions struct corresponding to values.yaml->executor: (?)
         image_pull_policy: Some("Always".to_owned()), // FIXME: inject from helm
         args: Some(args),
-        // Pass the full resource as an environment variable.
-        env: Some(vec![EnvVar {
-            name: "RESOURCE".to_owned(),
-            value: Some(resource),
-            ..EnvVar::default()
-        }]),
+        // Pass the full resource as an environment variable,
+        // along with the operator's upload configuration, the
+        // storage proxy, the deadline, and the progress port.
+        // Uploads go through the targets' storage proxy, if any.
+        env: Some(
+            vec![EnvVar {
+                name: "RESOURCE".to_owned(),
+                value: Some(resource),
+                ..EnvVar::default()
+            }]
+            .into_iter()
+            .chain(upload::get_pod_env())
+            .chain(targets.storage_proxy.iter().flat_map(get_storage_proxy_env))
+            .chain(deadline_env)
+            .chain(progress::get_pod_env())
+            .collect(),
+        ),
+        // The port the controller scrapes the progress from, if any.
+        ports: get_progress_port()?.map(|port| {
+            vec![ContainerPort {
+                name: Some(PROGRESS_PORT_NAME.to_owned()),
+                container_port: i32::from(port),
+                ..ContainerPort::default()
+            }]
+        }),
         // We need the shared volume mounted as it contains
         // the unmasked IP retrieved during initialization.
         // The containers have a shared volume mounted at /share
@@ -115,35 +254,78 @@ pub async fn create_pod(
     let oref = instance.controller_owner_ref(&()).unwrap();
 
     // Build the full Pod resource with the VPN sidecar.
-    let pod: Pod = masked_pod(
+    let mut pod: Pod = masked_pod(
         name.to_owned(),
         namespace.to_owned(),
         Some(vec![oref]),
         service_account_name,
         container,
+        instance.spec.vpn.as_ref(),
+        get_vpn_region(instance),
+        instance.spec.proxy.as_ref(),
+        instance.spec.pod_template.as_ref(),
     );
 
-    // Create the pod.
-    let pod_api: Api<Pod> = Api::namespaced(client, namespace);
-    pod_api.create(&PostParams::default(), &pod).await?;
-    Ok(())
+    // Mount the cookies for authenticated downloads.
+    if let Some(secret) = get_cookies_secret(instance) {
+        mount_cookies(&mut pod, secret);
+    }
+
+    // Mount the account credentials for sites that require logging in.
+    if let Some(ref secret) = instance.spec.auth_secret {
+        mount_auth(&mut pod, secret);
+    }
+
+    // Mount the CA bundles of the targets the pod uploads to.
+    mount_ca_bundles(&mut pod, &targets.ca_bundles);
+
+    // Mount the claims of the volume targets the pod writes to.
+    mount_volume_targets(&mut pod, &targets.volume_targets);
+
+    // Mount the scratch volume.
+    if let Some(ref work_volume) = instance.spec.work_volume {
+        mount_work_volume(&mut pod, &instance.name_any(), work_volume);
+    }
+
+    // Kill the pod if the executor doesn't exit by its deadline.
+    if let Some(timeout) = timeout {
+        set_active_deadline(&mut pod, timeout);
+    }
+
+    // Inherit the Executor's user-defined labels and annotations.
+    propagate_metadata(
+        &instance.metadata,
+        &mut pod.metadata,
+        &get_propagate_prefixes(),
+    );
+    Ok(pod)
 }
 
-/// Deletes the download pod for the given Executor.
-pub async fn delete_pod(client: Client, name: &str, namespace: &str) -> Result<(), Error> {
-    let api: Api<Pod> = Api::namespaced(client, namespace);
-    api.delete(name, &DeleteParams::default()).await?;
+/// Deletes the download pod for the given Executor. Does nothing if
+/// the pod was already deleted.
+pub async fn delete_pod(client: Client, instance: &Executor) -> Result<(), Error> {
+    delete_owned_pods(
+        client,
+        &instance.namespace().unwrap(),
+        &instance.uid().unwrap(),
+    )
+    .await
+}
+
+/// Deletes the Executor, which happens once its TTL after finishing
+/// has elapsed. Does nothing if it was already deleted.
+pub async fn delete_executor(client: Client, name: &str, namespace: &str) -> Result<(), Error> {
+    let api: Api<Executor> = Api::namespaced(client, namespace);
+    delete_opt(&api, name, PropagationPolicy::Background).await?;
     Ok(())
 }
 
 /// Marks the Executor's status as Succeeded.
-pub async fn success(
-    client: Client,
-    instance: &Executor,
-) -> Result<(), Error> {
+pub async fn success(client: Client, instance: &Executor) -> Result<(), Error> {
     patch_status(client, instance, |status| {
         status.message = Some("download tasks completed without error".to_owned());
         status.phase = Some(ExecutorPhase::Succeeded);
+        status.completion_time = Some(chrono::Utc::now().to_rfc3339());
     })
     .await?;
     Ok(())
@@ -154,11 +336,15 @@ pub async fn progress(
     client: Client,
     instance: &Executor,
     start_time: Time,
+    progress: Option<DownloadProgress>,
 ) -> Result<(), Error> {
     patch_status(client, instance, |status| {
         status.message = Some("download tasks are in progress".to_owned());
         status.phase = Some(ExecutorPhase::Downloading);
         status.start_time = Some(start_time.0.to_rfc3339());
+        if progress.is_some() {
+            status.progress = progress;
+        }
     })
     .await?;
     Ok(())
@@ -166,10 +352,7 @@ pub async fn progress(
 
 /// Updates the Executor's phase to Pending, which indicates
 /// the resource made its initial appearance to the operator.
-pub async fn pending(
-    client: Client,
-    instance: &Executor,
-) -> Result<(), Error> {
+pub async fn pending(client: Client, instance: &Executor) -> Result<(), Error> {
     patch_status(client, instance, |status| {
         status.message = Some("the resource first appeared to the controller".to_owned());
         status.phase = Some(ExecutorPhase::Pending);
@@ -178,12 +361,20 @@ pub async fn pending(
     Ok(())
 }
 
+/// Update the Executor's phase to Throttled, which indicates the
+/// download pod is waiting for a slot in the concurrency budget.
+pub async fn throttled(client: Client, instance: &Executor) -> Result<(), Error> {
+    patch_status(client, instance, |status| {
+        status.message = Some("waiting for other downloads to finish".to_owned());
+        status.phase = Some(ExecutorPhase::Throttled);
+    })
+    .await?;
+    Ok(())
+}
+
 /// Update the Executor's phase to Starting, which indicates
 /// the download pod is currently running.
-pub async fn starting(
-    client: Client,
-    instance: &Executor,
-) -> Result<(), Error> {
+pub async fn starting(client: Client, instance: &Executor) -> Result<(), Error> {
     patch_status(client, instance, |status| {
         status.message = Some("the download pod is starting".to_owned());
         status.phase = Some(ExecutorPhase::Starting);
@@ -192,11 +383,147 @@ pub async fn starting(
     Ok(())
 }
 
-pub async fn failure(
+/// Marks the Executor's status as Starting after its download pod was
+/// created, and records the pod's creation. Returns the number of
+/// download pods created within the last hour.
+pub async fn pod_created(client: Client, instance: &Executor) -> Result<usize, Error> {
+    let mut starts = 0;
+    patch_status(client, instance, |status| {
+        status.message = Some("the download pod is starting".to_owned());
+        status.phase = Some(ExecutorPhase::Starting);
+        starts = record_pod_start(status.pod_starts.get_or_insert_with(Vec::new));
+    })
+    .await?;
+    Ok(starts)
+}
+
+/// Marks the Executor's status as Skipped, which indicates the
+/// video was intentionally not downloaded per the user's policy.
+pub async fn skipped(
+    client: Client,
+    instance: &Executor,
+    policy: &str,
+    message: String,
+) -> Result<(), Error> {
+    patch_status(client, instance, move |status| {
+        status.message = Some(message);
+        status.phase = Some(ExecutorPhase::Skipped);
+        status.skip_policy = Some(policy.to_owned());
+    })
+    .await?;
+    Ok(())
+}
+
+/// Updates the Executor's status to use the VPN server region at the
+/// given index for the next download pod, as the video was geo-blocked.
+pub async fn retry_region(
+    client: Client,
+    instance: &Executor,
+    index: u32,
+    message: String,
+) -> Result<(), Error> {
+    patch_status(client, instance, move |status| {
+        status.message = Some(message);
+        status.phase = Some(ExecutorPhase::Failed);
+        status.geo_region_index = Some(index);
+    })
+    .await?;
+    Ok(())
+}
+
+/// Updates the Executor's status to mount the cookies into the next
+/// download pod, as the video hit an age gate.
+pub async fn retry_with_cookies(
+    client: Client,
+    instance: &Executor,
+    message: String,
+) -> Result<(), Error> {
+    patch_status(client, instance, move |status| {
+        status.message = Some(message);
+        status.phase = Some(ExecutorPhase::Failed);
+        status.with_cookies = Some(true);
+    })
+    .await?;
+    Ok(())
+}
+
+/// Marks the Executor's status as Failed and records the retry, which
+/// delays the recreation of the download pod by the given backoff.
+pub async fn retry(
     client: Client,
     instance: &Executor,
     message: String,
+    retries: u32,
+    backoff: Duration,
 ) -> Result<(), Error> {
+    patch_status(client, instance, move |status| {
+        status.message = Some(message);
+        status.phase = Some(ExecutorPhase::Failed);
+        status.retries = Some(retries);
+        status.last_failure_time = Some(chrono::Utc::now().to_rfc3339());
+        status.backoff_seconds = Some(backoff.as_secs());
+    })
+    .await?;
+    Ok(())
+}
+
+/// Records that the bytes downloaded up to `total` have been added
+/// to the namespace's egress accounting.
+pub async fn egress_accounted(
+    client: Client,
+    instance: &Executor,
+    total: u64,
+) -> Result<(), Error> {
+    patch_status(client, instance, move |status| {
+        status.bytes_accounted = Some(total);
+    })
+    .await?;
+    Ok(())
+}
+
+/// Adds the bytes to the current month's counter in the egress
+/// ConfigMap, creating it if necessary. Each key is a month in the
+/// `YYYY-MM` format and its value is the total bytes downloaded in
+/// the namespace that month. The write is rejected if another
+/// controller modified the ConfigMap concurrently, in which case
+/// the reconciliation is retried.
+pub async fn add_monthly_egress(
+    client: Client,
+    namespace: &str,
+    name: &str,
+    bytes: u64,
+) -> Result<(), Error> {
+    let month = chrono::Utc::now().format("%Y-%m").to_string();
+    let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    match api.get_opt(name).await? {
+        Some(mut cm) => {
+            let data = cm.data.get_or_insert_with(BTreeMap::new);
+            let total = data
+                .get(&month)
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(0)
+                + bytes;
+            data.insert(month, total.to_string());
+            // The resourceVersion is retained for optimistic concurrency.
+            api.replace(name, &PostParams::default(), &cm).await?;
+        }
+        None => {
+            let cm = ConfigMap {
+                metadata: ObjectMeta {
+                    name: Some(name.to_owned()),
+                    namespace: Some(namespace.to_owned()),
+                    ..ObjectMeta::default()
+                },
+                data: Some(BTreeMap::from([(month, bytes.to_string())])),
+                ..ConfigMap::default()
+            };
+            api.create(&PostParams::default(), &cm).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn failure(client: Client, instance: &Executor, message: String) -> Result<(), Error> {
     patch_status(client, instance, move |status| {
         status.message = Some(message);
         status.phase = Some(ExecutorPhase::Failed);
@@ -215,29 +542,66 @@ async fn patch_status(
 ) -> Result<Executor, Error> {
     let name = instance.metadata.name.as_deref().unwrap();
     let namespace = instance.metadata.namespace.as_deref().unwrap();
-    let patch = Patch::Apply({
-        let mut status = instance.status.clone().unwrap_or_default();
-        f(&mut status);
-        status.last_updated = Some(chrono::Utc::now().to_rfc3339());
-        serde_json::json!({
-            "apiVersion": "vpn.beebs.dev/v1",
-            "kind": Executor::crd().spec.names.kind.clone(),
-            "status": status,
-        })
-    });
-    let api: Api<Executor> = Api::namespaced(client, namespace);
-    Ok(api
+    let old_phase = instance.status.as_ref().and_then(|status| status.phase);
+    let mut status = instance.status.clone().unwrap_or_default();
+    f(&mut status);
+    status.last_updated = Some(chrono::Utc::now().to_rfc3339());
+    if let Some(timing) = get_stage_timing(instance) {
+        status.timing = Some(timing);
+    }
+    let objects = get_stored_objects(instance);
+    if !objects.is_empty() {
+        status.objects = Some(objects);
+    }
+    let new_phase = status.phase;
+    let message = status.message.clone();
+    if let Some(phase) = new_phase.filter(|phase| Some(*phase) != old_phase) {
+        record_transition(
+            status.phase_history.get_or_insert_with(Vec::new),
+            &phase.to_string(),
+            message.as_deref(),
+        );
+    }
+    if let Some(phase) = new_phase {
+        let health = match phase {
+            ExecutorPhase::Succeeded | ExecutorPhase::Skipped => Health::Ready,
+            ExecutorPhase::Failed => Health::Stalled,
+            _ => Health::Reconciling,
+        };
+        set_health_conditions(
+            status.conditions.get_or_insert_with(Vec::new),
+            health,
+            &phase.to_string(),
+            message.as_deref(),
+            instance.metadata.generation,
+        );
+    }
+    let patch = Patch::Apply(serde_json::json!({
+        "apiVersion": "vpn.beebs.dev/v1",
+        "kind": Executor::crd().spec.names.kind.clone(),
+        "status": status,
+    }));
+    let api: Api<Executor> = Api::namespaced(client.clone(), namespace);
+    let result = api
         .patch_status(name, &PatchParams::apply(MANAGER_NAME), &patch)
-        .await?)
+        .await?;
+    if let Some(phase) = new_phase.filter(|phase| Some(*phase) != old_phase) {
+        // Record the phase transition as an Event so the history
+        // survives subsequent status updates.
+        let type_ = match phase {
+            ExecutorPhase::Failed => EventType::Warning,
+            _ => EventType::Normal,
+        };
+        events::publish(client, instance, type_, &phase.to_string(), message).await;
+    }
+    Ok(result)
 }
 
 pub mod finalizer {
     use super::*;
-    use kube::api::{Patch, PatchParams};
-    use serde_json::{json, Value};
 
-    /// Adds a finalizer record into an `Executor` kind of resource. If the finalizer already exists,
-    /// this action has no effect.
+    /// Adds the finalizer to an `Executor` kind of resource, leaving the finalizers of other
+    /// controllers in place. If the finalizer already exists, this action has no effect.
     ///
     /// # Arguments:
     /// - `client` - Kubernetes client to modify the `Executor` resource with.
@@ -247,17 +611,11 @@ pub mod finalizer {
     /// Note: Does not check for resource's existence for simplicity.
     pub async fn add(client: Client, name: &str, namespace: &str) -> Result<Executor, Error> {
         let api: Api<Executor> = Api::namespaced(client, namespace);
-        let finalizer: Value = json!({
-            "metadata": {
-                "finalizers": ["ytdl.beebs.dev/finalizer"]
-            }
-        });
-        let patch: Patch<&Value> = Patch::Merge(&finalizer);
-        Ok(api.patch(name, &PatchParams::default(), &patch).await?)
+        crate::finalizer::add(&api, name).await
     }
 
-    /// Removes all finalizers from an `Executor` resource. If there are no finalizers already, this
-    /// action has no effect.
+    /// Removes the finalizer from an `Executor` resource, leaving the finalizers of other
+    /// controllers in place. If the finalizer is already gone, this action has no effect.
     ///
     /// # Arguments:
     /// - `client` - Kubernetes client to modify the `Executor` resource with.
@@ -267,12 +625,21 @@ pub mod finalizer {
     /// Note: Does not check for resource's existence for simplicity.
     pub async fn delete(client: Client, name: &str, namespace: &str) -> Result<Executor, Error> {
         let api: Api<Executor> = Api::namespaced(client, namespace);
-        let finalizer: Value = json!({
-            "metadata": {
-                "finalizers": null
-            }
+        crate::finalizer::remove(&api, name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_only_pod_publishes_metadata() {
+        let args = get_executor_args(DownloadPodOptions {
+            download_video: false,
+            download_thumbnail: false,
+            publish_metadata: true,
         });
-        let patch: Patch<&Value> = Patch::Merge(&finalizer);
-        Ok(api.patch(name, &PatchParams::default(), &patch).await?)
+        assert_eq!(args, vec!["download", "--publish-metadata"]);
     }
 }
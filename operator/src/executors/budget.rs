@@ -0,0 +1,220 @@
+use futures::StreamExt;
+use kube::{
+    api::ListParams,
+    runtime::{reflector, reflector::Store, watcher},
+    Api, ResourceExt,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use ytdl_types::{Executor, ExecutorPhase};
+
+/// How long a grant counts against the limit while the caches don't
+/// reflect its Executor as active. This covers the time it takes for
+/// the new phase to reach the caches, after which a grant whose pod
+/// was never started no longer holds a slot.
+const GRANT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Operator-wide limit on the number of download pods. Slots are shared
+/// fairly between Downloads: when the limit is reached, a freed slot goes
+/// to the Download with the fewest active pods among those waiting for
+/// one, so a single huge channel cannot starve everyone else.
+pub struct Budget {
+    /// Maximum number of active download pods. Zero means unlimited.
+    limit: usize,
+
+    /// Caches of the Executors in each of the watched namespaces.
+    stores: Vec<Store<Executor>>,
+
+    /// Executors granted a slot whose new phase may not yet be
+    /// reflected by the caches, keyed by namespace and name, with
+    /// the time of the grant.
+    granted: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl Budget {
+    /// Starts caching the Executors visible to the given Apis, which
+    /// correspond to the watched namespaces.
+    pub fn new(limit: usize, apis: Vec<Api<Executor>>) -> Self {
+        let stores = apis
+            .into_iter()
+            .map(|api| {
+                let (reader, writer) = reflector::store();
+                let stream = reflector(writer, watcher(api, ListParams::default()));
+                tokio::spawn(stream.for_each(|_| futures::future::ready(())));
+                reader
+            })
+            .collect();
+        Budget {
+            limit,
+            stores,
+            granted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns true if the Executor may create its download pod, in
+    /// which case the slot is counted as used until the Executor
+    /// reaches a terminal phase.
+    pub fn try_acquire(&self, instance: &Executor) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+        let mut granted = self.granted.lock().unwrap();
+        let executors: Vec<_> = self.stores.iter().flat_map(|store| store.state()).collect();
+        let phases: HashMap<_, _> = executors
+            .iter()
+            .map(|executor| (executor_key(executor), get_phase(executor)))
+            .collect();
+        // Forget the grants the caches have caught up with, and those
+        // whose Executor never left its phase.
+        granted.retain(|key, granted_at| {
+            matches!(
+                phases.get(key),
+                Some(None)
+                    | Some(Some(ExecutorPhase::Pending))
+                    | Some(Some(ExecutorPhase::Throttled))
+            ) && granted_at.elapsed() < GRANT_TIMEOUT
+        });
+        // Count the active pods and the Executors waiting for a slot,
+        // grouped by the uid of the parent Download.
+        let mut active: HashMap<String, usize> = HashMap::new();
+        let mut waiting: HashSet<String> = HashSet::new();
+        let mut total = 0;
+        for executor in executors.iter() {
+            let parent = get_parent_uid(executor);
+            let phase = get_phase(executor);
+            if granted.contains_key(&executor_key(executor)) || phase.map_or(false, is_active) {
+                total += 1;
+                *active.entry(parent).or_default() += 1;
+            } else if phase == Some(ExecutorPhase::Throttled) {
+                waiting.insert(parent);
+            }
+        }
+        if total >= self.limit {
+            return false;
+        }
+        // Defer to any waiting Download with fewer active pods.
+        let parent = get_parent_uid(instance);
+        let mine = active.get(&parent).copied().unwrap_or(0);
+        if waiting
+            .iter()
+            .any(|other| *other != parent && active.get(other).copied().unwrap_or(0) < mine)
+        {
+            return false;
+        }
+        granted.insert(executor_key(instance), Instant::now());
+        true
+    }
+
+    /// Frees the slot granted to the Executor, as its download pod
+    /// could not be created.
+    pub fn release(&self, instance: &Executor) {
+        self.granted.lock().unwrap().remove(&executor_key(instance));
+    }
+}
+
+/// Returns the namespace and name identifying the Executor.
+fn executor_key(instance: &Executor) -> (String, String) {
+    (
+        instance.namespace().unwrap_or_default(),
+        instance.name_any(),
+    )
+}
+
+/// Returns true if the phase is one in which the Executor holds a slot,
+/// i.e. any from Starting until it has succeeded, failed, or skipped.
+fn is_active(phase: ExecutorPhase) -> bool {
+    !matches!(
+        phase,
+        ExecutorPhase::Pending
+            | ExecutorPhase::Waiting
+            | ExecutorPhase::Throttled
+            | ExecutorPhase::Succeeded
+            | ExecutorPhase::Failed
+            | ExecutorPhase::Skipped
+    )
+}
+
+fn get_phase(instance: &Executor) -> Option<ExecutorPhase> {
+    instance.status.as_ref().and_then(|status| status.phase)
+}
+
+/// Returns the uid of the Download that owns the Executor. Executors
+/// without an owner are each treated as their own Download.
+fn get_parent_uid(instance: &Executor) -> String {
+    instance
+        .owner_references()
+        .iter()
+        .find(|oref| oref.controller == Some(true))
+        .map(|oref| oref.uid.clone())
+        .unwrap_or_else(|| instance.uid().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ytdl_types::{ExecutorSpec, ExecutorStatus};
+
+    /// Returns a Budget for a single download pod whose cache holds
+    /// the Executors.
+    fn budget(executors: &[&Executor]) -> Budget {
+        let (reader, mut writer) = reflector::store();
+        for executor in executors {
+            writer.apply_watcher_event(&watcher::Event::Applied((*executor).clone()));
+        }
+        Budget {
+            limit: 1,
+            stores: vec![reader],
+            granted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn executor(name: &str, phase: ExecutorPhase) -> Executor {
+        let mut instance = Executor::new(name, ExecutorSpec::default());
+        instance.status = Some(ExecutorStatus {
+            phase: Some(phase),
+            ..ExecutorStatus::default()
+        });
+        instance
+    }
+
+    #[test]
+    fn released_grant_frees_slot() {
+        let first = executor("first", ExecutorPhase::Pending);
+        let second = executor("second", ExecutorPhase::Pending);
+        let budget = budget(&[&first, &second]);
+        assert!(budget.try_acquire(&first));
+        assert!(!budget.try_acquire(&second));
+        // The first Executor's download pod failed to be created.
+        budget.release(&first);
+        assert!(budget.try_acquire(&second));
+    }
+
+    #[test]
+    fn stale_grant_frees_slot() {
+        let first = executor("first", ExecutorPhase::Throttled);
+        let second = executor("second", ExecutorPhase::Throttled);
+        let budget = budget(&[&first, &second]);
+        assert!(budget.try_acquire(&first));
+        // The first Executor is still not running long after its grant.
+        let granted_at = Instant::now()
+            .checked_sub(GRANT_TIMEOUT)
+            .expect("the clock is past the timeout");
+        budget
+            .granted
+            .lock()
+            .unwrap()
+            .insert(executor_key(&first), granted_at);
+        assert!(budget.try_acquire(&second));
+    }
+
+    #[test]
+    fn running_executor_holds_slot() {
+        let first = executor("first", ExecutorPhase::Running);
+        let second = executor("second", ExecutorPhase::Pending);
+        let budget = budget(&[&first, &second]);
+        assert!(!budget.try_acquire(&second));
+    }
+}
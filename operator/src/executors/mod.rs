@@ -1,4 +1,5 @@
 mod action;
+mod budget;
 mod reconcile;
 
 pub use reconcile::main;
@@ -1,24 +1,49 @@
-use futures::stream::StreamExt;
+use futures::stream::{self, StreamExt};
 use k8s_openapi::api::core::v1::{Pod, PodStatus};
 use kube::Resource;
 use kube::ResourceExt;
 use kube::{
-    api::ListParams, client::Client, runtime::controller::Action, runtime::Controller, Api,
+    api::ListParams,
+    client::Client,
+    runtime::{controller::Action, events::EventType, Controller},
+    Api,
 };
 use s3::bucket::Bucket;
-use std::sync::Arc;
-use tokio::time::Duration;
+use std::{sync::Arc, time::Duration};
+use tracing::{debug, error, info, instrument, warn};
 
-use super::action::{self, DownloadPodOptions, ProgressOptions};
+use super::{
+    action::{self, DownloadPodOptions, ProgressOptions},
+    budget::Budget,
+};
+use crate::{
+    drain, events, metrics,
+    util::{get_concurrency, get_egress_configmap, get_watch_apis, RequeueIntervals},
+};
 use ytdl_common::{
-    check_pod_scheduling_error, get_executor_phase, get_executor_service_account_name,
-    get_thumbnail_output, get_video_output, Error, IMMEDIATELY,
+    chaos, check_pod_scheduling_error,
+    egress::get_bytes_downloaded,
+    failure::{get_pod_exit_code, get_pod_failure, FailureReason},
+    get_executor_phase, get_executor_service_account_name, get_job_metadata, get_remaining_ttl,
+    get_thumbnail_output, get_video_output,
+    manifest::{get_stored_objects, CHECKSUM_METADATA_KEY},
+    mongodb::get_mongodb_outputs,
+    pod::get_owned_pod,
+    progress::{get_download_progress, get_progress_port, scrape_progress},
+    redis::get_redis_outputs,
+    skip::{AGE_RESTRICTED_POLICY, GEO_BLOCKED_POLICY},
+    store::get_store_outputs,
+    upload::UploadConfig,
+    volume::get_volume_outputs,
+    wants_content, Error, DEFAULT_MAX_RETRIES, IMMEDIATELY,
+};
+use ytdl_types::{
+    AgeRestrictedPolicy, ContentType, Download, DownloadPhase, DownloadProgress, Executor,
+    ExecutorPhase, GeoBlockedPolicy, Target, TargetSpec,
 };
-use ytdl_types::{Executor, ExecutorPhase};
-use crate::util::get_concurrency;
 
-pub async fn main() {
-    println!("Initializing Executor controller...");
+pub async fn main(namespaces: Vec<String>) {
+    info!("Initializing Executor controller...");
 
     // First, a Kubernetes client must be obtained using the `kube` crate
     // The client will later be moved to the custom controller
@@ -31,12 +56,32 @@ pub async fn main() {
     let service_account_name = get_executor_service_account_name()
         .expect("Expected a valid executor service account name.");
 
+    // Fail fast on an upload config the download pods would reject.
+    UploadConfig::from_env().expect("Expected a valid upload configuration.");
+
+    // Fail fast on a finalizer name the API server would reject.
+    crate::finalizer::get_finalizer().expect("Expected a valid finalizer name.");
+
+    // Keep the per-phase gauges up to date for the metrics server.
+    let apis = get_watch_apis::<Executor>(kubernetes_client.clone(), &namespaces);
+    metrics::spawn_phase_gauges(apis, "Executor", |instance: &Executor| {
+        instance
+            .status
+            .as_ref()
+            .and_then(|status| status.phase)
+            .map(|phase| phase.to_string())
+    });
+
     // Preparation of resources used by the `kube_runtime::Controller`
-    let crd_api: Api<Executor> = Api::all(kubernetes_client.clone());
     let context: Arc<ContextData> = Arc::new(ContextData::new(
         kubernetes_client.clone(),
         service_account_name,
-        get_concurrency(),
+        Budget::new(
+            get_concurrency(),
+            get_watch_apis(kubernetes_client.clone(), &namespaces),
+        ),
+        RequeueIntervals::from_env(),
+        get_egress_configmap(),
     ));
 
     // The controller comes from the `kube_runtime` crate and manages the reconciliation process.
@@ -45,16 +90,29 @@ pub async fn main() {
     // - `kube::api::ListParams` to select the `Executor` resources with. Can be used for Executor filtering `Executor` resources before reconciliation,
     // - `reconcile` function with reconciliation logic to be called each time a resource of `Executor` kind is created/updated/deleted,
     // - `on_error` function to call whenever reconciliation fails.
-    println!("Starting Executor controller...");
-    Controller::new(crd_api.clone(), ListParams::default())
-        .run(reconcile, on_error, context)
+    // One Controller is run for each watched namespace, or a single
+    // cluster-wide Controller if no namespaces are specified.
+    info!(?namespaces, "Starting Executor controller...");
+    let mut stores = Vec::new();
+    let controllers = get_watch_apis::<Executor>(kubernetes_client.clone(), &namespaces)
+        .into_iter()
+        .map(|api| {
+            let controller = Controller::new(api, ListParams::default());
+            stores.push(controller.store());
+            controller.run(reconcile, on_error, context.clone()).boxed()
+        })
+        .collect::<Vec<_>>();
+    // Report the size of the caches for the metrics server.
+    metrics::spawn_cache_gauges(stores, "Executor");
+    stream::select_all(controllers)
         .for_each(|reconciliation_result| async move {
             match reconciliation_result {
                 Ok(video_resource) => {
-                    println!("Reconciliation successful. Resource: {:?}", video_resource);
+                    debug!(resource = ?video_resource, "Reconciliation successful");
                 }
                 Err(reconciliation_err) => {
-                    eprintln!("Reconciliation error: {:?}", reconciliation_err)
+                    metrics::controller_error("Executor", &reconciliation_err);
+                    warn!(error = ?reconciliation_err, "Reconciliation error")
                 }
             }
         })
@@ -69,7 +127,15 @@ struct ContextData {
     /// Service account name for the download pod. The download pod needs access to secrets.
     service_account_name: String,
 
-    concurrency: usize,
+    /// Limit on the number of download pods across all Downloads.
+    budget: Budget,
+
+    /// Intervals after which resources are requeued.
+    intervals: RequeueIntervals,
+
+    /// Name of the ConfigMap that accumulates monthly egress per
+    /// namespace, if enabled.
+    egress_configmap: Option<String>,
 }
 
 impl ContextData {
@@ -78,11 +144,19 @@ impl ContextData {
     /// # Arguments:
     /// - `client`: A Kubernetes client to make Kubernetes REST API requests with. Resources
     /// will be created and deleted with this client.
-    pub fn new(client: Client, service_account_name: String, concurrency: usize) -> Self {
+    pub fn new(
+        client: Client,
+        service_account_name: String,
+        budget: Budget,
+        intervals: RequeueIntervals,
+        egress_configmap: Option<String>,
+    ) -> Self {
         ContextData {
             client,
             service_account_name,
-            concurrency,
+            budget,
+            intervals,
+            egress_configmap,
         }
     }
 }
@@ -93,7 +167,7 @@ struct FailureOptions {
     recreate: bool,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 enum ReconcileAction {
     // The resource first appeared to the controller and requires
     // its phase to be set to "Pending" to indicate that reconciliation
@@ -119,12 +193,64 @@ enum ReconcileAction {
     // Download pod has failed with an error message.
     Failure(FailureOptions),
 
+    // The video was intentionally not downloaded per the user's policy.
+    Skipped {
+        policy: &'static str,
+        message: String,
+    },
+
+    // The download pod failed recently and should not be recreated
+    // until the remaining backoff has elapsed.
+    Backoff(Duration),
+
+    // The video was geo-blocked and the download pod should be recreated
+    // with the VPN connected to the region at the given index.
+    RetryRegion {
+        index: u32,
+        message: String,
+    },
+
+    // The video hit an age gate and the download pod should be
+    // recreated with the cookies, per allowWithCookies.
+    RetryWithCookies(String),
+
+    // The download pods reported bytes downloaded that have not
+    // yet been added to the namespace's egress accounting.
+    RecordEgress {
+        bytes: u64,
+        total: u64,
+    },
+
+    // The Executor and its parent Download succeeded, and the
+    // Executor's ttlSecondsAfterFinished elapsed.
+    Expire,
+
+    // Wait before checking the Executor's TTL again.
+    AwaitTtl(Duration),
+
     // Nothing to do (reconciliation successful)
     NoOp,
 }
 
 /// Main reconciliation loop for the `Executor` resource.
+#[instrument(
+    skip_all,
+    fields(
+        name = %instance.name_any(),
+        namespace = ?instance.namespace(),
+        generation = ?instance.metadata.generation,
+    )
+)]
 async fn reconcile(instance: Arc<Executor>, context: Arc<ContextData>) -> Result<Action, Error> {
+    // Observe the duration of this reconciliation.
+    let _timer = metrics::reconcile_started("Executor");
+
+    // Nothing is written once the leader Lease is being handed over.
+    let _writer = match drain::writer().await {
+        Some(writer) => writer,
+        None => return Ok(Action::await_change()),
+    };
+
     // The `Client` is shared -> a clone from the reference is obtained.
     let client: Client = context.client.clone();
 
@@ -144,6 +270,12 @@ async fn reconcile(instance: Arc<Executor>, context: Arc<ContextData>) -> Result
     // Name of the Executor resource is used to name the subresources as well.
     let name = instance.name_any();
 
+    // Simulate slow API responses and the loss of the download pod.
+    chaos::api_delay_fault().await;
+    if chaos::pod_deletion_fault() {
+        action::delete_pod(client.clone(), &instance).await?;
+    }
+
     // Read phase of the reconciliation loop.
     let action = determine_action(client.clone(), &instance).await?;
 
@@ -154,7 +286,7 @@ async fn reconcile(instance: Arc<Executor>, context: Arc<ContextData>) -> Result
         // deserve their own enum entries may come down to
         // how badly you want to see them in the log, and
         // that alone is a perfectly valid reason to do so.
-        println!("{}/{} ACTION: {:?}", namespace, name, action);
+        info!(action = ?action, "Reconciling");
     }
 
     // Write phase of the reconciliation loop.
@@ -167,16 +299,31 @@ async fn reconcile(instance: Arc<Executor>, context: Arc<ContextData>) -> Result
             Ok(Action::requeue(IMMEDIATELY))
         }
         ReconcileAction::Create(options) => {
-            if context.concurrency > 0 {
-                // Ensure the number of running pods is below the concurrency limit.
+            if drain::is_draining() {
+                // Leave the download pod to the replica that replaces this one.
+                return Ok(Action::requeue(context.intervals.throttled));
+            }
+            if !context.budget.try_acquire(&instance) {
+                // Wait for a slot to be freed by another download pod.
+                if get_executor_phase(&instance)? != ExecutorPhase::Throttled {
+                    action::throttled(client, &instance).await?;
+                }
+                return Ok(Action::requeue(context.intervals.throttled));
             }
-            
+
             // Apply the finalizer first. This way the Executor resource
             // won't be deleted before the download pod is deleted.
-            let instance = action::finalizer::add(client.clone(), &name, &namespace).await?;
+            let instance = match action::finalizer::add(client.clone(), &name, &namespace).await {
+                Ok(instance) => instance,
+                Err(e) => {
+                    // No pod was created, so the slot is free again.
+                    context.budget.release(&instance);
+                    return Err(e);
+                }
+            };
 
             // Create the download pod.
-            action::create_pod(
+            if let Err(e) = action::create_pod(
                 client.clone(),
                 &name,
                 &namespace,
@@ -184,18 +331,34 @@ async fn reconcile(instance: Arc<Executor>, context: Arc<ContextData>) -> Result
                 context.service_account_name.clone(),
                 options,
             )
-            .await?;
+            .await
+            {
+                context.budget.release(&instance);
+                // Surface the failure in the resource's Event history.
+                events::publish(
+                    client,
+                    &instance,
+                    EventType::Warning,
+                    "PodCreateFailed",
+                    Some(format!("failed to create download pod: {}", e)),
+                )
+                .await;
+                return Err(e);
+            }
 
             // Update the phase to reflect that the download has started.
-            action::starting(client, &instance).await?;
+            let starts = action::pod_created(client.clone(), &instance).await?;
+
+            // Warn if the download pod keeps getting recreated.
+            events::check_pod_restarts(client, &instance, "Executor", starts).await;
 
             // Download pod will take at least a couple seconds to start.
-            Ok(Action::requeue(Duration::from_secs(3)))
+            Ok(Action::requeue(context.intervals.progress))
         }
         ReconcileAction::Delete => {
             // Deletes any subresources related to this `Executor` resources. If and only if all subresources
             // are deleted, the finalizer is removed and Kubernetes is free to remove the `Executor` resource.
-            action::delete_pod(client.clone(), &name, &namespace).await?;
+            action::delete_pod(client.clone(), &instance).await?;
 
             // Once the pod is successfully removed, remove the finalizer to make it possible
             // for Kubernetes to delete the `Executor` resource (if needed)
@@ -217,30 +380,24 @@ async fn reconcile(instance: Arc<Executor>, context: Arc<ContextData>) -> Result
             match options.start_time {
                 // Post progress event with start time.
                 Some(start_time) => {
-                    action::progress(
-                        client.clone(),
-                        &instance,
-                        start_time,
-                    )
-                    .await?
+                    action::progress(client.clone(), &instance, start_time, options.progress)
+                        .await?
                 }
                 // Indicate that the downloads are starting.
-                None => {
-                    action::starting(client.clone(), &instance).await?
-                }
+                None => action::starting(client.clone(), &instance).await?,
             }
 
             // Requeue the resource to be reconciled again. Expect
             // the download(s) to take at least a couple seconds
             // before completion occurs.
-            Ok(Action::requeue(Duration::from_secs(3)))
+            Ok(Action::requeue(context.intervals.progress))
         }
         ReconcileAction::Succeeded => {
             // Update the status of the resource to reflect download completion.
             action::success(client.clone(), &instance).await?;
 
             // Delete the download pod before the finalizer is removed.
-            action::delete_pod(client.clone(), &name, &namespace).await?;
+            action::delete_pod(client.clone(), &instance).await?;
 
             // Remove the finalizer now that the download pod is gone.
             action::finalizer::delete(client, &name, &namespace).await?;
@@ -248,26 +405,88 @@ async fn reconcile(instance: Arc<Executor>, context: Arc<ContextData>) -> Result
             // Requeue immediately.
             Ok(Action::requeue(IMMEDIATELY))
         }
-        ReconcileAction::Failure(options) => {
-            // Update the status of the resource to communicate the error.
-            action::failure(
-                client.clone(),
-                &instance,
-                options.message,
-            )
-            .await?;
+        ReconcileAction::Skipped { policy, message } => {
+            // Update the status of the resource to explain why it was skipped.
+            action::skipped(client.clone(), &instance, policy, message).await?;
+
+            // Skipped is a final state, so the download pod can be deleted.
+            action::delete_pod(client.clone(), &instance).await?;
+
+            // Remove the finalizer now that the download pod is gone.
+            action::finalizer::delete(client, &name, &namespace).await?;
+
+            // Requeue only when the resource changes.
+            Ok(Action::await_change())
+        }
+        ReconcileAction::RetryRegion { index, message } => {
+            // Record the region to use for the next download pod.
+            action::retry_region(client.clone(), &instance, index, message).await?;
+
+            // Delete the download pod so it can be recreated.
+            action::delete_pod(client, &instance).await?;
 
+            // Display the error message for a short period of time
+            // before requeueing as a form of back-off.
+            Ok(Action::requeue(context.intervals.failure))
+        }
+        ReconcileAction::RetryWithCookies(message) => {
+            // Mount the cookies into the next download pod.
+            action::retry_with_cookies(client.clone(), &instance, message).await?;
+
+            // Delete the download pod so it can be recreated.
+            action::delete_pod(client, &instance).await?;
+
+            // Display the error message for a short period of time
+            // before requeueing as a form of back-off.
+            Ok(Action::requeue(context.intervals.failure))
+        }
+        ReconcileAction::Failure(options) => {
             if options.recreate {
+                // Record the retry so the backoff survives requeues.
+                let retries = get_retries(&instance) + 1;
+                let backoff = get_backoff(context.intervals.failure, retries);
+                action::retry(client.clone(), &instance, options.message, retries, backoff).await?;
                 // Delete the download pod so it can be recreated.
-                action::delete_pod(client, &name, &namespace).await?;
-                // Display the error message for a short period of time
-                // before requeueing as a form of back-off.
-                return Ok(Action::requeue(Duration::from_secs(5)));
+                action::delete_pod(client, &instance).await?;
+                // Don't recreate the pod until the backoff has elapsed.
+                return Ok(Action::requeue(backoff));
             }
 
+            // Update the status of the resource to communicate the error.
+            action::failure(client.clone(), &instance, options.message).await?;
+
             // Wait for the resource to change before requeueing.
             Ok(Action::await_change())
         }
+        ReconcileAction::RecordEgress { bytes, total } => {
+            // Add to the monthly counter first so a failure to do so
+            // is retried, as the status is left unchanged.
+            if let Some(configmap) = context.egress_configmap.as_deref() {
+                action::add_monthly_egress(client.clone(), &namespace, configmap, bytes).await?;
+            }
+            metrics::egress(&namespace, bytes);
+
+            // Mark the bytes as accounted for.
+            action::egress_accounted(client, &instance, total).await?;
+
+            // Requeue immediately to proceed with reconciliation.
+            Ok(Action::requeue(IMMEDIATELY))
+        }
+        ReconcileAction::Expire => {
+            // Delete the Executor now that it is no longer needed.
+            action::delete_executor(client, &name, &namespace).await?;
+
+            // The deletion is handled by the next reconciliation.
+            Ok(Action::await_change())
+        }
+        ReconcileAction::AwaitTtl(remaining) => {
+            // Check again once the TTL has elapsed.
+            Ok(Action::requeue(remaining))
+        }
+        ReconcileAction::Backoff(remaining) => {
+            // Wait out the rest of the backoff before recreating the pod.
+            Ok(Action::requeue(remaining))
+        }
         ReconcileAction::NoOp => {
             // Nothing to do (resource is fully reconciled).
             Ok(Action::await_change())
@@ -275,15 +494,153 @@ async fn reconcile(instance: Arc<Executor>, context: Arc<ContextData>) -> Result
     }
 }
 
-/// Returns true if the bucket has an object with the given key
-/// and the object is not empty (i.e. corrupt or incomplete).
-async fn bucket_has_obj(bucket: Bucket, key: &str) -> Result<bool, Error> {
+/// Returns true if the bucket has an object with the given key and
+/// the object is intact. If the Executor's download pod reported
+/// uploading the object, its size must match the reported size, and
+/// its checksum must match too if it was stored as user metadata.
+/// Otherwise the object only needs to be non-empty.
+async fn bucket_has_obj(bucket: Bucket, key: &str, instance: &Executor) -> Result<bool, Error> {
     let (head, code) = bucket.head_object(key).await?;
     if code == 404 {
         // The object does not exist
         return Ok(false);
     }
-    Ok(head.content_length.unwrap_or(0) > 0)
+    let size = head.content_length.unwrap_or(0);
+    let recorded = get_stored_objects(instance)
+        .into_iter()
+        .find(|object| object.bucket == bucket.name && object.key == key);
+    let recorded = match recorded {
+        Some(recorded) => recorded,
+        // Not uploaded by this Executor, so there's nothing to compare.
+        None => return Ok(size > 0),
+    };
+    if size < 0 || size as u64 != recorded.size {
+        warn!(
+            bucket = %bucket.name,
+            key,
+            size,
+            expected = recorded.size,
+            "Object size does not match the uploaded size"
+        );
+        return Ok(false);
+    }
+    let checksum = head
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get(CHECKSUM_METADATA_KEY));
+    match checksum {
+        Some(checksum) if *checksum != recorded.sha256 => {
+            warn!(
+                bucket = %bucket.name,
+                key,
+                "Object checksum does not match the uploaded checksum"
+            );
+            Ok(false)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Returns true if every GCS and Azure target of the content has the
+/// object and the object is intact, which is checked the same way as
+/// with [`bucket_has_obj`].
+async fn store_has_objs(
+    client: Client,
+    metadata: &serde_json::Value,
+    instance: &Executor,
+    content: ContentType,
+) -> Result<bool, Error> {
+    let namespace = instance.namespace().unwrap();
+    let outputs =
+        get_store_outputs(client, &namespace, &instance.spec.output, metadata, content).await?;
+    for (store, key) in outputs {
+        let object = match store.head_object(&key).await? {
+            Some(object) => object,
+            None => return Ok(false),
+        };
+        let recorded = get_stored_objects(instance)
+            .into_iter()
+            .find(|object| object.bucket == store.name() && object.key == key);
+        let intact = match recorded {
+            Some(recorded) => {
+                let checksum = object.metadata.get(CHECKSUM_METADATA_KEY);
+                object.size == recorded.size
+                    && checksum.map_or(true, |sha256| *sha256 == recorded.sha256)
+            }
+            // Not uploaded by this Executor, so there's nothing to compare.
+            None => object.size > 0,
+        };
+        if !intact {
+            warn!(
+                bucket = %store.name(),
+                key,
+                "{} object does not match the uploaded object",
+                store.service()
+            );
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns true if the Executor recorded the file of every volume
+/// target of the content. The controller doesn't mount the claims, so
+/// the files themselves can't be checked.
+async fn volume_has_objs(
+    client: Client,
+    metadata: &serde_json::Value,
+    instance: &Executor,
+    content: ContentType,
+) -> Result<bool, Error> {
+    let namespace = instance.namespace().unwrap();
+    let outputs =
+        get_volume_outputs(client, &namespace, &instance.spec.output, metadata, content).await?;
+    let recorded = get_stored_objects(instance);
+    Ok(outputs.iter().all(|(dir, path)| {
+        recorded
+            .iter()
+            .any(|object| object.bucket == dir.name && object.key == *path)
+    }))
+}
+
+/// Returns true if the Executor recorded the document or GridFS file
+/// of every MongoDB target of the content. The controller doesn't link
+/// a MongoDB driver, so the documents themselves can't be checked.
+async fn mongodb_has_objs(
+    client: Client,
+    metadata: &serde_json::Value,
+    instance: &Executor,
+    content: ContentType,
+) -> Result<bool, Error> {
+    let namespace = instance.namespace().unwrap();
+    let outputs =
+        get_mongodb_outputs(client, &namespace, &instance.spec.output, metadata, content).await?;
+    let recorded = get_stored_objects(instance);
+    Ok(outputs.iter().all(|(collection, id)| {
+        recorded
+            .iter()
+            .any(|object| object.bucket == collection.name && object.key == *id)
+    }))
+}
+
+/// Returns true if the Executor recorded the key of every Redis target
+/// of the content. A target's script may write to other keys than its
+/// own, so the keys themselves aren't checked.
+async fn redis_has_objs(
+    client: Client,
+    metadata: &serde_json::Value,
+    instance: &Executor,
+    content: ContentType,
+) -> Result<bool, Error> {
+    let namespace = instance.namespace().unwrap();
+    let outputs =
+        get_redis_outputs(client, &namespace, &instance.spec.output, metadata, content).await?;
+    let recorded = get_stored_objects(instance);
+    Ok(outputs.iter().all(|(server, key)| {
+        recorded
+            .iter()
+            .any(|object| object.bucket == server.name && object.key == *key)
+    }))
 }
 
 /// Returns true if the video needs to be downloaded.
@@ -292,6 +649,27 @@ async fn needs_video_download(
     metadata: &serde_json::Value,
     instance: &Executor,
 ) -> Result<bool, Error> {
+    if !wants_content(&instance.spec.content, ContentType::Audiovisual) {
+        // The user does not want to store the video, so there
+        // is no reason to check storage for its existence.
+        return Ok(false);
+    }
+    if !store_has_objs(client.clone(), metadata, instance, ContentType::Audiovisual).await? {
+        // A GCS, Azure, SFTP, or WebDAV target is missing the video.
+        return Ok(true);
+    }
+    if !volume_has_objs(client.clone(), metadata, instance, ContentType::Audiovisual).await? {
+        // A volume target is missing the video.
+        return Ok(true);
+    }
+    if !mongodb_has_objs(client.clone(), metadata, instance, ContentType::Audiovisual).await? {
+        // A MongoDB target is missing the video.
+        return Ok(true);
+    }
+    if !redis_has_objs(client.clone(), metadata, instance, ContentType::Audiovisual).await? {
+        // A Redis target is missing the video.
+        return Ok(true);
+    }
     let (bucket, key) = match get_video_output(client, metadata, instance).await? {
         // Resource is requesting video output.
         Some(v) => v,
@@ -301,7 +679,7 @@ async fn needs_video_download(
         None => return Ok(false),
     };
     // Check if the object exists and is not empty.
-    bucket_has_obj(bucket, &key).await
+    bucket_has_obj(bucket, &key, instance).await
 }
 
 /// Returns true if the thumbnail needs to be downloaded.
@@ -310,6 +688,26 @@ async fn needs_thumbnail_download(
     metadata: &serde_json::Value,
     instance: &Executor,
 ) -> Result<bool, Error> {
+    if !wants_content(&instance.spec.content, ContentType::Thumbnail) {
+        // The user does not want to store the thumbnail.
+        return Ok(false);
+    }
+    if !store_has_objs(client.clone(), metadata, instance, ContentType::Thumbnail).await? {
+        // A GCS, Azure, SFTP, or WebDAV target is missing the thumbnail.
+        return Ok(true);
+    }
+    if !volume_has_objs(client.clone(), metadata, instance, ContentType::Thumbnail).await? {
+        // A volume target is missing the thumbnail.
+        return Ok(true);
+    }
+    if !mongodb_has_objs(client.clone(), metadata, instance, ContentType::Thumbnail).await? {
+        // A MongoDB target is missing the thumbnail.
+        return Ok(true);
+    }
+    if !redis_has_objs(client.clone(), metadata, instance, ContentType::Thumbnail).await? {
+        // A Redis target is missing the thumbnail.
+        return Ok(true);
+    }
     let (bucket, key) = match get_thumbnail_output(client, metadata, instance).await? {
         // Resource is requesting thumbnail output.
         Some(v) => v,
@@ -317,39 +715,84 @@ async fn needs_thumbnail_download(
         None => return Ok(false),
     };
     // Check if the object exists and is not empty.
-    bucket_has_obj(bucket, &key).await
+    bucket_has_obj(bucket, &key, instance).await
 }
 
 /// Returns the download pod if it exists, or None if it does not.
-async fn get_download_pod(client: Client, instance: &Executor) -> Result<Option<Pod>, kube::Error> {
-    let pod_api: Api<Pod> = Api::namespaced(client, &instance.namespace().unwrap());
-    let pod_name = instance.name_any();
-    match pod_api.get(&pod_name).await {
-        Ok(pod) => Ok(Some(pod)),
-        Err(e) => match &e {
-            kube::Error::Api(ae) => match ae.code {
-                // If the pod does not exist, return None
-                404 => Ok(None),
-                // If the pod exists but we can't access it, return an error
-                _ => Err(e),
-            },
-            _ => Err(e),
-        },
+/// The pod is found by its owner rather than by name.
+async fn get_download_pod(client: Client, instance: &Executor) -> Result<Option<Pod>, Error> {
+    get_owned_pod(
+        client,
+        &instance.namespace().unwrap(),
+        &instance.uid().unwrap(),
+    )
+    .await
+}
+
+/// Kinds of the metadata targets the download pod publishes to. The
+/// info json of the S3 targets is written by the Download controller.
+const PUBLISHED_METADATA_KINDS: &[&str] = &[
+    "SqlTarget",
+    "MongoDBTarget",
+    "RedisTarget",
+    "KafkaTarget",
+    "NatsTarget",
+];
+
+/// Returns true if the download pod needs to publish the metadata. Unlike
+/// the video and thumbnail, whose objects are found in storage, the
+/// metadata is published once, by the pod that takes the Executor to
+/// Succeeded, so it's no longer needed once the Executor has succeeded.
+fn needs_metadata_publish(instance: &Executor, target: Option<&TargetSpec>) -> bool {
+    if !wants_content(&instance.spec.content, ContentType::Metadata) {
+        // The user does not want to store the metadata.
+        return false;
+    }
+    let phase = instance.status.as_ref().and_then(|status| status.phase);
+    if phase == Some(ExecutorPhase::Succeeded) {
+        return false;
     }
+    target
+        .and_then(|target| target.metadata.as_ref())
+        .map_or(false, |refs| {
+            refs.iter()
+                .any(|target_ref| PUBLISHED_METADATA_KINDS.contains(&target_ref.kind.as_str()))
+        })
 }
 
-/// Returns a tuple of booleans indicating whether the video
-/// and/or the thumbnail should be downloaded. Both checks
-/// are made concurrently for maximum performance.
-async fn check_downloads(client: Client, instance: &Executor) -> Result<(bool, bool), Error> {
-    let metadata: serde_json::Value = instance.spec.metadata.parse()?;
-    let result = tokio::join!(
-        needs_video_download(client.clone(), &metadata, instance),
-        needs_thumbnail_download(client, &metadata, instance),
-    );
-    let download_video = result.0?;
-    let download_thumbnail = result.1?;
-    Ok((download_video, download_thumbnail))
+/// Returns which of the video and the thumbnail should be downloaded,
+/// and whether the metadata should be published. The video and thumbnail
+/// checks are made concurrently for maximum performance. If the Executor
+/// is assigned a batch of entities, a download is necessary if any
+/// entity in the batch requires it.
+async fn check_downloads(client: Client, instance: &Executor) -> Result<DownloadPodOptions, Error> {
+    let target = Api::<Target>::namespaced(client.clone(), &instance.namespace().unwrap())
+        .get_opt(&instance.spec.output)
+        .await?;
+    let mut options = DownloadPodOptions {
+        download_video: false,
+        download_thumbnail: false,
+        publish_metadata: needs_metadata_publish(instance, target.as_ref().map(|t| &t.spec)),
+    };
+    for metadata in get_job_metadata(instance) {
+        let metadata: serde_json::Value = metadata.parse()?;
+        let result = tokio::join!(
+            needs_video_download(client.clone(), &metadata, instance),
+            needs_thumbnail_download(client.clone(), &metadata, instance),
+        );
+        options.download_video |= result.0?;
+        options.download_thumbnail |= result.1?;
+        if options.download_video && options.download_thumbnail {
+            // No need to check the rest of the batch.
+            break;
+        }
+    }
+    Ok(options)
+}
+
+/// Returns true if the download pod has anything to do.
+fn needs_download_pod(options: &DownloadPodOptions) -> bool {
+    options.download_video || options.download_thumbnail || options.publish_metadata
 }
 
 /// Determines the action to take after all downloads have completed.
@@ -375,9 +818,128 @@ async fn determine_download_success_action(
     }
 }
 
+/// Determines the action to take for a download pod that failed with
+/// a structured failure reason, in accordance with the user's policy.
+fn determine_failure_action(instance: &Executor, pod: &Pod) -> Option<ReconcileAction> {
+    let failure = get_pod_failure(pod)?;
+    match failure.reason {
+        FailureReason::AgeRestricted => {
+            Some(match instance.spec.age_restricted.unwrap_or_default() {
+                AgeRestrictedPolicy::Skip => ReconcileAction::Skipped {
+                    policy: AGE_RESTRICTED_POLICY,
+                    message: format!("skipped age-restricted video: {}", failure.message),
+                },
+                AgeRestrictedPolicy::AllowWithCookies
+                    if instance.spec.cookies_secret.is_some() && !has_cookies(instance) =>
+                {
+                    ReconcileAction::RetryWithCookies(format!(
+                        "video is age-restricted, retrying with cookies: {}",
+                        failure.message
+                    ))
+                }
+                // Retrying will hit the same age gate, so don't recreate.
+                AgeRestrictedPolicy::AllowWithCookies | AgeRestrictedPolicy::Fail => {
+                    ReconcileAction::Failure(FailureOptions {
+                        message: failure.message,
+                        recreate: false,
+                    })
+                }
+            })
+        }
+        FailureReason::GeoBlocked => Some(match instance.spec.geo_blocked.unwrap_or_default() {
+            GeoBlockedPolicy::Skip => ReconcileAction::Skipped {
+                policy: GEO_BLOCKED_POLICY,
+                message: format!("skipped geo-blocked video: {}", failure.message),
+            },
+            GeoBlockedPolicy::RetryOtherRegion => {
+                // The first attempt uses the VPN's default region, after
+                // which each of the configured regions is tried in order.
+                let index = instance
+                    .status
+                    .as_ref()
+                    .and_then(|status| status.geo_region_index)
+                    .map_or(0, |index| index + 1);
+                let regions = instance.spec.geo_regions.as_deref().unwrap_or_default();
+                match regions.get(index as usize) {
+                    Some(region) => ReconcileAction::RetryRegion {
+                        index,
+                        message: format!(
+                            "video is geo-blocked, retrying in region {}: {}",
+                            region, failure.message
+                        ),
+                    },
+                    None => ReconcileAction::Failure(FailureOptions {
+                        message: format!(
+                            "video is geo-blocked in all regions: {}",
+                            failure.message
+                        ),
+                        recreate: false,
+                    }),
+                }
+            }
+            // Retrying will hit the same geo-block, so don't recreate.
+            GeoBlockedPolicy::Fail => ReconcileAction::Failure(FailureOptions {
+                message: failure.message,
+                recreate: false,
+            }),
+        }),
+        // Timeouts are retried like any other failure, unless the exit
+        // code says the executor is misconfigured, which no retry fixes.
+        FailureReason::DeadlineExceeded | FailureReason::Unknown => match get_pod_exit_code(pod) {
+            Some(code) if !code.is_retryable() => Some(ReconcileAction::Failure(FailureOptions {
+                message: format!("{}: {}", code, failure.message),
+                recreate: false,
+            })),
+            _ => None,
+        },
+    }
+}
+
+/// Returns true if the download pods use the cookies since the video
+/// hit an age gate.
+fn has_cookies(instance: &Executor) -> bool {
+    instance
+        .status
+        .as_ref()
+        .and_then(|status| status.with_cookies)
+        .unwrap_or(false)
+}
+
+/// Returns true if the download pod failed because its deadline passed,
+/// whether the executor reported it or Kubernetes killed the pod.
+fn is_timed_out(pod: &Pod) -> bool {
+    get_pod_failure(pod).map(|failure| failure.reason) == Some(FailureReason::DeadlineExceeded)
+        || pod
+            .status
+            .as_ref()
+            .and_then(|status| status.reason.as_deref())
+            == Some("DeadlineExceeded")
+}
+
+/// Returns the progress of the running download pod, scraped from the
+/// pod if it serves it, or else from the annotation it reports it with.
+/// A failed scrape is only logged, and the last progress is kept.
+async fn get_pod_progress(instance: &Executor, pod: &Pod) -> Option<DownloadProgress> {
+    let port = match get_progress_port() {
+        Ok(Some(port)) => port,
+        _ => return get_download_progress(instance),
+    };
+    let pod_ip = pod.status.as_ref()?.pod_ip.as_deref()?;
+    match scrape_progress(pod_ip, port).await {
+        Ok(progress) => progress,
+        Err(e) => {
+            debug!(error = %e, pod = %pod.name_any(), "Failed to scrape download progress");
+            None
+        }
+    }
+}
+
 /// Determines the action to take given that the download pod
 /// exists and we need to check its status.
-async fn determine_download_pod_action(pod: Pod) -> Result<Option<ReconcileAction>, Error> {
+async fn determine_download_pod_action(
+    instance: &Executor,
+    pod: Pod,
+) -> Result<Option<ReconcileAction>, Error> {
     // Check the status of the download pod.
     let status: &PodStatus = pod
         .status
@@ -401,13 +963,15 @@ async fn determine_download_pod_action(pod: Pod) -> Result<Option<ReconcileActio
             // Mark the Executor phase as being in-progress.
             Ok(Some(ReconcileAction::Progress(ProgressOptions {
                 start_time: None,
+                progress: None,
             })))
         }
         "Running" => {
-            // Download is in progress.
-            // TODO: report verbose download statistics.
+            // Download is in progress. Include the progress
+            // reported by the download pod, if any.
             Ok(Some(ReconcileAction::Progress(ProgressOptions {
                 start_time: pod.creation_timestamp(),
+                progress: get_pod_progress(instance, &pod).await,
             })))
         }
         "Succeeded" => {
@@ -415,9 +979,25 @@ async fn determine_download_pod_action(pod: Pod) -> Result<Option<ReconcileActio
             Ok(Some(ReconcileAction::Succeeded))
         }
         _ => {
+            // Act on the failure reason reported by the executor, if any.
+            if let Some(action) = determine_failure_action(instance, &pod) {
+                return Ok(Some(action));
+            }
+            let state = if is_timed_out(&pod) {
+                "timed out".to_owned()
+            } else {
+                format!("is in phase {}", phase)
+            };
+            let retries = get_retries(instance);
+            if retries >= get_max_retries(instance) {
+                // Give up and leave the pod in place for inspection.
+                return Ok(Some(ReconcileAction::Failure(FailureOptions {
+                    message: format!("download pod {} after {} retries", state, retries),
+                    recreate: false,
+                })));
+            }
             // Report error, delete pod, and re-create.
-            // TODO: find way to extract a verbose error message from the pod.
-            let message = format!("download pod is in phase {}", phase);
+            let message = format!("download pod {}", state);
             Ok(Some(ReconcileAction::Failure(FailureOptions {
                 message,
                 recreate: true,
@@ -442,28 +1022,136 @@ async fn determine_download_action(
         // Download pod exists, no reason to check storage
         // as the results of `check_downloads` are cached
         // in the pod's spec.
-        Some(pod) => determine_download_pod_action(pod).await,
+        Some(pod) => determine_download_pod_action(instance, pod).await,
         // Download pod does not exist, check storage to see
         // which files, if any, require downloading.
         None => {
-            // Determine which parts are already downloaded.
-            let (download_video, download_thumbnail) =
-                check_downloads(client.clone(), instance).await?;
-            if !download_video && !download_thumbnail {
+            // Determine which parts are already downloaded, and whether
+            // the metadata is yet to be published.
+            let options = check_downloads(client.clone(), instance).await?;
+            if !needs_download_pod(&options) {
                 // All downloads have completed successfully. Note that
                 // This is the only branch that has the ability to return
                 // None, signaling reconciliation is complete.
                 return determine_download_success_action(client, instance).await;
             }
             // Create the download pod, downloading only the requested parts.
-            Ok(Some(ReconcileAction::Create(DownloadPodOptions {
-                download_video,
-                download_thumbnail,
-            })))
+            Ok(Some(ReconcileAction::Create(options)))
         }
     }
 }
 
+/// Upper bound on the delay before a failed download pod is recreated.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Returns the number of times the download pod has been recreated.
+fn get_retries(instance: &Executor) -> u32 {
+    instance
+        .status
+        .as_ref()
+        .and_then(|status| status.retries)
+        .unwrap_or(0)
+}
+
+/// Returns the number of retries allowed before the Executor
+/// enters a terminal Failed phase.
+fn get_max_retries(instance: &Executor) -> u32 {
+    instance.spec.max_retries.unwrap_or(DEFAULT_MAX_RETRIES)
+}
+
+/// Returns the delay before the download pod is recreated for the
+/// given retry, which doubles with each retry up to [`MAX_BACKOFF`].
+fn get_backoff(base: Duration, retries: u32) -> Duration {
+    let factor = 2u32.saturating_pow(retries.saturating_sub(1));
+    base.saturating_mul(factor).min(MAX_BACKOFF)
+}
+
+/// Returns the time left before the failed download pod may be
+/// recreated, or None if the backoff has elapsed.
+fn get_remaining_backoff(instance: &Executor) -> Option<Duration> {
+    let status = instance.status.as_ref()?;
+    let last_failure_time =
+        chrono::DateTime::parse_from_rfc3339(status.last_failure_time.as_deref()?).ok()?;
+    let backoff = chrono::Duration::seconds(status.backoff_seconds? as i64);
+    let remaining = last_failure_time + backoff - chrono::Utc::now();
+    remaining
+        .to_std()
+        .ok()
+        .filter(|remaining| !remaining.is_zero())
+}
+
+/// Returns the action to record the bytes reported by the download
+/// pods that have not yet been accounted for, if any.
+fn determine_egress_action(instance: &Executor) -> Option<ReconcileAction> {
+    let total = get_bytes_downloaded(instance);
+    let accounted = instance
+        .status
+        .as_ref()
+        .and_then(|status| status.bytes_accounted)
+        .unwrap_or(0);
+    if total <= accounted {
+        return None;
+    }
+    Some(ReconcileAction::RecordEgress {
+        bytes: total - accounted,
+        total,
+    })
+}
+
+/// Minimum delay before checking again whether an expired Executor's
+/// parent Download has succeeded.
+const MIN_TTL_RECHECK: Duration = Duration::from_secs(60);
+
+/// Returns the action to delete the Executor once its TTL after
+/// succeeding has elapsed, if it has a TTL.
+async fn determine_ttl_action(
+    client: Client,
+    instance: &Executor,
+) -> Result<Option<ReconcileAction>, Error> {
+    let remaining = match get_remaining_ttl(
+        instance
+            .status
+            .as_ref()
+            .and_then(|status| status.completion_time.as_deref()),
+        instance.spec.ttl_seconds_after_finished,
+    ) {
+        Some(remaining) => remaining,
+        None => return Ok(None),
+    };
+    if !remaining.is_zero() {
+        return Ok(Some(ReconcileAction::AwaitTtl(remaining)));
+    }
+    if !is_download_finished(client, instance).await? {
+        // Deleting the Executor before its Download has succeeded
+        // would cause the Download controller to recreate it.
+        let ttl = Duration::from_secs(instance.spec.ttl_seconds_after_finished.unwrap_or(0) as u64);
+        return Ok(Some(ReconcileAction::AwaitTtl(ttl.max(MIN_TTL_RECHECK))));
+    }
+    Ok(Some(ReconcileAction::Expire))
+}
+
+/// Returns `true` if the Executor's parent Download has succeeded
+/// or no longer exists.
+async fn is_download_finished(client: Client, instance: &Executor) -> Result<bool, Error> {
+    let owner = instance
+        .owner_references()
+        .iter()
+        .find(|oref| oref.kind == "Download");
+    let owner = match owner {
+        Some(owner) => owner,
+        None => return Ok(true),
+    };
+    let api: Api<Download> = Api::namespaced(client, &instance.namespace().unwrap());
+    Ok(match api.get_opt(&owner.name).await? {
+        Some(download) => download
+            .status
+            .as_ref()
+            .and_then(|status| status.phase)
+            .map_or(false, |phase| phase == DownloadPhase::Succeeded),
+        None => true,
+    })
+}
+
 /// needs_pending returns true if the `Executor` resource
 /// requires a status update to set the phase to Pending.
 /// This should be the first action for any managed resource.
@@ -487,11 +1175,33 @@ async fn determine_action(client: Client, instance: &Executor) -> Result<Reconci
         return Ok(ReconcileAction::Pending);
     }
 
+    // Account for any bytes reported by the download pods. This is
+    // done before all else so the final report of a pod is recorded
+    // even after the Executor reaches a terminal phase.
+    if let Some(action) = determine_egress_action(instance) {
+        return Ok(action);
+    }
+
+    match get_executor_phase(instance)? {
+        ExecutorPhase::Skipped => {
+            // The video was intentionally skipped and there
+            // is nothing left to do.
+            return Ok(ReconcileAction::NoOp);
+        }
+        ExecutorPhase::Failed => {
+            if let Some(remaining) = get_remaining_backoff(instance) {
+                // The download pod failed too recently to be recreated.
+                return Ok(ReconcileAction::Backoff(remaining));
+            }
+        }
+        _ => {}
+    }
+
     // Check if the video and/or thumbnail need to
     // be downloaded. Both of these operations must
     // occur behind a VPN connection, so we will do
     // both tasks in the same pod.
-    if let Some(action) = determine_download_action(client, instance).await? {
+    if let Some(action) = determine_download_action(client.clone(), instance).await? {
         return Ok(action);
     };
 
@@ -499,19 +1209,158 @@ async fn determine_action(client: Client, instance: &Executor) -> Result<Reconci
     // Any additional actions that occur after the
     // Executor is completed will go here.
 
+    // Clean up the Executor per its ttlSecondsAfterFinished.
+    if let Some(action) = determine_ttl_action(client, instance).await? {
+        return Ok(action);
+    }
+
     // Everything is done and there is nothing to do.
     Ok(ReconcileAction::NoOp)
 }
 
 /// Actions to be taken when a reconciliation fails - for whatever reason.
-/// Prints out the error to `stderr` and requeues the resource for another reconciliation after
-/// five seconds.
+/// Logs the error and requeues the resource for another reconciliation after
+/// the configured error backoff.
 ///
 /// # Arguments
 /// - `instance`: The erroneous resource.
 /// - `error`: A reference to the `kube::Error` that occurred during reconciliation.
-/// - `_context`: Unused argument. Context Data "injected" automatically by kube-rs.
-fn on_error(instance: Arc<Executor>, error: &Error, _context: Arc<ContextData>) -> Action {
-    eprintln!("Reconciliation error:\n{:?}.\n{:?}", error, instance);
-    Action::requeue(Duration::from_secs(5))
+/// - `context`: Context Data "injected" automatically by kube-rs.
+fn on_error(instance: Arc<Executor>, error: &Error, context: Arc<ContextData>) -> Action {
+    metrics::reconcile_failed("Executor");
+    error!(
+        name = %instance.name_any(),
+        namespace = ?instance.namespace(),
+        error = ?error,
+        "Reconciliation error"
+    );
+    Action::requeue(context.intervals.error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ytdl_types::{ExecutorSpec, ExecutorStatus, TargetRef};
+
+    /// Returns an Executor that wants the content, in the phase.
+    fn executor(content: Option<Vec<ContentType>>, phase: ExecutorPhase) -> Executor {
+        let mut instance = Executor::new(
+            "test",
+            ExecutorSpec {
+                content,
+                ..ExecutorSpec::default()
+            },
+        );
+        instance.status = Some(ExecutorStatus {
+            phase: Some(phase),
+            ..ExecutorStatus::default()
+        });
+        instance
+    }
+
+    /// Returns a Target that stores the metadata in a target of each kind.
+    fn target(kinds: &[&str]) -> TargetSpec {
+        TargetSpec {
+            metadata: Some(
+                kinds
+                    .iter()
+                    .map(|kind| TargetRef {
+                        kind: kind.to_string(),
+                        name: "test".to_owned(),
+                    })
+                    .collect(),
+            ),
+            ..TargetSpec::default()
+        }
+    }
+
+    /// Returns the options of the pod created for an Executor whose
+    /// video and thumbnail need no download.
+    fn get_options(instance: &Executor, target: &TargetSpec) -> DownloadPodOptions {
+        DownloadPodOptions {
+            download_video: false,
+            download_thumbnail: false,
+            publish_metadata: needs_metadata_publish(instance, Some(target)),
+        }
+    }
+
+    /// Asserts that a metadata-only Executor whose metadata is stored in
+    /// targets of the kinds gets a pod to publish it.
+    fn assert_publishes_metadata(kinds: &[&str]) {
+        let instance = executor(Some(vec![ContentType::Metadata]), ExecutorPhase::Pending);
+        let options = get_options(&instance, &target(kinds));
+        assert!(options.publish_metadata, "{:?} aren't published", kinds);
+        assert!(needs_download_pod(&options));
+    }
+
+    #[test]
+    fn metadata_only_executor_creates_pod() {
+        let instance = executor(Some(vec![ContentType::Metadata]), ExecutorPhase::Pending);
+        let options = get_options(&instance, &target(&["KafkaTarget"]));
+        assert!(options.publish_metadata);
+        assert!(needs_download_pod(&options));
+    }
+
+    #[test]
+    fn stored_content_still_publishes_metadata() {
+        let instance = executor(None, ExecutorPhase::Pending);
+        let options = get_options(&instance, &target(&["KafkaTarget"]));
+        assert!(needs_download_pod(&options));
+    }
+
+    #[test]
+    fn succeeded_executor_creates_no_pod() {
+        let instance = executor(None, ExecutorPhase::Succeeded);
+        let options = get_options(&instance, &target(&["KafkaTarget"]));
+        assert!(!needs_download_pod(&options));
+    }
+
+    #[test]
+    fn failed_executor_publishes_metadata_again() {
+        let instance = executor(None, ExecutorPhase::Failed);
+        let target = target(&["KafkaTarget"]);
+        assert!(needs_metadata_publish(&instance, Some(&target)));
+    }
+
+    #[test]
+    fn excluded_metadata_creates_no_pod() {
+        let instance = executor(Some(vec![ContentType::Thumbnail]), ExecutorPhase::Pending);
+        let options = get_options(&instance, &target(&["KafkaTarget"]));
+        assert!(!needs_download_pod(&options));
+    }
+
+    #[test]
+    fn unpublished_metadata_kinds_create_no_pod() {
+        let instance = executor(None, ExecutorPhase::Pending);
+        let target = target(&["S3Target"]);
+        assert!(!needs_metadata_publish(&instance, Some(&target)));
+        assert!(!needs_metadata_publish(&instance, None));
+    }
+
+    #[test]
+    fn nats_metadata_creates_pod() {
+        assert_publishes_metadata(&["NatsTarget"]);
+    }
+
+    #[test]
+    fn sql_metadata_creates_pod() {
+        assert_publishes_metadata(&["SqlTarget"]);
+    }
+
+    #[test]
+    fn mixed_metadata_targets_create_pod() {
+        // The S3 target's info json is written by the Download controller,
+        // but the database and message targets still need the pod.
+        assert_publishes_metadata(&["S3Target", "SqlTarget", "MongoDBTarget", "NatsTarget"]);
+    }
+
+    #[test]
+    fn mongodb_metadata_creates_pod() {
+        assert_publishes_metadata(&["MongoDBTarget"]);
+    }
+
+    #[test]
+    fn redis_metadata_creates_pod() {
+        assert_publishes_metadata(&["RedisTarget"]);
+    }
 }
@@ -0,0 +1,98 @@
+//! The finalizer that keeps Downloads and Executors around until the
+//! controllers have cleaned up after them. Other controllers may add
+//! finalizers of their own to the same resources, so only the ytdl
+//! finalizer is ever added or removed, with JSON patches that fail
+//! rather than clobber the list if it changed in the meantime.
+use json_patch::Patch as JsonPatch;
+use kube::{
+    api::{Api, Patch, PatchParams},
+    Resource, ResourceExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+use std::fmt::Debug;
+use ytdl_common::Error;
+
+/// Environment variable with the name of the finalizer, which lets
+/// multiple installations in the same cluster tell theirs apart.
+pub const FINALIZER_ENV: &str = "FINALIZER_NAME";
+
+/// Default name of the finalizer.
+pub const DEFAULT_FINALIZER: &str = "ytdl.beebs.dev/finalizer";
+
+/// Returns the name of the finalizer. Fails if the configured name
+/// is not qualified with a domain, e.g. `example.com/finalizer`.
+pub fn get_finalizer() -> Result<String, Error> {
+    let finalizer = match std::env::var(FINALIZER_ENV) {
+        Ok(finalizer) if !finalizer.is_empty() => finalizer,
+        _ => return Ok(DEFAULT_FINALIZER.to_owned()),
+    };
+    match finalizer.split_once('/') {
+        Some((domain, name)) if domain.contains('.') && !name.is_empty() => Ok(finalizer),
+        _ => Err(Error::UserInputError(format!(
+            "{} must be a domain-qualified name such as {}, got {}",
+            FINALIZER_ENV, DEFAULT_FINALIZER, finalizer
+        ))),
+    }
+}
+
+/// Adds the finalizer to the named resource, leaving any other
+/// finalizers in place. Has no effect if it's already there.
+pub async fn add<K>(api: &Api<K>, name: &str) -> Result<K, Error>
+where
+    K: Resource + Clone + DeserializeOwned + Serialize + Debug,
+    <K as Resource>::DynamicType: Default,
+{
+    let finalizer = get_finalizer()?;
+    let instance = api.get(name).await?;
+    let finalizers = instance.finalizers();
+    if finalizers.contains(&finalizer) {
+        return Ok(instance);
+    }
+    // The list may not exist yet, so the resource version guards
+    // against finalizers added since the resource was read.
+    let version = json!({
+        "op": "test",
+        "path": "/metadata/resourceVersion",
+        "value": instance.resource_version(),
+    });
+    let patch = if finalizers.is_empty() {
+        json!([version, { "op": "add", "path": "/metadata/finalizers", "value": [finalizer] }])
+    } else {
+        json!([version, { "op": "add", "path": "/metadata/finalizers/-", "value": finalizer }])
+    };
+    apply(api, name, patch).await
+}
+
+/// Removes the finalizer from the named resource, leaving any other
+/// finalizers in place. Has no effect if it's not there.
+pub async fn remove<K>(api: &Api<K>, name: &str) -> Result<K, Error>
+where
+    K: Resource + Clone + DeserializeOwned + Serialize + Debug,
+    <K as Resource>::DynamicType: Default,
+{
+    let finalizer = get_finalizer()?;
+    let instance = api.get(name).await?;
+    let index = match instance.finalizers().iter().position(|f| *f == finalizer) {
+        Some(index) => index,
+        None => return Ok(instance),
+    };
+    let path = format!("/metadata/finalizers/{}", index);
+    let patch = json!([
+        { "op": "test", "path": path, "value": finalizer },
+        { "op": "remove", "path": path },
+    ]);
+    apply(api, name, patch).await
+}
+
+/// Applies the JSON patch to the named resource.
+async fn apply<K>(api: &Api<K>, name: &str, patch: serde_json::Value) -> Result<K, Error>
+where
+    K: Resource + Clone + DeserializeOwned + Serialize + Debug,
+    <K as Resource>::DynamicType: Default,
+{
+    let patch: JsonPatch = serde_json::from_value(patch)?;
+    Ok(api
+        .patch(name, &PatchParams::default(), &Patch::<()>::Json(patch))
+        .await?)
+}
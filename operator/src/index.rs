@@ -0,0 +1,166 @@
+use futures::StreamExt;
+use kube::{
+    api::ListParams,
+    runtime::watcher::{self, watcher},
+    Api, Resource, ResourceExt,
+};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+use tracing::warn;
+
+/// Children keyed by name, for each parent uid.
+type Children<K> = HashMap<String, HashMap<String, Arc<K>>>;
+
+/// In-memory cache of resources indexed by the uid of their controller
+/// owner, similar to a field indexer in controller-runtime. This lets
+/// a parent's reconciler look up its children without a round-trip to
+/// the API server for each one.
+pub struct OwnerIndex<K> {
+    children: Arc<RwLock<Children<K>>>,
+
+    /// Number of watchers that completed their initial list.
+    synced: Arc<AtomicUsize>,
+
+    /// Total number of watchers, one for each watched namespace.
+    watchers: usize,
+}
+
+impl<K> OwnerIndex<K>
+where
+    K: Resource + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+    <K as Resource>::DynamicType: Default,
+{
+    /// Starts watching the resources visible to the given Apis, which
+    /// correspond to the watched namespaces.
+    pub fn new(apis: Vec<Api<K>>) -> Self {
+        let children: Arc<RwLock<Children<K>>> = Default::default();
+        let synced = Arc::new(AtomicUsize::new(0));
+        let watchers = apis.len();
+        for api in apis {
+            let children = children.clone();
+            let synced = synced.clone();
+            tokio::spawn(async move {
+                // Objects from this watcher, so a restart only replaces
+                // the ones from the same namespace.
+                let mut mine: HashMap<(String, String), String> = HashMap::new();
+                let mut initial = true;
+                let mut stream = watcher(api, ListParams::default()).boxed();
+                while let Some(event) = stream.next().await {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(e) => {
+                            // The watcher will retry on the next poll.
+                            warn!(error = %e, "Failed to watch children for the owner index");
+                            continue;
+                        }
+                    };
+                    let mut children = children.write().unwrap();
+                    match event {
+                        watcher::Event::Applied(obj) => insert(&mut children, &mut mine, obj),
+                        watcher::Event::Deleted(obj) => remove(&mut children, &mut mine, &obj),
+                        watcher::Event::Restarted(objs) => {
+                            for ((_, name), owner) in mine.drain() {
+                                remove_key(&mut children, &owner, &name);
+                            }
+                            for obj in objs {
+                                insert(&mut children, &mut mine, obj);
+                            }
+                            if initial {
+                                initial = false;
+                                synced.fetch_add(1, Ordering::SeqCst);
+                            }
+                        }
+                    }
+                }
+            });
+        }
+        OwnerIndex {
+            children,
+            synced,
+            watchers,
+        }
+    }
+
+    /// Waits for every watcher to complete its initial list, so that
+    /// missing children are not mistaken for ones that do not exist.
+    pub async fn wait_until_synced(&self) {
+        while self.synced.load(Ordering::SeqCst) < self.watchers {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Returns the child with the given name owned by the parent.
+    pub fn get(&self, owner_uid: &str, name: &str) -> Option<Arc<K>> {
+        self.children
+            .read()
+            .unwrap()
+            .get(owner_uid)
+            .and_then(|children| children.get(name))
+            .cloned()
+    }
+
+    /// Returns every child owned by the parent.
+    pub fn list(&self, owner_uid: &str) -> Vec<Arc<K>> {
+        self.children
+            .read()
+            .unwrap()
+            .get(owner_uid)
+            .map(|children| children.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Returns the uid of the object's controller owner, if it has one.
+fn get_owner_uid<K: Resource>(obj: &K) -> Option<String> {
+    obj.owner_references()
+        .iter()
+        .find(|oref| oref.controller == Some(true))
+        .map(|oref| oref.uid.clone())
+}
+
+fn insert<K: Resource>(
+    children: &mut Children<K>,
+    mine: &mut HashMap<(String, String), String>,
+    obj: K,
+) {
+    let owner = match get_owner_uid(&obj) {
+        Some(owner) => owner,
+        None => return,
+    };
+    let key = (obj.namespace().unwrap_or_default(), obj.name_any());
+    mine.insert(key, owner.clone());
+    children
+        .entry(owner)
+        .or_default()
+        .insert(obj.name_any(), Arc::new(obj));
+}
+
+fn remove<K: Resource>(
+    children: &mut Children<K>,
+    mine: &mut HashMap<(String, String), String>,
+    obj: &K,
+) {
+    let key = (obj.namespace().unwrap_or_default(), obj.name_any());
+    if let Some(owner) = mine.remove(&key) {
+        remove_key(children, &owner, &key.1);
+    }
+}
+
+/// Removes the child with the given name from the owner's children.
+/// Children of a single owner always share its namespace.
+fn remove_key<K>(children: &mut Children<K>, owner: &str, name: &str) {
+    if let Some(siblings) = children.get_mut(owner) {
+        siblings.remove(name);
+        if siblings.is_empty() {
+            children.remove(owner);
+        }
+    }
+}
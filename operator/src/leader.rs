@@ -0,0 +1,182 @@
+use crate::{drain, util::MANAGER_NAME};
+use chrono::Utc;
+use k8s_openapi::{
+    api::coordination::v1::{Lease, LeaseSpec},
+    apimachinery::pkg::apis::meta::v1::{MicroTime, ObjectMeta},
+};
+use kube::{
+    api::{Api, PostParams},
+    Client,
+};
+use tokio::time::{self, Duration, Instant};
+use tracing::{error, info};
+use ytdl_common::Error;
+
+/// Number of seconds a Lease is valid for after it was last renewed.
+/// If the leader fails to renew the Lease within this time frame,
+/// another replica is free to take it over.
+const LEASE_DURATION_SECONDS: i32 = 15;
+
+/// Interval at which the leader renews its Lease, and at which
+/// standby replicas attempt to acquire it.
+const RENEW_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Returns the identity of this replica, which is the pod name.
+fn get_identity() -> String {
+    std::env::var("HOSTNAME").unwrap_or_else(|_| format!("{}-{}", MANAGER_NAME, std::process::id()))
+}
+
+/// Returns the namespace in which the Lease is created. This is
+/// injected by the helm chart using the downward API.
+fn get_namespace() -> String {
+    std::env::var("POD_NAMESPACE").unwrap_or_else(|_| "default".to_owned())
+}
+
+/// Blocks until this replica holds the Lease with the given name,
+/// then renews it in the background. If the Lease is ever lost,
+/// the process exits so that the controller stops reconciling and
+/// the pod is restarted as a standby replica. This guarantees at
+/// most one replica is creating pods at any given time. Once the
+/// operator drains, it stops reconciling after the reconciliations in
+/// flight finish, then releases the Lease for another replica to take
+/// over without waiting for it to expire.
+pub async fn acquire(client: Client, lease_name: &str) {
+    let identity = get_identity();
+    let api: Api<Lease> = Api::namespaced(client, &get_namespace());
+    info!(lease = lease_name, %identity, "Waiting to acquire leader lease");
+    loop {
+        match try_acquire_or_renew(&api, lease_name, &identity).await {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(e) => error!(lease = lease_name, error = %e, "Failed to acquire leader lease"),
+        }
+        time::sleep(RENEW_INTERVAL).await;
+    }
+    info!(lease = lease_name, %identity, "Acquired leader lease");
+    let lease_name = lease_name.to_owned();
+    tokio::spawn(async move {
+        let lease_duration = Duration::from_secs(LEASE_DURATION_SECONDS as u64);
+        let mut last_renewed = Instant::now();
+        loop {
+            time::sleep(RENEW_INTERVAL).await;
+            if drain::is_draining() {
+                // The Lease is only handed over once this replica has
+                // stopped writing, so the next leader doesn't race it.
+                drain::stop_writers().await;
+                match release(&api, &lease_name, &identity).await {
+                    Ok(()) => info!(lease = %lease_name, "Released leader lease"),
+                    Err(e) => {
+                        error!(lease = %lease_name, error = %e, "Failed to release leader lease")
+                    }
+                }
+                return;
+            }
+            match try_acquire_or_renew(&api, &lease_name, &identity).await {
+                Ok(true) => last_renewed = Instant::now(),
+                Ok(false) => {
+                    error!(lease = %lease_name, "Lost leader lease, exiting");
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    // Transient errors are tolerated until the lease
+                    // expires, as another replica may then take over.
+                    error!(lease = %lease_name, error = %e, "Failed to renew leader lease");
+                    if last_renewed.elapsed() >= lease_duration {
+                        error!(lease = %lease_name, "Leader lease expired, exiting");
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Attempts to acquire the Lease, or renew it if it is already held
+/// by this replica. Returns `true` if this replica holds the Lease.
+/// Conflicting writes from other replicas are detected through the
+/// Lease's resourceVersion and result in `false`.
+async fn try_acquire_or_renew(
+    api: &Api<Lease>,
+    lease_name: &str,
+    identity: &str,
+) -> Result<bool, Error> {
+    let now = MicroTime(Utc::now());
+    let result = match api.get_opt(lease_name).await? {
+        None => {
+            let lease = Lease {
+                metadata: ObjectMeta {
+                    name: Some(lease_name.to_owned()),
+                    ..ObjectMeta::default()
+                },
+                spec: Some(LeaseSpec {
+                    holder_identity: Some(identity.to_owned()),
+                    lease_duration_seconds: Some(LEASE_DURATION_SECONDS),
+                    acquire_time: Some(now.clone()),
+                    renew_time: Some(now),
+                    lease_transitions: Some(0),
+                    ..LeaseSpec::default()
+                }),
+            };
+            api.create(&PostParams::default(), &lease).await
+        }
+        Some(mut lease) => {
+            let spec = lease.spec.get_or_insert_with(LeaseSpec::default);
+            let held = spec.holder_identity.as_deref() == Some(identity);
+            if !held {
+                let duration = spec
+                    .lease_duration_seconds
+                    .unwrap_or(LEASE_DURATION_SECONDS);
+                let expired = match spec.renew_time.as_ref() {
+                    Some(renew_time) => {
+                        renew_time.0 + chrono::Duration::seconds(duration as i64) < now.0
+                    }
+                    None => true,
+                };
+                if !expired {
+                    // Another replica is the active leader.
+                    return Ok(false);
+                }
+                // Take over the expired lease.
+                spec.holder_identity = Some(identity.to_owned());
+                spec.acquire_time = Some(now.clone());
+                spec.lease_transitions = Some(spec.lease_transitions.unwrap_or(0) + 1);
+            }
+            spec.lease_duration_seconds = Some(LEASE_DURATION_SECONDS);
+            spec.renew_time = Some(now);
+            api.replace(lease_name, &PostParams::default(), &lease)
+                .await
+        }
+    };
+    match result {
+        Ok(_) => Ok(true),
+        // Another replica modified the Lease first.
+        Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Gives up the Lease if this replica holds it. The Lease is left to
+/// expire a second later rather than deleted, so that its transitions
+/// are still counted.
+async fn release(api: &Api<Lease>, lease_name: &str, identity: &str) -> Result<(), Error> {
+    let mut lease = match api.get_opt(lease_name).await? {
+        Some(lease) => lease,
+        None => return Ok(()),
+    };
+    let spec = match lease.spec.as_mut() {
+        Some(spec) if spec.holder_identity.as_deref() == Some(identity) => spec,
+        _ => return Ok(()),
+    };
+    spec.holder_identity = None;
+    spec.lease_duration_seconds = Some(1);
+    spec.renew_time = Some(MicroTime(Utc::now()));
+    match api
+        .replace(lease_name, &PostParams::default(), &lease)
+        .await
+    {
+        Ok(_) => Ok(()),
+        // Another replica took over the Lease first.
+        Err(kube::Error::Api(e)) if e.code == 409 => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
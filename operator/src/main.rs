@@ -1,8 +1,22 @@
 use clap::{Parser, Subcommand};
+use kube::Client;
+use std::time::Duration;
+use tracing::{error, warn};
+use ytdl_common::chaos::{self, ChaosConfig};
 
+mod backup;
+mod bench;
 mod downloads;
+mod drain;
+mod events;
 mod executors;
+mod finalizer;
+mod index;
+mod leader;
+mod metrics;
+mod render;
 mod util;
+mod webhook;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -10,22 +24,217 @@ mod util;
 struct Cli {
     #[command(subcommand)]
     command: Option<Command>,
+
+    /// Only reconcile resources in this namespace. May be repeated.
+    /// Defaults to the comma-separated `WATCH_NAMESPACES` variable,
+    /// or all namespaces if neither is specified.
+    #[arg(long = "namespace", global = true)]
+    namespaces: Vec<String>,
+
+    /// Soak testing: probability that an S3 upload fails.
+    #[arg(long, global = true, hide = true, default_value_t = 0.0)]
+    chaos_s3_failure_rate: f64,
+
+    /// Soak testing: probability that a reconciliation deletes the pod.
+    #[arg(long, global = true, hide = true, default_value_t = 0.0)]
+    chaos_pod_deletion_rate: f64,
+
+    /// Soak testing: maximum random delay (ms) added to reconciliations.
+    #[arg(long, global = true, hide = true, default_value_t = 0)]
+    chaos_api_delay_ms: u64,
 }
 
 #[derive(Subcommand)]
 enum Command {
     ManageDownloads,
     ManageExecutors,
+
+    /// Export all ytdl resources and metadata to a tarball.
+    Export {
+        /// Path of the gzipped tarball to create.
+        #[arg(long, short)]
+        output: String,
+    },
+
+    /// Import the resources from a tarball created by `export`.
+    Import {
+        /// Path of the gzipped tarball to read.
+        #[arg(long, short)]
+        input: String,
+    },
+
+    /// Serve the admission webhooks, which reject invalid specs
+    /// before they reach the controllers and fill in defaults.
+    Webhook {
+        /// Port to serve https on.
+        #[arg(long, default_value_t = 8443)]
+        port: u16,
+
+        /// Path of the PEM encoded TLS certificate.
+        #[arg(long, default_value = "/tls/tls.crt")]
+        tls_cert: String,
+
+        /// Path of the PEM encoded TLS private key.
+        #[arg(long, default_value = "/tls/tls.key")]
+        tls_key: String,
+    },
+
+    /// Print the pods the controllers would create for a resource
+    /// without touching the cluster, e.g. to debug pod templates.
+    Render {
+        /// Path of a Download manifest. Prints its query pod and the
+        /// download pod of one of its Executors.
+        #[arg(
+            long,
+            conflicts_with = "executor",
+            required_unless_present = "executor"
+        )]
+        download: Option<String>,
+
+        /// Path of an Executor manifest. Prints its download pod.
+        #[arg(long)]
+        executor: Option<String>,
+    },
+
+    /// Development tool that creates fake Downloads in a disposable
+    /// cluster (e.g. kind) running the operator, and measures reconcile
+    /// throughput and API server requests. Uses the first `--namespace`.
+    #[command(hide = true)]
+    Bench {
+        /// Number of Downloads to create.
+        #[arg(long, default_value_t = 100)]
+        count: usize,
+
+        /// Maximum number of concurrent create requests.
+        #[arg(long, default_value_t = 10)]
+        parallelism: usize,
+
+        /// Create the Downloads unsuspended, which starts a query pod
+        /// for each of them.
+        #[arg(long, default_value_t = false)]
+        unsuspended: bool,
+
+        /// Seconds to wait for every Download to be reconciled.
+        #[arg(long, default_value_t = 300)]
+        timeout: u64,
+
+        /// Leave the Downloads in place after the run.
+        #[arg(long, default_value_t = false)]
+        keep: bool,
+    },
+}
+
+impl Command {
+    /// Returns `true` if the command runs a controller.
+    fn is_controller(&self) -> bool {
+        matches!(self, Command::ManageDownloads | Command::ManageExecutors)
+    }
 }
 
 #[tokio::main]
 async fn main() {
+    ytdl_common::logging::init();
     let cli = Cli::parse();
+    chaos::configure(ChaosConfig {
+        s3_failure_rate: cli.chaos_s3_failure_rate,
+        pod_deletion_rate: cli.chaos_pod_deletion_rate,
+        api_delay: Duration::from_millis(cli.chaos_api_delay_ms),
+    });
+    if cli.command.as_ref().map_or(false, Command::is_controller) {
+        // Stop creating new pods once Kubernetes asks us to terminate.
+        drain::spawn_signal_handler();
+        // Serve the Prometheus metrics in the background.
+        tokio::spawn(async {
+            if let Err(e) = metrics::serve(util::get_metrics_port()).await {
+                error!(error = %e, "Metrics server error");
+            }
+        });
+    }
+    if let Some(command) = cli
+        .command
+        .as_ref()
+        .filter(|command| command.is_controller())
+    {
+        if util::get_leader_election() {
+            // Only the replica holding the Lease may run the controller.
+            let lease_name = match command {
+                Command::ManageDownloads => format!("{}-downloads", util::MANAGER_NAME),
+                Command::ManageExecutors => format!("{}-executors", util::MANAGER_NAME),
+                _ => unreachable!(),
+            };
+            let client = Client::try_default()
+                .await
+                .expect("Expected a valid KUBECONFIG environment variable.");
+            leader::acquire(client, &lease_name).await;
+        }
+    }
+    let namespaces = if cli.namespaces.is_empty() {
+        util::get_watch_namespaces()
+    } else {
+        cli.namespaces
+    };
     match cli.command {
-        Some(Command::ManageDownloads) => downloads::main().await,
-        Some(Command::ManageExecutors) => executors::main().await,
+        Some(Command::ManageDownloads) => downloads::main(namespaces).await,
+        Some(Command::ManageExecutors) => executors::main(namespaces).await,
+        Some(Command::Export { output }) => {
+            let client = Client::try_default()
+                .await
+                .expect("Expected a valid KUBECONFIG environment variable.");
+            backup::export(client, &namespaces, &output)
+                .await
+                .expect("failed to export resources");
+        }
+        Some(Command::Import { input }) => {
+            let client = Client::try_default()
+                .await
+                .expect("Expected a valid KUBECONFIG environment variable.");
+            backup::import(client, &input)
+                .await
+                .expect("failed to import resources");
+        }
+        Some(Command::Webhook {
+            port,
+            tls_cert,
+            tls_key,
+        }) => {
+            webhook::serve(port, &tls_cert, &tls_key)
+                .await
+                .expect("admission webhook failed");
+        }
+        Some(Command::Bench {
+            count,
+            parallelism,
+            unsuspended,
+            timeout,
+            keep,
+        }) => {
+            let client = Client::try_default()
+                .await
+                .expect("Expected a valid KUBECONFIG environment variable.");
+            let options = bench::BenchOptions {
+                namespace: namespaces
+                    .into_iter()
+                    .next()
+                    .unwrap_or_else(|| "default".to_owned()),
+                count,
+                parallelism,
+                unsuspended,
+                timeout: Duration::from_secs(timeout),
+                keep,
+            };
+            bench::run(client, options).await.expect("benchmark failed");
+        }
+        Some(Command::Render { download, executor }) => {
+            let mut out = std::io::stdout().lock();
+            match (download, executor) {
+                (Some(path), _) => render::render_download(&path, &mut out),
+                (None, Some(path)) => render::render_executor(&path, &mut out),
+                (None, None) => unreachable!(),
+            }
+            .expect("failed to render");
+        }
         None => {
-            println!("Please choose a subcommand.");
+            warn!("Please choose a subcommand.");
         }
     }
 }
@@ -0,0 +1,260 @@
+use futures::Future;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use kube::{
+    api::ListParams,
+    runtime::{controller, reflector::Store, watcher},
+    Api, Resource,
+};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramTimer, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use serde::de::DeserializeOwned;
+use std::{collections::HashMap, convert::Infallible, fmt::Debug, hash::Hash, net::SocketAddr};
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+lazy_static! {
+    /// Total number of reconciliations, labeled by resource kind.
+    pub static ref RECONCILE_COUNT: IntCounterVec = register_int_counter_vec!(
+        "ytdl_reconcile_total",
+        "Total number of reconciliations.",
+        &["kind"]
+    )
+    .unwrap();
+
+    /// Duration of each reconciliation, labeled by resource kind.
+    pub static ref RECONCILE_DURATION: HistogramVec = register_histogram_vec!(
+        "ytdl_reconcile_duration_seconds",
+        "Duration of reconciliations in seconds.",
+        &["kind"]
+    )
+    .unwrap();
+
+    /// Total number of failed reconciliations, labeled by resource kind.
+    pub static ref RECONCILE_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "ytdl_reconcile_errors_total",
+        "Total number of failed reconciliations.",
+        &["kind"]
+    )
+    .unwrap();
+
+    /// Total bytes downloaded from the video service, labeled by namespace.
+    pub static ref EGRESS_BYTES: IntCounterVec = register_int_counter_vec!(
+        "ytdl_egress_bytes_total",
+        "Total number of bytes downloaded by download pods.",
+        &["namespace"]
+    )
+    .unwrap();
+
+    /// Total number of pods created for a resource that already had more
+    /// pods created within the last hour than the alert threshold allows.
+    pub static ref POD_RESTART_ALERTS: IntCounterVec = register_int_counter_vec!(
+        "ytdl_pod_restart_alerts_total",
+        "Total number of pods created for resources whose pods are flapping.",
+        &["kind", "namespace"]
+    )
+    .unwrap();
+
+    /// Number of resources in each phase, labeled by resource kind.
+    pub static ref RESOURCE_PHASE: IntGaugeVec = register_int_gauge_vec!(
+        "ytdl_resources",
+        "Number of resources in each phase.",
+        &["kind", "phase"]
+    )
+    .unwrap();
+
+    /// Number of reconciliations in progress, labeled by resource kind.
+    /// Stays at the concurrency limit while reconciles are backing up.
+    pub static ref RECONCILES_IN_FLIGHT: IntGaugeVec = register_int_gauge_vec!(
+        "ytdl_reconciles_in_flight",
+        "Number of reconciliations in progress.",
+        &["kind"]
+    )
+    .unwrap();
+
+    /// Total number of errors from the controllers' watches, labeled
+    /// by resource kind.
+    pub static ref WATCH_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "ytdl_watch_errors_total",
+        "Total number of errors from the controllers' watches.",
+        &["kind"]
+    )
+    .unwrap();
+
+    /// Total number of times a controller's cache was listed again from
+    /// scratch because its watch fell behind, labeled by resource kind.
+    pub static ref WATCH_RESTARTS: IntCounterVec = register_int_counter_vec!(
+        "ytdl_watch_restarts_total",
+        "Total number of times a controller's cache was relisted.",
+        &["kind"]
+    )
+    .unwrap();
+
+    /// Number of objects in the controllers' caches, labeled by resource kind.
+    pub static ref CACHE_OBJECTS: IntGaugeVec = register_int_gauge_vec!(
+        "ytdl_cache_objects",
+        "Number of objects in the controllers' caches.",
+        &["kind"]
+    )
+    .unwrap();
+}
+
+/// Interval for refreshing the per-phase resource gauges.
+const PHASE_GAUGE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Phase label used for resources that have no status yet.
+const NO_PHASE: &str = "None";
+
+/// HTTP status of a watch error after which the watch is restarted
+/// with a new list, as the resource version it resumed from expired.
+const GONE: u16 = 410;
+
+/// A reconciliation in progress. Its duration is observed and it
+/// stops counting as in flight when dropped.
+pub struct ReconcileTimer {
+    _duration: HistogramTimer,
+    in_flight: IntGauge,
+}
+
+impl Drop for ReconcileTimer {
+    fn drop(&mut self) {
+        self.in_flight.dec();
+    }
+}
+
+/// Records the start of a reconciliation for the given kind. The
+/// duration is observed when the returned timer is dropped.
+pub fn reconcile_started(kind: &str) -> ReconcileTimer {
+    RECONCILE_COUNT.with_label_values(&[kind]).inc();
+    let in_flight = RECONCILES_IN_FLIGHT.with_label_values(&[kind]);
+    in_flight.inc();
+    ReconcileTimer {
+        _duration: RECONCILE_DURATION.with_label_values(&[kind]).start_timer(),
+        in_flight,
+    }
+}
+
+/// Records an error from the controller of the given kind. Errors of
+/// the reconciler itself are counted by [`reconcile_failed`].
+pub fn controller_error<E: std::error::Error + 'static>(
+    kind: &str,
+    error: &controller::Error<E, watcher::Error>,
+) {
+    if let controller::Error::QueueError(error) = error {
+        WATCH_ERRORS.with_label_values(&[kind]).inc();
+        if let watcher::Error::WatchError(response) = error {
+            if response.code == GONE {
+                WATCH_RESTARTS.with_label_values(&[kind]).inc();
+            }
+        }
+    }
+}
+
+/// Records a failed reconciliation for the given kind.
+pub fn reconcile_failed(kind: &str) {
+    RECONCILE_ERRORS.with_label_values(&[kind]).inc();
+}
+
+/// Records bytes downloaded by the download pods in the namespace.
+pub fn egress(namespace: &str, bytes: u64) {
+    EGRESS_BYTES.with_label_values(&[namespace]).inc_by(bytes);
+}
+
+/// Records a pod created for a resource whose pods are flapping.
+pub fn pod_restart_alert(kind: &str, namespace: &str) {
+    POD_RESTART_ALERTS
+        .with_label_values(&[kind, namespace])
+        .inc();
+}
+
+/// Periodically lists all resources of the given kind and updates
+/// the per-phase gauges. The `phase` function returns the phase of
+/// a resource, if it has one. Resources are listed with each of the
+/// given Apis, which correspond to the watched namespaces.
+pub fn spawn_phase_gauges<K>(
+    apis: Vec<Api<K>>,
+    kind: &'static str,
+    phase: impl Fn(&K) -> Option<String> + Send + 'static,
+) where
+    K: Resource + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+    <K as Resource>::DynamicType: Default,
+{
+    tokio::spawn(async move {
+        loop {
+            let mut counts: HashMap<String, i64> = HashMap::new();
+            let mut ok = true;
+            for api in apis.iter() {
+                match api.list(&ListParams::default()).await {
+                    Ok(list) => {
+                        for instance in list.items.iter() {
+                            let phase = phase(instance).unwrap_or_else(|| NO_PHASE.to_owned());
+                            *counts.entry(phase).or_default() += 1;
+                        }
+                    }
+                    Err(e) => {
+                        warn!(kind, error = %e, "Failed to list resources for metrics");
+                        ok = false;
+                    }
+                }
+            }
+            if ok {
+                // Reset the gauges so phases with no resources read zero.
+                RESOURCE_PHASE.reset();
+                for (phase, count) in counts {
+                    RESOURCE_PHASE.with_label_values(&[kind, &phase]).set(count);
+                }
+            }
+            tokio::time::sleep(PHASE_GAUGE_INTERVAL).await;
+        }
+    });
+}
+
+/// Periodically updates the cache gauge with the number of objects in
+/// the controllers' caches of the given kind, one per watched namespace.
+pub fn spawn_cache_gauges<K>(stores: Vec<Store<K>>, kind: &'static str)
+where
+    K: Resource + Clone + Send + Sync + 'static,
+    <K as Resource>::DynamicType: Eq + Hash + Clone + Send + Sync,
+{
+    tokio::spawn(async move {
+        loop {
+            let count: usize = stores.iter().map(|store| store.state().len()).sum();
+            CACHE_OBJECTS.with_label_values(&[kind]).set(count as i64);
+            tokio::time::sleep(PHASE_GAUGE_INTERVAL).await;
+        }
+    });
+}
+
+/// Handles a single HTTP request to the metrics server.
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.uri().path() != "/metrics" {
+        let mut res = Response::new(Body::empty());
+        *res.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(res);
+    }
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        let mut res = Response::new(Body::from(e.to_string()));
+        *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return Ok(res);
+    }
+    Ok(Response::builder()
+        .header(hyper::header::CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))
+        .unwrap())
+}
+
+/// Returns a future that serves the `/metrics` endpoint on the given port.
+pub fn serve(port: u16) -> impl Future<Output = Result<(), hyper::Error>> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    info!(%addr, "Serving metrics");
+    Server::bind(&addr).serve(make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(handle))
+    }))
+}
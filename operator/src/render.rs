@@ -0,0 +1,125 @@
+//! Dry run of the pods the controllers create, for debugging pod
+//! template overrides without touching the cluster. The resource is
+//! read from a file and the manifests are printed as YAML documents.
+//! Targets are separate resources, so the parts of a download pod that
+//! depend on them, i.e. the storage proxy, CA bundles, and volume
+//! target mounts, are left out.
+use kube::{Resource, ResourceExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs::File, io::Write};
+use ytdl_common::{
+    get_entity_executor, get_executor_service_account_name,
+    work_volume::{get_work_claim, is_persistent},
+    Entity, Error,
+};
+use ytdl_types::{Download, Executor};
+
+use crate::{
+    downloads::action::get_query_pod,
+    executors::action::{get_download_pod, DownloadPodOptions, PodTargets},
+};
+
+/// Namespace of a resource that doesn't set one.
+const DEFAULT_NAMESPACE: &str = "default";
+
+/// UID of a resource that doesn't have one, as the owner references
+/// of its pods need one.
+const PLACEHOLDER_UID: &str = "00000000-0000-0000-0000-000000000000";
+
+/// ID of the entity a Download's download pod is rendered for.
+const PLACEHOLDER_ENTITY_ID: &str = "example";
+
+/// Service account of the executor pods if the operator's isn't set.
+const DEFAULT_SERVICE_ACCOUNT_NAME: &str = "default";
+
+/// Comment at the top of the output, as the pods are incomplete.
+const HEADER: &str = "# Rendered without the cluster. The storage proxy, CA bundles, and volume
+# target mounts of the targets are not included in download pods.
+";
+
+/// Prints the query pod of the Download, followed by the download pod
+/// of an Executor for a placeholder entity, which inherits the
+/// Download's pod template, VPN, and work volume.
+pub fn render_download<W: Write>(path: &str, out: &mut W) -> Result<(), Error> {
+    let mut instance: Download = read_resource(path)?;
+    fill_metadata(&mut instance);
+    out.write_all(HEADER.as_bytes())?;
+    let pod = get_query_pod(
+        &instance.name_any(),
+        &instance.namespace().unwrap(),
+        &instance,
+        get_service_account_name(),
+    )?;
+    write_manifest(out, &pod)?;
+    let entity = Entity {
+        id: PLACEHOLDER_ENTITY_ID.to_owned(),
+        metadata: serde_json::json!({ "id": PLACEHOLDER_ENTITY_ID }).to_string(),
+    };
+    let mut executor = get_entity_executor(&instance, vec![entity]);
+    fill_metadata(&mut executor);
+    write_executor(out, &executor)
+}
+
+/// Prints the download pod of the Executor, preceded by the claim of
+/// its work volume if it's persistent.
+pub fn render_executor<W: Write>(path: &str, out: &mut W) -> Result<(), Error> {
+    let mut instance: Executor = read_resource(path)?;
+    fill_metadata(&mut instance);
+    out.write_all(HEADER.as_bytes())?;
+    write_executor(out, &instance)
+}
+
+/// Prints the manifests the Executor controller would create for a
+/// download pod that downloads every type of content.
+fn write_executor<W: Write>(out: &mut W, instance: &Executor) -> Result<(), Error> {
+    if let Some(ref work_volume) = instance.spec.work_volume {
+        if is_persistent(work_volume) {
+            write_manifest(out, &get_work_claim(instance, work_volume)?)?;
+        }
+    }
+    let options = DownloadPodOptions {
+        download_video: true,
+        download_thumbnail: true,
+        publish_metadata: true,
+    };
+    let pod = get_download_pod(
+        &instance.name_any(),
+        &instance.namespace().unwrap(),
+        instance,
+        get_service_account_name(),
+        options,
+        &PodTargets::default(),
+    )?;
+    write_manifest(out, &pod)
+}
+
+/// Reads a resource from a YAML or JSON file.
+fn read_resource<K: DeserializeOwned>(path: &str) -> Result<K, Error> {
+    serde_yaml::from_reader(File::open(path)?)
+        .map_err(|e| Error::UserInputError(format!("failed to parse {}: {}", path, e)))
+}
+
+/// Gives the resource the namespace and UID the API server would.
+fn fill_metadata<K: Resource>(instance: &mut K) {
+    let metadata = instance.meta_mut();
+    metadata
+        .namespace
+        .get_or_insert_with(|| DEFAULT_NAMESPACE.to_owned());
+    metadata
+        .uid
+        .get_or_insert_with(|| PLACEHOLDER_UID.to_owned());
+}
+
+/// Writes the manifest as a YAML document.
+fn write_manifest<W: Write, T: Serialize>(out: &mut W, manifest: &T) -> Result<(), Error> {
+    let yaml = serde_yaml::to_string(manifest)
+        .map_err(|e| Error::UnknownError(format!("failed to serialize manifest: {}", e)))?;
+    write!(out, "---\n{}", yaml)?;
+    Ok(())
+}
+
+/// Returns the executor service account the operator is configured
+/// with, as the controllers would use.
+fn get_service_account_name() -> String {
+    get_executor_service_account_name().unwrap_or_else(|_| DEFAULT_SERVICE_ACCOUNT_NAME.to_owned())
+}
@@ -1,3 +1,9 @@
+use k8s_openapi::NamespaceResourceScope;
+use kube::{Api, Client, Resource};
+use std::time::Duration;
+use ytdl_common::units::parse_duration;
+use ytdl_types::HostPolicySpec;
+
 /// Friendly name for the controller.
 pub const MANAGER_NAME: &str = "ytdl-operator";
 
@@ -6,4 +12,167 @@ pub fn get_concurrency() -> usize {
         Ok(concurrency) => concurrency.parse().expect("failed to parse concurrency"),
         _ => 1,
     }
-}
\ No newline at end of file
+}
+
+/// Intervals after which the reconcilers requeue a resource. Large
+/// installations with thousands of resources may need to increase
+/// these to avoid being throttled by the API server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequeueIntervals {
+    /// Delay between checks on a running query or download.
+    /// Configured with `PROGRESS_INTERVAL`. Default is `"3s"`.
+    pub progress: Duration,
+
+    /// Delay after creating a query pod before checking on it.
+    /// Configured with `STARTING_INTERVAL`. Default is `"5s"`.
+    pub starting: Duration,
+
+    /// Delay before retrying a query pod when too many queries are
+    /// running. Configured with `THROTTLE_INTERVAL`. Default is `"15s"`.
+    pub throttled: Duration,
+
+    /// Delay before recreating a failed pod.
+    /// Configured with `FAILURE_BACKOFF`. Default is `"5s"`.
+    pub failure: Duration,
+
+    /// Delay before retrying a reconciliation that returned an error.
+    /// Configured with `ERROR_BACKOFF`. Default is `"5s"`.
+    pub error: Duration,
+}
+
+impl RequeueIntervals {
+    /// Reads the requeue intervals from the environment.
+    pub fn from_env() -> Self {
+        RequeueIntervals {
+            progress: get_interval("PROGRESS_INTERVAL", Duration::from_secs(3)),
+            starting: get_interval("STARTING_INTERVAL", Duration::from_secs(5)),
+            throttled: get_interval("THROTTLE_INTERVAL", Duration::from_secs(15)),
+            failure: get_interval("FAILURE_BACKOFF", Duration::from_secs(5)),
+            error: get_interval("ERROR_BACKOFF", Duration::from_secs(5)),
+        }
+    }
+}
+
+/// Parses the duration (e.g. `"10s"`) from the environment variable,
+/// falling back to the default if it is unset.
+fn get_interval(name: &str, default: Duration) -> Duration {
+    match std::env::var(name) {
+        Ok(value) => {
+            parse_duration(&value).unwrap_or_else(|e| panic!("failed to parse {}: {}", name, e))
+        }
+        _ => default,
+    }
+}
+
+/// Returns `true` if Lease-based leader election is enabled,
+/// which is required to safely run more than one replica of
+/// a controller. It can be disabled for single-replica dev
+/// clusters by setting `LEADER_ELECTION=false`.
+pub fn get_leader_election() -> bool {
+    match std::env::var("LEADER_ELECTION") {
+        Ok(enabled) => enabled
+            .parse()
+            .expect("failed to parse leader election flag"),
+        _ => true,
+    }
+}
+
+/// Returns `true` if a Download's metadata ConfigMap is deleted with
+/// the foreground propagation policy when the Download is deleted,
+/// so that any resources owned by the ConfigMap are deleted first.
+/// Enabled by setting `FOREGROUND_CONFIGMAP_DELETION=true`.
+pub fn get_foreground_configmap_deletion() -> bool {
+    match std::env::var("FOREGROUND_CONFIGMAP_DELETION") {
+        Ok(enabled) => enabled
+            .parse()
+            .expect("failed to parse foreground ConfigMap deletion flag"),
+        _ => false,
+    }
+}
+
+/// Default number of pods that may be created for the same resource
+/// within an hour before a warning is raised.
+pub const DEFAULT_RESTART_ALERT_THRESHOLD: usize = 5;
+
+/// Returns the number of pods that may be created for the same
+/// Download or Executor within an hour before a warning Event is
+/// published, from `RESTART_ALERT_THRESHOLD`. Zero disables alerting.
+pub fn get_restart_alert_threshold() -> usize {
+    match std::env::var("RESTART_ALERT_THRESHOLD") {
+        Ok(threshold) => threshold
+            .parse()
+            .expect("failed to parse restart alert threshold"),
+        _ => DEFAULT_RESTART_ALERT_THRESHOLD,
+    }
+}
+
+/// Returns the operator-wide host policy from the comma-separated
+/// `ALLOWED_HOSTS` and `BLOCKED_HOSTS` environment variables. If
+/// `ALLOWED_HOSTS` is unset or empty, all hosts are allowed.
+pub fn get_host_policy() -> HostPolicySpec {
+    let get_hosts = |name: &str| -> Option<Vec<String>> {
+        let hosts: Vec<String> = std::env::var(name)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|host| !host.is_empty())
+            .map(str::to_owned)
+            .collect();
+        Some(hosts).filter(|hosts| !hosts.is_empty())
+    };
+    HostPolicySpec {
+        allowed_hosts: get_hosts("ALLOWED_HOSTS"),
+        blocked_hosts: get_hosts("BLOCKED_HOSTS"),
+    }
+}
+
+/// Default port for the Prometheus metrics server.
+pub const DEFAULT_METRICS_PORT: u16 = 9090;
+
+pub fn get_metrics_port() -> u16 {
+    match std::env::var("METRICS_PORT") {
+        Ok(port) => port.parse().expect("failed to parse metrics port"),
+        _ => DEFAULT_METRICS_PORT,
+    }
+}
+
+/// Returns the name of the ConfigMap in each tenant namespace that
+/// accumulates the bytes downloaded per calendar month, for chargeback
+/// in shared clusters. Set with `EGRESS_CONFIGMAP`; unset disables it.
+pub fn get_egress_configmap() -> Option<String> {
+    std::env::var("EGRESS_CONFIGMAP")
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+/// Returns the namespaces the controllers are restricted to, from
+/// the comma-separated `WATCH_NAMESPACES` environment variable. An
+/// empty list means resources in all namespaces are reconciled.
+pub fn get_watch_namespaces() -> Vec<String> {
+    match std::env::var("WATCH_NAMESPACES") {
+        Ok(namespaces) => namespaces
+            .split(',')
+            .map(str::trim)
+            .filter(|namespace| !namespace.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Returns an Api for each of the given namespaces, or a single
+/// cluster-wide Api if no namespaces are given. This way the operator
+/// only requires namespaced RBAC when it is deployed per-tenant.
+pub fn get_watch_apis<K>(client: Client, namespaces: &[String]) -> Vec<Api<K>>
+where
+    K: Resource<Scope = NamespaceResourceScope>,
+    <K as Resource>::DynamicType: Default,
+{
+    if namespaces.is_empty() {
+        return vec![Api::all(client)];
+    }
+    namespaces
+        .iter()
+        .map(|namespace| Api::namespaced(client.clone(), namespace))
+        .collect()
+}
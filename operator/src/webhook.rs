@@ -0,0 +1,256 @@
+use hyper::{server::conn::Http, service::service_fn, Body, Method, Request, Response, StatusCode};
+use kube::{
+    api::{Api, ListParams},
+    core::{
+        admission::{AdmissionRequest, AdmissionResponse, AdmissionReview},
+        DynamicObject,
+    },
+    Client,
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::{
+    convert::{Infallible, TryInto},
+    fs::File,
+    io::BufReader,
+    net::SocketAddr,
+    sync::Arc,
+};
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+use tracing::{info, warn};
+use ytdl_common::{
+    compliance::MetadataOnlyPolicy, defaults, extra_args::ExtraArgsPolicy, format_field_errors,
+    host_policy::validate_host_policies, validate, Error, FieldError,
+};
+use ytdl_types::{
+    AzureBlobTargetSpec, DefaultTargets, DownloadSpec, GcsTargetSpec, KafkaTargetSpec,
+    MongoDBTargetSpec, NatsTargetSpec, RedisTargetSpec, S3TargetSpec, SftpTargetSpec,
+    SqlTargetSpec, TargetSpec, VolumeTargetSpec, WebDavTargetSpec, WebhookTargetSpec,
+};
+
+use crate::util::get_host_policy;
+
+/// Serves the validating and mutating admission webhooks over https on
+/// the given port. The API server only calls webhooks over TLS, so a
+/// certificate and key (PEM encoded) are required.
+pub async fn serve(port: u16, tls_cert: &str, tls_key: &str) -> Result<(), Error> {
+    let client = Client::try_default().await?;
+    let acceptor = TlsAcceptor::from(Arc::new(load_tls_config(tls_cert, tls_key)?));
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = TcpListener::bind(&addr).await?;
+    info!(%addr, "Serving admission webhook");
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let client = client.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!(%peer, error = %e, "TLS handshake failed");
+                    return;
+                }
+            };
+            let service = service_fn(move |req| handle(client.clone(), req));
+            if let Err(e) = Http::new().serve_connection(stream, service).await {
+                warn!(%peer, error = %e, "Admission webhook connection error");
+            }
+        });
+    }
+}
+
+/// Loads the certificate chain and private key from the PEM files.
+fn load_tls_config(tls_cert: &str, tls_key: &str) -> Result<ServerConfig, Error> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(tls_cert)?))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let mut keys = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(tls_key)?))?;
+    if keys.is_empty() {
+        keys = rustls_pemfile::rsa_private_keys(&mut BufReader::new(File::open(tls_key)?))?;
+    }
+    let key = keys
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::UserInputError(format!("no private key found in {}", tls_key)))?;
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, PrivateKey(key))
+        .map_err(|e| Error::UserInputError(format!("invalid TLS certificate: {}", e)))
+}
+
+/// Handles a single AdmissionReview request. Specs are validated at
+/// `POST /validate`, and their omitted fields are defaulted at
+/// `POST /mutate`.
+async fn handle(client: Client, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let mutate = match (req.method(), req.uri().path()) {
+        (&Method::POST, "/validate") => false,
+        (&Method::POST, "/mutate") => true,
+        _ => {
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = StatusCode::NOT_FOUND;
+            return Ok(res);
+        }
+    };
+    let review = match review(client, req, mutate).await {
+        Ok(review) => review,
+        Err(e) => {
+            let mut res = Response::new(Body::from(e.to_string()));
+            *res.status_mut() = StatusCode::BAD_REQUEST;
+            return Ok(res);
+        }
+    };
+    let body = serde_json::to_vec(&review).unwrap();
+    Ok(Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap())
+}
+
+/// Parses the AdmissionReview from the request body and returns the
+/// review with the response populated.
+async fn review(
+    client: Client,
+    req: Request<Body>,
+    mutate: bool,
+) -> Result<AdmissionReview<DynamicObject>, Error> {
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .map_err(|e| Error::UnknownError(format!("failed to read request body: {}", e)))?;
+    let review: AdmissionReview<DynamicObject> = serde_json::from_slice(&body)?;
+    let req: AdmissionRequest<DynamicObject> = review
+        .try_into()
+        .map_err(|e| Error::UserInputError(format!("invalid AdmissionReview: {}", e)))?;
+    let res = if mutate {
+        default_response(&req)
+    } else {
+        validate_response(&client, &req).await
+    };
+    Ok(res.into_review())
+}
+
+/// Returns the response that denies the resource if its spec is invalid.
+async fn validate_response(
+    client: &Client,
+    req: &AdmissionRequest<DynamicObject>,
+) -> AdmissionResponse {
+    let res = AdmissionResponse::from(req);
+    match validate(client, req).await {
+        Ok(errors) if errors.is_empty() => res,
+        Ok(errors) => {
+            info!(
+                kind = %req.kind.kind,
+                name = %req.name,
+                namespace = ?req.namespace,
+                ?errors,
+                "Rejecting invalid resource"
+            );
+            res.deny(format_field_errors(&errors))
+        }
+        // Admit the resource rather than blocking all writes when
+        // the webhook cannot do its job.
+        Err(e) => {
+            warn!(kind = %req.kind.kind, error = %e, "Failed to validate resource");
+            res
+        }
+    }
+}
+
+/// Returns the response that patches the omitted optional fields of
+/// the resource's spec with their default values.
+fn default_response(req: &AdmissionRequest<DynamicObject>) -> AdmissionResponse {
+    let object = match req.object.as_ref() {
+        Some(object) => object,
+        None => return AdmissionResponse::from(req),
+    };
+    let original = object.data.get("spec").cloned().unwrap_or_default();
+    let mut spec = original.clone();
+    if !defaults::apply_spec_defaults(&req.kind.kind, &mut spec) {
+        return AdmissionResponse::from(req);
+    }
+    let patch = json_patch::diff(&json!({ "spec": original }), &json!({ "spec": spec }));
+    match AdmissionResponse::from(req).with_patch(patch) {
+        Ok(res) => res,
+        Err(e) => {
+            warn!(kind = %req.kind.kind, error = %e, "Failed to serialize defaults patch");
+            AdmissionResponse::from(req)
+        }
+    }
+}
+
+/// Returns the problems with the resource in the request, if any.
+async fn validate(
+    client: &Client,
+    req: &AdmissionRequest<DynamicObject>,
+) -> Result<Vec<FieldError>, Error> {
+    let object = match req.object.as_ref() {
+        Some(object) => object,
+        // Deletions carry no object and are always allowed.
+        None => return Ok(vec![]),
+    };
+    Ok(match req.kind.kind.as_str() {
+        "Download" => {
+            let spec: DownloadSpec = get_spec(object)?;
+            let mut errors = validate::validate_download(&spec);
+            let namespace = req.namespace.as_deref().unwrap_or("default");
+            errors.extend(MetadataOnlyPolicy::from_env().validate(namespace, &spec));
+            errors.extend(ExtraArgsPolicy::from_env().validate(&spec));
+            errors.extend(
+                validate_host_policies(client.clone(), namespace, &spec, &get_host_policy())
+                    .await?,
+            );
+            if spec.targets.is_empty() {
+                if !has_default_targets(client.clone(), namespace).await? {
+                    errors.push(FieldError::new(
+                        "targets",
+                        "must not be empty unless the namespace has DefaultTargets",
+                    ));
+                }
+            }
+            errors
+        }
+        "Target" => validate::validate_target(&get_spec::<TargetSpec>(object)?),
+        "S3Target" => validate::validate_s3_target(&get_spec::<S3TargetSpec>(object)?),
+        "WebhookTarget" => {
+            validate::validate_webhook_target(&get_spec::<WebhookTargetSpec>(object)?)
+        }
+        "MongoDBTarget" => {
+            validate::validate_mongodb_target(&get_spec::<MongoDBTargetSpec>(object)?)
+        }
+        "SqlTarget" => validate::validate_sql_target(&get_spec::<SqlTargetSpec>(object)?),
+        "RedisTarget" => validate::validate_redis_target(&get_spec::<RedisTargetSpec>(object)?),
+        "GcsTarget" => validate::validate_gcs_target(&get_spec::<GcsTargetSpec>(object)?),
+        "AzureBlobTarget" => {
+            validate::validate_azure_blob_target(&get_spec::<AzureBlobTargetSpec>(object)?)
+        }
+        "VolumeTarget" => validate::validate_volume_target(&get_spec::<VolumeTargetSpec>(object)?),
+        "SftpTarget" => validate::validate_sftp_target(&get_spec::<SftpTargetSpec>(object)?),
+        "WebDavTarget" => validate::validate_webdav_target(&get_spec::<WebDavTargetSpec>(object)?),
+        "KafkaTarget" => validate::validate_kafka_target(&get_spec::<KafkaTargetSpec>(object)?),
+        "NatsTarget" => validate::validate_nats_target(&get_spec::<NatsTargetSpec>(object)?),
+        _ => vec![],
+    })
+}
+
+/// Deserializes the spec of the resource.
+fn get_spec<T: DeserializeOwned>(object: &DynamicObject) -> Result<T, Error> {
+    let spec = object.data.get("spec").cloned().unwrap_or_default();
+    Ok(serde_json::from_value(spec)?)
+}
+
+/// Returns `true` if the namespace has any [`DefaultTargets`] with
+/// at least one target, from which a Download may inherit.
+async fn has_default_targets(client: Client, namespace: &str) -> Result<bool, Error> {
+    let api: Api<DefaultTargets> = Api::namespaced(client, namespace);
+    Ok(api
+        .list(&ListParams::default())
+        .await?
+        .items
+        .iter()
+        .any(|default_targets| !default_targets.spec.targets.is_empty()))
+}
@@ -0,0 +1,37 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Configuration for a [`Download`](crate::Download)'s archive, which
+/// records the IDs of the videos that were downloaded successfully. It
+/// is the equivalent of youtube-dl's `--download-archive` option: videos
+/// already in the archive are not downloaded again, even if the Download
+/// is recreated or the same channel is queried by a different Download.
+/// Exactly one backend must be specified.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct DownloadArchiveSpec {
+    /// Name of a `ConfigMap` in the Download's namespace that stores the
+    /// archive under the `archive.txt` key, one ID per line. It is created
+    /// if it does not exist. ConfigMaps are limited to 1 MiB, so use one of
+    /// the other backends for very large channels.
+    #[serde(rename = "configMap")]
+    pub config_map: Option<String>,
+
+    /// Store the archive as a text object, one ID per line, in the bucket
+    /// of an [`S3Target`](crate::S3Target).
+    pub s3: Option<ArchiveObjectSpec>,
+
+    /// Store the archive as a set in the database of a
+    /// [`RedisTarget`](crate::RedisTarget).
+    pub redis: Option<ArchiveObjectSpec>,
+}
+
+/// Location of an archive within a target's backing service.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct ArchiveObjectSpec {
+    /// Name of the target resource in the Download's namespace, whose
+    /// connection details and credentials are used to access the archive.
+    pub target: String,
+
+    /// S3 object key or Redis key of the archive.
+    pub key: String,
+}
@@ -14,9 +14,23 @@ pub struct TargetVerifySpec {
     /// Interval for re-verifying the credentials after they have been
     /// verified for the first time. If unset, the credentials will
     /// only be verified once.
+    #[schemars(regex(pattern = r"^\d+[smhd]?$"))]
     pub interval: Option<String>,
 }
 
+/// A single entry in a resource's phase history.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct PhaseTransition {
+    /// Phase the resource entered.
+    pub phase: String,
+
+    /// Timestamp of when the resource entered the phase.
+    pub timestamp: String,
+
+    /// The status message at the time of the transition.
+    pub message: Option<String>,
+}
+
 /// Status object for the target resources.
 #[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
 pub struct TargetStatus {
@@ -46,9 +60,6 @@ pub enum TargetPhase {
 
     /// The target's backing service is ready to be used.
     Ready,
-
-    /// The credentials test failed with an error.
-    ErrVerifyFailed,
 }
 
 impl FromStr for TargetPhase {
@@ -59,7 +70,6 @@ impl FromStr for TargetPhase {
             "Pending" => Ok(TargetPhase::Pending),
             "Verifying" => Ok(TargetPhase::Verifying),
             "Ready" => Ok(TargetPhase::Ready),
-            "ErrVerifyFailed" => Ok(TargetPhase::ErrVerifyFailed),
             _ => Err(()),
         }
     }
@@ -71,7 +81,6 @@ impl fmt::Display for TargetPhase {
             TargetPhase::Pending => write!(f, "Pending"),
             TargetPhase::Verifying => write!(f, "Verifying"),
             TargetPhase::Ready => write!(f, "Ready"),
-            TargetPhase::ErrVerifyFailed => write!(f, "ErrVerifyFailed"),
         }
     }
 }
@@ -0,0 +1,29 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Standard Kubernetes condition describing one aspect of a resource's
+/// current state. Tools such as Argo CD and kstatus rely on the `Ready`,
+/// `Reconciling`, and `Stalled` conditions to determine health.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct Condition {
+    /// Type of the condition, e.g. `"Ready"`.
+    #[serde(rename = "type")]
+    pub type_: String,
+
+    /// Status of the condition, one of `"True"`, `"False"`, or `"Unknown"`.
+    pub status: String,
+
+    /// Machine-readable reason for the condition's last transition.
+    pub reason: Option<String>,
+
+    /// Human-readable message with details about the transition.
+    pub message: Option<String>,
+
+    /// Timestamp of when the condition last changed status.
+    #[serde(rename = "lastTransitionTime")]
+    pub last_transition_time: Option<String>,
+
+    /// The `metadata.generation` the condition was set based upon.
+    #[serde(rename = "observedGeneration")]
+    pub observed_generation: Option<i64>,
+}
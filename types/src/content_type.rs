@@ -0,0 +1,49 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// A type of content associated with a video that can be stored by ytdl-operator.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ContentType {
+    /// The info json from `youtube-dl -j`.
+    Metadata,
+
+    /// The audiovisual file, which is the video itself.
+    Audiovisual,
+
+    /// The video's thumbnail image.
+    Thumbnail,
+}
+
+impl ContentType {
+    /// All content types, which is the default when none are specified.
+    pub const ALL: [ContentType; 3] = [
+        ContentType::Metadata,
+        ContentType::Audiovisual,
+        ContentType::Thumbnail,
+    ];
+}
+
+impl FromStr for ContentType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "metadata" => Ok(ContentType::Metadata),
+            "audiovisual" => Ok(ContentType::Audiovisual),
+            "thumbnail" => Ok(ContentType::Thumbnail),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentType::Metadata => write!(f, "metadata"),
+            ContentType::Audiovisual => write!(f, "audiovisual"),
+            ContentType::Thumbnail => write!(f, "thumbnail"),
+        }
+    }
+}
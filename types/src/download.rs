@@ -3,6 +3,13 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{fmt, str::FromStr};
 
+use crate::{
+    AgeRestrictedPolicy, Condition, ContentType, CullPolicy, DownloadArchiveSpec,
+    DownloadResultsSpec, GeoBlockedPolicy, ManifestSpec, MetadataFieldsSpec, PhaseTransition,
+    PodTemplate, ProxySpec, QueryEngineKind, ReplicationSpec, UpcomingPolicy, VpnSpec,
+    WorkVolumeSpec,
+};
+
 /// Specification for the [`Download`] resource, which is the central custom resource
 /// for downloading videos with ytdl-operator. The controller will first query the
 /// URL for the info json, then individual pods are created to download each video.
@@ -21,14 +28,29 @@ use std::{fmt, str::FromStr};
 #[kube(
     printcolumn = "{\"jsonPath\": \".status.phase\", \"name\": \"PHASE\", \"type\": \"string\" }"
 )]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.counts.succeeded\", \"name\": \"SUCCEEDED\", \"type\": \"integer\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.totalVideos\", \"name\": \"TOTAL\", \"type\": \"integer\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.percent\", \"name\": \"PROGRESS\", \"type\": \"number\" }"
+)]
 #[kube(
     printcolumn = "{\"jsonPath\": \".status.lastUpdated\", \"name\": \"AGE\", \"type\": \"date\" }"
 )]
 pub struct DownloadSpec {
     /// Input query to youtube-dl. Can be a URL, YouTube video ID, or anything
-    /// else accepted as input by `youtube-dl`.
+    /// else accepted as input by `youtube-dl`. With an [`engine`](DownloadSpec::engine)
+    /// other than `"youtubeDl"`, it's interpreted by the engine instead.
     pub input: String,
 
+    /// How the input is queried for the videos to download. Default is
+    /// `"youtubeDl"`. Use `"flatPlaylist"` to list large channels quickly,
+    /// `"rss"` for a feed URL, or `"static"` for a list of video URLs.
+    pub engine: Option<QueryEngineKind>,
+
     /// If `true`, ignore errors in querying individual entities. This is usually
     /// recommended for playlists and channels because the query will continue
     /// even if some videos are age restricted or otherwise not available.
@@ -43,10 +65,239 @@ pub struct DownloadSpec {
     /// synchronized after the initial query. Example: `"48h"` will re-query the
     /// input every two days, downloading new videos as they are discovered.
     #[serde(rename = "queryInterval")]
+    #[schemars(regex(pattern = r"^\d+[smhd]?$"))]
     pub query_interval: Option<String>,
 
+    /// Maximum number of entities handed to a single executor pod. Entities in a
+    /// batch are downloaded sequentially behind the same VPN connection, which
+    /// drastically reduces pod churn for channels with many short videos or for
+    /// thumbnail-only jobs. Entities keep their batch once it is created, and
+    /// videos discovered by a later query are batched among themselves.
+    /// Default is `1`.
+    #[serde(rename = "batchSize")]
+    #[schemars(range(min = 1))]
+    pub batch_size: Option<u32>,
+
+    /// Types of content to store for each video. Use `["metadata"]` to index
+    /// a channel without storing any videos, or `["metadata", "thumbnail"]`
+    /// to include thumbnails. If unset, all content is stored.
+    pub content: Option<Vec<ContentType>>,
+
+    /// Maximum file size of a video, in the format accepted by youtube-dl's
+    /// `--max-filesize` (e.g. `"500M"`, `"2G"`). Videos whose metadata reports
+    /// a larger size are skipped before any [`DownloadChildProcess`] is created,
+    /// and the value is also passed to youtube-dl as a safeguard.
+    #[serde(rename = "maxFilesize")]
+    #[schemars(regex(pattern = r"^\d+(\.\d+)?[kKmMgGtT]?$"))]
+    pub max_filesize: Option<String>,
+
+    /// Maximum download rate of each download pod in bytes per second, in
+    /// the format accepted by youtube-dl's `--limit-rate` (e.g. `"4M"`).
+    /// Keeps the bandwidth of each pod predictable so that the node's egress
+    /// isn't saturated. Defaults to the operator's configured rate limit, if
+    /// any, and is otherwise unlimited.
+    #[serde(rename = "rateLimit")]
+    #[schemars(regex(pattern = r"^\d+(\.\d+)?[kKmMgGtT]?$"))]
+    pub rate_limit: Option<String>,
+
+    /// If `true`, videos with chapters are split with ffmpeg after they are
+    /// downloaded, and one object is uploaded per chapter instead of one for
+    /// the whole video. The video key template should then include the
+    /// `%(chapter_number)s` and/or `%(chapter_title)s` variables so that the
+    /// chapters don't overwrite each other. Videos without chapter data are
+    /// uploaded whole. Default is `false`.
+    #[serde(rename = "splitChapters")]
+    pub split_chapters: Option<bool>,
+
+    /// Maximum duration of a video (e.g. `"90m"`, `"3h"`). Videos whose
+    /// metadata reports a longer duration are skipped before any
+    /// [`DownloadChildProcess`] is created. Useful for avoiding accidental
+    /// downloads of multi-hour livestream VODs.
+    #[serde(rename = "maxDuration")]
+    #[schemars(regex(pattern = r"^\d+[smhd]?$"))]
+    pub max_duration: Option<String>,
+
+    /// Only download videos uploaded on or after this date, either
+    /// `YYYYMMDD` or relative to today (e.g. `"today-2weeks"`). Passed to
+    /// youtube-dl as `--dateafter`, and videos whose `upload_date` falls
+    /// outside the window are also skipped before any
+    /// [`DownloadChildProcess`] is created. This keeps re-queries of large
+    /// channels cheap.
+    #[serde(rename = "dateAfter")]
+    #[schemars(regex(pattern = r"^(\d{8}|(now|today)([+-]\d+(day|week|month|year)s?)?)$"))]
+    pub date_after: Option<String>,
+
+    /// Only download videos uploaded on or before this date, in the same
+    /// format as [`date_after`](DownloadSpec::date_after). Passed to
+    /// youtube-dl as `--datebefore`.
+    #[serde(rename = "dateBefore")]
+    #[schemars(regex(pattern = r"^(\d{8}|(now|today)([+-]\d+(day|week|month|year)s?)?)$"))]
+    pub date_before: Option<String>,
+
+    /// Expression in yt-dlp's `--match-filter` syntax that videos must
+    /// match to be downloaded, e.g. `"!is_live & original_url!*=/shorts/"`
+    /// to exclude livestreams and shorts, or `"availability=public"` to
+    /// exclude members-only videos. Passed to youtube-dl, and videos that
+    /// don't match are also skipped before any [`DownloadChildProcess`] is
+    /// created. Regular expressions (`~=`) are not supported.
+    #[serde(rename = "matchFilter")]
+    pub match_filter: Option<String>,
+
+    /// Extra arguments for youtube-dl when downloading, one option or
+    /// value per item (e.g. `["--format", "bestaudio"]`). The arguments
+    /// are passed to youtube-dl as is rather than through a shell, so
+    /// values need no quoting. Options that run commands or that the
+    /// operator manages (e.g. `--exec`, `--output`, `--load-info-json`)
+    /// are rejected, as are options outside the cluster's allowlist or
+    /// inside its denylist.
+    #[serde(rename = "extraArgs")]
+    pub extra_args: Option<Vec<String>>,
+
+    /// Determines how videos that fail due to an age gate are handled.
+    /// Default is `"fail"`, which marks the video as failed without
+    /// retrying it.
+    #[serde(rename = "ageRestricted")]
+    pub age_restricted: Option<AgeRestrictedPolicy>,
+
+    /// Determines how videos that are not available in the VPN's current
+    /// region are handled. Default is `"fail"`, which marks the video as
+    /// failed without retrying it.
+    #[serde(rename = "geoBlocked")]
+    pub geo_blocked: Option<GeoBlockedPolicy>,
+
+    /// VPN server regions to try, in order, when a video is geo-blocked and
+    /// [`geo_blocked`](DownloadSpec::geo_blocked) is `"retryOtherRegion"`.
+    /// Each region is attempted at most once. The values are passed to the
+    /// VPN sidecar as-is, so they must be valid for the VPN provider.
+    #[serde(rename = "geoRegions")]
+    pub geo_regions: Option<Vec<String>>,
+
+    /// Determines how premieres and scheduled livestreams that have yet to
+    /// start (a `live_status` of `is_upcoming`) are handled. Default is
+    /// `"skip"`. With `"wait"`, the video's [`DownloadChildProcess`] is not
+    /// created until its scheduled start time.
+    pub upcoming: Option<UpcomingPolicy>,
+
+    /// Maximum number of times a failed download pod is recreated before
+    /// the [`DownloadChildProcess`] enters a terminal `Failed` phase. Retries
+    /// are delayed with exponential backoff. Default is `5`, maximum is `100`.
+    #[serde(rename = "maxRetries")]
+    #[schemars(range(max = 100))]
+    pub max_retries: Option<u32>,
+
+    /// Window without any bytes downloaded (e.g. `"90s"`, `"5m"`) after
+    /// which youtube-dl is killed and restarted, rather than hanging until
+    /// the pod's deadline. A video downloaded to disk, as when splitting
+    /// chapters or with a work volume, resumes from the partial file, while
+    /// a video streamed to storage starts over. After 3 restarts the download
+    /// pod fails and is retried as usual. `"0"` disables stall detection.
+    /// Default is `"5m"`.
+    #[serde(rename = "stallTimeout")]
+    #[schemars(regex(pattern = r"^\d+[smhd]?$"))]
+    pub stall_timeout: Option<String>,
+
+    /// Maximum duration of each download pod (e.g. `"2h"`), counted from
+    /// its creation. When it passes, the executor aborts its uploads,
+    /// cleaning up any partial multipart upload, and the pod fails with
+    /// a timeout that is retried as usual. The pod's
+    /// `activeDeadlineSeconds` is set 30 seconds later in case the
+    /// executor doesn't exit by itself. Default is no limit.
+    #[serde(rename = "downloadTimeout")]
+    #[schemars(regex(pattern = r"^\d+[smhd]?$"))]
+    pub download_timeout: Option<String>,
+
+    /// Scratch volume videos are downloaded to before they are uploaded.
+    /// If omitted, videos are streamed straight to storage, which needs no
+    /// disk space but starts over from the beginning after any failure.
+    #[serde(rename = "workVolume")]
+    pub work_volume: Option<WorkVolumeSpec>,
+
+    /// If `true`, the controller stops creating query pods and child
+    /// [`DownloadChildProcess`] resources until this is unset. Existing
+    /// pods and resources are left alone. Useful for halting a large
+    /// channel sync without deleting it, e.g. when the VPN provider is
+    /// rate-limiting. Default is `false`.
+    pub suspend: Option<bool>,
+
+    /// Number of seconds after the [`Download`] has succeeded before it is
+    /// automatically deleted, along with its child [`DownloadChildProcess`]
+    /// resources. Mirrors the field of the same name on Jobs. If unset, the
+    /// resource is never deleted automatically.
+    #[serde(rename = "ttlSecondsAfterFinished")]
+    pub ttl_seconds_after_finished: Option<u32>,
+
+    /// Determines what happens to the child [`DownloadChildProcess`]
+    /// resources once they succeed. Default is `"retain"`. With
+    /// `"succeeded"`, they are deleted as soon as their videos are in the
+    /// [`archive`](DownloadSpec::archive), which is then required, and
+    /// their totals are kept in [`DownloadStatus::culled`]. Their objects
+    /// are no longer known once they are deleted, so this can't be
+    /// combined with a [`manifest`](DownloadSpec::manifest) or
+    /// [`replication`](DownloadSpec::replication).
+    pub cull: Option<CullPolicy>,
+
+    /// Record of what each [`DownloadChildProcess`] downloaded, which is
+    /// appended to before they are culled. Only written with a
+    /// [`cull`](DownloadSpec::cull) of `"succeeded"`. If unset, only the
+    /// totals in [`DownloadStatus::culled`] are kept.
+    pub results: Option<DownloadResultsSpec>,
+
+    /// Name of a `Secret` with cookies for the video service in its
+    /// `cookies.txt` field, in the Netscape format. The Secret is mounted
+    /// into the query and download pods and passed to youtube-dl with
+    /// `--cookies`, which is necessary for age-restricted and membership
+    /// content. With an [`age_restricted`](DownloadSpec::age_restricted) of
+    /// `"allowWithCookies"`, download pods only use them after the video
+    /// hit an age gate.
+    #[serde(rename = "cookiesSecret")]
+    pub cookies_secret: Option<String>,
+
+    /// Name of a `Secret` with account credentials for sites that require
+    /// logging in. Either `username` and `password` (and optionally
+    /// `twofactor`), passed to youtube-dl as `--username`/`--password`, or
+    /// `netrc`, a netrc file with credentials keyed by extractor name that
+    /// youtube-dl reads with `--netrc`. The Secret is mounted into the
+    /// query and download pods.
+    #[serde(rename = "authSecret")]
+    pub auth_secret: Option<String>,
+
+    /// Configuration of the VPN sidecar of the query and download pods.
+    pub vpn: Option<VpnSpec>,
+
+    /// Proxy for the query and download pods. If set, the pods are
+    /// created without the VPN sidecar and [`vpn`](DownloadSpec::vpn)
+    /// is ignored.
+    pub proxy: Option<ProxySpec>,
+
+    /// Overrides for the query and download pods, e.g. node selectors
+    /// and tolerations for placing them on dedicated nodes.
+    #[serde(rename = "podTemplate")]
+    pub pod_template: Option<PodTemplate>,
+
+    /// Archive of the video IDs that were already downloaded. Videos in the
+    /// archive are not assigned to a [`DownloadChildProcess`], and the IDs
+    /// of successful downloads are added to it. This keeps re-queries of
+    /// large channels from creating thousands of redundant resources.
+    pub archive: Option<DownloadArchiveSpec>,
+
+    /// Manifest of the uploaded objects, generated once the Download
+    /// succeeds. If unset, no manifest is generated.
+    pub manifest: Option<ManifestSpec>,
+
+    /// Replication of the uploaded objects to secondary S3 targets once
+    /// the Download succeeds. If unset, objects are only stored in the
+    /// primary targets.
+    pub replication: Option<ReplicationSpec>,
+
+    /// Fields of the info json that are persisted to the metadata targets.
+    /// If omitted, the whole info json is stored.
+    #[serde(rename = "metadataFields")]
+    pub metadata_fields: Option<MetadataFieldsSpec>,
+
     /// Names of the [`Target`] resources that describe where the different outputs
-    /// will be stored. At least one target must be specified.
+    /// will be stored. If omitted, the targets of the [`DefaultTargets`] resources
+    /// in the Download's namespace are used.
+    #[serde(default)]
     pub targets: Vec<String>,
 }
 
@@ -88,6 +339,115 @@ pub struct DownloadStatus {
     /// due to age restrictions or other errors.
     #[serde(rename = "downloadedVideos")]
     pub downloaded_videos: Option<u32>,
+
+    /// Number of videos in each state, aggregated from the child
+    /// [`DownloadChildProcesses`](DownloadChildProcess).
+    pub counts: Option<DownloadCounts>,
+
+    /// Overall completion of the [`Download`] from 0 to 100. Videos that
+    /// are downloading count for the fraction reported by their pods.
+    pub percent: Option<f64>,
+
+    /// Targets inherited from the namespace's [`DefaultTargets`] resources
+    /// when [`DownloadSpec::targets`] is empty. Resolved once so that later
+    /// changes to the defaults do not affect an in-progress Download.
+    pub targets: Option<Vec<String>>,
+
+    /// Timestamp of when the [`Download`] entered the `Succeeded` phase.
+    #[serde(rename = "completionTime")]
+    pub completion_time: Option<String>,
+
+    /// Totals of the succeeded [`DownloadChildProcesses`](DownloadChildProcess)
+    /// that were deleted per [`DownloadSpec::cull`].
+    pub culled: Option<CulledTotals>,
+
+    /// Names of the [`DownloadChildProcesses`](DownloadChildProcess) whose
+    /// totals were added to [`culled`](DownloadStatus::culled) but that
+    /// may not be deleted yet. They are not counted again while listed.
+    pub culling: Option<Vec<String>>,
+
+    /// Totals for the whole [`Download`], written once it succeeds. Also
+    /// available as JSON in the `ytdl.beebs.dev/summary` annotation.
+    pub summary: Option<DownloadSummary>,
+
+    /// Timestamps of when the query pods created within the last hour
+    /// were created, oldest first. Used to detect a query pod that is
+    /// recreated over and over.
+    #[serde(rename = "podStarts")]
+    pub pod_starts: Option<Vec<String>>,
+
+    /// The most recent phase transitions, oldest first. Only a bounded
+    /// number of entries are kept.
+    #[serde(rename = "phaseHistory")]
+    pub phase_history: Option<Vec<PhaseTransition>>,
+
+    /// Standard Kubernetes conditions summarizing the resource's health,
+    /// for consumption by tools such as Argo CD and kstatus.
+    pub conditions: Option<Vec<Condition>>,
+}
+
+/// Number of videos of a [`Download`] in each state. Videos in the same
+/// batch share a [`DownloadChildProcess`] and therefore its state.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq, Eq)]
+pub struct DownloadCounts {
+    /// Number of videos whose download has yet to start.
+    pub queued: u32,
+
+    /// Number of videos being downloaded.
+    pub running: u32,
+
+    /// Number of videos that were downloaded.
+    pub succeeded: u32,
+
+    /// Number of videos whose download pod failed and is being retried.
+    pub failed: u32,
+
+    /// Number of videos that were skipped per policy.
+    pub skipped: u32,
+}
+
+/// Totals of the [`DownloadChildProcesses`](DownloadChildProcess) that
+/// were deleted after succeeding, which are included in the counts and
+/// summary of the [`Download`] as if they still existed.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq, Eq)]
+pub struct CulledTotals {
+    /// Number of resources that were deleted.
+    pub resources: u32,
+
+    /// Number of videos they downloaded.
+    pub succeeded: u32,
+
+    /// Number of their download pods that failed and were recreated.
+    pub failures: u32,
+
+    /// Total number of bytes they downloaded from the video service.
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+}
+
+/// Totals for a completed [`Download`].
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq, Eq)]
+pub struct DownloadSummary {
+    /// Number of videos that were downloaded.
+    pub succeeded: u32,
+
+    /// Number of videos that were skipped per policy.
+    pub skipped: u32,
+
+    /// Number of download pods that failed and were recreated.
+    pub failures: u32,
+
+    /// Total number of bytes downloaded from the video service.
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+
+    /// Seconds between the creation of the [`Download`] and its completion.
+    #[serde(rename = "wallSeconds")]
+    pub wall_seconds: u64,
+
+    /// Total bytes divided by the wall time.
+    #[serde(rename = "averageBytesPerSecond")]
+    pub average_bytes_per_second: u64,
 }
 
 /// A short description of the [`Download`] resource's current state.
@@ -124,6 +484,9 @@ pub enum DownloadPhase {
     /// backend error or if an age restriction error message is received and the
     /// [`DownloadSpec::ignore_errors`] option is `false`.
     ErrDownloadFailed,
+
+    /// Reconciliation is paused because [`DownloadSpec::suspend`] is `true`.
+    Suspended,
 }
 
 impl FromStr for DownloadPhase {
@@ -138,6 +501,7 @@ impl FromStr for DownloadPhase {
             "Succeeded" => Ok(DownloadPhase::Succeeded),
             "ErrQueryFailed" => Ok(DownloadPhase::ErrQueryFailed),
             "ErrDownloadFailed" => Ok(DownloadPhase::ErrDownloadFailed),
+            "Suspended" => Ok(DownloadPhase::Suspended),
             _ => Err(()),
         }
     }
@@ -153,6 +517,7 @@ impl fmt::Display for DownloadPhase {
             DownloadPhase::Succeeded => write!(f, "Succeeded"),
             DownloadPhase::ErrQueryFailed => write!(f, "ErrQueryFailed"),
             DownloadPhase::ErrDownloadFailed => write!(f, "ErrDownloadFailed"),
+            DownloadPhase::Suspended => write!(f, "Suspended"),
         }
     }
 }
@@ -3,6 +3,11 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{fmt, str::FromStr};
 
+use crate::{
+    AgeRestrictedPolicy, Condition, ContentType, GeoBlockedPolicy, PhaseTransition, PodTemplate,
+    ProxySpec, StoredObject, VpnSpec, WorkVolumeSpec,
+};
+
 /// Specification for the [`DownloadChildProcess`] custom resource, which are created
 /// by the [`Download`] controller for each line in the query's metadata jsonl. This
 /// way individual videos are downloaded using different IP addresses and overall
@@ -23,6 +28,15 @@ use std::{fmt, str::FromStr};
 #[kube(
     printcolumn = "{\"jsonPath\": \".status.phase\", \"name\": \"PHASE\", \"type\": \"string\" }"
 )]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.progress.percent\", \"name\": \"PROGRESS\", \"type\": \"number\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.progress.bytesPerSecond\", \"name\": \"SPEED\", \"type\": \"number\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.progress.etaSeconds\", \"name\": \"ETA\", \"type\": \"integer\" }"
+)]
 #[kube(
     printcolumn = "{\"jsonPath\": \".status.lastUpdated\", \"name\": \"AGE\", \"type\": \"date\" }"
 )]
@@ -34,6 +48,111 @@ pub struct DownloadChildProcessSpec {
     /// the metadata was already queried by the parent [`Download`].
     pub metadata: String,
 
+    /// Metadata json for any additional entities assigned to this resource.
+    /// These are downloaded sequentially by the same pod, after the entity
+    /// described by [`metadata`](DownloadChildProcessSpec::metadata). Only
+    /// populated when the parent [`DownloadSpec::batch_size`] is greater
+    /// than one.
+    pub batch: Option<Vec<String>>,
+
+    /// Types of content to store for the video(s). Inherited from the
+    /// parent [`DownloadSpec::content`]. If unset, all content is stored.
+    pub content: Option<Vec<ContentType>>,
+
+    /// Determines how a geo-blocked video is handled. Inherited from
+    /// the parent [`DownloadSpec::geo_blocked`].
+    #[serde(rename = "geoBlocked")]
+    pub geo_blocked: Option<GeoBlockedPolicy>,
+
+    /// VPN server regions to try when the video is geo-blocked. Inherited
+    /// from the parent [`DownloadSpec::geo_regions`].
+    #[serde(rename = "geoRegions")]
+    pub geo_regions: Option<Vec<String>>,
+
+    /// Maximum file size passed to youtube-dl as `--max-filesize`.
+    /// Inherited from the parent [`DownloadSpec::max_filesize`].
+    #[serde(rename = "maxFilesize")]
+    #[schemars(regex(pattern = r"^\d+(\.\d+)?[kKmMgGtT]?$"))]
+    pub max_filesize: Option<String>,
+
+    /// Maximum download rate in bytes per second, passed to youtube-dl as
+    /// `--limit-rate`. Inherited from the parent
+    /// [`DownloadSpec::rate_limit`](crate::DownloadSpec::rate_limit), or the
+    /// operator's default if unset.
+    #[serde(rename = "rateLimit")]
+    #[schemars(regex(pattern = r"^\d+(\.\d+)?[kKmMgGtT]?$"))]
+    pub rate_limit: Option<String>,
+
+    /// Whether videos are uploaded as one object per chapter. Inherited
+    /// from the parent [`DownloadSpec::split_chapters`](crate::DownloadSpec::split_chapters).
+    #[serde(rename = "splitChapters")]
+    pub split_chapters: Option<bool>,
+
+    /// Determines how an age gate failure is handled. Inherited from
+    /// the parent [`DownloadSpec::age_restricted`].
+    #[serde(rename = "ageRestricted")]
+    pub age_restricted: Option<AgeRestrictedPolicy>,
+
+    /// Maximum number of times a failed download pod is recreated.
+    /// Inherited from the parent [`DownloadSpec::max_retries`].
+    #[serde(rename = "maxRetries")]
+    #[schemars(range(max = 100))]
+    pub max_retries: Option<u32>,
+
+    /// Window without any bytes downloaded after which youtube-dl is
+    /// restarted. Inherited from the parent
+    /// [`DownloadSpec::stall_timeout`](crate::DownloadSpec::stall_timeout).
+    #[serde(rename = "stallTimeout")]
+    #[schemars(regex(pattern = r"^\d+[smhd]?$"))]
+    pub stall_timeout: Option<String>,
+
+    /// Maximum duration of each download pod. Inherited from the parent
+    /// [`DownloadSpec::download_timeout`](crate::DownloadSpec::download_timeout).
+    #[serde(rename = "downloadTimeout")]
+    #[schemars(regex(pattern = r"^\d+[smhd]?$"))]
+    pub download_timeout: Option<String>,
+
+    /// Scratch volume the video is downloaded to before it is uploaded.
+    /// Inherited from the parent
+    /// [`DownloadSpec::work_volume`](crate::DownloadSpec::work_volume).
+    #[serde(rename = "workVolume")]
+    pub work_volume: Option<WorkVolumeSpec>,
+
+    /// Number of seconds after the [`DownloadChildProcess`] has succeeded,
+    /// and its parent [`Download`](crate::Download) has too, before it is
+    /// automatically deleted. Inherited from the parent
+    /// [`DownloadSpec::ttl_seconds_after_finished`](crate::DownloadSpec::ttl_seconds_after_finished).
+    #[serde(rename = "ttlSecondsAfterFinished")]
+    pub ttl_seconds_after_finished: Option<u32>,
+
+    /// Extra arguments for youtube-dl. Inherited from the parent
+    /// [`DownloadSpec::extra_args`](crate::DownloadSpec::extra_args).
+    #[serde(rename = "extraArgs")]
+    pub extra_args: Option<Vec<String>>,
+
+    /// Name of the `Secret` with the cookies for youtube-dl. Inherited from
+    /// the parent [`DownloadSpec::cookies_secret`](crate::DownloadSpec::cookies_secret).
+    #[serde(rename = "cookiesSecret")]
+    pub cookies_secret: Option<String>,
+
+    /// Name of the `Secret` with account credentials for youtube-dl. Inherited
+    /// from the parent [`DownloadSpec::auth_secret`](crate::DownloadSpec::auth_secret).
+    #[serde(rename = "authSecret")]
+    pub auth_secret: Option<String>,
+
+    /// Configuration of the download pod's VPN sidecar. Inherited
+    /// from the parent [`DownloadSpec::vpn`](crate::DownloadSpec::vpn).
+    pub vpn: Option<VpnSpec>,
+
+    /// Proxy for the download pod, used instead of the VPN sidecar.
+    /// Inherited from the parent [`DownloadSpec::proxy`](crate::DownloadSpec::proxy).
+    pub proxy: Option<ProxySpec>,
+
+    /// Overrides for the download pod. Inherited from the parent
+    /// [`DownloadSpec::pod_template`](crate::DownloadSpec::pod_template).
+    #[serde(rename = "podTemplate")]
+    pub pod_template: Option<PodTemplate>,
+
     /// Name reference to a `ContentStorage` resource. Inherited from
     /// the parent [`DownloadSpec::output`].
     pub output: String,
@@ -58,6 +177,122 @@ pub struct DownloadChildProcessStatus {
     /// Timestamp of when the [`DownloadChildProcessStatus`] object was last updated.
     #[serde(rename = "lastUpdated")]
     pub last_updated: Option<String>,
+
+    /// Index into [`DownloadChildProcessSpec::geo_regions`] of the VPN server
+    /// region used by the download pod. Unset when the VPN's default region
+    /// is used, which is always the case for the first attempt.
+    #[serde(rename = "geoRegionIndex")]
+    pub geo_region_index: Option<u32>,
+
+    /// True once the download pod is recreated with the cookies after the
+    /// video hit an age gate, per [`AgeRestrictedPolicy::AllowWithCookies`].
+    #[serde(rename = "withCookies")]
+    pub with_cookies: Option<bool>,
+
+    /// Name of the [`DownloadSpec`](crate::DownloadSpec) policy field that
+    /// caused the video to be skipped, e.g. `"ageRestricted"`. Only set in
+    /// the [`Skipped`](DownloadChildProcessPhase::Skipped) phase.
+    #[serde(rename = "skipPolicy")]
+    pub skip_policy: Option<String>,
+
+    /// Number of times the download pod has been recreated after failing.
+    pub retries: Option<u32>,
+
+    /// Timestamp of when the download pod last failed.
+    #[serde(rename = "lastFailureTime")]
+    pub last_failure_time: Option<String>,
+
+    /// Number of seconds after [`last_failure_time`](DownloadChildProcessStatus::last_failure_time)
+    /// before the download pod is recreated. Doubles with each retry.
+    #[serde(rename = "backoffSeconds")]
+    pub backoff_seconds: Option<u64>,
+
+    /// Number of bytes downloaded by the download pods that have been
+    /// added to the namespace's egress accounting. Compared against the
+    /// reported total to account for each byte exactly once.
+    #[serde(rename = "bytesAccounted")]
+    pub bytes_accounted: Option<u64>,
+
+    /// Timestamp of when the [`DownloadChildProcess`] entered the
+    /// `Succeeded` phase.
+    #[serde(rename = "completionTime")]
+    pub completion_time: Option<String>,
+
+    /// Time spent by the most recent download pod in each stage, as
+    /// reported by the pod, to tell whether slowness comes from the VPN,
+    /// the video service, or the storage backend.
+    pub timing: Option<StageTiming>,
+
+    /// Progress of the video currently being downloaded, as reported by
+    /// youtube-dl in the download pod.
+    pub progress: Option<DownloadProgress>,
+
+    /// Objects uploaded by the download pods, with the size and SHA-256
+    /// checksum of each computed as it was uploaded, so that consumers
+    /// can verify the objects they read from storage.
+    pub objects: Option<Vec<StoredObject>>,
+
+    /// Timestamps of when the download pods created within the last hour
+    /// were created, oldest first. Used to detect a download pod that is
+    /// recreated over and over.
+    #[serde(rename = "podStarts")]
+    pub pod_starts: Option<Vec<String>>,
+
+    /// The most recent phase transitions, oldest first. Only a bounded
+    /// number of entries are kept.
+    #[serde(rename = "phaseHistory")]
+    pub phase_history: Option<Vec<PhaseTransition>>,
+
+    /// Standard Kubernetes conditions summarizing the resource's health,
+    /// for consumption by tools such as Argo CD and kstatus.
+    pub conditions: Option<Vec<Condition>>,
+}
+
+/// Seconds spent by a download pod in each stage of its work. The stages
+/// of a streamed video overlap, as its upload proceeds while it downloads.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct StageTiming {
+    /// Waiting for the VPN sidecar to connect.
+    #[serde(rename = "vpnWaitSeconds")]
+    pub vpn_wait_seconds: f64,
+
+    /// Waiting for the video service, i.e. between starting youtube-dl
+    /// and receiving the first bytes of the video.
+    #[serde(rename = "querySeconds")]
+    pub query_seconds: f64,
+
+    /// Receiving the video and thumbnail from the video service,
+    /// including the upload of a streamed video.
+    #[serde(rename = "downloadSeconds")]
+    pub download_seconds: f64,
+
+    /// Uploading content that was received in full before its upload,
+    /// e.g. a thumbnail or the chapters of a video split by chapter.
+    #[serde(rename = "uploadSeconds")]
+    pub upload_seconds: f64,
+}
+
+/// Progress of a video download as reported by youtube-dl.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct DownloadProgress {
+    /// Percentage of the video downloaded so far, if its size is known.
+    pub percent: Option<f64>,
+
+    /// Bytes of the video downloaded so far.
+    #[serde(rename = "downloadedBytes")]
+    pub downloaded_bytes: u64,
+
+    /// Size of the video in bytes, either exact or estimated.
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: Option<u64>,
+
+    /// Current download speed.
+    #[serde(rename = "bytesPerSecond")]
+    pub bytes_per_second: Option<f64>,
+
+    /// Estimated seconds until the download completes.
+    #[serde(rename = "etaSeconds")]
+    pub eta_seconds: Option<u64>,
 }
 
 /// A short description of the [`DownloadChildProcess`] resource's current state.
@@ -70,6 +305,10 @@ pub enum DownloadChildProcessPhase {
     /// [`Waiting`](vpn_types::MaskPhase::Waiting) phase.
     Waiting,
 
+    /// Creation of the [`DownloadChildProcess`]'s child [`Pod`](k8s_openapi::api::core::v1::Pod)
+    /// is delayed because the operator-wide limit on download pods is reached.
+    Throttled,
+
     /// The [`DownloadChildProcess`]'s child [`Pod`](k8s_openapi::api::core::v1::Pod) is being created.
     Starting,
 
@@ -85,6 +324,10 @@ pub enum DownloadChildProcessPhase {
     /// The failure could originate from either the child [`Mask`](vpn_types::Mask) or
     /// the child [`Pod`](k8s_openapi::api::core::v1::Pod).
     Failed,
+
+    /// The video was intentionally not downloaded in accordance with a
+    /// policy, e.g. [`AgeRestrictedPolicy::Skip`]. This is a final state.
+    Skipped,
 }
 
 impl FromStr for DownloadChildProcessPhase {
@@ -94,10 +337,12 @@ impl FromStr for DownloadChildProcessPhase {
         match s {
             "Pending" => Ok(DownloadChildProcessPhase::Pending),
             "Waiting" => Ok(DownloadChildProcessPhase::Waiting),
+            "Throttled" => Ok(DownloadChildProcessPhase::Throttled),
             "Starting" => Ok(DownloadChildProcessPhase::Starting),
             "Running" => Ok(DownloadChildProcessPhase::Running),
             "Succeeded" => Ok(DownloadChildProcessPhase::Succeeded),
             "Failed" => Ok(DownloadChildProcessPhase::Failed),
+            "Skipped" => Ok(DownloadChildProcessPhase::Skipped),
             _ => Err(()),
         }
     }
@@ -108,10 +353,12 @@ impl fmt::Display for DownloadChildProcessPhase {
         match self {
             DownloadChildProcessPhase::Pending => write!(f, "Pending"),
             DownloadChildProcessPhase::Waiting => write!(f, "Waiting"),
+            DownloadChildProcessPhase::Throttled => write!(f, "Throttled"),
             DownloadChildProcessPhase::Starting => write!(f, "Starting"),
             DownloadChildProcessPhase::Running => write!(f, "Running"),
             DownloadChildProcessPhase::Succeeded => write!(f, "Succeeded"),
             DownloadChildProcessPhase::Failed => write!(f, "Failed"),
+            DownloadChildProcessPhase::Skipped => write!(f, "Skipped"),
         }
     }
 }
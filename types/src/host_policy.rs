@@ -0,0 +1,29 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Restricts the hosts that [`Download`](crate::Download) resources in the
+/// namespace may download from. A host matches an entry if it is equal to
+/// it or is one of its subdomains, so `"example.com"` also matches
+/// `"media.example.com"`. Every `HostPolicy` in the namespace applies, as
+/// well as the operator-wide policy configured by the cluster admin.
+#[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "ytdl.beebs.dev",
+    version = "v1",
+    kind = "HostPolicy",
+    plural = "hostpolicies",
+    namespaced
+)]
+#[kube(derive = "PartialEq")]
+#[kube(derive = "Default")]
+pub struct HostPolicySpec {
+    /// If set, the input of a Download must be a URL whose host matches
+    /// one of these entries.
+    #[serde(rename = "allowedHosts")]
+    pub allowed_hosts: Option<Vec<String>>,
+
+    /// Hosts that Downloads may not download from, even if allowed.
+    #[serde(rename = "blockedHosts")]
+    pub blocked_hosts: Option<Vec<String>>,
+}
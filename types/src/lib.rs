@@ -1,13 +1,41 @@
+mod archive;
 mod common;
+mod condition;
+mod content_type;
 mod download;
 mod download_child_process;
+mod host_policy;
 mod image_filter;
 mod image_format;
+mod manifest;
+mod metadata_fields;
+mod pod_template;
+mod policy;
+mod proxy;
+mod query_engine;
+mod replication;
+mod results;
 mod targets;
+mod vpn;
+mod work_volume;
 
+pub use archive::*;
 pub use common::*;
+pub use condition::*;
+pub use content_type::*;
 pub use download::*;
 pub use download_child_process::*;
+pub use host_policy::*;
 pub use image_filter::*;
 pub use image_format::*;
+pub use manifest::*;
+pub use metadata_fields::*;
+pub use pod_template::*;
+pub use policy::*;
+pub use proxy::*;
+pub use query_engine::*;
+pub use replication::*;
+pub use results::*;
 pub use targets::*;
+pub use vpn::*;
+pub use work_volume::*;
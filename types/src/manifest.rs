@@ -0,0 +1,37 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Configuration for the manifest of a completed [`Download`](crate::Download).
+/// The manifest lists every object uploaded by its downloads along with
+/// their sizes and checksums, so that the integrity of the whole archive
+/// can be verified downstream. It is uploaded to each of the Download's
+/// [`S3Target`](crate::S3Target) metadata targets.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct ManifestSpec {
+    /// Object key template for the manifest. The `%(namespace)s`,
+    /// `%(name)s`, and `%(uid)s` variables are replaced with those
+    /// of the Download. Default is `"manifests/%(namespace)s/%(name)s.json"`.
+    pub key: Option<String>,
+
+    /// Name of the `Secret` with the signing key in its `key` field. If
+    /// set, the hex-encoded HMAC-SHA256 of the manifest is uploaded next
+    /// to it with a `.sig` suffix. If unset, the manifest is not signed.
+    #[serde(rename = "signingSecretRef")]
+    pub signing_secret_ref: Option<String>,
+}
+
+/// An object uploaded by a [`DownloadChildProcess`](crate::DownloadChildProcess).
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq, Eq)]
+pub struct StoredObject {
+    /// Name of the bucket the object was uploaded to.
+    pub bucket: String,
+
+    /// Key of the object.
+    pub key: String,
+
+    /// Size of the object in bytes.
+    pub size: u64,
+
+    /// Hex-encoded SHA-256 checksum of the object.
+    pub sha256: String,
+}
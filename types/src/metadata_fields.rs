@@ -0,0 +1,25 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Selection of the info json fields that are persisted to metadata
+/// targets such as [`SqlTarget`](crate::SqlTarget). The info json is
+/// often huge (formats, comments, every caption track), so storing
+/// only the fields an application needs keeps rows small and schemas
+/// stable. Fields are named by their path with `.` between the keys
+/// of nested objects, e.g. `"uploader"` or `"subtitles.en"`.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct MetadataFieldsSpec {
+    /// Fields to keep. A nested field keeps the objects it's in, but
+    /// none of their other keys. All fields are kept if omitted.
+    pub include: Option<Vec<String>>,
+
+    /// Fields to remove after applying `include`, e.g. `"formats"`
+    /// or `"comments"`.
+    pub exclude: Option<Vec<String>>,
+
+    /// Languages of the `subtitles` and `automatic_captions` tracks to
+    /// keep, e.g. `["en", "de"]`. A language also matches its regional
+    /// variants (`"en"` matches `"en-US"`). All tracks are kept if omitted.
+    #[serde(rename = "captionLanguages")]
+    pub caption_languages: Option<Vec<String>>,
+}
@@ -0,0 +1,34 @@
+use k8s_openapi::api::core::v1::{Affinity, ResourceRequirements, Toleration};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Overrides merged into the pods created by the operator, for placing
+/// them on specific nodes or accounting for their resource usage. Fields
+/// left unset keep the operator's defaults.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct PodTemplate {
+    /// Compute resources of the executor container. The VPN sidecar
+    /// is not affected.
+    pub resources: Option<ResourceRequirements>,
+
+    /// Node labels the pod must be scheduled on.
+    #[serde(rename = "nodeSelector")]
+    pub node_selector: Option<BTreeMap<String, String>>,
+
+    /// Tolerations of the pod, e.g. for running on tainted spot nodes.
+    pub tolerations: Option<Vec<Toleration>>,
+
+    /// Scheduling constraints of the pod.
+    pub affinity: Option<Affinity>,
+
+    /// Extra annotations of the pod.
+    pub annotations: Option<BTreeMap<String, String>>,
+
+    /// Extra labels of the pod. The operator's own labels take precedence.
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// Name of the `RuntimeClass` used to run the pod.
+    #[serde(rename = "runtimeClassName")]
+    pub runtime_class_name: Option<String>,
+}
@@ -0,0 +1,180 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Determines how age-restricted videos are handled.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AgeRestrictedPolicy {
+    /// Age-restricted videos are skipped and do not count as failures.
+    Skip,
+
+    /// Age-restricted videos are retried with the cookies of the
+    /// [`cookiesSecret`](crate::DownloadSpec::cookies_secret), which the
+    /// download pod otherwise doesn't use, so that the account is only
+    /// exposed for the videos that need it. If the age gate is still hit,
+    /// or there are no cookies, the download fails without being retried.
+    AllowWithCookies,
+
+    /// Age-restricted videos fail without being retried. This is the default.
+    Fail,
+}
+
+impl Default for AgeRestrictedPolicy {
+    fn default() -> Self {
+        AgeRestrictedPolicy::Fail
+    }
+}
+
+impl FromStr for AgeRestrictedPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(AgeRestrictedPolicy::Skip),
+            "allowWithCookies" => Ok(AgeRestrictedPolicy::AllowWithCookies),
+            "fail" => Ok(AgeRestrictedPolicy::Fail),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for AgeRestrictedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgeRestrictedPolicy::Skip => write!(f, "skip"),
+            AgeRestrictedPolicy::AllowWithCookies => write!(f, "allowWithCookies"),
+            AgeRestrictedPolicy::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+/// Determines how geo-blocked videos are handled.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum GeoBlockedPolicy {
+    /// Geo-blocked videos are skipped and do not count as failures.
+    Skip,
+
+    /// The download is rescheduled with the VPN connected to the next
+    /// server region in the list of regions to try. Once every region
+    /// has been attempted, the download fails without being retried.
+    RetryOtherRegion,
+
+    /// Geo-blocked videos fail without being retried. This is the default.
+    Fail,
+}
+
+impl Default for GeoBlockedPolicy {
+    fn default() -> Self {
+        GeoBlockedPolicy::Fail
+    }
+}
+
+impl FromStr for GeoBlockedPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(GeoBlockedPolicy::Skip),
+            "retryOtherRegion" => Ok(GeoBlockedPolicy::RetryOtherRegion),
+            "fail" => Ok(GeoBlockedPolicy::Fail),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for GeoBlockedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeoBlockedPolicy::Skip => write!(f, "skip"),
+            GeoBlockedPolicy::RetryOtherRegion => write!(f, "retryOtherRegion"),
+            GeoBlockedPolicy::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+/// Determines how premieres and scheduled livestreams that have yet
+/// to start are handled.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum UpcomingPolicy {
+    /// Upcoming videos are skipped and do not count as failures. This is
+    /// the default, as they have no formats to download until they start.
+    Skip,
+
+    /// Creation of the download pod is deferred until the scheduled start
+    /// time, after which the metadata is queried again and the video is
+    /// downloaded as usual.
+    Wait,
+}
+
+impl Default for UpcomingPolicy {
+    fn default() -> Self {
+        UpcomingPolicy::Skip
+    }
+}
+
+impl FromStr for UpcomingPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(UpcomingPolicy::Skip),
+            "wait" => Ok(UpcomingPolicy::Wait),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for UpcomingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpcomingPolicy::Skip => write!(f, "skip"),
+            UpcomingPolicy::Wait => write!(f, "wait"),
+        }
+    }
+}
+
+/// Determines what happens to the child [`DownloadChildProcess`](crate::DownloadChildProcess)
+/// resources of a [`Download`](crate::Download) once they succeed.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum CullPolicy {
+    /// Succeeded resources are kept until the Download is deleted, or
+    /// until their own TTL elapses. This is the default.
+    Retain,
+
+    /// Succeeded resources are deleted as soon as their videos are in
+    /// the Download's archive, with only their totals kept in the
+    /// Download's status. This keeps long-lived channel syncs from
+    /// accumulating a resource for every video ever downloaded.
+    Succeeded,
+}
+
+impl Default for CullPolicy {
+    fn default() -> Self {
+        CullPolicy::Retain
+    }
+}
+
+impl FromStr for CullPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "retain" => Ok(CullPolicy::Retain),
+            "succeeded" => Ok(CullPolicy::Succeeded),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for CullPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CullPolicy::Retain => write!(f, "retain"),
+            CullPolicy::Succeeded => write!(f, "succeeded"),
+        }
+    }
+}
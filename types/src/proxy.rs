@@ -0,0 +1,18 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// HTTP or SOCKS5 proxy used by the query and download pods instead of
+/// the VPN sidecar. Intended for clusters that already operate a fleet
+/// of rotating proxies, as the pods then no longer need `NET_ADMIN`.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct ProxySpec {
+    /// URL of the proxy, e.g. `"socks5://proxy.example.com:1080"`. The
+    /// scheme must be one of `http`, `https`, `socks5`, or `socks5h`.
+    pub url: String,
+
+    /// Name of the `Secret` with the proxy credentials in its `username`
+    /// and `password` fields. If unset, the proxy is used without
+    /// authentication.
+    #[serde(rename = "secretRef")]
+    pub secret_ref: Option<String>,
+}
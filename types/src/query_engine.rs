@@ -0,0 +1,59 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Determines how the [`Download`](crate::Download)'s input is queried
+/// for the videos to download.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum QueryEngineKind {
+    /// The input is passed to youtube-dl, which queries the full metadata
+    /// of every video. This is the default.
+    YoutubeDl,
+
+    /// The input is passed to youtube-dl with `--flat-playlist`, which
+    /// only lists the videos of a playlist or channel without querying
+    /// each of them. This is much faster for large channels. The full
+    /// metadata of each video is queried before it is downloaded.
+    FlatPlaylist,
+
+    /// The input is the URL of an RSS or Atom feed, whose items are the
+    /// videos. Their full metadata is queried before they are downloaded.
+    Rss,
+
+    /// The input is a list of video URLs, one per line, which are
+    /// downloaded without being queried first. Blank lines and lines
+    /// starting with `#` are ignored.
+    Static,
+}
+
+impl Default for QueryEngineKind {
+    fn default() -> Self {
+        QueryEngineKind::YoutubeDl
+    }
+}
+
+impl FromStr for QueryEngineKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "youtubeDl" => Ok(QueryEngineKind::YoutubeDl),
+            "flatPlaylist" => Ok(QueryEngineKind::FlatPlaylist),
+            "rss" => Ok(QueryEngineKind::Rss),
+            "static" => Ok(QueryEngineKind::Static),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for QueryEngineKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryEngineKind::YoutubeDl => write!(f, "youtubeDl"),
+            QueryEngineKind::FlatPlaylist => write!(f, "flatPlaylist"),
+            QueryEngineKind::Rss => write!(f, "rss"),
+            QueryEngineKind::Static => write!(f, "static"),
+        }
+    }
+}
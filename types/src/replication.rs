@@ -0,0 +1,16 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Replication of the objects uploaded by a [`Download`](crate::Download)
+/// to secondary storage for tiered backup. Once the Download succeeds,
+/// each object is copied from the primary [`S3Target`](crate::S3Target)
+/// it was uploaded to into every replica under the same key. Objects are
+/// copied server-side when the endpoints match, and are otherwise streamed
+/// through the controller. Each copy is read back and its checksum is
+/// verified against the one recorded by the download pod.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct ReplicationSpec {
+    /// Names of the [`S3Target`](crate::S3Target) resources in the
+    /// Download's namespace that the objects are copied to.
+    pub targets: Vec<String>,
+}
@@ -0,0 +1,26 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::ArchiveObjectSpec;
+
+/// Configuration for a [`Download`](crate::Download)'s results, which
+/// record what each culled [`DownloadChildProcess`](crate::DownloadChildProcess)
+/// downloaded. Each video gets a json line with its ID, the objects its
+/// content was uploaded as (with their keys, sizes, and checksums), and
+/// when it finished, so that an auditable record remains after the
+/// resources are deleted per [`DownloadSpec::cull`](crate::DownloadSpec::cull).
+/// Exactly one backend must be specified.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct DownloadResultsSpec {
+    /// Name of a `ConfigMap` in the Download's namespace that stores the
+    /// results under the `results.jsonl` key. It is created if it does not
+    /// exist, and owned by the Download. ConfigMaps are limited to 1 MiB,
+    /// so once it is nearly full the results continue in a ConfigMap with
+    /// the same name and a `-1` suffix, then `-2`, and so on.
+    #[serde(rename = "configMap")]
+    pub config_map: Option<String>,
+
+    /// Store the results as a jsonl object in the bucket of an
+    /// [`S3Target`](crate::S3Target).
+    pub s3: Option<ArchiveObjectSpec>,
+}
@@ -0,0 +1,70 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{common::*, ProxySpec};
+
+/// Azure Blob Storage configuration. Content is uploaded as block blobs,
+/// one block per part, so large videos are streamed without buffering
+/// the whole file. As with [`S3Target`](crate::S3Target), a single
+/// container can hold every type of content if the [`key`](AzureBlobTargetSpec::key)
+/// templates are prefixed with the type of content.
+#[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "ytdl.beebs.dev",
+    version = "v1",
+    kind = "AzureBlobTarget",
+    plural = "azureblobtargets",
+    status = "TargetStatus",
+    namespaced
+)]
+#[kube(derive = "PartialEq")]
+#[kube(derive = "Default")]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.phase\", \"name\": \"PHASE\", \"type\": \"string\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.lastUpdated\", \"name\": \"AGE\", \"type\": \"date\" }"
+)]
+pub struct AzureBlobTargetSpec {
+    /// Name of the blob container (required).
+    pub container: String,
+
+    /// Blob name template. Refer to youtube-dl documentation
+    /// for details on which template variables are available:
+    /// <https://github.com/ytdl-org/youtube-dl#output-template>.
+    /// The default value is `"%(id)s.%(ext)s"`.
+    pub key: Option<String>,
+
+    /// Kubernetes `Secret` resource name with the credentials. Either
+    /// a `connection_string` field, with an account key or shared access
+    /// signature, or a `client_id` field naming the user-assigned managed
+    /// identity to authenticate as. If unset, the system-assigned managed
+    /// identity of the node is used.
+    pub secret: Option<String>,
+
+    /// Name of the storage account. Required with managed identities, as
+    /// the account is otherwise taken from the connection string.
+    pub account: Option<String>,
+
+    /// Alternative blob service endpoint, e.g. for Azurite or a sovereign
+    /// cloud. Default is `"https://<account>.blob.core.windows.net"`.
+    pub endpoint: Option<String>,
+
+    /// Verification configuration for the blob service. Default behavior is
+    /// to verify the credentials once and never again.
+    pub verify: Option<TargetVerifySpec>,
+
+    /// Proxy the download pods upload to the container through, instead
+    /// of the VPN or proxy used for the video service. Use this for
+    /// split-tunnel network policies. All S3, GCS, Azure, and webhook
+    /// targets of a [`Target`](crate::Target) must use the same proxy.
+    pub proxy: Option<ProxySpec>,
+
+    /// Name of a Kubernetes `Secret` with the PEM-encoded CA certificates,
+    /// as the `ca.crt` field, that the endpoint's certificate is verified
+    /// against. Use this for endpoints with self-signed or private-CA
+    /// certificates. The system's CA certificates are trusted as well.
+    #[serde(rename = "caBundleSecret")]
+    pub ca_bundle_secret: Option<String>,
+}
@@ -0,0 +1,23 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Namespace-wide default targets. A [`Download`](crate::Download) that omits
+/// [`targets`](crate::DownloadSpec::targets) inherits the targets of every
+/// `DefaultTargets` resource in its namespace, which allows teams to create
+/// Downloads without knowing where the content is stored.
+#[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "ytdl.beebs.dev",
+    version = "v1",
+    kind = "DefaultTargets",
+    plural = "defaulttargets",
+    namespaced
+)]
+#[kube(derive = "PartialEq")]
+#[kube(derive = "Default")]
+pub struct DefaultTargetsSpec {
+    /// Names of the [`Target`](crate::Target) resources inherited by
+    /// Downloads in this namespace.
+    pub targets: Vec<String>,
+}
@@ -0,0 +1,65 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{common::*, ProxySpec};
+
+/// Google Cloud Storage configuration. Content is uploaded with the JSON
+/// API's resumable uploads, so large videos survive dropped connections
+/// without an S3 interoperability shim in between. As with [`S3Target`](crate::S3Target),
+/// a single bucket can hold every type of content if the [`key`](GcsTargetSpec::key)
+/// templates are prefixed with the type of content.
+#[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "ytdl.beebs.dev",
+    version = "v1",
+    kind = "GcsTarget",
+    plural = "gcstargets",
+    status = "TargetStatus",
+    namespaced
+)]
+#[kube(derive = "PartialEq")]
+#[kube(derive = "Default")]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.phase\", \"name\": \"PHASE\", \"type\": \"string\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.lastUpdated\", \"name\": \"AGE\", \"type\": \"date\" }"
+)]
+pub struct GcsTargetSpec {
+    /// GCS bucket name (required).
+    pub bucket: String,
+
+    /// Object name template. Refer to youtube-dl documentation
+    /// for details on which template variables are available:
+    /// <https://github.com/ytdl-org/youtube-dl#output-template>.
+    /// The default value is `"%(id)s.%(ext)s"`.
+    pub key: Option<String>,
+
+    /// Kubernetes `Secret` resource name containing a service account
+    /// key as the `service_account.json` field. If unset, the token of
+    /// the pod's service account is requested from the GKE metadata
+    /// server, which is the case with Workload Identity.
+    pub secret: Option<String>,
+
+    /// Alternative API endpoint, e.g. for a private service connect
+    /// endpoint or an emulator. Default is `"https://storage.googleapis.com"`.
+    pub endpoint: Option<String>,
+
+    /// Verification configuration for the GCS service. Default behavior is
+    /// to verify the credentials once and never again.
+    pub verify: Option<TargetVerifySpec>,
+
+    /// Proxy the download pods upload to the bucket through, instead of
+    /// the VPN or proxy used for the video service. Use this for
+    /// split-tunnel network policies. All S3, GCS, Azure, and webhook
+    /// targets of a [`Target`](crate::Target) must use the same proxy.
+    pub proxy: Option<ProxySpec>,
+
+    /// Name of a Kubernetes `Secret` with the PEM-encoded CA certificates,
+    /// as the `ca.crt` field, that the endpoint's certificate is verified
+    /// against. Use this for endpoints with self-signed or private-CA
+    /// certificates. The system's CA certificates are trusted as well.
+    #[serde(rename = "caBundleSecret")]
+    pub ca_bundle_secret: Option<String>,
+}
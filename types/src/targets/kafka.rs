@@ -0,0 +1,82 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+
+/// SASL authentication with the brokers.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct KafkaSaslSpec {
+    /// SASL mechanism, one of `"PLAIN"`, `"SCRAM-SHA-256"`, or
+    /// `"SCRAM-SHA-512"`. Default is `"PLAIN"`.
+    pub mechanism: Option<String>,
+
+    /// Name of the Kubernetes `Secret` with the credentials as the
+    /// `username` and `password` fields, as in a `kubernetes.io/basic-auth`
+    /// Secret (required).
+    pub secret: String,
+}
+
+/// TLS connections to the brokers. The brokers' certificates are
+/// verified against the system's CA certificates and the
+/// [`caBundleSecret`](KafkaTargetSpec::ca_bundle_secret), if any.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct KafkaTlsSpec {
+    /// Name of a Kubernetes `Secret` with the client certificate and
+    /// key, as the `tls.crt` and `tls.key` fields of a `kubernetes.io/tls`
+    /// Secret, for brokers that require mutual TLS.
+    pub secret: Option<String>,
+}
+
+/// Configuration for Kafka metadata output. After each video is
+/// downloaded, the executor produces a message with its metadata json
+/// to the topic, so that downstream pipelines can react to new videos
+/// instead of polling the other targets. Only valid as a metadata target.
+#[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "ytdl.beebs.dev",
+    version = "v1",
+    kind = "KafkaTarget",
+    plural = "kafkatargets",
+    status = "TargetStatus",
+    namespaced
+)]
+#[kube(derive = "PartialEq")]
+#[kube(derive = "Default")]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.phase\", \"name\": \"PHASE\", \"type\": \"string\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.lastUpdated\", \"name\": \"AGE\", \"type\": \"date\" }"
+)]
+pub struct KafkaTargetSpec {
+    /// Addresses of the bootstrap brokers as `host:port` (required).
+    pub brokers: Vec<String>,
+
+    /// Name of the topic the messages are produced to (required).
+    pub topic: String,
+
+    /// Template for the message key. Refer to the youtube-dl documentation
+    /// on output templates:
+    /// <https://github.com/ytdl-org/youtube-dl/blob/master/README.md#output-template>
+    /// Messages with the same key go to the same partition, so the
+    /// messages of a video are ordered. Default is `"%(id)s"`.
+    pub key: Option<String>,
+
+    /// SASL authentication with the brokers. Default is no authentication.
+    pub sasl: Option<KafkaSaslSpec>,
+
+    /// TLS connections to the brokers. Default is plaintext connections.
+    pub tls: Option<KafkaTlsSpec>,
+
+    /// Verification settings for the brokers. Default behavior is to
+    /// verify the credentials once and never again.
+    pub verify: Option<TargetVerifySpec>,
+
+    /// Name of a Kubernetes `Secret` with the PEM-encoded CA certificates,
+    /// as the `ca.crt` field, that the brokers' certificates are verified
+    /// against. Use this for brokers with self-signed or private-CA
+    /// certificates. The system's CA certificates are trusted as well.
+    #[serde(rename = "caBundleSecret")]
+    pub ca_bundle_secret: Option<String>,
+}
@@ -1,13 +1,29 @@
+mod azure;
+mod default_targets;
+mod gcs;
+mod kafka;
 mod mongodb;
+mod nats;
 mod redis;
 mod s3;
+mod sftp;
 mod sql;
 mod target;
+mod volume;
+mod webdav;
 mod webhook;
 
+pub use azure::*;
+pub use default_targets::*;
+pub use gcs::*;
+pub use kafka::*;
 pub use mongodb::*;
+pub use nats::*;
 pub use redis::*;
 pub use s3::*;
+pub use sftp::*;
 pub use sql::*;
 pub use target::*;
+pub use volume::*;
+pub use webdav::*;
 pub use webhook::*;
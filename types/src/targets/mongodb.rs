@@ -4,14 +4,17 @@ use serde::{Deserialize, Serialize};
 
 use crate::common::*;
 
-/// Configuration for MongoDB-compatible metadata output. Use this if your application
+/// Configuration for MongoDB-compatible output. Use this if your application
 /// is designed to retrieved the metadata json from a MongoDB database. The executor
-/// pods will connect to the database and insert the metadata json while querying.
+/// pods connect to the database once each video's content is stored and upsert
+/// the metadata json as-is into a single collection, keyed by the document's `_id`.
 /// The executor uses the [`mongodb`](https://crates.io/crates/mongodb) crate to
-/// connect to the database and store the metadata json as-is in a single collection.
+/// connect to the database.
 /// Thumbnails and AV files are stored in the `thumbnails` and `av` collections,
 /// respectively. The document's `_id` field is derived the same way as with metadata,
-/// and the only other field in the document is `payload` that contains the file bytes.
+/// and the other fields are `payload`, which contains the file bytes, `contentType`,
+/// `size`, and `sha256`. Files too large for a document (16 MiB) are instead stored
+/// in the GridFS bucket named after the collection, with the same `_id`.
 #[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
 #[kube(
     group = "ytdl.beebs.dev",
@@ -32,14 +35,13 @@ use crate::common::*;
 pub struct MongoDBTargetSpec {
     /// Name of the Kubernetes [`Secret`](https://kubernetes.io/docs/concepts/configuration/secret/)
     /// resource containing the database credentials. The secret must contain
-    /// the following fields:
-    ///     - `username`
-    ///     - `password`
+    /// a `database` field with the name of the database, and either a `uri`
+    /// field with a [connection string](https://www.mongodb.com/docs/manual/reference/connection-string/)
+    /// or the following fields:
     ///     - `host`
-    ///     - `port`
-    ///     - `database`
-    ///     - `sslmode`
-    ///     - `sslcert` (where necessary)
+    ///     - `port` (default 27017)
+    ///     - `username` and `password` (where necessary)
+    ///     - `tls` (`"true"` to connect with TLS)
     pub secret: String,
 
     /// Collection name override. Default depends on the type of content being stored.
@@ -58,4 +60,11 @@ pub struct MongoDBTargetSpec {
     /// Verification settings for the MongoDB database. Default behavior is to
     /// verify the credentials once and never again.
     pub verify: Option<TargetVerifySpec>,
+
+    /// Name of a Kubernetes `Secret` with the PEM-encoded CA certificates,
+    /// as the `ca.crt` field, that the database's certificate is verified
+    /// against. Use this for endpoints with self-signed or private-CA
+    /// certificates. The system's CA certificates are trusted as well.
+    #[serde(rename = "caBundleSecret")]
+    pub ca_bundle_secret: Option<String>,
 }
@@ -0,0 +1,95 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+
+/// Determines what is published for each video.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum NatsPayload {
+    /// The video's metadata json. This is the default.
+    Metadata,
+
+    /// A completion event with the video's ID and the objects its
+    /// content was uploaded as, for consumers that don't need the
+    /// whole metadata json.
+    Event,
+}
+
+impl Default for NatsPayload {
+    fn default() -> Self {
+        NatsPayload::Metadata
+    }
+}
+
+/// JetStream persistence of the messages.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct NatsJetStreamSpec {
+    /// Name of the stream the subject is expected to be captured by.
+    /// Publishing fails if the subject belongs to any other stream.
+    /// Default is any stream.
+    pub stream: Option<String>,
+}
+
+/// Configuration for NATS output. After each video is downloaded, the
+/// executor publishes its metadata json or a completion event to the
+/// subject. With [`jetStream`](NatsTargetSpec::jet_stream) set, the
+/// executor waits for the stream to acknowledge each message, so that
+/// no message is lost if a consumer is offline. Only valid as a
+/// metadata target.
+#[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "ytdl.beebs.dev",
+    version = "v1",
+    kind = "NatsTarget",
+    plural = "natstargets",
+    status = "TargetStatus",
+    namespaced
+)]
+#[kube(derive = "PartialEq")]
+#[kube(derive = "Default")]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.phase\", \"name\": \"PHASE\", \"type\": \"string\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.lastUpdated\", \"name\": \"AGE\", \"type\": \"date\" }"
+)]
+pub struct NatsTargetSpec {
+    /// URLs of the servers (required), e.g. `"nats://nats.nats.svc:4222"`.
+    /// Use the `tls://` scheme for servers that require TLS.
+    pub servers: Vec<String>,
+
+    /// Subject template. Refer to the youtube-dl documentation on output
+    /// templates:
+    /// <https://github.com/ytdl-org/youtube-dl/blob/master/README.md#output-template>
+    /// e.g. `"videos.%(extractor)s"` (required).
+    pub subject: String,
+
+    /// What is published for each video. Default is `"metadata"`.
+    pub payload: Option<NatsPayload>,
+
+    /// Name of the Kubernetes `Secret` with the credentials, if the
+    /// servers require any. The secret must contain one of the following:
+    ///     - `creds`: a user credentials file, as created by `nsc`
+    ///     - `token`: an authentication token
+    ///     - `username` and `password`
+    pub secret: Option<String>,
+
+    /// Publish to JetStream rather than core NATS. Default is core NATS,
+    /// where messages are only received by the subscribers connected at
+    /// the time.
+    #[serde(rename = "jetStream")]
+    pub jet_stream: Option<NatsJetStreamSpec>,
+
+    /// Verification settings for the servers. Default behavior is to
+    /// verify the credentials once and never again.
+    pub verify: Option<TargetVerifySpec>,
+
+    /// Name of a Kubernetes `Secret` with the PEM-encoded CA certificates,
+    /// as the `ca.crt` field, that the servers' certificates are verified
+    /// against. Use this for servers with self-signed or private-CA
+    /// certificates. The system's CA certificates are trusted as well.
+    #[serde(rename = "caBundleSecret")]
+    pub ca_bundle_secret: Option<String>,
+}
@@ -26,15 +26,18 @@ use crate::common::*;
 )]
 pub struct RedisTargetSpec {
     /// Name of the Kubernetes [`Secret`](https://kubernetes.io/docs/concepts/configuration/secret/)
-    /// resource containing the database credentials. The secret must contain
-    /// the following fields:
-    ///     - `username`
-    ///     - `password`
+    /// resource containing the server's address and credentials. The secret
+    /// must contain the following fields:
     ///     - `host`
-    ///     - `port`
-    ///     - `database`
-    ///     - `sslmode`
-    ///     - `sslcert` (where necessary)
+    ///     - `port` (optional, default `6379`)
+    ///     - `username` (optional, for ACL users)
+    ///     - `password` (optional)
+    ///     - `database` (optional, default `0`)
+    ///     - `tls` (optional, `"true"` to connect with TLS)
+    ///
+    /// Secrets written for the `sslmode` field are still accepted: any mode
+    /// but `disable` connects with TLS, unless `tls` is set. An `sslcert`
+    /// is rejected, as client certificates aren't supported.
     pub secret: String,
 
     /// Template for the redis key. Refer to the youtube-dl documentation on output templates:
@@ -64,8 +67,27 @@ pub struct RedisTargetSpec {
     #[serde(rename = "extraKeys")]
     pub extra_keys: Option<Vec<String>>,
 
+    /// Optional expiry of the key, e.g. `"24h"`. With a `script`, the
+    /// expiry is set on `KEYS[1]` after the script runs, if the script
+    /// created it. Default is for the key to never expire.
+    pub ttl: Option<String>,
+
+    /// Largest video or thumbnail written to the key, in the same format
+    /// as youtube-dl's `--max-filesize` (e.g. `"100M"`). The download pod
+    /// holds the file in memory to write it, and Redis limits values to
+    /// 512 MiB, so larger files fail to upload. Default is `"64M"`.
+    #[serde(rename = "maxFilesize")]
+    pub max_filesize: Option<String>,
+
     /// Verification settings for the Redis service. The credentials are verified
     /// by dialing the server and executing a ping command. Default behavior is to
     /// verify the credentials once and never again.
     pub verify: Option<TargetVerifySpec>,
+
+    /// Name of a Kubernetes `Secret` with the PEM-encoded CA certificates,
+    /// as the `ca.crt` field, that the database's certificate is verified
+    /// against. Use this for endpoints with self-signed or private-CA
+    /// certificates. The system's CA certificates are trusted as well.
+    #[serde(rename = "caBundleSecret")]
+    pub ca_bundle_secret: Option<String>,
 }
@@ -1,8 +1,35 @@
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-use crate::common::*;
+use crate::{common::*, ProxySpec};
+
+/// Server-side encryption algorithm for uploaded objects.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub enum SseAlgorithm {
+    /// Keys managed by S3 (SSE-S3).
+    #[serde(rename = "AES256")]
+    Aes256,
+
+    /// Keys managed by AWS KMS (SSE-KMS).
+    #[serde(rename = "aws:kms")]
+    AwsKms,
+}
+
+/// Server-side encryption settings for the objects uploaded to a bucket.
+/// Use this for buckets whose policy rejects unencrypted uploads.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SseSpec {
+    /// Encryption algorithm, sent as `x-amz-server-side-encryption`.
+    pub algorithm: SseAlgorithm,
+
+    /// ID or ARN of the KMS key to encrypt with, sent as
+    /// `x-amz-server-side-encryption-aws-kms-key-id`. Only valid with
+    /// `aws:kms`. If unset, the bucket's default KMS key is used.
+    #[serde(rename = "kmsKeyId")]
+    pub kms_key_id: Option<String>,
+}
 
 /// S3-compatiable storage configuration. All content (video, audio, thumbnail,
 /// and metadata json) are stored in S3 buckets. You can use the same bucket
@@ -66,4 +93,41 @@ pub struct S3TargetSpec {
     /// Verification configuration for the S3 service. Default behavior is to
     /// verify the credentials once and never again.
     pub verify: Option<TargetVerifySpec>,
+
+    /// Server-side encryption for the objects uploaded to the bucket.
+    /// If unset, the bucket's default encryption applies.
+    pub sse: Option<SseSpec>,
+
+    /// Storage class of the objects uploaded to the bucket, e.g.
+    /// `"STANDARD_IA"` or `"GLACIER_IR"`. Use separate targets to keep
+    /// videos in cold storage and metadata in the standard class. If
+    /// unset, the bucket's default storage class applies.
+    #[serde(rename = "storageClass")]
+    pub storage_class: Option<String>,
+
+    /// Headers stored with the objects uploaded to the bucket, e.g.
+    /// `Cache-Control` for a CDN or `Content-Disposition`. Accepts the
+    /// standard object headers and `x-amz-meta-*` user metadata. A
+    /// `Content-Type` overrides the type detected from the content.
+    #[serde(rename = "objectHeaders")]
+    pub object_headers: Option<BTreeMap<String, String>>,
+
+    /// Tags applied to the objects uploaded to the bucket, for tag-based
+    /// lifecycle policies and cost allocation. Values may use the key
+    /// template variables, e.g. `channel: "%(channel_id)s"`. At most 10.
+    /// The credentials need the `s3:PutObjectTagging` permission.
+    pub tags: Option<BTreeMap<String, String>>,
+
+    /// Proxy the download pods upload to the bucket through, instead of
+    /// the VPN or proxy used for the video service. Use this for
+    /// split-tunnel network policies. All S3 and webhook targets of a
+    /// [`Target`](crate::Target) must use the same proxy.
+    pub proxy: Option<ProxySpec>,
+
+    /// Name of a Kubernetes `Secret` with the PEM-encoded CA certificates,
+    /// as the `ca.crt` field, that the endpoint's certificate is verified
+    /// against. Use this for endpoints with self-signed or private-CA
+    /// certificates. The system's CA certificates are trusted as well.
+    #[serde(rename = "caBundleSecret")]
+    pub ca_bundle_secret: Option<String>,
 }
@@ -0,0 +1,70 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+
+/// SFTP server configuration. The download pods upload the content to
+/// the server as files, e.g. to a seedbox that exposes nothing else.
+/// Unlike the object stores, SFTP connections can't go through an HTTP
+/// proxy, so the server is reached directly from the download pod, or
+/// through its VPN if it has one.
+#[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "ytdl.beebs.dev",
+    version = "v1",
+    kind = "SftpTarget",
+    plural = "sftptargets",
+    status = "TargetStatus",
+    namespaced
+)]
+#[kube(derive = "PartialEq")]
+#[kube(derive = "Default")]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.phase\", \"name\": \"PHASE\", \"type\": \"string\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.lastUpdated\", \"name\": \"AGE\", \"type\": \"date\" }"
+)]
+pub struct SftpTargetSpec {
+    /// Hostname or IP address of the server (required).
+    pub host: String,
+
+    /// Port of the server. Default is `22`.
+    pub port: Option<u16>,
+
+    /// Name of the user to log in as (required).
+    pub user: String,
+
+    /// File path template. Relative paths are relative to the user's
+    /// home directory. Refer to youtube-dl documentation for details
+    /// on which template variables are available:
+    /// <https://github.com/ytdl-org/youtube-dl#output-template>.
+    /// Directories in the path are created as needed.
+    /// The default value is `"%(id)s.%(ext)s"`.
+    pub path: Option<String>,
+
+    /// Kubernetes `Secret` resource name with the user's credentials
+    /// (required): an OpenSSH private key as the `ssh-privatekey` field,
+    /// as in a `kubernetes.io/ssh-auth` Secret, and/or a password as the
+    /// `password` field. The key is tried first if both are present.
+    pub secret: String,
+
+    /// SHA-256 fingerprint of the server's host key, as printed by
+    /// `ssh-keygen -l` (e.g. `"SHA256:nThbg6kXUpJWGl7E1IGOCspRomTxdCARLviKw6E5SY8"`).
+    /// Connections to a server with any other host key are refused.
+    /// Required unless [`insecure_skip_host_key_verification`](SftpTargetSpec::insecure_skip_host_key_verification)
+    /// is `true`.
+    #[serde(rename = "hostKeyFingerprint")]
+    pub host_key_fingerprint: Option<String>,
+
+    /// If `true`, the server's host key is not verified, which leaves
+    /// the uploads and credentials open to interception. Only use this
+    /// for testing. Default is `false`.
+    #[serde(rename = "insecureSkipHostKeyVerification")]
+    pub insecure_skip_host_key_verification: Option<bool>,
+
+    /// Verification configuration for the SFTP server. Default behavior
+    /// is to verify the credentials once and never again.
+    pub verify: Option<TargetVerifySpec>,
+}
@@ -6,7 +6,9 @@ use crate::common::*;
 
 /// Configuration for SQL-compatible metadata output. Use this if your application
 /// is designed to retrieved the metadata json from a SQL database. The executor
-/// pods will connect to the database and insert the metadata while querying.
+/// pods connect to the PostgreSQL database once each video's content is stored
+/// and upsert its metadata, keyed by the video ID, into the `videos`, `formats`,
+/// and `thumbnails` tables, which are created by migrations when first connecting.
 #[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
 #[kube(
     group = "ytdl.beebs.dev",
@@ -31,13 +33,23 @@ pub struct SqlTargetSpec {
     ///     - `username`
     ///     - `password`
     ///     - `host`
-    ///     - `port`
     ///     - `database`
-    ///     - `sslmode`
-    ///     - `sslcert` (where necessary)
+    ///
+    /// and may contain:
+    ///     - `port` (default 5432)
+    ///     - `sslmode` (default `prefer`)
+    ///     - `sslcert` and `sslkey`, the PEM-encoded client certificate and
+    ///       key (where necessary)
     pub secret: String,
 
     /// Verification settings for the SQL database. Default behavior is to
     /// verify the credentials once and never again.
     pub verify: Option<TargetVerifySpec>,
+
+    /// Name of a Kubernetes `Secret` with the PEM-encoded CA certificates,
+    /// as the `ca.crt` field, that the database's certificate is verified
+    /// against. Use this for endpoints with self-signed or private-CA
+    /// certificates. The system's CA certificates are trusted as well.
+    #[serde(rename = "caBundleSecret")]
+    pub ca_bundle_secret: Option<String>,
 }
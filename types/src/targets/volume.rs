@@ -0,0 +1,48 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+
+/// Filesystem configuration. The Executor controller mounts the
+/// `PersistentVolumeClaim` into the download pods, which write the
+/// content to it as files, e.g. to an NFS share or a ZFS dataset,
+/// without an S3 gateway in between.
+#[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "ytdl.beebs.dev",
+    version = "v1",
+    kind = "VolumeTarget",
+    plural = "volumetargets",
+    status = "TargetStatus",
+    namespaced
+)]
+#[kube(derive = "PartialEq")]
+#[kube(derive = "Default")]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.phase\", \"name\": \"PHASE\", \"type\": \"string\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.lastUpdated\", \"name\": \"AGE\", \"type\": \"date\" }"
+)]
+pub struct VolumeTargetSpec {
+    /// Name of the `PersistentVolumeClaim` in the target's namespace
+    /// (required). Download pods of several Executors may run at once,
+    /// so the claim should be `ReadWriteMany` unless they all run on
+    /// the same node.
+    #[serde(rename = "claimName")]
+    pub claim_name: String,
+
+    /// File path template, relative to the [`sub_path`](VolumeTargetSpec::sub_path).
+    /// Refer to youtube-dl documentation for details on which template
+    /// variables are available:
+    /// <https://github.com/ytdl-org/youtube-dl#output-template>.
+    /// Directories in the path are created as needed.
+    /// The default value is `"%(id)s.%(ext)s"`.
+    pub path: Option<String>,
+
+    /// Directory within the volume the paths are relative to. Default
+    /// is the root of the volume.
+    #[serde(rename = "subPath")]
+    pub sub_path: Option<String>,
+}
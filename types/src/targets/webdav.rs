@@ -0,0 +1,64 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{common::*, ProxySpec};
+
+/// WebDAV server configuration, e.g. a Nextcloud or ownCloud instance.
+/// The download pods upload the content as files with streaming PUT
+/// requests, so large videos are never buffered in memory.
+#[derive(CustomResource, Serialize, Default, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "ytdl.beebs.dev",
+    version = "v1",
+    kind = "WebDavTarget",
+    plural = "webdavtargets",
+    status = "TargetStatus",
+    namespaced
+)]
+#[kube(derive = "PartialEq")]
+#[kube(derive = "Default")]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.phase\", \"name\": \"PHASE\", \"type\": \"string\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.lastUpdated\", \"name\": \"AGE\", \"type\": \"date\" }"
+)]
+pub struct WebDavTargetSpec {
+    /// URL of the collection the files are uploaded to (required), e.g.
+    /// `"https://cloud.example.com/remote.php/dav/files/alice/videos"`
+    /// for a Nextcloud user's `videos` folder.
+    pub url: String,
+
+    /// File path template, relative to the [`url`](WebDavTargetSpec::url).
+    /// Refer to youtube-dl documentation for details on which template
+    /// variables are available:
+    /// <https://github.com/ytdl-org/youtube-dl#output-template>.
+    /// Collections in the path are created as needed.
+    /// The default value is `"%(id)s.%(ext)s"`.
+    pub path: Option<String>,
+
+    /// Kubernetes `Secret` resource name with the HTTP basic auth
+    /// credentials (required), as the `username` and `password` fields
+    /// of a `kubernetes.io/basic-auth` Secret. For Nextcloud, use an
+    /// app password rather than the user's own.
+    pub secret: String,
+
+    /// Verification configuration for the WebDAV server. Default behavior
+    /// is to verify the credentials once and never again.
+    pub verify: Option<TargetVerifySpec>,
+
+    /// Proxy the download pods upload to the server through, instead
+    /// of the VPN or proxy used for the video service. Use this for
+    /// split-tunnel network policies. All S3, GCS, Azure, WebDAV, and
+    /// webhook targets of a [`Target`](crate::Target) must use the same
+    /// proxy.
+    pub proxy: Option<ProxySpec>,
+
+    /// Name of a Kubernetes `Secret` with the PEM-encoded CA certificates,
+    /// as the `ca.crt` field, that the server's certificate is verified
+    /// against. Use this for servers with self-signed or private-CA
+    /// certificates. The system's CA certificates are trusted as well.
+    #[serde(rename = "caBundleSecret")]
+    pub ca_bundle_secret: Option<String>,
+}
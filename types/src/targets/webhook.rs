@@ -3,7 +3,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
-use crate::common::*;
+use crate::{common::*, ProxySpec};
 
 /// Configuration for a webhook's HTTP Basic Auth.
 #[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
@@ -50,12 +50,20 @@ pub struct WebhookTargetSpec {
     /// `"test"`. The server should respond with a 200 status code.
     pub verify: Option<TargetVerifySpec>,
 
+    /// Name of a Kubernetes `Secret` with the PEM-encoded CA certificates,
+    /// as the `ca.crt` field, that the webhook's certificate is verified
+    /// against. Use this for endpoints with self-signed or private-CA
+    /// certificates. The system's CA certificates are trusted as well.
+    #[serde(rename = "caBundleSecret")]
+    pub ca_bundle_secret: Option<String>,
+
     /// HTTP method override. Default is `"POST"`.
     pub method: Option<String>,
 
     /// Request timeout duration string. Default is `"10s"`. You will
     /// want to increase this value to something like `"5m"` if you are
     /// sending large AV files.
+    #[schemars(regex(pattern = r"^\d+[smhd]?$"))]
     pub timeout: Option<String>,
 
     /// Optional HTTP basic auth configuration.
@@ -67,4 +75,10 @@ pub struct WebhookTargetSpec {
     /// For HTTP basic auth, it is recommended to use the `basicAuth` field
     /// instead of hard-coding them into this map.
     pub headers: Option<BTreeMap<String, String>>,
+
+    /// Proxy the download pods upload to the webhook through, instead of
+    /// the VPN or proxy used for the video service. Use this for
+    /// split-tunnel network policies. All S3 and webhook targets of a
+    /// [`Target`](crate::Target) must use the same proxy.
+    pub proxy: Option<ProxySpec>,
 }
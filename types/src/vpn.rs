@@ -0,0 +1,47 @@
+use k8s_openapi::api::core::v1::EnvVar;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Configuration for the [gluetun](https://github.com/qdm12/gluetun) VPN
+/// sidecar that masks the public IP of the query and download pods. Any
+/// provider supported by gluetun may be used. Fields left unset keep the
+/// defaults, which connect to Private Internet Access.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct VpnSpec {
+    /// If `true`, the pods are created without the VPN sidecar and
+    /// connect to the video service directly. Intended for internal or
+    /// self-hosted media services. All other fields are then ignored.
+    pub disabled: Option<bool>,
+
+    /// Value of gluetun's `VPN_SERVICE_PROVIDER`, e.g. `"mullvad"`.
+    /// Default is `"private internet access"`.
+    pub provider: Option<String>,
+
+    /// Name of the `Secret` with the OpenVPN credentials in its `username`
+    /// and `password` fields. Default is `"pia-creds"`. Providers that use
+    /// other credentials (e.g. a WireGuard private key) can reference their
+    /// secrets with [`env`](VpnSpec::env) instead.
+    #[serde(rename = "secretRef")]
+    pub secret_ref: Option<String>,
+
+    /// Image of the VPN sidecar. Default is `"qmcgaw/gluetun:v3.32.0"`.
+    pub image: Option<String>,
+
+    /// Extra environment variables for the VPN sidecar. Refer to the
+    /// gluetun wiki for the options of each provider:
+    /// <https://github.com/qdm12/gluetun/wiki>
+    pub env: Option<Vec<EnvVar>>,
+
+    /// Server regions the VPN may connect to, passed to gluetun as
+    /// `SERVER_REGIONS`. If unset, the provider's default is used. When a
+    /// geo-blocked video is retried in another region, that region is used
+    /// instead.
+    pub regions: Option<Vec<String>>,
+
+    /// If set, the download pod reconnects the VPN after every this many
+    /// entities of its batch, so that large batches are downloaded from
+    /// several exit IPs without paying the pod startup cost. Uses the
+    /// control server of gluetun. Default is to never rotate.
+    #[serde(rename = "rotateIpEvery")]
+    pub rotate_ip_every: Option<u32>,
+}
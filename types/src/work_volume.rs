@@ -0,0 +1,27 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Scratch volume the download pod writes videos to before uploading
+/// them, instead of streaming youtube-dl's output straight to storage.
+/// youtube-dl resumes a partial file with `--continue`, so a stalled
+/// download picks up where it left off. With a PersistentVolumeClaim,
+/// the partial file also survives the pod being recreated after a
+/// failure, which saves downloading multi-gigabyte videos from zero.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct WorkVolumeSpec {
+    /// If `true`, a PersistentVolumeClaim owned by the
+    /// [`DownloadChildProcess`](crate::DownloadChildProcess) is mounted,
+    /// which outlives its pods. Otherwise an `emptyDir` is mounted, which
+    /// is lost with the pod. Default is `false`.
+    pub persistent: Option<bool>,
+
+    /// Capacity of the volume, e.g. `"50Gi"`, which must fit the largest
+    /// video. Required for a PersistentVolumeClaim. For an `emptyDir`,
+    /// this is its size limit.
+    pub size: Option<String>,
+
+    /// Storage class of the PersistentVolumeClaim. The cluster's default
+    /// storage class is used if omitted.
+    #[serde(rename = "storageClassName")]
+    pub storage_class_name: Option<String>,
+}